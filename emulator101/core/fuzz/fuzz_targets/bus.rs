@@ -0,0 +1,26 @@
+// Arbitrary memory bus traffic: every address must be readable and writable in any hardware
+// state. The input is a list of 4-byte operations: kind, address (little-endian), value.
+#![no_main]
+
+use emulator101_core::{Emulator, Model};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut emulator = Emulator::new(vec![0; 0x8000], Model::Dmg);
+    for op in data.chunks_exact(4) {
+        let addr = u16::from_le_bytes([op[1], op[2]]);
+        match op[0] % 4 {
+            0 => {
+                emulator.bus().read_byte(addr);
+            },
+            1 => emulator.bus_mut().write_byte(addr, op[3]),
+            // Let the hardware move on so accesses land in every PPU mode, DMA and timer state
+            2 => {
+                emulator.step();
+            },
+            _ => {
+                emulator.run_for_m_cycles(op[3] as u64 * 16);
+            },
+        }
+    }
+});
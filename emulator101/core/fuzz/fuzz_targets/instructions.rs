@@ -0,0 +1,28 @@
+// Arbitrary instruction streams: the input is placed at the cartridge entry point (0x0100) of an
+// otherwise empty ROM, its first byte picking the model, and executed for a bounded number of
+// steps under every error policy.
+#![no_main]
+
+use emulator101_core::{Emulator, ErrorPolicy, Model};
+use libfuzzer_sys::fuzz_target;
+
+const ROM_SIZE: usize = 0x8000;
+const ENTRY: usize = 0x0100;
+const MAX_STEPS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, code)) = data.split_first() else {
+        return;
+    };
+    let mut rom = vec![0; ROM_SIZE];
+    let len = code.len().min(ROM_SIZE - ENTRY);
+    rom[ENTRY..ENTRY + len].copy_from_slice(&code[..len]);
+
+    let model = Model::ALL[selector as usize % Model::ALL.len()];
+    let policy = [ErrorPolicy::Ignore, ErrorPolicy::Report, ErrorPolicy::Stop][selector as usize / 16 % 3];
+    let mut emulator = Emulator::new(rom, model);
+    emulator.set_error_policy(policy);
+    for _ in 0..MAX_STEPS {
+        emulator.step();
+    }
+});
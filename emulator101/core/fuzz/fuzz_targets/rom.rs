@@ -0,0 +1,20 @@
+// Arbitrary cartridge images: whatever the header and code say, building the emulator, running
+// it for a while and round-tripping a savestate must not panic.
+#![no_main]
+
+use emulator101_core::{EmulatorBuilder, ErrorPolicy};
+use libfuzzer_sys::fuzz_target;
+
+// Enough for a few frames' worth of instructions while keeping runs fast
+const MAX_STEPS: usize = 20_000;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut emulator) = EmulatorBuilder::new().error_policy(ErrorPolicy::Ignore).build(data) else {
+        return;
+    };
+    for _ in 0..MAX_STEPS {
+        emulator.step();
+    }
+    let state = emulator.save_state();
+    emulator.load_state(&state).expect("a fresh savestate loads");
+});
@@ -0,0 +1,82 @@
+// Accuracy profiles
+// A few pieces of hardware behavior cost speed, or only matter to test ROMs and the odd game
+// that depends on them. A profile switches them as a group; the individual settings stay
+// available for embedders that want a different mix.
+// There is only the scanline renderer, so no profile changes how pixels are drawn.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Accuracy {
+    // DMG OAM corruption by writes and 16-bit inc/dec in 0xFE00-0xFEFF during mode 2
+    pub oam_bug: bool,
+    // VRAM (mode 3) and OAM (modes 2 and 3) read as open bus (0xFF) and drop writes while the
+    // PPU is using them
+    pub memory_locking: bool,
+    // Step the timer, serial port and DMA one T-cycle at a time instead of jumping to the next
+    // event (see MemoryBus::tick). Gives the same results, slower; useful to rule out the
+    // scheduler when chasing a timing bug.
+    pub per_cycle_ticks: bool,
+    // The unusable area past OAM (0xFEA0-0xFEFF) reads as the model does instead of as 0xFF (see
+    // MemoryBus::read_byte). Echo RAM itself mirrors work RAM either way, as it does on every
+    // model emulated so far; CGB work RAM banking would change that for 0xF000-0xFDFF.
+    pub strict_echo_ram: bool,
+    // At most 10 sprites per line, the rest dropped in priority order. On in every profile;
+    // turning it off is an enhancement that stops sprite-heavy games flickering, at the cost of
+    // showing sprites a game hides on purpose by putting 10 others on the same lines.
+    pub sprite_limit: bool,
+}
+
+impl Default for Accuracy {
+    fn default() -> Self {
+        AccuracyProfile::default().settings()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AccuracyProfile {
+    // No OAM bug or memory locking: forgiving towards homebrew that ignores the PPU modes
+    Fast,
+    #[default]
+    Balanced,
+    // Everything on, including per-cycle ticking
+    CycleAccurate,
+}
+
+impl AccuracyProfile {
+    pub const ALL: [AccuracyProfile; 3] =
+        [AccuracyProfile::Fast, AccuracyProfile::Balanced, AccuracyProfile::CycleAccurate];
+
+    pub fn settings(self) -> Accuracy {
+        match self {
+            AccuracyProfile::Fast => Accuracy { oam_bug: false, memory_locking: false, per_cycle_ticks: false, strict_echo_ram: false, sprite_limit: true },
+            AccuracyProfile::Balanced => Accuracy { oam_bug: true, memory_locking: true, per_cycle_ticks: false, strict_echo_ram: false, sprite_limit: true },
+            AccuracyProfile::CycleAccurate => Accuracy { oam_bug: true, memory_locking: true, per_cycle_ticks: true, strict_echo_ram: true, sprite_limit: true },
+        }
+    }
+}
+
+impl fmt::Display for AccuracyProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AccuracyProfile::Fast => "fast",
+            AccuracyProfile::Balanced => "balanced",
+            AccuracyProfile::CycleAccurate => "cycle-accurate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for AccuracyProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AccuracyProfile::ALL
+            .into_iter()
+            .find(|profile| profile.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown accuracy profile '{}' (expected fast, balanced or cycle-accurate)", s))
+    }
+}
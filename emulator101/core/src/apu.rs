@@ -0,0 +1,1060 @@
+// Audio processing unit (NR10-NR52, wave RAM)
+// Four channels: two square waves (1 and 2), the wave channel playing the 32 4-bit samples in
+// wave RAM (3) and the noise channel's LFSR (4). Each channel's frequency timer counts T-cycles
+// from MemoryBus::tick; the frame sequencer that clocks length counters (256 Hz), channel 1's
+// frequency sweep (128 Hz) and volume envelopes (64 Hz) is driven by falling edges of DIV bit 4,
+// so writing DIV moves it the way it does on hardware.
+//
+// Channel outputs go through their DACs, NR51 panning and NR50 volume, are point-sampled at
+// SAMPLE_RATE and run through the high-pass filter the hardware has on each output, which
+// removes the DC offset of enabled DACs. Samples are interleaved stereo i16 (left first).
+
+use alloc::vec::Vec;
+
+use crate::model::Model;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+// Output sample rate in Hz
+pub const SAMPLE_RATE: u32 = 48_000;
+const T_CYCLE_HZ: u32 = 4_194_304;
+
+// Share of the high-pass filter's charge kept from one sample to the next: 0.999958 per T-cycle
+// (as on the DMG), to the power of the T-cycles in a sample
+const HIGH_PASS_CHARGE: f32 = 0.996;
+
+// Waveforms of the four NRx1 duty settings (12.5%, 25%, 50%, 75%), played from bit 7 down
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// Wave RAM as the boot ROM leaves it; the DMG's is random on power-up, these are typical values
+const DMG_WAVE_RAM: [u8; 16] = [0x84, 0x40, 0x43, 0xAA, 0x2D, 0x78, 0x92, 0x3C, 0x60, 0x59, 0x59, 0xB0, 0x34, 0xB8, 0x2E, 0xDA];
+const CGB_WAVE_RAM: [u8; 16] = [0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF, 0x00, 0xFF];
+
+// Length counter: the channel stops when it runs out, if NRx4 bit 6 is set
+#[derive(Default, Clone, Copy)]
+struct Length {
+    counter: u16,
+    enabled: bool,
+}
+
+impl Length {
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+
+    // NRx4 write. Enabling the counter while the next frame sequencer step doesn't clock it
+    // (`extra_clock`) clocks it once right away, and a trigger reloads a counter that ran out.
+    // Returns true when the extra clock ran it out and nothing restarted the channel.
+    fn write_control(&mut self, value: u8, max: u16, extra_clock: bool) -> bool {
+        let was_enabled = self.enabled;
+        self.enabled = value & 0x40 != 0;
+        let mut expired = false;
+        if extra_clock && !was_enabled && self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            expired = self.counter == 0;
+        }
+        if value & 0x80 != 0 && self.counter == 0 {
+            self.counter = if self.enabled && extra_clock { max - 1 } else { max };
+        }
+        expired && value & 0x80 == 0
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        w.write_u16(self.counter);
+        w.write_bool(self.enabled);
+    }
+
+    fn load(r: &mut StateReader, max: u16) -> Result<Self, SaveStateError> {
+        let counter = r.read_u16()?;
+        if counter > max {
+            return Err(SaveStateError::InvalidValue("APU length counter"));
+        }
+        Ok(Length { counter, enabled: r.read_bool()? })
+    }
+}
+
+// Volume envelope of the square and noise channels (NRx2: initial volume, direction, period)
+#[derive(Default, Clone, Copy)]
+struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    // The upper five bits power the channel's DAC
+    fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.register & 0x07;
+    }
+
+    fn clock(&mut self) {
+        let period = self.register & 0x07;
+        if period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = period;
+            if self.register & 0x08 != 0 && self.volume < 15 {
+                self.volume += 1;
+            } else if self.register & 0x08 == 0 && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        w.write_u8(self.register);
+        w.write_u8(self.volume);
+        w.write_u8(self.timer);
+    }
+
+    fn load(r: &mut StateReader) -> Result<Self, SaveStateError> {
+        Ok(Envelope { register: r.read_u8()?, volume: r.read_u8()? & 0x0F, timer: r.read_u8()? & 0x07 })
+    }
+}
+
+// Channel 1's frequency sweep (NR10: period, negate, shift)
+#[derive(Default, Clone, Copy)]
+struct Sweep {
+    register: u8,
+    enabled: bool,
+    timer: u8,
+    // The frequency the sweep works from, copied from NR13/NR14 on trigger
+    shadow: u16,
+    // A subtraction was done since the trigger; clearing negate after it disables the channel
+    negated: bool,
+}
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        self.register >> 4 & 0x07
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    // A period of 0 counts as 8
+    fn reload(&mut self) {
+        self.timer = if self.period() == 0 { 8 } else { self.period() };
+    }
+
+    // The next frequency; above 2047 the channel is disabled
+    fn calculate(&mut self, square: &mut Square) -> u16 {
+        let delta = self.shadow >> self.shift();
+        let frequency = if self.register & 0x08 != 0 {
+            self.negated = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+        if frequency > 2047 {
+            square.enabled = false;
+        }
+        frequency
+    }
+
+    // Channel 1 was triggered: with a shift the overflow check runs right away
+    fn trigger(&mut self, square: &mut Square) {
+        self.shadow = square.frequency;
+        self.reload();
+        self.enabled = self.period() != 0 || self.shift() != 0;
+        self.negated = false;
+        if self.shift() != 0 {
+            self.calculate(square);
+        }
+    }
+
+    // Frame sequencer steps 2 and 6. A new frequency is written back (with a shift) and checked
+    // again for overflow, without writing the second result.
+    fn clock(&mut self, square: &mut Square) {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return;
+        }
+        self.reload();
+        if !self.enabled || self.period() == 0 {
+            return;
+        }
+        let frequency = self.calculate(square);
+        if frequency <= 2047 && self.shift() != 0 {
+            self.shadow = frequency;
+            square.frequency = frequency;
+            self.calculate(square);
+        }
+    }
+
+    fn write(&mut self, value: u8, square: &mut Square) {
+        if self.negated && value & 0x08 == 0 {
+            square.enabled = false;
+        }
+        self.register = value & 0x7F;
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        w.write_u8(self.register);
+        w.write_bool(self.enabled);
+        w.write_u8(self.timer);
+        w.write_u16(self.shadow);
+        w.write_bool(self.negated);
+    }
+
+    fn load(r: &mut StateReader) -> Result<Self, SaveStateError> {
+        Ok(Sweep {
+            register: r.read_u8()? & 0x7F,
+            enabled: r.read_bool()?,
+            timer: r.read_u8()?.min(8),
+            shadow: r.read_u16()? & 0x7FF,
+            negated: r.read_bool()?,
+        })
+    }
+}
+
+// Run a frequency timer for `cycles` T-cycles, calling `step` each time it expires
+fn run_timer(timer: &mut u32, period: u32, mut cycles: u32, mut step: impl FnMut()) {
+    while cycles >= *timer {
+        cycles -= *timer;
+        *timer = period;
+        step();
+    }
+    *timer -= cycles;
+}
+
+// Channels 1 and 2
+#[derive(Clone, Copy)]
+struct Square {
+    enabled: bool,
+    duty: u8,
+    duty_step: u8,
+    length: Length,
+    envelope: Envelope,
+    frequency: u16,
+    timer: u32,
+}
+
+impl Square {
+    fn new() -> Self {
+        Square { enabled: false, duty: 0, duty_step: 0, length: Length::default(), envelope: Envelope::default(), frequency: 0, timer: 8192 }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 4
+    }
+
+    fn run(&mut self, cycles: u32) {
+        let duty_step = &mut self.duty_step;
+        run_timer(&mut self.timer, (2048 - self.frequency as u32) * 4, cycles, || *duty_step = (*duty_step + 1) & 7);
+    }
+
+    fn output(&self) -> u8 {
+        let high = DUTY_PATTERNS[self.duty as usize] >> (7 - self.duty_step) & 1;
+        if self.enabled { high * self.envelope.volume } else { 0 }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = self.period();
+        self.envelope.trigger();
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        match register {
+            1 => self.duty << 6 | 0x3F,
+            2 => self.envelope.register,
+            4 => (self.length.enabled as u8) << 6 | 0xBF,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+        match register {
+            1 => {
+                self.duty = value >> 6;
+                self.length.counter = 64 - (value & 0x3F) as u16;
+            },
+            2 => {
+                self.envelope.register = value;
+                self.enabled &= self.envelope.dac_enabled();
+            },
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value & 0x07) as u16) << 8;
+                if self.length.write_control(value, 64, extra_length_clock) {
+                    self.enabled = false;
+                }
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_u8(self.duty);
+        w.write_u8(self.duty_step);
+        self.length.save(w);
+        self.envelope.save(w);
+        w.write_u16(self.frequency);
+        w.write_u32(self.timer);
+    }
+
+    fn load(r: &mut StateReader) -> Result<Self, SaveStateError> {
+        let mut square = Square {
+            enabled: r.read_bool()?,
+            duty: r.read_u8()? & 0x03,
+            duty_step: r.read_u8()? & 0x07,
+            length: Length::load(r, 64)?,
+            envelope: Envelope::load(r)?,
+            frequency: r.read_u16()? & 0x7FF,
+            timer: r.read_u32()?,
+        };
+        square.timer = square.timer.clamp(1, square.period());
+        Ok(square)
+    }
+}
+
+// Channel 3
+#[derive(Clone, Copy)]
+struct Wave {
+    enabled: bool,
+    dac_enabled: bool,
+    length: Length,
+    // NR32 bits 5-6: mute, 100%, 50%, 25%
+    volume: u8,
+    frequency: u16,
+    timer: u32,
+    // Sample being played (0-31) and its value
+    position: u8,
+    sample: u8,
+    ram: [u8; 16],
+}
+
+impl Wave {
+    fn new(model: Model) -> Self {
+        let ram = if model.is_cgb() { CGB_WAVE_RAM } else { DMG_WAVE_RAM };
+        Wave { enabled: false, dac_enabled: false, length: Length::default(), volume: 0, frequency: 0, timer: 4096, position: 0, sample: 0, ram }
+    }
+
+    fn period(&self) -> u32 {
+        (2048 - self.frequency as u32) * 2
+    }
+
+    fn run(&mut self, cycles: u32) {
+        let (position, sample, ram) = (&mut self.position, &mut self.sample, &self.ram);
+        run_timer(&mut self.timer, (2048 - self.frequency as u32) * 2, cycles, || {
+            *position = (*position + 1) & 31;
+            let byte = ram[*position as usize / 2];
+            *sample = if *position & 1 == 0 { byte >> 4 } else { byte & 0x0F };
+        });
+    }
+
+    fn output(&self) -> u8 {
+        match self.volume {
+            _ if !self.enabled => 0,
+            0 => 0,
+            volume => self.sample >> (volume - 1),
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    // While the channel plays, the CPU only reaches the byte being played
+    fn ram_index(&self, addr: u16) -> usize {
+        if self.enabled { self.position as usize / 2 } else { (addr & 0x0F) as usize }
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        match register {
+            0 => (self.dac_enabled as u8) << 7 | 0x7F,
+            2 => self.volume << 5 | 0x9F,
+            4 => (self.length.enabled as u8) << 6 | 0xBF,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                self.enabled &= self.dac_enabled;
+            },
+            1 => self.length.counter = 256 - value as u16,
+            2 => self.volume = (value >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0xFF) | ((value & 0x07) as u16) << 8;
+                if self.length.write_control(value, 256, extra_length_clock) {
+                    self.enabled = false;
+                }
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        w.write_bool(self.dac_enabled);
+        self.length.save(w);
+        w.write_u8(self.volume);
+        w.write_u16(self.frequency);
+        w.write_u32(self.timer);
+        w.write_u8(self.position);
+        w.write_u8(self.sample);
+        w.write_bytes(&self.ram);
+    }
+
+    fn load(r: &mut StateReader) -> Result<Self, SaveStateError> {
+        let mut wave = Wave {
+            enabled: r.read_bool()?,
+            dac_enabled: r.read_bool()?,
+            length: Length::load(r, 256)?,
+            volume: r.read_u8()? & 0x03,
+            frequency: r.read_u16()? & 0x7FF,
+            timer: r.read_u32()?,
+            position: r.read_u8()? & 31,
+            sample: r.read_u8()? & 0x0F,
+            ram: [0; 16],
+        };
+        r.read_into(&mut wave.ram)?;
+        wave.timer = wave.timer.clamp(1, wave.period());
+        Ok(wave)
+    }
+}
+
+// Channel 4
+#[derive(Clone, Copy)]
+struct Noise {
+    enabled: bool,
+    length: Length,
+    envelope: Envelope,
+    // NR43: clock shift (bits 4-7), 7-bit LFSR (bit 3), divisor code (bits 0-2)
+    register: u8,
+    lfsr: u16,
+    timer: u32,
+}
+
+impl Noise {
+    fn new() -> Self {
+        Noise { enabled: false, length: Length::default(), envelope: Envelope::default(), register: 0, lfsr: 0x7FFF, timer: 8 }
+    }
+
+    fn period(&self) -> u32 {
+        NOISE_DIVISORS[(self.register & 0x07) as usize] << (self.register >> 4)
+    }
+
+    fn run(&mut self, cycles: u32) {
+        let (period, narrow, lfsr) = (self.period(), self.register & 0x08 != 0, &mut self.lfsr);
+        // Shifts 14 and 15 don't clock the LFSR at all
+        if self.register >> 4 >= 14 {
+            return;
+        }
+        run_timer(&mut self.timer, period, cycles, || {
+            let bit = (*lfsr ^ (*lfsr >> 1)) & 1;
+            *lfsr = (*lfsr >> 1) | bit << 14;
+            if narrow {
+                *lfsr = (*lfsr & !0x40) | bit << 6;
+            }
+        });
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && self.lfsr & 1 == 0 { self.envelope.volume } else { 0 }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+        self.envelope.trigger();
+    }
+
+    fn read(&self, register: u16) -> u8 {
+        match register {
+            2 => self.envelope.register,
+            3 => self.register,
+            4 => (self.length.enabled as u8) << 6 | 0xBF,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, register: u16, value: u8, extra_length_clock: bool) {
+        match register {
+            1 => self.length.counter = 64 - (value & 0x3F) as u16,
+            2 => {
+                self.envelope.register = value;
+                self.enabled &= self.envelope.dac_enabled();
+            },
+            3 => {
+                self.register = value;
+                self.timer = self.timer.clamp(1, self.period());
+            },
+            4 => {
+                if self.length.write_control(value, 64, extra_length_clock) {
+                    self.enabled = false;
+                }
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        w.write_bool(self.enabled);
+        self.length.save(w);
+        self.envelope.save(w);
+        w.write_u8(self.register);
+        w.write_u16(self.lfsr);
+        w.write_u32(self.timer);
+    }
+
+    fn load(r: &mut StateReader) -> Result<Self, SaveStateError> {
+        let mut noise = Noise {
+            enabled: r.read_bool()?,
+            length: Length::load(r, 64)?,
+            envelope: Envelope::load(r)?,
+            register: r.read_u8()?,
+            lfsr: r.read_u16()? & 0x7FFF,
+            timer: r.read_u32()?,
+        };
+        noise.timer = noise.timer.clamp(1, noise.period());
+        Ok(noise)
+    }
+}
+
+pub struct Apu {
+    model: Model,
+    // NR52 bit 7; while off every register but NR52 and wave RAM reads 0 and ignores writes
+    powered: bool,
+    sweep: Sweep,
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    nr50: u8,
+    nr51: u8,
+    // Frame sequencer step (0-7) run on the next DIV edge
+    frame_step: u8,
+    // SAMPLE_RATE per T-cycle, a sample each time it passes T_CYCLE_HZ
+    sample_clock: u32,
+    // High-pass filter charge, left and right; not saved, loading a state settles it instead
+    capacitors: [f32; 2],
+    samples: Vec<i16>,
+}
+
+impl Apu {
+    // In the state the boot ROM leaves it: on, both outputs at full volume and channel 1 still
+    // enabled from the boot sound, silent
+    pub fn new(model: Model) -> Self {
+        let mut apu = Self::powered_off(model);
+        apu.powered = true;
+        apu.square1.duty = 2;
+        apu.square1.envelope.register = 0xF3;
+        apu.square1.frequency = 0x7C1;
+        apu.square1.timer = apu.square1.period();
+        apu.square1.enabled = model != Model::Sgb;
+        apu.nr50 = 0x77;
+        apu.nr51 = 0xF3;
+        // Settled on channel 1's idle DAC, so the game doesn't start with a pop
+        apu.capacitors = apu.mix();
+        apu
+    }
+
+    // At power-on, before the boot ROM has run
+    pub fn powered_off(model: Model) -> Self {
+        Apu {
+            model,
+            powered: false,
+            sweep: Sweep::default(),
+            square1: Square::new(),
+            square2: Square::new(),
+            wave: Wave::new(model),
+            noise: Noise::new(),
+            nr50: 0,
+            nr51: 0,
+            frame_step: 0,
+            sample_clock: 0,
+            capacitors: [0.0; 2],
+            samples: Vec::new(),
+        }
+    }
+
+    // Move the samples produced so far to the end of `out`
+    pub(crate) fn take_samples(&mut self, out: &mut Vec<i16>) {
+        if !self.samples.is_empty() {
+            out.append(&mut self.samples);
+        }
+    }
+
+    // Advance by `cycles` T-cycles, producing the samples that fall in them
+    pub(crate) fn advance(&mut self, mut cycles: u32) {
+        while cycles > 0 {
+            let until_sample = (T_CYCLE_HZ - self.sample_clock).div_ceil(SAMPLE_RATE);
+            let step = cycles.min(until_sample);
+            if self.powered {
+                self.run_channels(step);
+            }
+            self.sample_clock += step * SAMPLE_RATE;
+            if self.sample_clock >= T_CYCLE_HZ {
+                self.sample_clock -= T_CYCLE_HZ;
+                self.push_sample();
+            }
+            cycles -= step;
+        }
+    }
+
+    fn run_channels(&mut self, cycles: u32) {
+        if self.square1.enabled {
+            self.square1.run(cycles);
+        }
+        if self.square2.enabled {
+            self.square2.run(cycles);
+        }
+        if self.wave.enabled {
+            self.wave.run(cycles);
+        }
+        if self.noise.enabled {
+            self.noise.run(cycles);
+        }
+    }
+
+    // A falling edge of DIV bit 4: length counters on even steps, the sweep on steps 2 and 6,
+    // envelopes on step 7
+    pub(crate) fn clock_frame_sequencer(&mut self) {
+        if !self.powered {
+            return;
+        }
+        let step = self.frame_step;
+        self.frame_step = (step + 1) & 7;
+        if step & 1 == 0 {
+            if self.square1.length.clock() {
+                self.square1.enabled = false;
+            }
+            if self.square2.length.clock() {
+                self.square2.enabled = false;
+            }
+            if self.wave.length.clock() {
+                self.wave.enabled = false;
+            }
+            if self.noise.length.clock() {
+                self.noise.enabled = false;
+            }
+        }
+        if step == 2 || step == 6 {
+            self.sweep.clock(&mut self.square1);
+        }
+        if step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+    }
+
+    // Mix the channels into one stereo sample
+    fn push_sample(&mut self) {
+        let mixed = self.mix();
+        for (input, capacitor) in mixed.into_iter().zip(&mut self.capacitors) {
+            let output = input - *capacitor;
+            *capacitor = input - output * HIGH_PASS_CHARGE;
+            self.samples.push((output.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+        }
+    }
+
+    // Left and right output before the high-pass filter
+    fn mix(&self) -> [f32; 2] {
+        if !self.powered {
+            return [0.0; 2];
+        }
+        // Each DAC maps 0-15 to +1..-1; a DAC that is off outputs nothing
+        let dac = |on: bool, value: u8| if on { 1.0 - value as f32 / 7.5 } else { 0.0 };
+        let channels = [
+            dac(self.square1.envelope.dac_enabled(), self.square1.output()),
+            dac(self.square2.envelope.dac_enabled(), self.square2.output()),
+            dac(self.wave.dac_enabled, self.wave.output()),
+            dac(self.noise.envelope.dac_enabled(), self.noise.output()),
+        ];
+        // Left is NR51 bits 4-7 and NR50 bits 4-6, right the low bits
+        [4, 0].map(|shift| {
+            let mut mixed = 0.0;
+            for (channel, value) in channels.iter().enumerate() {
+                if self.nr51 >> (shift + channel) & 1 != 0 {
+                    mixed += value;
+                }
+            }
+            // Four channels at the top master volume (8) reach +-32
+            mixed * (((self.nr50 >> shift) & 0x07) as f32 + 1.0) / 32.0
+        })
+    }
+
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF26 => {
+                let status = [self.square1.enabled, self.square2.enabled, self.wave.enabled, self.noise.enabled]
+                    .iter()
+                    .enumerate()
+                    .fold(0, |status, (channel, &on)| status | (on as u8) << channel);
+                (self.powered as u8) << 7 | 0x70 | status
+            },
+            0xFF30..=0xFF3F => self.wave.ram[self.wave.ram_index(addr)],
+            0xFF10 => self.sweep.register | 0x80,
+            0xFF11..=0xFF14 => self.square1.read(addr - 0xFF10),
+            0xFF16..=0xFF19 => self.square2.read(addr - 0xFF15),
+            0xFF1A..=0xFF1E => self.wave.read(addr - 0xFF1A),
+            0xFF20..=0xFF23 => self.noise.read(addr - 0xFF1F),
+            0xFF24 => self.nr50,
+            0xFF25 => self.nr51,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        if addr == 0xFF26 {
+            self.set_power(value & 0x80 != 0);
+            return;
+        }
+        if let 0xFF30..=0xFF3F = addr {
+            let index = self.wave.ram_index(addr);
+            self.wave.ram[index] = value;
+            return;
+        }
+        if !self.powered {
+            // The DMG keeps its length counters powered, and they can still be written
+            if !self.model.is_cgb() {
+                match addr {
+                    0xFF11 => self.square1.length.counter = 64 - (value & 0x3F) as u16,
+                    0xFF16 => self.square2.length.counter = 64 - (value & 0x3F) as u16,
+                    0xFF1B => self.wave.length.counter = 256 - value as u16,
+                    0xFF20 => self.noise.length.counter = 64 - (value & 0x3F) as u16,
+                    _ => {},
+                }
+            }
+            return;
+        }
+        // The frame sequencer's next step doesn't clock length counters
+        let extra_length_clock = self.frame_step & 1 == 1;
+        match addr {
+            0xFF10 => self.sweep.write(value, &mut self.square1),
+            0xFF11..=0xFF14 => {
+                self.square1.write(addr - 0xFF10, value, extra_length_clock);
+                if addr == 0xFF14 && value & 0x80 != 0 {
+                    self.sweep.trigger(&mut self.square1);
+                }
+            },
+            0xFF16..=0xFF19 => self.square2.write(addr - 0xFF15, value, extra_length_clock),
+            0xFF1A..=0xFF1E => self.wave.write(addr - 0xFF1A, value, extra_length_clock),
+            0xFF20..=0xFF23 => self.noise.write(addr - 0xFF1F, value, extra_length_clock),
+            0xFF24 => self.nr50 = value,
+            0xFF25 => self.nr51 = value,
+            _ => {},
+        }
+    }
+
+    // NR52 bit 7. Turning the APU off clears every register (the DMG keeps length counters);
+    // turning it on restarts the frame sequencer.
+    fn set_power(&mut self, on: bool) {
+        if on == self.powered {
+            return;
+        }
+        if on {
+            self.powered = true;
+            self.frame_step = 0;
+            return;
+        }
+        let lengths = [self.square1.length, self.square2.length, self.wave.length, self.noise.length];
+        let mut off = Self::powered_off(self.model);
+        off.wave.ram = self.wave.ram;
+        off.sample_clock = self.sample_clock;
+        off.capacitors = self.capacitors;
+        off.samples = core::mem::take(&mut self.samples);
+        if !self.model.is_cgb() {
+            off.square1.length.counter = lengths[0].counter;
+            off.square2.length.counter = lengths[1].counter;
+            off.wave.length.counter = lengths[2].counter;
+            off.noise.length.counter = lengths[3].counter;
+        }
+        *self = off;
+    }
+
+    // Wave RAM (0xFF30-0xFF3F) as stored, even while channel 3 is playing it
+    pub fn wave_ram(&self) -> [u8; 16] {
+        self.wave.ram
+    }
+
+    // Sample channel 3 is playing (0-31, two per wave RAM byte, high nibble first), None while
+    // it is off
+    pub fn wave_position(&self) -> Option<u8> {
+        self.wave.enabled.then_some(self.wave.position)
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.powered);
+        self.sweep.save(w);
+        self.square1.save(w);
+        self.square2.save(w);
+        self.wave.save(w);
+        self.noise.save(w);
+        w.write_u8(self.nr50);
+        w.write_u8(self.nr51);
+        w.write_u8(self.frame_step);
+        w.write_u32(self.sample_clock);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.powered = r.read_bool()?;
+        self.sweep = Sweep::load(r)?;
+        self.square1 = Square::load(r)?;
+        self.square2 = Square::load(r)?;
+        self.wave = Wave::load(r)?;
+        self.noise = Noise::load(r)?;
+        self.nr50 = r.read_u8()?;
+        self.nr51 = r.read_u8()?;
+        self.frame_step = r.read_u8()? & 7;
+        self.sample_clock = r.read_u32()? % T_CYCLE_HZ;
+        self.capacitors = self.mix();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Trigger channel 1 at `frequency` with NR10 = `nr10`, its DAC on
+    fn trigger_square1(nr10: u8, frequency: u16) -> Apu {
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write_register(0xFF10, nr10);
+        apu.write_register(0xFF12, 0xF0);
+        apu.write_register(0xFF13, frequency as u8);
+        apu.write_register(0xFF14, 0x80 | (frequency >> 8) as u8);
+        apu
+    }
+
+    fn square1_on(apu: &Apu) -> bool {
+        apu.read_register(0xFF26) & 0x01 != 0
+    }
+
+    #[test]
+    fn sweep_overflow_on_trigger_disables_channel_1() {
+        // 0x7FF + (0x7FF >> 1) overflows right away, even with a period of 0
+        assert!(!square1_on(&trigger_square1(0x01, 0x7FF)));
+        // Without a shift nothing is calculated on trigger
+        assert!(square1_on(&trigger_square1(0x00, 0x7FF)));
+        assert!(square1_on(&trigger_square1(0x01, 0x400)));
+    }
+
+    #[test]
+    fn sweep_period_0_does_not_recalculate() {
+        let mut apu = trigger_square1(0x01, 0x500);
+        for _ in 0..64 {
+            apu.clock_frame_sequencer();
+        }
+        assert!(square1_on(&apu));
+        assert_eq!(apu.square1.frequency, 0x500);
+
+        // Period 1 moves it on steps 2 and 6: 0x300, 0x480, then 0x6C0 and the check after it
+        // (0xA20) overflows
+        let mut apu = trigger_square1(0x11, 0x200);
+        for _ in 0..3 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.square1.frequency, 0x300);
+        for _ in 0..4 {
+            apu.clock_frame_sequencer();
+        }
+        assert!(square1_on(&apu));
+        assert_eq!(apu.square1.frequency, 0x480);
+        for _ in 0..4 {
+            apu.clock_frame_sequencer();
+        }
+        assert!(!square1_on(&apu));
+    }
+
+    #[test]
+    fn clearing_negate_after_a_negated_calculation_disables_channel_1() {
+        // The trigger's overflow check already subtracted
+        let mut apu = trigger_square1(0x19, 0x400);
+        assert!(square1_on(&apu));
+        apu.write_register(0xFF10, 0x11);
+        assert!(!square1_on(&apu));
+
+        // Negate without a calculation since the trigger can be cleared
+        let mut apu = trigger_square1(0x08, 0x400);
+        apu.write_register(0xFF10, 0x00);
+        assert!(square1_on(&apu));
+    }
+
+    fn square2_on(apu: &Apu) -> bool {
+        apu.read_register(0xFF26) & 0x02 != 0
+    }
+
+    #[test]
+    fn length_counter_disables_the_channel_when_it_runs_out() {
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write_register(0xFF17, 0xF0);
+        apu.write_register(0xFF16, 0x3E);
+        apu.write_register(0xFF19, 0xC0);
+        // Steps 0 and 2 clock it
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        assert!(square2_on(&apu));
+        apu.clock_frame_sequencer();
+        assert!(!square2_on(&apu));
+
+        // Left disabled, the counter doesn't run
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write_register(0xFF17, 0xF0);
+        apu.write_register(0xFF16, 0x3F);
+        apu.write_register(0xFF19, 0x80);
+        for _ in 0..16 {
+            apu.clock_frame_sequencer();
+        }
+        assert!(square2_on(&apu));
+    }
+
+    #[test]
+    fn enabling_length_before_a_step_that_skips_it_clocks_it_once() {
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write_register(0xFF17, 0xF0);
+        apu.write_register(0xFF16, 0x3F);
+        // Step 1 is next, which doesn't clock length counters
+        apu.clock_frame_sequencer();
+        apu.write_register(0xFF19, 0x80);
+        assert!(square2_on(&apu));
+        apu.write_register(0xFF19, 0x40);
+        assert!(!square2_on(&apu));
+
+        // A trigger reloads the expired counter with 64, and the extra clock takes one off
+        apu.write_register(0xFF19, 0xC0);
+        assert!(square2_on(&apu));
+        assert_eq!(apu.square2.length.counter, 63);
+
+        // Before a step that clocks them, enabling does nothing more
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write_register(0xFF17, 0xF0);
+        apu.write_register(0xFF16, 0x3F);
+        apu.write_register(0xFF19, 0x80);
+        apu.write_register(0xFF19, 0x40);
+        assert!(square2_on(&apu));
+        assert_eq!(apu.square2.length.counter, 1);
+    }
+
+    #[test]
+    fn volume_envelope_steps_once_per_period() {
+        // Volume 5, decreasing every second step 7
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write_register(0xFF17, 0x52);
+        apu.write_register(0xFF19, 0x80);
+        assert_eq!(apu.square2.envelope.volume, 5);
+        for _ in 0..8 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.square2.envelope.volume, 5);
+        for _ in 0..8 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.square2.envelope.volume, 4);
+
+        // Increasing stops at 15
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write_register(0xFF17, 0xE9);
+        apu.write_register(0xFF19, 0x80);
+        for _ in 0..8 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.square2.envelope.volume, 15);
+        for _ in 0..8 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.square2.envelope.volume, 15);
+
+        // Period 0 never moves it
+        let mut apu = Apu::new(Model::Dmg);
+        apu.write_register(0xFF17, 0x70);
+        apu.write_register(0xFF19, 0x80);
+        for _ in 0..64 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.square2.envelope.volume, 7);
+    }
+
+    #[test]
+    fn power_off_clears_the_registers_and_ignores_writes() {
+        for model in [Model::Dmg, Model::Cgb] {
+            let mut apu = Apu::new(model);
+            apu.write_register(0xFF30, 0x12);
+            apu.write_register(0xFF17, 0xF0);
+            apu.write_register(0xFF16, 0x3F);
+            apu.write_register(0xFF19, 0x80);
+            apu.write_register(0xFF26, 0x00);
+
+            assert_eq!(apu.read_register(0xFF26), 0x70);
+            assert_eq!(apu.read_register(0xFF24), 0x00);
+            assert_eq!(apu.read_register(0xFF25), 0x00);
+            assert_eq!(apu.read_register(0xFF17), 0x00);
+            apu.write_register(0xFF24, 0x77);
+            apu.write_register(0xFF17, 0xF0);
+            assert_eq!(apu.read_register(0xFF24), 0x00);
+            assert_eq!(apu.read_register(0xFF17), 0x00);
+            // Wave RAM survives and stays writable
+            assert_eq!(apu.read_register(0xFF30), 0x12);
+            apu.write_register(0xFF31, 0x34);
+            assert_eq!(apu.read_register(0xFF31), 0x34);
+            // Only the DMG keeps its length counters, and lets them be written
+            apu.write_register(0xFF20, 0x3E);
+            let kept = if model.is_cgb() { (0, 0) } else { (1, 2) };
+            assert_eq!((apu.square2.length.counter, apu.noise.length.counter), kept);
+
+            apu.write_register(0xFF26, 0x80);
+            assert_eq!(apu.read_register(0xFF26), 0xF0);
+            apu.write_register(0xFF24, 0x77);
+            assert_eq!(apu.read_register(0xFF24), 0x77);
+        }
+    }
+
+    #[test]
+    fn wave_ram_access_while_playing_reaches_the_byte_being_played() {
+        let mut apu = Apu::new(Model::Dmg);
+        for index in 0..16 {
+            apu.write_register(0xFF30 + index, index as u8 * 0x11);
+        }
+        assert_eq!(apu.read_register(0xFF35), 0x55);
+
+        // The fastest frequency steps a sample every 2 T-cycles
+        apu.write_register(0xFF1A, 0x80);
+        apu.write_register(0xFF1D, 0xFF);
+        apu.write_register(0xFF1E, 0x87);
+        assert_eq!(apu.read_register(0xFF3F), 0x00);
+        apu.advance(6);
+        assert_eq!(apu.wave_position(), Some(3));
+        assert_eq!(apu.read_register(0xFF3F), 0x11);
+        apu.write_register(0xFF30, 0xAB);
+        assert_eq!(apu.wave_ram()[1], 0xAB);
+        assert_eq!(apu.wave_ram()[0], 0x00);
+
+        // Turning the DAC off stops the channel and gives the CPU the whole RAM again
+        apu.write_register(0xFF1A, 0x00);
+        assert_eq!(apu.wave_position(), None);
+        assert_eq!(apu.read_register(0xFF3F), 0xFF);
+        assert_eq!(apu.read_register(0xFF31), 0xAB);
+    }
+}
@@ -0,0 +1,132 @@
+// Emulator construction
+// Collects the optional setup (model, mapper, boot ROM, palette, error policy, RTC mode) and validates
+// the inputs, so embedders get a descriptive error instead of a machine that silently misbehaves.
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use crate::accuracy::Accuracy;
+use crate::clock::RtcMode;
+use crate::emulator::Emulator;
+use crate::error::{ErrorPolicy, LoadError};
+use crate::mapper::MapperKind;
+use crate::model::Model;
+use crate::ppu::Palette;
+
+// End of the cartridge header
+const MIN_ROM_SIZE: usize = 0x150;
+
+enum BootRomSource {
+    Bytes(Vec<u8>),
+    #[cfg(feature = "std")]
+    File(PathBuf),
+}
+
+#[derive(Default)]
+pub struct EmulatorBuilder {
+    model: Option<Model>,
+    mapper: Option<MapperKind>,
+    boot_rom: Option<BootRomSource>,
+    palette: Palette,
+    accuracy: Accuracy,
+    error_policy: ErrorPolicy,
+    rtc_mode: RtcMode,
+}
+
+#[cfg(feature = "std")]
+fn read_file(path: &Path) -> Result<Vec<u8>, LoadError> {
+    std::fs::read(path).map_err(|source| LoadError::Io { path: path.to_path_buf(), source })
+}
+
+impl EmulatorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Without this the mapper is guessed from the cartridge header (MapperKind::detect)
+    pub fn mapper(mut self, mapper: MapperKind) -> Self {
+        self.mapper = Some(mapper);
+        self
+    }
+
+    // Without this the model is picked from the cartridge header
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    // Start from power-on and run this boot ROM instead of skipping to the post-boot state
+    pub fn bootrom(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.boot_rom = Some(BootRomSource::Bytes(data.into()));
+        self
+    }
+
+    // Same as bootrom, but read from a file when the emulator is built
+    #[cfg(feature = "std")]
+    pub fn bootrom_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.boot_rom = Some(BootRomSource::File(path.into()));
+        self
+    }
+
+    pub fn palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    // Defaults to AccuracyProfile::Balanced
+    pub fn accuracy(mut self, accuracy: Accuracy) -> Self {
+        self.accuracy = accuracy;
+        self
+    }
+
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    pub fn rtc_mode(mut self, mode: RtcMode) -> Self {
+        self.rtc_mode = mode;
+        self
+    }
+
+    pub fn build(self, rom: impl Into<Vec<u8>>) -> Result<Emulator, LoadError> {
+        let rom = rom.into();
+        if rom.len() < MIN_ROM_SIZE {
+            return Err(LoadError::RomTooSmall(rom.len()));
+        }
+        let model = self.model.unwrap_or_else(|| Model::from_header(&rom));
+
+        let boot_rom = match self.boot_rom {
+            Some(BootRomSource::Bytes(data)) => Some(data),
+            #[cfg(feature = "std")]
+            Some(BootRomSource::File(path)) => Some(read_file(&path)?),
+            None => None,
+        };
+        if let Some(boot_rom) = &boot_rom {
+            let expected = if model.is_cgb() { 0x900 } else { 0x100 };
+            if boot_rom.len() != expected {
+                return Err(LoadError::BootRomSize { expected, found: boot_rom.len() });
+            }
+        }
+
+        let mut emulator = Emulator::new(rom, model);
+        emulator.set_palette(self.palette);
+        emulator.set_accuracy(self.accuracy);
+        emulator.set_error_policy(self.error_policy);
+        emulator.set_rtc_mode(self.rtc_mode);
+        emulator.set_mapper(self.mapper);
+        let boot = boot_rom.is_some();
+        emulator.set_boot_rom(boot_rom);
+        if boot || self.mapper.is_some() {
+            emulator.reset();
+        }
+        Ok(emulator)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn build_from_file(self, path: impl AsRef<Path>) -> Result<Emulator, LoadError> {
+        let rom = read_file(path.as_ref())?;
+        self.build(rom)
+    }
+}
@@ -0,0 +1,155 @@
+// Cartridge header
+// Every ROM describes itself at 0x0134-0x014F: the title, whether it uses Color features, the
+// cartridge type (mapper, RAM, battery, clock), ROM and RAM sizes and two checksums. The boot
+// ROM refuses carts whose header checksum is wrong; the global checksum is never checked by the
+// hardware and many homebrew ROMs leave it at zero. ROMs too short for a header read as zeros.
+
+use alloc::string::String;
+use core::fmt;
+
+const TITLE: core::ops::Range<usize> = 0x0134..0x0144;
+const CGB_FLAG: usize = 0x0143;
+const CARTRIDGE_TYPE: usize = 0x0147;
+const ROM_SIZE: usize = 0x0148;
+const RAM_SIZE: usize = 0x0149;
+const HEADER_CHECKSUM: usize = 0x014D;
+const GLOBAL_CHECKSUM: usize = 0x014E;
+
+// Header byte 0x0143
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CgbSupport {
+    // Made for the original Game Boy
+    #[default]
+    None,
+    // Runs on both, with colors on a Color (0x80)
+    Dual,
+    // Only runs on a Color (0xC0)
+    Only,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cartridge {
+    // Printable ASCII up to the first other byte, trimmed; empty when the header has none
+    pub title: String,
+    pub cgb: CgbSupport,
+    // Header byte 0x0147 (see type_name)
+    pub cartridge_type: u8,
+    // Sizes in bytes the header claims; 0 for codes it doesn't define
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub header_checksum: u8,
+    pub header_checksum_valid: bool,
+    pub global_checksum: u16,
+    pub global_checksum_valid: bool,
+}
+
+impl Cartridge {
+    pub fn parse(rom: &[u8]) -> Self {
+        let byte = |addr: usize| rom.get(addr).copied().unwrap_or(0);
+        let title = rom.get(TITLE).unwrap_or_default();
+        let title: String = title.iter().take_while(|&&b| (0x20..0x7F).contains(&b)).map(|&b| b as char).collect();
+        let header_sum = TITLE.start..HEADER_CHECKSUM;
+        let header_checksum = byte(HEADER_CHECKSUM);
+        let computed_header = header_sum.fold(0u8, |sum, addr| sum.wrapping_sub(byte(addr)).wrapping_sub(1));
+        let global_checksum = u16::from_be_bytes([byte(GLOBAL_CHECKSUM), byte(GLOBAL_CHECKSUM + 1)]);
+        let computed_global = rom.iter().enumerate()
+            .filter(|&(addr, _)| addr != GLOBAL_CHECKSUM && addr != GLOBAL_CHECKSUM + 1)
+            .fold(0u16, |sum, (_, &b)| sum.wrapping_add(b as u16));
+
+        Cartridge {
+            title: String::from(title.trim()),
+            cgb: match byte(CGB_FLAG) {
+                0xC0 => CgbSupport::Only,
+                0x80 => CgbSupport::Dual,
+                _ => CgbSupport::None,
+            },
+            cartridge_type: byte(CARTRIDGE_TYPE),
+            rom_size: match byte(ROM_SIZE) {
+                code @ 0x00..=0x08 => 0x8000 << code,
+                _ => 0,
+            },
+            ram_size: match byte(RAM_SIZE) {
+                0x01 => 0x800,
+                0x02 => 0x2000,
+                0x03 => 0x8000,
+                0x04 => 0x20000,
+                0x05 => 0x10000,
+                _ => 0,
+            },
+            header_checksum,
+            header_checksum_valid: rom.len() > HEADER_CHECKSUM && computed_header == header_checksum,
+            global_checksum,
+            global_checksum_valid: rom.len() > GLOBAL_CHECKSUM + 1 && computed_global == global_checksum,
+        }
+    }
+
+    // Cartridge types with battery-backed RAM (or a clock that keeps running)
+    pub fn has_battery(&self) -> bool {
+        matches!(self.cartridge_type, 0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E | 0x22 | 0xFE | 0xFF)
+    }
+
+    // Cartridge types with a real-time clock (MBC3+TIMER)
+    pub fn has_timer(&self) -> bool {
+        matches!(self.cartridge_type, 0x0F | 0x10)
+    }
+
+    // Cartridge types with a rumble motor (MBC5+RUMBLE)
+    pub fn has_rumble(&self) -> bool {
+        matches!(self.cartridge_type, 0x1C..=0x1E)
+    }
+
+    // Name of the cartridge type, as the Pan Docs list them
+    pub fn type_name(&self) -> &'static str {
+        match self.cartridge_type {
+            0x00 => "ROM ONLY",
+            0x01 => "MBC1",
+            0x02 => "MBC1+RAM",
+            0x03 => "MBC1+RAM+BATTERY",
+            0x05 => "MBC2",
+            0x06 => "MBC2+BATTERY",
+            0x08 => "ROM+RAM",
+            0x09 => "ROM+RAM+BATTERY",
+            0x0B => "MMM01",
+            0x0C => "MMM01+RAM",
+            0x0D => "MMM01+RAM+BATTERY",
+            0x0F => "MBC3+TIMER+BATTERY",
+            0x10 => "MBC3+TIMER+RAM+BATTERY",
+            0x11 => "MBC3",
+            0x12 => "MBC3+RAM",
+            0x13 => "MBC3+RAM+BATTERY",
+            0x19 => "MBC5",
+            0x1A => "MBC5+RAM",
+            0x1B => "MBC5+RAM+BATTERY",
+            0x1C => "MBC5+RUMBLE",
+            0x1D => "MBC5+RUMBLE+RAM",
+            0x1E => "MBC5+RUMBLE+RAM+BATTERY",
+            0x20 => "MBC6",
+            0x22 => "MBC7+SENSOR+RUMBLE+RAM+BATTERY",
+            0xFC => "POCKET CAMERA",
+            0xFD => "BANDAI TAMA5",
+            0xFE => "HuC3",
+            0xFF => "HuC1+RAM+BATTERY",
+            _ => "unknown",
+        }
+    }
+}
+
+// `"TETRIS" (ROM ONLY, 32 KiB ROM)`
+impl fmt::Display for Cartridge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let title = if self.title.is_empty() { "untitled" } else { &self.title };
+        write!(f, "\"{}\" ({}, {} KiB ROM", title, self.type_name(), self.rom_size / 1024)?;
+        if self.ram_size > 0 {
+            write!(f, ", {} KiB RAM", self.ram_size / 1024)?;
+        }
+        match self.cgb {
+            CgbSupport::None => {},
+            CgbSupport::Dual => f.write_str(", Color enhanced")?,
+            CgbSupport::Only => f.write_str(", Color only")?,
+        }
+        if !self.header_checksum_valid {
+            f.write_str(", bad header checksum")?;
+        }
+        f.write_str(")")
+    }
+}
@@ -0,0 +1,151 @@
+// Cheats: Game Genie codes and frozen addresses
+// A Game Genie sits between the console and the cartridge and replaces the byte read from one
+// ROM address. Codes come in two formats:
+//
+//   ABC-DEF      always replace
+//   ABC-DEF-GHI  only replace when the ROM holds the compare value (so the patch stays out of
+//                other banks mapped at the same address)
+//
+// AB is the new value and the address is (F ^ 0xF) CDE. The compare value is the byte GI rotated
+// right by two and XORed with 0xBA; H is not used by the hardware.
+//
+// Freezing locks a RAM address to a value: it is written when the freeze is added and every
+// later write to the address stores the frozen value instead, the way practice tools and
+// GameShark-style cheats keep a counter full.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use crate::error::CheatError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GameGenieCode {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl GameGenieCode {
+    // The replacement byte, if the code applies when the cartridge holds `original`
+    fn patch(&self, original: u8) -> Option<u8> {
+        match self.compare {
+            Some(compare) if compare != original => None,
+            _ => Some(self.value),
+        }
+    }
+}
+
+impl FromStr for GameGenieCode {
+    type Err = CheatError;
+
+    fn from_str(s: &str) -> Result<Self, CheatError> {
+        let digits = s
+            .chars()
+            .filter(|&c| c != '-')
+            .map(|c| c.to_digit(16).map(|d| d as u8).ok_or(CheatError::InvalidDigit(c)))
+            .collect::<Result<Vec<u8>, CheatError>>()?;
+        if digits.len() != 6 && digits.len() != 9 {
+            return Err(CheatError::InvalidLength(digits.len()));
+        }
+
+        let value = digits[0] << 4 | digits[1];
+        let address = ((digits[5] ^ 0xF) as u16) << 12 | (digits[2] as u16) << 8 | (digits[3] as u16) << 4 | digits[4] as u16;
+        if address >= 0x8000 {
+            return Err(CheatError::NotRomAddress(address));
+        }
+        let compare = (digits.len() == 9).then(|| (digits[6] << 4 | digits[8]).rotate_right(2) ^ 0xBA);
+
+        Ok(Self { address, value, compare })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    // The code as the user entered it
+    pub text: String,
+    pub code: GameGenieCode,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Freeze {
+    pub address: u16,
+    pub value: u8,
+}
+
+// Codes plugged into the (virtual) Game Genie and frozen addresses. They are a user setting
+// rather than machine state, so they survive resets and state loads and are not saved in
+// savestates.
+#[derive(Debug, Clone, Default)]
+pub struct Cheats {
+    list: Vec<Cheat>,
+    freezes: Vec<Freeze>,
+}
+
+impl Cheats {
+    // Add an enabled code, returning its index
+    pub fn add(&mut self, text: &str) -> Result<usize, CheatError> {
+        let code = text.trim().parse()?;
+        self.list.push(Cheat { text: text.trim().to_uppercase(), code, enabled: true });
+        Ok(self.list.len() - 1)
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Cheat> {
+        (index < self.list.len()).then(|| self.list.remove(index))
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) {
+        if let Some(cheat) = self.list.get_mut(index) {
+            cheat.enabled = enabled;
+        }
+    }
+
+    pub fn set_all_enabled(&mut self, enabled: bool) {
+        for cheat in &mut self.list {
+            cheat.enabled = enabled;
+        }
+    }
+
+    // Remove every code and freeze
+    pub fn clear(&mut self) {
+        self.list.clear();
+        self.freezes.clear();
+    }
+
+    pub fn list(&self) -> &[Cheat] {
+        &self.list
+    }
+
+    // Frozen addresses; use Emulator::freeze / unfreeze to change them
+    pub fn freezes(&self) -> &[Freeze] {
+        &self.freezes
+    }
+
+    pub(crate) fn freeze(&mut self, address: u16, value: u8) {
+        match self.freezes.iter_mut().find(|freeze| freeze.address == address) {
+            Some(freeze) => freeze.value = value,
+            None => self.freezes.push(Freeze { address, value }),
+        }
+    }
+
+    pub(crate) fn unfreeze(&mut self, address: u16) -> bool {
+        let count = self.freezes.len();
+        self.freezes.retain(|freeze| freeze.address != address);
+        self.freezes.len() != count
+    }
+
+    // The value a write to `addr` actually stores
+    pub(crate) fn frozen_value(&self, addr: u16, value: u8) -> u8 {
+        self.freezes.iter().find(|freeze| freeze.address == addr).map_or(value, |freeze| freeze.value)
+    }
+
+    // Apply the enabled codes to a byte read from ROM
+    pub(crate) fn patch_rom(&self, addr: u16, original: u8) -> u8 {
+        self.list
+            .iter()
+            .filter(|cheat| cheat.enabled && cheat.code.address == addr)
+            .find_map(|cheat| cheat.code.patch(original))
+            .unwrap_or(original)
+    }
+}
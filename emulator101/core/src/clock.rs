@@ -0,0 +1,117 @@
+// Emulated time
+// Counts machine cycles executed since power-on and converts between cycles and wall-clock
+// durations at the DMG clock rate. This is the emulator's only timeline: run_until targets,
+// frame budgets and savestates are all expressed in its M-cycles.
+//
+// Real time comes from a separate WallClock. Cartridge clocks (MBC3 and HuC3) read whichever of
+// the two RtcMode selects: emulated time stops while the emulator is paused or closed and
+// speeds up with fast-forward, wall-clock time keeps running regardless.
+
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+// Master clock (T-cycles per second)
+pub const CPU_CLOCK_HZ: u64 = 4_194_304;
+// One M-cycle is four T-cycles
+pub const M_CYCLE_HZ: u64 = CPU_CLOCK_HZ / 4;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+// Source of real time, replaceable so tests and replays can control it
+pub trait WallClock: Send {
+    // Time since the Unix epoch
+    fn now(&self) -> Duration;
+}
+
+// The host's system clock
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemWallClock;
+
+#[cfg(feature = "std")]
+impl WallClock for SystemWallClock {
+    fn now(&self) -> Duration {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+}
+
+// A wall clock stopped at a fixed time: what movies pin the clock to, and the default without
+// the std feature
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedWallClock(pub Duration);
+
+impl WallClock for FixedWallClock {
+    fn now(&self) -> Duration {
+        self.0
+    }
+}
+
+// Which time a cartridge real-time clock follows
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RtcMode {
+    // Emulated time: pauses with the emulator and runs faster when fast-forwarding
+    #[default]
+    Emulated,
+    // Wall-clock time: keeps counting while paused or closed, unaffected by emulation speed
+    WallClock,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Clock {
+    m_cycles: u64,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self { m_cycles: 0 }
+    }
+
+    pub fn m_cycles(&self) -> u64 {
+        self.m_cycles
+    }
+
+    pub fn t_cycles(&self) -> u64 {
+        self.m_cycles * 4
+    }
+
+    // M-cycles left until the clock reaches `target` (0 once it has)
+    pub fn m_cycles_until(&self, target: u64) -> u64 {
+        target.saturating_sub(self.m_cycles)
+    }
+
+    pub fn reached(&self, target: u64) -> bool {
+        self.m_cycles >= target
+    }
+
+    // Emulated time since power-on
+    pub fn elapsed(&self) -> Duration {
+        Self::duration_of(self.m_cycles)
+    }
+
+    pub(crate) fn advance_t_cycles(&mut self, t_cycles: u32) {
+        self.m_cycles += (t_cycles / 4) as u64;
+    }
+
+    // Whole M-cycles that fit in the given duration (rounded down)
+    pub fn m_cycles_in(duration: Duration) -> u64 {
+        (duration.as_nanos() * M_CYCLE_HZ as u128 / NANOS_PER_SEC) as u64
+    }
+
+    // Real time taken by the given number of M-cycles on hardware
+    pub fn duration_of(m_cycles: u64) -> Duration {
+        let nanos = m_cycles as u128 * NANOS_PER_SEC / M_CYCLE_HZ as u128;
+        Duration::from_nanos(nanos as u64)
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u64(self.m_cycles);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.m_cycles = r.read_u64()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+// Opcode coverage
+// Counts how often each of the 256 base and 256 CB-prefixed opcodes ran, so a test ROM run can
+// show which instructions the suite never exercises. Off by default; it is a session statistic
+// like Stats, so it survives resets and state loads and is not written to savestates.
+
+use alloc::format;
+use alloc::string::String;
+use core::fmt::Write;
+
+// Opcodes that lock up the CPU; they don't count towards coverage
+pub const ILLEGAL_OPCODES: [u8; 11] = [0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpcodeCoverage {
+    // Base opcodes at 0x000-0x0FF, CB-prefixed ones at 0x100-0x1FF
+    counts: [u64; 512],
+}
+
+impl Default for OpcodeCoverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpcodeCoverage {
+    pub fn new() -> Self {
+        OpcodeCoverage { counts: [0; 512] }
+    }
+
+    pub(crate) fn record(&mut self, opcode: u8, cb_prefixed: bool) {
+        self.counts[opcode as usize + if cb_prefixed { 0x100 } else { 0 }] += 1;
+    }
+
+    // Times the opcode ran
+    pub fn count(&self, opcode: u8, cb_prefixed: bool) -> u64 {
+        self.counts[opcode as usize + if cb_prefixed { 0x100 } else { 0 }]
+    }
+
+    // Executed and total opcodes of one table (illegal opcodes excluded)
+    pub fn covered(&self, cb_prefixed: bool) -> (usize, usize) {
+        let opcodes = (0..=0xFF).filter(|&opcode| cb_prefixed || !ILLEGAL_OPCODES.contains(&opcode));
+        opcodes.fold((0, 0), |(executed, total), opcode| {
+            (executed + (self.count(opcode, cb_prefixed) > 0) as usize, total + 1)
+        })
+    }
+
+    // Plain-text report: totals, a 16x16 map of each table and the opcodes never executed
+    pub fn report(&self) -> String {
+        let (base, base_total) = self.covered(false);
+        let (cb, cb_total) = self.covered(true);
+        let mut out = format!(
+            "Opcode coverage: {}/{} base (illegal opcodes excluded), {}/{} CB-prefixed\n",
+            base, base_total, cb, cb_total
+        );
+        for cb_prefixed in [false, true] {
+            let name = if cb_prefixed { "CB-prefixed" } else { "Base" };
+            let _ = writeln!(out, "\n{} opcodes (# executed, . never, x illegal)", name);
+            out.push_str("     0 1 2 3 4 5 6 7 8 9 A B C D E F\n");
+            for row in 0..16u8 {
+                let _ = write!(out, "  {:X}x", row);
+                for column in 0..16u8 {
+                    let opcode = row << 4 | column;
+                    let mark = if !cb_prefixed && ILLEGAL_OPCODES.contains(&opcode) {
+                        'x'
+                    } else if self.count(opcode, cb_prefixed) > 0 {
+                        '#'
+                    } else {
+                        '.'
+                    };
+                    let _ = write!(out, " {}", mark);
+                }
+                out.push('\n');
+            }
+        }
+        out.push_str("\nNever executed:\n");
+        for cb_prefixed in [false, true] {
+            out.push_str(if cb_prefixed { "  CB:  " } else { "  base:" });
+            let missing = (0..=0xFFu8)
+                .filter(|&opcode| self.count(opcode, cb_prefixed) == 0)
+                .filter(|&opcode| cb_prefixed || !ILLEGAL_OPCODES.contains(&opcode));
+            let mut count = 0usize;
+            for opcode in missing {
+                if count > 0 && count.is_multiple_of(16) {
+                    out.push_str("\n       ");
+                }
+                let _ = write!(out, " {:02X}", opcode);
+                count += 1;
+            }
+            out.push_str(if count == 0 { " none\n" } else { "\n" });
+        }
+        out
+    }
+}
@@ -1,5 +1,38 @@
+use alloc::boxed::Box;
+use core::fmt;
+
+use crate::coverage::OpcodeCoverage;
+use crate::error::EmulatorError;
 use crate::memory::MemoryBus;
-use crate::interrupts::{InterruptController, InterruptType};
+use crate::model::Model;
+use crate::interrupts::InterruptController;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+// M-cycles the CPU sits out after STOP switches the CGB's speed
+pub const SPEED_SWITCH_M_CYCLES: u16 = 2050;
+
+// What the CPU is doing between instructions, for debuggers
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CpuMode {
+    #[default]
+    Running,
+    Halted,
+    // STOP mode: DIV and the timer are frozen until a selected joypad line goes low
+    Stopped,
+    // Waiting out a CGB speed switch; M-cycles left
+    SpeedSwitch(u16),
+}
+
+impl fmt::Display for CpuMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuMode::Running => write!(f, "running"),
+            CpuMode::Halted => write!(f, "halted"),
+            CpuMode::Stopped => write!(f, "stopped"),
+            CpuMode::SpeedSwitch(cycles) => write!(f, "speed switch ({} M-cycles left)", cycles),
+        }
+    }
+}
 
 struct Flags {
     z: bool, // Zero flag
@@ -36,7 +69,7 @@ impl Flags {
     }
 
     // Set from u8 value
-    fn from_byte(&mut self, byte: u8) { 
+    fn set_from_byte(&mut self, byte: u8) { 
         self.c = (byte & CpuFlag::C as u8) != 0;
         self.h = (byte & CpuFlag::H as u8) != 0;
         self.n = (byte & CpuFlag::N as u8) != 0;
@@ -60,9 +93,18 @@ pub struct Cpu {
     ime: bool,     // interrupt master enable
     pending_ime: bool, // for EI's 1-instruction delay
     halt_bug: bool,    // for HALT bug tracking
+    speed_switch: u16, // M-cycles left of a CGB speed switch
     
-    // Cycle counting
-    pub cycle_count: u64,
+    // Instructions executed (elapsed time is kept by the emulator's Clock)
+    pub instruction_count: u64,
+    // Per-opcode execution counts, when enabled (see Emulator::set_opcode_coverage)
+    pub(crate) coverage: Option<Box<OpcodeCoverage>>,
+}
+
+impl Default for Cpu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Cpu {
@@ -80,29 +122,113 @@ impl Cpu {
             ime: false,
             pending_ime: false,
             halt_bug: false,
-            cycle_count: 0,
+            speed_switch: 0,
+            instruction_count: 0,
+            coverage: None,
         }
     }
 
-    // Reset the CPU state
-    pub fn reset(&mut self) {
-        self.af = 0x01B0;
-        self.bc = 0x0013;
-        self.de = 0x00D8;
-        self.hl = 0x014D;
-        self.f = Flags {
-            z: true,
-            n: false,
-            h: true,
-            c: true,
-        };
+    // Reset the CPU to the state the given model's boot ROM leaves it in
+    pub fn reset(&mut self, model: Model) {
+        let regs = model.boot_registers();
+        self.set_af(regs.af);
+        self.bc = regs.bc;
+        self.de = regs.de;
+        self.hl = regs.hl;
         self.sp = 0xFFFE;
         self.pc = 0x0100;
         self.halted = false;
         self.ime = false;
         self.pending_ime = false;
         self.halt_bug = false;
-        self.cycle_count = 0;
+        self.speed_switch = 0;
+        self.instruction_count = 0;
+    }
+
+    // Power-on state for running a boot ROM from 0x0000
+    pub fn reset_for_boot_rom(&mut self) {
+        self.set_af(0);
+        self.bc = 0;
+        self.de = 0;
+        self.hl = 0;
+        self.sp = 0;
+        self.pc = 0;
+    }
+
+    // Address of the next instruction
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.sp
+    }
+
+    pub fn mode(&self, memory: &MemoryBus) -> CpuMode {
+        if memory.stopped() {
+            CpuMode::Stopped
+        } else if self.speed_switch > 0 {
+            CpuMode::SpeedSwitch(self.speed_switch)
+        } else if self.halted {
+            CpuMode::Halted
+        } else {
+            CpuMode::Running
+        }
+    }
+
+    pub fn af(&self) -> u16 {
+        self.af
+    }
+
+    pub fn bc(&self) -> u16 {
+        self.bc
+    }
+
+    pub fn de(&self) -> u16 {
+        self.de
+    }
+
+    pub fn hl(&self) -> u16 {
+        self.hl
+    }
+
+    // Interrupt master enable, and whether an EI is about to set it
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    pub fn ime_pending(&self) -> bool {
+        self.pending_ime
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.af);
+        w.write_u16(self.bc);
+        w.write_u16(self.de);
+        w.write_u16(self.hl);
+        w.write_u16(self.sp);
+        w.write_u16(self.pc);
+        w.write_bool(self.halted);
+        w.write_bool(self.ime);
+        w.write_bool(self.pending_ime);
+        w.write_bool(self.halt_bug);
+        w.write_u16(self.speed_switch);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        let af = r.read_u16()?;
+        self.set_af(af);
+        self.bc = r.read_u16()?;
+        self.de = r.read_u16()?;
+        self.hl = r.read_u16()?;
+        self.sp = r.read_u16()?;
+        self.pc = r.read_u16()?;
+        self.halted = r.read_bool()?;
+        self.ime = r.read_bool()?;
+        self.pending_ime = r.read_bool()?;
+        self.halt_bug = r.read_bool()?;
+        self.speed_switch = r.read_u16()?;
+        Ok(())
     }
 
     // Get register BC as 16-bit
@@ -139,7 +265,7 @@ impl Cpu {
         let f = (value & 0x00FF) as u8 & 0xF0;
         
         // Update the flags struct with the new value
-        self.f.from_byte(f);
+        self.f.set_from_byte(f);
         
         // Update the full AF register
         self.af = value & 0xFFF0; // Ensure lower 4 bits are always 0
@@ -164,7 +290,7 @@ impl Cpu {
         }
         
         // Update the Flags struct
-        self.f.from_byte(f_value);
+        self.f.set_from_byte(f_value);
         
         // Update the F register in the af register pair
         self.af = (self.af & 0xFF00) | (f_value as u16);
@@ -219,27 +345,27 @@ impl Cpu {
     }
     
     // Fetch the next byte from memory and increment PC
-    fn fetch_byte<'a>(&mut self, memory: &'a MemoryBus) -> u8 {
+    fn fetch_byte(&mut self, memory: &MemoryBus) -> u8 {
         let byte = memory.read_byte(self.pc);
         self.pc = self.pc.wrapping_add(1);
         byte
     }
     
     // Fetch the next 16-bit word from memory and increment PC
-    fn fetch_word<'a>(&mut self, memory: &'a MemoryBus) -> u16 {
+    fn fetch_word(&mut self, memory: &MemoryBus) -> u16 {
         let lo = self.fetch_byte(memory) as u16;
         let hi = self.fetch_byte(memory) as u16;
         (hi << 8) | lo
     }
 
     // Write word to memory
-    fn write_word<'a>(&mut self, memory: &mut MemoryBus<'a>, addr: u16, value: u16) {
+    fn write_word(&mut self, memory: &mut MemoryBus, addr: u16, value: u16) {
         memory.write_byte(addr, (value & 0xFF) as u8);
-        memory.write_byte(addr + 1, (value >> 8) as u8);
+        memory.write_byte(addr.wrapping_add(1), (value >> 8) as u8);
     }
     
     // Push a 16-bit value onto the stack
-    fn push_word<'a>(&mut self, memory: &mut MemoryBus<'a>, value: u16) {
+    fn push_word(&mut self, memory: &mut MemoryBus, value: u16) {
         self.sp = self.sp.wrapping_sub(1);
         memory.write_byte(self.sp, (value >> 8) as u8);
         self.sp = self.sp.wrapping_sub(1);
@@ -247,7 +373,7 @@ impl Cpu {
     }
     
     // Pop a 16-bit value from the stack
-    fn pop_word<'a>(&mut self, memory: &'a MemoryBus) -> u16 {
+    fn pop_word(&mut self, memory: &MemoryBus) -> u16 {
         let lo = memory.read_byte(self.sp) as u16;
         self.sp = self.sp.wrapping_add(1);
         let hi = memory.read_byte(self.sp) as u16;
@@ -256,32 +382,53 @@ impl Cpu {
     }
 
     #[allow(dead_code)]
-    fn debugging(&self, memory: &MemoryBus, opcode: u8) {
-        println!("Opcode: {:#04X}", opcode);
-        println!("AF: {:#06X}", self.af);
-        println!("BC: {:#06X}", self.bc);
-        println!("DE: {:#06X}", self.de);
-        println!("HL: {:#06X}", self.hl);
-        println!("SP: {:#06X}", self.sp);
-        println!("PC: {:#06X}", self.pc);
-        println!("Z: {}", self.f.z);
-        println!("N: {}", self.f.n);
-        println!("H: {}", self.f.h);
-        println!("C: {}", self.f.c);
-        println!("ie: {:#04X}", memory.get_ie());
-        println!("if: {:#04X}", memory.get_if());
-        println!("ime: {}", self.ime);
-        println!("pending_ime: {}", self.pending_ime);
-        println!("halted: {}", self.halted);
+    fn debugging(&self, out: &mut impl fmt::Write, memory: &MemoryBus, opcode: u8) -> fmt::Result {
+        writeln!(out, "Opcode: {:#04X}", opcode)?;
+        writeln!(out, "AF: {:#06X}", self.af)?;
+        writeln!(out, "BC: {:#06X}", self.bc)?;
+        writeln!(out, "DE: {:#06X}", self.de)?;
+        writeln!(out, "HL: {:#06X}", self.hl)?;
+        writeln!(out, "SP: {:#06X}", self.sp)?;
+        writeln!(out, "PC: {:#06X}", self.pc)?;
+        writeln!(out, "Z: {}", self.f.z)?;
+        writeln!(out, "N: {}", self.f.n)?;
+        writeln!(out, "H: {}", self.f.h)?;
+        writeln!(out, "C: {}", self.f.c)?;
+        writeln!(out, "ie: {:#04X}", memory.get_ie())?;
+        writeln!(out, "if: {:#04X}", memory.get_if())?;
+        writeln!(out, "ime: {}", self.ime)?;
+        writeln!(out, "pending_ime: {}", self.pending_ime)?;
+        writeln!(out, "halted: {}", self.halted)?;
+        Ok(())
     }
 
     // Execute a single instruction
-    pub fn step<'a>(&mut self, memory: &mut MemoryBus<'a>) -> u8 {
+    pub fn step(&mut self, memory: &mut MemoryBus) -> u8 {
         // First, handle any pending interrupts
         let mut total_cycles = 0;
         
-        // Only process interrupts if IME is enabled or if HALT checking needs to happen
-        if self.ime || self.halted {
+        // While halted the CPU idles an M-cycle at a time. Once an interrupt is pending, waking up
+        // takes one more M-cycle; after that it either dispatches the interrupt (IME=1, 5 M-cycles)
+        // or carries on after the HALT without touching IF (IME=0).
+        if self.halted {
+            self.halted = !InterruptController::has_pending_interrupts(memory.get_ie(), memory.get_if());
+            return 4;
+        }
+
+        // STOP mode ends as soon as a selected joypad line goes low; interrupts don't wake it
+        if memory.stopped() {
+            if memory.joypad_held() {
+                memory.set_stopped(false);
+            }
+            return 4;
+        }
+        if self.speed_switch > 0 {
+            self.speed_switch -= 1;
+            return 4;
+        }
+
+        // Only process interrupts if IME is enabled
+        if self.ime {
             let interrupt_cycles = self.handle_interrupts(memory);
             total_cycles += interrupt_cycles;
             
@@ -291,27 +438,21 @@ impl Cpu {
             }
         }
         
-        // If halted, check if we should wake up
-        if self.halted {
-            if InterruptController::has_pending_interrupts(memory) {
-                self.halted = false;
-            } else {
-                // Stay halted for 4 T-cycles
-                self.cycle_count += 4;
-                return 4;
-            }
-        }
-        
         // Execute an instruction
         let opcode = self.fetch_byte(memory);
+        trace!(pc = self.pc.wrapping_sub(1), opcode, sp = self.sp, "instruction");
     
         if self.halt_bug {
             self.pc = self.pc.wrapping_sub(1);
             self.halt_bug = false;
         }
         
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(opcode, false);
+        }
         let cycles = self.execute_instruction(opcode, memory);
         total_cycles += cycles;
+        self.instruction_count += 1;
         
         // Handle EI's delayed effect
         if self.pending_ime {
@@ -321,12 +462,32 @@ impl Cpu {
         
         //self.debugging(memory, opcode);
 
-        // Count cycles
-        self.cycle_count += total_cycles as u64;
-        
         total_cycles
     }
 
+    // STOP, following the Pan Docs flowchart minus the CGB cases that glitch with IME set. A held
+    // button keeps the CPU from stopping; otherwise DIV is reset and the CPU either stops or, with
+    // a speed switch prepared in KEY1, switches speed and sits out SPEED_SWITCH_M_CYCLES. The byte
+    // after the opcode is skipped unless an interrupt is pending.
+    fn stop(&mut self, memory: &mut MemoryBus) {
+        let pending = InterruptController::has_pending_interrupts(memory.get_ie(), memory.get_if());
+        if !pending {
+            self.pc = self.pc.wrapping_add(1);
+        }
+        if memory.joypad_held() {
+            // HALT instead, or nothing at all with an interrupt pending
+            self.halted = !pending;
+            return;
+        }
+        memory.reset_div();
+        if memory.speed_switch_prepared() {
+            memory.switch_speed();
+            self.speed_switch = SPEED_SWITCH_M_CYCLES;
+        } else {
+            memory.set_stopped(true);
+        }
+    }
+
     // Process pending interrupts
     /*
        1. We check if all interrupts were disabled (in which case we cancel completely)
@@ -335,13 +496,13 @@ impl Cpu {
        4. If another interrupt is found, we proceed with that one instead
        5. Only if no interrupts remain enabled do we cancel the entire process
     */
-    fn handle_interrupts<'a>(&mut self, memory: &mut MemoryBus<'a>) -> u8 {
+    fn handle_interrupts(&mut self, memory: &mut MemoryBus) -> u8 {
         if !self.ime {
             return 0;
         }
         
         // Check if any interrupts are pending
-        if let Some(original_interrupt) = InterruptController::get_highest_priority_interrupt(memory) {
+        if let Some(original_interrupt) = InterruptController::get_highest_priority_interrupt(memory.get_ie(), memory.get_if()) {
             // Step 1: Disable IME
             self.ime = false;
             
@@ -379,7 +540,7 @@ impl Cpu {
                     // The original interrupt was disabled, but there might be others
                     
                     // Check for the next highest priority interrupt
-                    if let Some(new_interrupt) = InterruptController::get_highest_priority_interrupt(memory) {
+                    if let Some(new_interrupt) = InterruptController::get_highest_priority_interrupt(memory.get_ie(), memory.get_if()) {
                         // A different interrupt is now the highest priority
                         // Continue with the lower byte push
                         self.sp = self.sp.wrapping_sub(1);
@@ -406,6 +567,7 @@ impl Cpu {
             
             // Step 3: ONLY NOW clear the interrupt flag
             memory.clear_interrupt(original_interrupt);
+            debug!(interrupt = ?original_interrupt, return_to = self.pc, "interrupt");
             
             // Step 4: Jump to interrupt vector
             self.pc = InterruptController::get_interrupt_vector(original_interrupt);
@@ -418,7 +580,7 @@ impl Cpu {
     }
 
     // Execute a single instruction
-    fn execute_instruction<'a>(&mut self, opcode: u8, memory: &mut MemoryBus<'a>) -> u8 {
+    fn execute_instruction(&mut self, opcode: u8, memory: &mut MemoryBus) -> u8 {
         match opcode {
             0x00 => 4, // NOP
             0x01 => {
@@ -432,6 +594,7 @@ impl Cpu {
                 8
             },
             0x03 => {
+                memory.oam_bug_idu(self.get_bc());
                 let value = self.get_bc().wrapping_add(1);
                 self.set_bc(value);
                 8
@@ -473,6 +636,7 @@ impl Cpu {
                 8
             },
             0x0B => {
+                memory.oam_bug_idu(self.get_bc());
                 let value = self.get_bc().wrapping_sub(1);
                 self.set_bc(value);
                 8
@@ -498,7 +662,10 @@ impl Cpu {
                 self.flag(CpuFlag::Z, false);
                 4
             },
-            0x10 => 4, // STOP
+            0x10 => {
+                self.stop(memory);
+                4
+            },
             0x11 => {
                 let value = self.fetch_word(memory);
                 self.set_de(value);
@@ -510,6 +677,7 @@ impl Cpu {
                 8
             },
             0x13 => {
+                memory.oam_bug_idu(self.get_de());
                 let value = self.get_de().wrapping_add(1);
                 self.set_de(value);
                 8
@@ -549,6 +717,7 @@ impl Cpu {
                 8
             },
             0x1B => {
+                memory.oam_bug_idu(self.get_de());
                 let value = self.get_de().wrapping_sub(1);
                 self.set_de(value);
                 8
@@ -589,6 +758,7 @@ impl Cpu {
                 8
             },
             0x23 => {
+                memory.oam_bug_idu(self.get_hl());
                 let value = self.get_hl().wrapping_add(1);
                 self.set_hl(value);
                 8
@@ -627,6 +797,7 @@ impl Cpu {
                 8
             },
             0x2B => {
+                memory.oam_bug_idu(self.get_hl());
                 let value = self.get_hl().wrapping_sub(1);
                 self.set_hl(value);
                 8
@@ -668,6 +839,7 @@ impl Cpu {
                 8
             },
             0x33 => {
+                memory.oam_bug_idu(self.sp);
                 let value = self.sp.wrapping_add(1);
                 self.sp = value;
                 8
@@ -713,6 +885,7 @@ impl Cpu {
                 8
             },
             0x3B => {
+                memory.oam_bug_idu(self.sp);
                 let value = self.sp.wrapping_sub(1);
                 self.sp = value;
                 8
@@ -998,7 +1171,7 @@ impl Cpu {
             },
             0x76 => {
                 // Check for HALT bug condition
-                if !self.ime && InterruptController::has_pending_interrupts(memory) {
+                if !self.ime && InterruptController::has_pending_interrupts(memory.get_ie(), memory.get_if()) {
                     // HALT bug triggered
                     self.halt_bug = true;
                     // In this case, HALT ends immediately
@@ -1553,14 +1726,17 @@ impl Cpu {
                 16
             },
             _ => {
-                println!("Unimplemented opcode: 0x{:02X}", opcode);
+                memory.report(EmulatorError::IllegalOpcode { opcode, pc: self.pc.wrapping_sub(1) });
                 4
             }
         }
     }
 
-    fn call_cb<'a>(&mut self, memory: &mut MemoryBus<'a>) -> u8 {
+    fn call_cb(&mut self, memory: &mut MemoryBus) -> u8 {
         let opcode = self.fetch_byte(memory);
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(opcode, true);
+        }
         match opcode {
             0x00 => {
                 let b = self.get_b();
@@ -2901,16 +3077,16 @@ impl Cpu {
         }
     }
 
-    fn call<'a>(&mut self, memory: &mut MemoryBus<'a>) -> u8 {
-        self.push_word(memory, self.pc + 2);
+    fn call(&mut self, memory: &mut MemoryBus) -> u8 {
+        self.push_word(memory, self.pc.wrapping_add(2));
         let addr = self.fetch_word(memory);
         self.pc = addr;
         24
     }
 
-    fn call_cc<'a>(&mut self, memory: &mut MemoryBus<'a>, condition: bool) -> u8 {
+    fn call_cc(&mut self, memory: &mut MemoryBus, condition: bool) -> u8 {
         if condition {
-            self.push_word(memory, self.pc + 2);
+            self.push_word(memory, self.pc.wrapping_add(2));
             let addr = self.fetch_word(memory);
             self.pc = addr;
             24
@@ -2920,7 +3096,7 @@ impl Cpu {
         }
     }
 
-    fn cpu_jp<'a>(&mut self, memory: &mut MemoryBus<'a>, condition: bool) -> u8 {
+    fn cpu_jp(&mut self, memory: &mut MemoryBus, condition: bool) -> u8 {
         if condition {
             self.pc = self.fetch_word(memory);
             16
@@ -2930,7 +3106,7 @@ impl Cpu {
         }
     }
 
-    fn ret_cc<'a>(&mut self, memory: &mut MemoryBus<'a>, condition: bool) -> u8 {
+    fn ret_cc(&mut self, memory: &mut MemoryBus, condition: bool) -> u8 {
         if condition {
             self.pc = self.pop_word(memory);
             20
@@ -2988,7 +3164,7 @@ impl Cpu {
         self.flag(CpuFlag::H, false);
         self.flag(CpuFlag::N, false);
         self.flag(CpuFlag::Z, value == 0);
-        (value >> 4) | (value << 4)
+        value.rotate_left(4)
     }
 
     fn rlc_r8(&mut self, value: u8) -> u8 {
@@ -3065,7 +3241,7 @@ impl Cpu {
         self.set_a(a);
     }
 
-    fn cpu_jr<'a>(&mut self, memory: &'a MemoryBus, condition: bool) -> u8 {
+    fn cpu_jr(&mut self, memory: &MemoryBus, condition: bool) -> u8 {
         if condition {
             let n = self.fetch_byte(memory) as i8;
             self.pc = ((self.pc as u32 as i32) + (n as i32)) as u16;
@@ -3130,4 +3306,233 @@ impl Cpu {
         self.sub_r8(value, false);
         self.set_a(a);
     }
-}
\ No newline at end of file
+}
+
+// ALU flag checks against a slow reference: a bit-by-bit ripple adder/subtractor, so the half
+// and full carries are read off the carry chain instead of computed with the same nibble
+// arithmetic as the implementation. DAA is checked by what it is for: after adding or
+// subtracting two BCD numbers it must leave the decimal result.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // Result, carry out of bit 3 and carry out of bit 7 of a + b + carry_in (or the borrows of
+    // a - b - carry_in)
+    fn ripple(a: u8, b: u8, carry_in: bool, subtract: bool) -> (u8, bool, bool) {
+        let mut result = 0;
+        let mut carry = carry_in;
+        let mut half = false;
+        for bit in 0..8 {
+            let x = (a >> bit) & 1 == 1;
+            let y = (b >> bit) & 1 == 1;
+            let out = x ^ y ^ carry;
+            carry = if subtract { (!x && (y || carry)) || (y && carry) } else { (x && y) || (carry && (x ^ y)) };
+            result |= (out as u8) << bit;
+            if bit == 3 {
+                half = carry;
+            }
+        }
+        (result, half, carry)
+    }
+
+    fn flags(z: bool, n: bool, h: bool, c: bool) -> u8 {
+        ((z as u8) << 7) | ((n as u8) << 6) | ((h as u8) << 5) | ((c as u8) << 4)
+    }
+
+    fn cpu_with(a: u8, f: u8) -> Cpu {
+        let mut cpu = Cpu::new();
+        cpu.set_af(((a as u16) << 8) | f as u16);
+        cpu
+    }
+
+    fn to_bcd(value: u8) -> u8 {
+        ((value / 10) << 4) | (value % 10)
+    }
+
+    proptest! {
+        #[test]
+        fn add_and_adc(a: u8, b: u8, f in 0u8..16, use_carry: bool) {
+            let f = f << 4;
+            let carry_in = use_carry && f & CpuFlag::C as u8 != 0;
+            let mut cpu = cpu_with(a, f);
+            cpu.add_r8(b, use_carry);
+            let (result, half, carry) = ripple(a, b, carry_in, false);
+            prop_assert_eq!(cpu.get_af(), ((result as u16) << 8) | flags(result == 0, false, half, carry) as u16);
+        }
+
+        #[test]
+        fn sub_and_sbc(a: u8, b: u8, f in 0u8..16, use_carry: bool) {
+            let f = f << 4;
+            let carry_in = use_carry && f & CpuFlag::C as u8 != 0;
+            let mut cpu = cpu_with(a, f);
+            cpu.sub_r8(b, use_carry);
+            let (result, half, carry) = ripple(a, b, carry_in, true);
+            prop_assert_eq!(cpu.get_af(), ((result as u16) << 8) | flags(result == 0, true, half, carry) as u16);
+        }
+
+        #[test]
+        fn cp_keeps_a(a: u8, b: u8, f in 0u8..16) {
+            let mut cpu = cpu_with(a, f << 4);
+            cpu.cp_r8(b);
+            let (result, half, carry) = ripple(a, b, false, true);
+            prop_assert_eq!(cpu.get_af(), ((a as u16) << 8) | flags(result == 0, true, half, carry) as u16);
+        }
+
+        #[test]
+        fn daa_after_bcd_add(x in 0u8..100, y in 0u8..100, use_carry: bool, carry_in: bool) {
+            let mut cpu = cpu_with(to_bcd(x), flags(false, false, false, carry_in));
+            cpu.add_r8(to_bcd(y), use_carry);
+            cpu.daa();
+            let sum = x as u16 + y as u16 + (use_carry && carry_in) as u16;
+            let result = to_bcd((sum % 100) as u8);
+            prop_assert_eq!(cpu.get_af(), ((result as u16) << 8) | flags(result == 0, false, false, sum >= 100) as u16);
+        }
+
+        #[test]
+        fn daa_after_bcd_sub(x in 0u8..100, y in 0u8..100, use_carry: bool, carry_in: bool) {
+            let mut cpu = cpu_with(to_bcd(x), flags(false, false, false, carry_in));
+            cpu.sub_r8(to_bcd(y), use_carry);
+            cpu.daa();
+            let difference = x as i16 - y as i16 - (use_carry && carry_in) as i16;
+            let result = to_bcd(difference.rem_euclid(100) as u8);
+            prop_assert_eq!(cpu.get_af(), ((result as u16) << 8) | flags(result == 0, true, false, difference < 0) as u16);
+        }
+    }
+}
+
+// The CPU's low-power modes: entering them, idling in them and waking up
+#[cfg(test)]
+mod mode_tests {
+    use super::*;
+
+    // A CPU at 0x100 with HALT, NOP, NOP there and the timer interrupt enabled, stepped into HALT
+    fn halted_cpu(ime: bool) -> (Cpu, MemoryBus) {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100] = 0x76;
+        let mut bus = MemoryBus::new(rom, Model::Dmg);
+        bus.set_ie(0x04);
+        bus.set_if(0x00);
+        let mut cpu = Cpu::new();
+        cpu.reset(Model::Dmg);
+        cpu.ime = ime;
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert!(cpu.halted);
+        // Nothing pending: it idles an M-cycle at a time
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert!(cpu.halted);
+        (cpu, bus)
+    }
+
+    #[test]
+    fn halt_wakes_up_in_an_extra_m_cycle_before_dispatching() {
+        let (mut cpu, mut bus) = halted_cpu(true);
+        bus.set_if(0x04);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert!(!cpu.halted);
+        assert_eq!(cpu.pc(), 0x101);
+        assert_eq!(cpu.step(&mut bus), 20);
+        assert_eq!(cpu.pc(), 0x0050);
+        assert_eq!(bus.get_if() & 0x04, 0);
+    }
+
+    #[test]
+    fn halt_with_ime_clear_resumes_without_dispatching() {
+        let (mut cpu, mut bus) = halted_cpu(false);
+        bus.set_if(0x04);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert!(!cpu.halted);
+        // The NOP after the HALT runs, and IF keeps the request
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.pc(), 0x102);
+        assert_eq!(bus.get_if() & 0x04, 0x04);
+    }
+
+    // A CPU at 0x100 with STOP, its padding byte and NOPs there, the d-pad selected and DIV
+    // counted up from 0
+    fn cpu_before_stop(model: Model) -> (Cpu, MemoryBus) {
+        let mut rom = vec![0; 0x8000];
+        rom[0x100] = 0x10;
+        let mut bus = MemoryBus::new(rom, model);
+        bus.set_ie(0x00);
+        bus.set_if(0x00);
+        bus.write_byte(0xFF00, 0x20);
+        bus.write_byte(0xFF04, 0x00);
+        bus.tick(255);
+        bus.tick(255);
+        assert_eq!(bus.read_byte(0xFF04), 0x01);
+        let mut cpu = Cpu::new();
+        cpu.reset(model);
+        (cpu, bus)
+    }
+
+    #[test]
+    fn stop_resets_div_and_waits_for_the_joypad() {
+        let (mut cpu, mut bus) = cpu_before_stop(Model::Dmg);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.mode(&bus), CpuMode::Stopped);
+        assert_eq!(cpu.pc(), 0x102);
+        assert_eq!(bus.read_byte(0xFF04), 0x00);
+
+        // Interrupts don't wake it, and the timer stands still
+        bus.set_ie(0x04);
+        bus.set_if(0x04);
+        bus.tick(255);
+        bus.tick(255);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.mode(&bus), CpuMode::Stopped);
+        assert_eq!(bus.read_byte(0xFF04), 0x00);
+
+        // A button in an unselected group doesn't either
+        bus.set_buttons(0x10);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.mode(&bus), CpuMode::Stopped);
+        bus.set_buttons(0x01);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.mode(&bus), CpuMode::Running);
+        assert_eq!(cpu.pc(), 0x102);
+    }
+
+    #[test]
+    fn stop_with_a_button_held_halts_instead() {
+        let (mut cpu, mut bus) = cpu_before_stop(Model::Dmg);
+        bus.set_buttons(0x01);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.mode(&bus), CpuMode::Halted);
+        assert_eq!(cpu.pc(), 0x102);
+        assert_eq!(bus.read_byte(0xFF04), 0x01);
+
+        // With an interrupt pending it does nothing, and the padding byte runs as an instruction
+        let (mut cpu, mut bus) = cpu_before_stop(Model::Dmg);
+        bus.set_buttons(0x01);
+        bus.set_ie(0x04);
+        bus.set_if(0x04);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.mode(&bus), CpuMode::Running);
+        assert_eq!(cpu.pc(), 0x101);
+    }
+
+    #[test]
+    fn stop_with_key1_prepared_switches_speed_for_2050_m_cycles() {
+        let (mut cpu, mut bus) = cpu_before_stop(Model::Cgb);
+        bus.write_byte(0xFF4D, 0x01);
+        assert_eq!(bus.read_byte(0xFF4D), 0x7F);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(bus.read_byte(0xFF4D), 0xFE);
+        assert_eq!(bus.read_byte(0xFF04), 0x00);
+        assert_eq!(cpu.mode(&bus), CpuMode::SpeedSwitch(SPEED_SWITCH_M_CYCLES));
+        for _ in 0..SPEED_SWITCH_M_CYCLES {
+            assert_eq!(cpu.step(&mut bus), 4);
+            assert_eq!(cpu.pc(), 0x102);
+        }
+        assert_eq!(cpu.mode(&bus), CpuMode::Running);
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.pc(), 0x103);
+
+        // The DMG has no KEY1 and stops as usual
+        let (mut cpu, mut bus) = cpu_before_stop(Model::Dmg);
+        bus.write_byte(0xFF4D, 0x01);
+        cpu.step(&mut bus);
+        assert_eq!(cpu.mode(&bus), CpuMode::Stopped);
+    }
+}
@@ -0,0 +1,113 @@
+// Diagnostics
+// Things a game did that the emulator copes with but that point at a bug or an odd trick: writes
+// the hardware drops, registers that don't exist, ... Each one goes to the diagnostic hook with a
+// severity, for frontends to log or show. Unlike the ErrorPolicy they never stop emulation.
+
+use core::fmt;
+
+use crate::error::EmulatorError;
+use crate::stack_checks::StackMisuse;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+// Memory the PPU locks against the CPU while it uses it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockedMemory {
+    Vram,
+    Oam,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnostic {
+    // Write to an I/O address with no register behind it on this model
+    UnknownIoWrite { addr: u16, value: u8 },
+    // A CPU write the PPU ignored because it had the memory locked: VRAM during mode 3, OAM
+    // during modes 2 and 3. Hardware loses these writes too (tearing, missing sprites).
+    LockedWrite { memory: LockedMemory, addr: u16, value: u8, mode: u8 },
+    // Write to the ROM area of a cartridge whose header says it has no mapper
+    RomWriteWithoutMbc { addr: u16, value: u8 },
+    // Read or write of echo RAM (0xE000-0xFDFF). It mirrors work RAM, but Nintendo forbids it
+    // and some flash carts and clones don't mirror it.
+    EchoRamAccess { addr: u16, write: bool },
+    // OAM DMA from 0xE000 or above (echo RAM, OAM, I/O) rather than ROM or RAM
+    OddDmaSource { source: u16 },
+    // Found by the stack checks (see Emulator::set_stack_checks); `sp` is SP afterwards
+    StackMisuse { misuse: StackMisuse, sp: u16 },
+    // An EmulatorError, also handled according to the ErrorPolicy
+    Error(EmulatorError),
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::UnknownIoWrite { .. } | Diagnostic::EchoRamAccess { .. } => Severity::Info,
+            Diagnostic::LockedWrite { .. }
+            | Diagnostic::RomWriteWithoutMbc { .. }
+            | Diagnostic::OddDmaSource { .. }
+            | Diagnostic::StackMisuse { .. } => Severity::Warning,
+            Diagnostic::Error(_) => Severity::Error,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::UnknownIoWrite { addr, value } => {
+                write!(f, "write of {:#04X} to unknown I/O register {:#06X}", value, addr)
+            },
+            Diagnostic::LockedWrite { memory, addr, value, mode } => {
+                let memory = match memory {
+                    LockedMemory::Vram => "VRAM",
+                    LockedMemory::Oam => "OAM",
+                };
+                write!(f, "{} write of {:#04X} to {:#06X} lost during mode {}", memory, value, addr, mode)
+            },
+            Diagnostic::RomWriteWithoutMbc { addr, value } => {
+                write!(f, "write of {:#04X} to ROM at {:#06X} but the cartridge has no mapper", value, addr)
+            },
+            Diagnostic::EchoRamAccess { addr, write } => {
+                let access = if *write { "write to" } else { "read of" };
+                write!(f, "{} echo RAM at {:#06X} (mirror of {:#06X})", access, addr, addr - 0x2000)
+            },
+            Diagnostic::OddDmaSource { source } => write!(f, "OAM DMA from {:#06X}", source),
+            Diagnostic::StackMisuse { misuse, sp } => write!(f, "{} (SP {:#06X})", misuse, sp),
+            Diagnostic::Error(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticEvent {
+    pub diagnostic: Diagnostic,
+    // Address of the instruction that caused it
+    pub pc: u16,
+    pub ly: u8,
+}
+
+impl DiagnosticEvent {
+    pub fn severity(&self) -> Severity {
+        self.diagnostic.severity()
+    }
+}
+
+impl fmt::Display for DiagnosticEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (PC {:#06X}, LY {})", self.diagnostic, self.pc, self.ly)
+    }
+}
@@ -0,0 +1,132 @@
+// Disassembler
+// Decodes single SM83 instructions into RGBDS syntax, with what each does to control flow so a
+// caller can follow the code. 16-bit addresses are written as `$XXXX` and also returned in
+// `address`, so a listing can swap them for labels.
+
+use alloc::format;
+use alloc::string::String;
+
+use crate::coverage::ILLEGAL_OPCODES;
+
+const R8: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const R16: [&str; 4] = ["bc", "de", "hl", "sp"];
+const R16_STACK: [&str; 4] = ["bc", "de", "hl", "af"];
+const R16_MEMORY: [&str; 4] = ["[bc]", "[de]", "[hl+]", "[hl-]"];
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [&str; 8] = ["add a,", "adc a,", "sub", "sbc a,", "and", "xor", "or", "cp"];
+const ROTATIONS: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+const ACCUMULATOR: [&str; 8] = ["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"];
+
+// Where execution can go after an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    // The next instruction only
+    Continue,
+    // Only the target (JP, JR)
+    Jump(u16),
+    // The target or the next instruction (conditional JP and JR)
+    Branch(u16),
+    // The target, then back to the next instruction (CALL, RST)
+    Call(u16),
+    // Nowhere that can be told from the code (RET, RETI, JP HL, illegal opcodes)
+    End,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub text: String,
+    pub length: u8,
+    // Jump target or memory address written as `$XXXX` in `text`
+    pub address: Option<u16>,
+    pub flow: Flow,
+}
+
+// Decode the instruction at the start of `bytes`, which sits at `address`. None if `bytes`
+// ends before the instruction does.
+pub fn decode(bytes: &[u8], address: u16) -> Option<Instruction> {
+    let opcode = *bytes.first()?;
+    let n8 = || bytes.get(1).copied();
+    let n16 = || Some(u16::from_le_bytes([*bytes.get(1)?, *bytes.get(2)?]));
+    let relative = || n8().map(|offset| address.wrapping_add(2).wrapping_add(offset as i8 as u16));
+    let signed = |offset: u8| match offset as i8 {
+        offset if offset < 0 => format!("- {}", -(offset as i16)),
+        offset => format!("+ {}", offset),
+    };
+
+    let (x, y, z) = (opcode >> 6, (opcode >> 3) & 7, opcode & 7);
+    let (p, q) = ((y >> 1) as usize, y & 1);
+    let (y, z) = (y as usize, z as usize);
+    let simple = |text: String, length: u8| Instruction { text, length, address: None, flow: Flow::Continue };
+    let with_address = |text: String, length: u8, address: u16, flow: Flow| Instruction { text, length, address: Some(address), flow };
+
+    let instruction = match (x, z) {
+        (0, 0) => match y {
+            0 => simple("nop".into(), 1),
+            1 => with_address(format!("ld [${:04X}], sp", n16()?), 3, n16()?, Flow::Continue),
+            // STOP is followed by a byte that RGBDS writes as 0
+            2 if n8()? == 0 => simple("stop".into(), 2),
+            2 => simple(format!("db $10, ${:02X} ; stop", n8()?), 2),
+            3 => with_address(format!("jr ${:04X}", relative()?), 2, relative()?, Flow::Jump(relative()?)),
+            _ => with_address(format!("jr {}, ${:04X}", CONDITIONS[y - 4], relative()?), 2, relative()?, Flow::Branch(relative()?)),
+        },
+        (0, 1) if q == 0 => simple(format!("ld {}, ${:04X}", R16[p], n16()?), 3),
+        (0, 1) => simple(format!("add hl, {}", R16[p]), 1),
+        (0, 2) if q == 0 => simple(format!("ld {}, a", R16_MEMORY[p]), 1),
+        (0, 2) => simple(format!("ld a, {}", R16_MEMORY[p]), 1),
+        (0, 3) => simple(format!("{} {}", if q == 0 { "inc" } else { "dec" }, R16[p]), 1),
+        (0, 4) => simple(format!("inc {}", R8[y]), 1),
+        (0, 5) => simple(format!("dec {}", R8[y]), 1),
+        (0, 6) => simple(format!("ld {}, ${:02X}", R8[y], n8()?), 2),
+        (0, _) => simple(ACCUMULATOR[y].into(), 1),
+        (1, _) if opcode == 0x76 => simple("halt".into(), 1),
+        (1, _) => simple(format!("ld {}, {}", R8[y], R8[z]), 1),
+        (2, _) => simple(format!("{} {}", ALU[y], R8[z]), 1),
+        (3, 0) => match y {
+            0..=3 => simple(format!("ret {}", CONDITIONS[y]), 1),
+            4 => with_address(format!("ldh [${:04X}], a", 0xFF00 | n8()? as u16), 2, 0xFF00 | n8()? as u16, Flow::Continue),
+            5 => simple(format!("add sp, {}", n8()? as i8), 2),
+            6 => with_address(format!("ldh a, [${:04X}]", 0xFF00 | n8()? as u16), 2, 0xFF00 | n8()? as u16, Flow::Continue),
+            _ => simple(format!("ld hl, sp {}", signed(n8()?)), 2),
+        },
+        (3, 1) => match (q, p) {
+            (0, _) => simple(format!("pop {}", R16_STACK[p]), 1),
+            (_, 0) => Instruction { text: "ret".into(), length: 1, address: None, flow: Flow::End },
+            (_, 1) => Instruction { text: "reti".into(), length: 1, address: None, flow: Flow::End },
+            (_, 2) => Instruction { text: "jp hl".into(), length: 1, address: None, flow: Flow::End },
+            _ => simple("ld sp, hl".into(), 1),
+        },
+        (3, 2) => match y {
+            0..=3 => with_address(format!("jp {}, ${:04X}", CONDITIONS[y], n16()?), 3, n16()?, Flow::Branch(n16()?)),
+            4 => simple("ldh [c], a".into(), 1),
+            5 => with_address(format!("ld [${:04X}], a", n16()?), 3, n16()?, Flow::Continue),
+            6 => simple("ldh a, [c]".into(), 1),
+            _ => with_address(format!("ld a, [${:04X}]", n16()?), 3, n16()?, Flow::Continue),
+        },
+        _ if ILLEGAL_OPCODES.contains(&opcode) => {
+            Instruction { text: format!("db ${:02X} ; illegal", opcode), length: 1, address: None, flow: Flow::End }
+        },
+        (3, 3) => match y {
+            0 => with_address(format!("jp ${:04X}", n16()?), 3, n16()?, Flow::Jump(n16()?)),
+            1 => return Some(decode_cb(n8()?)),
+            6 => simple("di".into(), 1),
+            _ => simple("ei".into(), 1),
+        },
+        (3, 4) => with_address(format!("call {}, ${:04X}", CONDITIONS[y], n16()?), 3, n16()?, Flow::Call(n16()?)),
+        (3, 5) if q == 0 => simple(format!("push {}", R16_STACK[p]), 1),
+        (3, 5) => with_address(format!("call ${:04X}", n16()?), 3, n16()?, Flow::Call(n16()?)),
+        (3, 6) => simple(format!("{} ${:02X}", ALU[y], n8()?), 2),
+        _ => Instruction { text: format!("rst ${:02X}", y * 8), length: 1, address: None, flow: Flow::Call(y as u16 * 8) },
+    };
+    Some(instruction)
+}
+
+fn decode_cb(opcode: u8) -> Instruction {
+    let (x, y, z) = ((opcode >> 6) as usize, ((opcode >> 3) & 7) as usize, (opcode & 7) as usize);
+    let text = match x {
+        0 => format!("{} {}", ROTATIONS[y], R8[z]),
+        1 => format!("bit {}, {}", y, R8[z]),
+        2 => format!("res {}, {}", y, R8[z]),
+        _ => format!("set {}, {}", y, R8[z]),
+    };
+    Instruction { text, length: 2, address: None, flow: Flow::Continue }
+}
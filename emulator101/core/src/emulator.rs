@@ -0,0 +1,737 @@
+// Top-level emulator API
+// Ties the CPU and the memory bus together and exposes everything a frontend needs
+// (stepping, frame buffer, input, audio, savestates) without depending on SDL.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::path::Path;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+#[cfg(feature = "std")]
+use crate::builder::EmulatorBuilder;
+use crate::cartridge::Cartridge;
+use crate::cheats::Cheats;
+use crate::accuracy::Accuracy;
+use crate::apu::SAMPLE_RATE;
+use crate::clock::{Clock, RtcMode, WallClock};
+use crate::coverage::OpcodeCoverage;
+use crate::cpu::{Cpu, CpuMode};
+use crate::error::{EmulatorError, ErrorPolicy};
+#[cfg(feature = "std")]
+use crate::error::LoadError;
+use crate::diagnostics::{Diagnostic, DiagnosticEvent};
+use crate::hooks::{EmulatorEvent, Hooks};
+use crate::link::LinkPort;
+use crate::mapper::{MappedBanks, MapperKind, MapperWrite, RtcReading};
+use crate::memory::{JoypadButton, MemoryBus};
+use crate::model::Model;
+use crate::ppu::{Palette, Ppu};
+use crate::savestate::{self, SaveStateError, StateInfo, StateReader, StateWriter};
+use crate::stack_checks::StackChecks;
+use crate::stats::{Stats, StatsCounter};
+use crate::timer::Timer;
+
+// T-cycles in one full frame (154 lines * 456 dots)
+pub const CYCLES_PER_FRAME: u32 = 70224;
+
+// How often a cartridge clock is synced with the RTC time while running, in M-cycles
+const RTC_SYNC_M_CYCLES: u64 = CYCLES_PER_FRAME as u64 / 4;
+
+// Audio kept for pull_audio: a second of stereo samples. Nobody pulling (a headless run, a
+// frontend without audio) drops the oldest beyond that.
+const MAX_AUDIO_SAMPLES: usize = SAMPLE_RATE as usize * 2;
+
+// The host's clock, or the Unix epoch until the embedder sets one without std
+fn default_wall_clock() -> Box<dyn WallClock> {
+    #[cfg(feature = "std")]
+    return Box::new(crate::clock::SystemWallClock);
+    #[cfg(not(feature = "std"))]
+    return Box::new(crate::clock::FixedWallClock::default());
+}
+
+// The last rendered frame (see Emulator::frame)
+#[derive(Debug, Clone, Copy)]
+pub struct FrameBuffer<'a> {
+    // RGBA32, SCREEN_WIDTH * SCREEN_HEIGHT pixels, rows top to bottom
+    pub pixels: &'a [u8],
+    // Changes whenever `pixels` do: with every scanline the PPU draws (so a paused frontend
+    // stepping mid-frame sees the new lines) and on reset. A frontend that gets the same
+    // generation twice can keep the texture it uploaded last time.
+    pub generation: u64,
+}
+
+pub struct Emulator {
+    model: Model,
+    palette: Palette,
+    accuracy: Accuracy,
+    boot_rom: Option<Vec<u8>>,
+    // Mapper chosen by the embedder instead of the detected one
+    mapper: Option<MapperKind>,
+    cpu: Cpu,
+    bus: MemoryBus,
+    clock: Clock,
+    wall_clock: Box<dyn WallClock>,
+    rtc_mode: RtcMode,
+    hooks: Hooks,
+    frame_completed: bool,
+    lcd_enabled: bool,
+    // Rumble motor state last reported to the hook
+    rumble: bool,
+    audio_samples: Vec<i16>,
+    error_policy: ErrorPolicy,
+    // Set when an error stopped execution under ErrorPolicy::Stop
+    fault: Option<EmulatorError>,
+    // Frame generations used up by the PPUs before the current one (see FrameBuffer)
+    generations_before: u64,
+    stats: StatsCounter,
+    // Stack misuse heuristics, when enabled; a setting like the error policy
+    stack_checks: Option<StackChecks>,
+}
+
+impl Emulator {
+    // Takes an owned Vec<u8>, or copies a slice. Use Model::from_header(rom) to pick the model
+    // the cartridge asks for.
+    pub fn new(rom: impl Into<Vec<u8>>, model: Model) -> Self {
+        let mut cpu = Cpu::new();
+        cpu.reset(model);
+        let bus = MemoryBus::new(rom.into(), model);
+        let lcd_enabled = bus.ppu.lcdc & 0x80 != 0;
+        Self {
+            model,
+            palette: Palette::default(),
+            accuracy: Accuracy::default(),
+            boot_rom: None,
+            mapper: None,
+            cpu,
+            bus,
+            clock: Clock::new(),
+            wall_clock: default_wall_clock(),
+            rtc_mode: RtcMode::default(),
+            hooks: Hooks::default(),
+            frame_completed: false,
+            lcd_enabled,
+            rumble: false,
+            audio_samples: Vec::new(),
+            error_policy: ErrorPolicy::default(),
+            fault: None,
+            generations_before: 0,
+            stats: StatsCounter::default(),
+            stack_checks: None,
+        }
+    }
+
+    // Read a ROM file and set up an emulator with the default settings (see EmulatorBuilder)
+    #[cfg(feature = "std")]
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Emulator, LoadError> {
+        EmulatorBuilder::new().build_from_file(path)
+    }
+
+    // Power-cycle the machine, keeping the loaded ROM and registered hooks. Battery-backed
+    // cartridge RAM and cartridge clocks survive, as they do on hardware.
+    pub fn reset(&mut self) {
+        debug!(model = %self.model, "reset");
+        let battery = self.bus.has_battery().then(|| self.battery_save());
+        let rom = self.bus.take_rom();
+        self.power_on(rom);
+        if let Some(data) = battery {
+            self.load_battery_save(&data);
+        }
+    }
+
+    // Swap in a different cartridge and reset. Hooks, error policy and other settings are kept;
+    // Game Genie codes and RAM freezes are cleared, as they only make sense for the old game.
+    pub fn load_rom(&mut self, rom: impl Into<Vec<u8>>) {
+        self.bus.cheats.clear();
+        self.power_on(rom.into());
+        self.stats.set_play_time(Duration::ZERO);
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    // Takes effect on the next reset() or load_rom()
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+    }
+
+    // The loaded ROM's header: title, cartridge type, sizes and checksums
+    pub fn cartridge(&self) -> &Cartridge {
+        self.bus.cartridge()
+    }
+
+    // The cartridge's mapper, as detected or set with set_mapper
+    pub fn mapper(&self) -> MapperKind {
+        self.bus.mapper()
+    }
+
+    // ROM and RAM banks the mapper currently shows
+    pub fn mapped_banks(&self) -> MappedBanks {
+        self.bus.mapped_banks()
+    }
+
+    // None goes back to detecting the mapper from the header. Takes effect on the next reset()
+    // or load_rom().
+    pub fn set_mapper(&mut self, mapper: Option<MapperKind>) {
+        self.mapper = mapper;
+    }
+
+    // Takes effect on the next reset() or load_rom(). EmulatorBuilder checks the size.
+    pub fn set_boot_rom(&mut self, boot_rom: Option<Vec<u8>>) {
+        self.boot_rom = boot_rom;
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.bus.ppu.set_palette(palette);
+    }
+
+    // Running, halted, stopped or waiting out a speed switch
+    pub fn cpu_mode(&self) -> CpuMode {
+        self.cpu.mode(&self.bus)
+    }
+
+    pub fn accuracy(&self) -> Accuracy {
+        self.accuracy
+    }
+
+    // Takes effect immediately; see AccuracyProfile::settings for the presets
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.accuracy = accuracy;
+        self.bus.set_accuracy(accuracy);
+    }
+
+    // A bus in power-on state for this emulator's settings
+    fn new_bus(&self, rom: Vec<u8>) -> MemoryBus {
+        let mut bus = MemoryBus::new(rom, self.model);
+        bus.ppu.set_palette(self.palette);
+        bus.set_accuracy(self.accuracy);
+        bus.cheats = self.bus.cheats.clone();
+        bus.record_mapper_writes = self.hooks.has_mapper_write();
+        if let Some(mapper) = self.mapper {
+            bus.set_mapper(mapper);
+        }
+        if let Some(boot_rom) = &self.boot_rom {
+            bus.map_boot_rom(boot_rom.clone());
+        }
+        bus
+    }
+
+    fn power_on(&mut self, rom: Vec<u8>) {
+        let link = self.bus.link.take();
+        // The new PPU starts on a white screen, which is a new generation too
+        self.generations_before += self.bus.ppu.lines_drawn() + 1;
+        self.bus = self.new_bus(rom);
+        self.bus.link = link;
+        self.cpu.reset(self.model);
+        if self.boot_rom.is_some() {
+            self.cpu.reset_for_boot_rom();
+        }
+        self.clock = Clock::new();
+        self.bus.mapper.restart_rtc(self.rtc_time());
+        self.fault = None;
+        self.frame_completed = false;
+        self.lcd_enabled = self.bus.ppu.lcdc & 0x80 != 0;
+        self.bus.apply_freezes();
+        self.hooks.event(EmulatorEvent::Reset);
+    }
+
+    // Called with the finished RGBA32 frame every time the PPU enters VBlank
+    pub fn on_frame(&mut self, hook: impl FnMut(&[u8]) + Send + 'static) {
+        self.hooks.frame = Some(Box::new(hook));
+    }
+
+    // Called with every byte the game sends over the link port using the internal clock
+    pub fn on_serial_byte(&mut self, hook: impl FnMut(u8) + Send + 'static) {
+        self.hooks.serial = Some(Box::new(hook));
+    }
+
+    // Called with each batch of interleaved stereo samples as the APU produces them
+    pub fn on_audio_samples(&mut self, hook: impl FnMut(&[i16]) + Send + 'static) {
+        self.hooks.audio = Some(Box::new(hook));
+    }
+
+    pub fn on_event(&mut self, hook: impl FnMut(EmulatorEvent) + Send + 'static) {
+        self.hooks.event = Some(Box::new(hook));
+    }
+
+    // Called with every diagnostic the game raises (see diagnostics.rs)
+    pub fn on_diagnostic(&mut self, hook: impl FnMut(DiagnosticEvent) + Send + 'static) {
+        self.hooks.diagnostic = Some(Box::new(hook));
+    }
+
+    // Called whenever a rumble cartridge (MBC5+RUMBLE) switches its motor on or off. Games drive
+    // it in short pulses, so how often it is on over a frame gives the strength.
+    pub fn on_rumble(&mut self, hook: impl FnMut(bool) + Send + 'static) {
+        self.hooks.rumble = Some(Box::new(hook));
+    }
+
+    // Called with every write to the mapper's registers (0x0000-0x7FFF), decoded, with the banks
+    // it left mapped; for tracking down a wrong bank
+    pub fn on_mapper_write(&mut self, hook: impl FnMut(MapperWrite) + Send + 'static) {
+        self.hooks.mapper_write = Some(Box::new(hook));
+        self.bus.record_mapper_writes = true;
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    // The error that stopped execution, if any
+    pub fn fault(&self) -> Option<EmulatorError> {
+        self.fault
+    }
+
+    // Game Genie codes and frozen addresses; code changes take effect on the next ROM read
+    pub fn cheats(&self) -> &Cheats {
+        &self.bus.cheats
+    }
+
+    pub fn cheats_mut(&mut self) -> &mut Cheats {
+        &mut self.bus.cheats
+    }
+
+    // Lock `addr` to `value`: it is written now and every later write stores `value` instead
+    pub fn freeze(&mut self, addr: u16, value: u8) {
+        self.bus.cheats.freeze(addr, value);
+        self.bus.write_byte(addr, value);
+    }
+
+    // Returns false if the address was not frozen
+    pub fn unfreeze(&mut self, addr: u16) -> bool {
+        self.bus.cheats.unfreeze(addr)
+    }
+
+    // Plug a link cable into the serial port. It stays connected across resets and state loads.
+    pub fn connect_link(&mut self, port: impl LinkPort + 'static) {
+        self.bus.link = Some(Box::new(port));
+    }
+
+    pub fn disconnect_link(&mut self) {
+        self.bus.link = None;
+    }
+
+    // Drop every registered hook
+    pub fn clear_hooks(&mut self) {
+        self.hooks = Hooks::default();
+    }
+
+    // Execute a single instruction (or interrupt dispatch) and advance the rest of the
+    // system by the same number of T-cycles. Does nothing (and returns 0) while stopped by a fault.
+    pub fn step(&mut self) -> u8 {
+        if self.fault.is_some() {
+            return 0;
+        }
+        let (instructions, pc, sp) = (self.cpu.instruction_count, self.cpu.pc(), self.cpu.sp());
+        self.bus.clear_echo_read();
+        let cycles = self.cpu.step(&mut self.bus);
+        if let Some(checks) = &mut self.stack_checks
+            && let Some(misuse) = checks.check(&self.bus, pc, sp, self.cpu.sp())
+        {
+            self.bus.diagnose(Diagnostic::StackMisuse { misuse, sp: self.cpu.sp() });
+        }
+        self.bus.tick(cycles);
+        let frame = self.clock.m_cycles() / RTC_SYNC_M_CYCLES;
+        self.clock.advance_t_cycles(cycles as u32);
+        // A cartridge clock counts seconds at best, so it is brought up to date once per frame's
+        // worth of cycles rather than reading the time source every instruction
+        if self.clock.m_cycles() / RTC_SYNC_M_CYCLES != frame && self.bus.mapper.has_rtc() {
+            let now = self.rtc_time();
+            self.bus.mapper.update_rtc(now);
+        }
+        self.stats.add_step(cycles as u64 / 4, self.cpu.instruction_count - instructions);
+        self.dispatch_hooks(pc);
+        cycles
+    }
+
+    // Report whatever happened during the last step, which started at `pc`, to the registered hooks
+    fn dispatch_hooks(&mut self, pc: u16) {
+        for error in self.bus.take_errors() {
+            match self.error_policy {
+                ErrorPolicy::Ignore => {},
+                ErrorPolicy::Report => self.hooks.event(EmulatorEvent::Error(error)),
+                ErrorPolicy::Stop => {
+                    self.hooks.event(EmulatorEvent::Error(error));
+                    self.fault.get_or_insert(error);
+                },
+            }
+        }
+
+        let diagnostics = self.bus.take_diagnostics();
+        if self.hooks.has_diagnostic() {
+            for event in diagnostics {
+                self.hooks.diagnostic(DiagnosticEvent { pc, ..event });
+            }
+        }
+
+        if let Some(byte) = self.bus.take_serial_byte() {
+            self.hooks.serial(byte);
+        }
+
+        for write in self.bus.drain_mapper_writes() {
+            self.hooks.mapper_write(MapperWrite { pc, ..write });
+        }
+
+        self.bus.apu.take_samples(&mut self.audio_samples);
+        if self.audio_samples.len() > MAX_AUDIO_SAMPLES {
+            let excess = self.audio_samples.len() - MAX_AUDIO_SAMPLES;
+            self.audio_samples.drain(..excess);
+        }
+
+        let lcd_enabled = self.bus.ppu.lcdc & 0x80 != 0;
+        if lcd_enabled != self.lcd_enabled {
+            self.lcd_enabled = lcd_enabled;
+            self.hooks.event(if lcd_enabled { EmulatorEvent::LcdEnabled } else { EmulatorEvent::LcdDisabled });
+        }
+
+        let rumble = self.bus.mapper.rumble();
+        if rumble != self.rumble {
+            self.rumble = rumble;
+            self.hooks.rumble(rumble);
+        }
+
+        if self.bus.ppu.frame_ready {
+            self.bus.ppu.frame_ready = false;
+            self.frame_completed = true;
+            self.stats.frame_completed();
+            self.hooks.frame(self.bus.ppu.frame());
+            self.hooks.event(EmulatorEvent::FrameCompleted);
+
+            // Samples go to the audio hook once per frame; without one they wait for pull_audio
+            if self.hooks.has_audio() && !self.audio_samples.is_empty() {
+                self.hooks.audio(&self.audio_samples);
+                self.audio_samples.clear();
+            }
+        }
+    }
+
+    // Run until the PPU finishes a frame. A frame's worth of cycles is the most this runs: with
+    // the LCD off, or a PPU that never gets to VBlank, it returns there with a FrameTimeout event.
+    pub fn run_until_frame(&mut self) {
+        let _frame = span!("frame", number = self.stats.stats.frames);
+        #[cfg(feature = "std")]
+        let started = Instant::now();
+        let budget = CYCLES_PER_FRAME as u64 / 4;
+        let deadline = self.clock.m_cycles() + budget;
+        // Steps are counted too, so a step that doesn't move the clock can't spin forever
+        let mut steps = 0;
+        while !self.frame_completed && self.fault.is_none() && !self.clock.reached(deadline) && steps < budget {
+            self.step();
+            steps += 1;
+        }
+        if !self.frame_completed && self.fault.is_none() {
+            self.hooks.event(EmulatorEvent::FrameTimeout);
+        }
+        self.frame_completed = false;
+        #[cfg(feature = "std")]
+        {
+            self.stats.stats.last_frame_time = started.elapsed();
+        }
+    }
+
+    // Run until the clock reaches `target` M-cycles since power-on. Instructions are never
+    // split, so this can overshoot by a few cycles, or stop short on a fault; the number of
+    // M-cycles actually executed is returned.
+    pub fn run_until(&mut self, target: u64) -> u64 {
+        let start = self.clock.m_cycles();
+        while self.fault.is_none() && !self.clock.reached(target) {
+            self.step();
+        }
+        self.clock.m_cycles() - start
+    }
+
+    // Run for at least `m_cycles` M-cycles (see run_until)
+    pub fn run_for_m_cycles(&mut self, m_cycles: u64) -> u64 {
+        self.run_until(self.clock.m_cycles() + m_cycles)
+    }
+
+    // Run for the given amount of emulated time, returning how much was actually emulated
+    pub fn run_for(&mut self, duration: Duration) -> Duration {
+        let executed = self.run_for_m_cycles(Clock::m_cycles_in(duration));
+        Clock::duration_of(executed)
+    }
+
+    // Last rendered frame as RGBA32, SCREEN_WIDTH * SCREEN_HEIGHT pixels, and its generation
+    pub fn frame(&self) -> FrameBuffer<'_> {
+        let ppu = &self.bus.ppu;
+        FrameBuffer { pixels: ppu.frame(), generation: self.generations_before + ppu.lines_drawn() }
+    }
+
+    // Fill `buffer` from the achievement address space used by rcheevos, for its memory reads:
+    // 0x0000-0xFFFF is the CPU's view of memory. Reads have no side effects. Returns the bytes
+    // read, fewer than asked when the range runs past the end.
+    pub fn peek_memory(&self, address: u32, buffer: &mut [u8]) -> usize {
+        let available = (0x1_0000u32.saturating_sub(address) as usize).min(buffer.len());
+        for (offset, byte) in buffer[..available].iter_mut().enumerate() {
+            *byte = self.bus.read_byte((address as usize + offset) as u16);
+        }
+        available
+    }
+
+    pub fn set_button(&mut self, button: JoypadButton, pressed: bool) {
+        self.bus.set_button(button, pressed);
+    }
+
+    // Held buttons as a bitmask (see MemoryBus::buttons), the form movies record them in
+    pub fn buttons(&self) -> u8 {
+        self.bus.buttons()
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.bus.set_buttons(buttons);
+    }
+
+    // Move any pending audio samples (interleaved stereo at apu::SAMPLE_RATE) into `out`,
+    // returning how many were added
+    pub fn pull_audio(&mut self, out: &mut Vec<i16>) -> usize {
+        let count = self.audio_samples.len();
+        out.append(&mut self.audio_samples);
+        count
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new(&StateInfo {
+            rom_hash: savestate::rom_hash(self.bus.rom()),
+            saved_at: self.wall_time(),
+            play_time: self.clock.elapsed(),
+            thumbnail: StateInfo::thumbnail_of(self.frame().pixels),
+        });
+        self.cpu.save_state(&mut w);
+        self.bus.save_state(&mut w);
+        self.clock.save_state(&mut w);
+        w.finish()
+    }
+
+    // Restore a state produced by `save_state`. On error the machine is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let components = savestate::unpack(data, savestate::rom_hash(self.bus.rom()))?;
+        let mut r = StateReader::raw(&components);
+        let mut cpu = Cpu::new();
+        let mut clock = Clock::new();
+        cpu.load_state(&mut r)?;
+
+        // The new bus takes over the ROM and hands it back if the state turns out to be bad
+        let rom = self.bus.take_rom();
+        let mut bus = self.new_bus(rom);
+        if let Err(e) = bus.load_state(&mut r).and_then(|()| clock.load_state(&mut r)) {
+            self.bus.set_rom(bus.take_rom());
+            return Err(e);
+        }
+
+        bus.link = self.bus.link.take();
+        cpu.coverage = self.cpu.coverage.take();
+        // The new PPU starts over at 0 lines drawn, so the generations carry on from the old one
+        self.generations_before += self.bus.ppu.lines_drawn() + 1;
+        self.cpu = cpu;
+        self.bus = bus;
+        self.clock = clock;
+        self.fault = None;
+        self.frame_completed = false;
+        self.lcd_enabled = self.bus.ppu.lcdc & 0x80 != 0;
+        self.bus.apply_freezes();
+        debug!("state loaded");
+        self.hooks.event(EmulatorEvent::StateLoaded);
+        Ok(())
+    }
+
+    // Direct access for debugging tools such as the VRAM viewer
+    pub fn ppu(&self) -> &Ppu {
+        &self.bus.ppu
+    }
+
+    // Performance counters for this session (see Stats)
+    pub fn stats(&self) -> Stats {
+        Stats { audio_buffered: self.audio_samples.len(), play_time: self.stats.play_time(), ..self.stats.stats }
+    }
+
+    // Time already played with this ROM, e.g. in earlier sessions; Stats::play_time counts on from
+    // it. Starts at zero and goes back to zero when another ROM is loaded.
+    pub fn set_play_time(&mut self, play_time: Duration) {
+        self.stats.set_play_time(play_time);
+    }
+
+    // Start counting executed opcodes (from zero), or stop and drop the counts
+    pub fn set_opcode_coverage(&mut self, enabled: bool) {
+        self.cpu.coverage = enabled.then(Box::default);
+    }
+
+    pub fn opcode_coverage(&self) -> Option<&OpcodeCoverage> {
+        self.cpu.coverage.as_deref()
+    }
+
+    // Report stack misuse (SP in ROM or VRAM, over the OAM DMA routine, underflowing) as
+    // diagnostics; see stack_checks.rs
+    pub fn set_stack_checks(&mut self, enabled: bool) {
+        self.stack_checks = enabled.then(StackChecks::default);
+    }
+
+    // The cartridge's rumble motor is running
+    pub fn rumble(&self) -> bool {
+        self.bus.mapper.rumble()
+    }
+
+    // Whether the cartridge keeps its RAM on a battery, i.e. has a save file
+    pub fn has_battery(&self) -> bool {
+        self.bus.has_battery()
+    }
+
+    pub fn cart_ram(&self) -> &[u8] {
+        self.bus.cart_ram()
+    }
+
+    // Restore the cartridge RAM from a save file
+    pub fn load_cart_ram(&mut self, data: &[u8]) {
+        self.bus.load_cart_ram(data);
+    }
+
+    // Patch cartridge RAM at an offset (see MemoryBus::write_cart_ram)
+    pub fn write_cart_ram(&mut self, offset: usize, data: &[u8]) {
+        self.bus.write_cart_ram(offset, data);
+    }
+
+    // Whether the game wrote cartridge RAM or set the clock since the battery save was loaded or
+    // mark_battery_saved was called; lets frontends write the save out periodically without
+    // rewriting an unchanged file
+    pub fn battery_dirty(&self) -> bool {
+        self.bus.cart_ram_dirty()
+    }
+
+    pub fn mark_battery_saved(&mut self) {
+        self.bus.mark_cart_ram_saved();
+    }
+
+    // Contents of a battery save: the cartridge RAM, followed by the clock of carts with one,
+    // brought up to date
+    pub fn battery_save(&self) -> Vec<u8> {
+        self.bus.mapper.save_ram(self.bus.cart_ram(), self.rtc_time(), self.wall_time())
+    }
+
+    // Restore a battery save. Under RtcMode::WallClock the cartridge clock catches up on the time
+    // since the save was written; saves without clock data leave the clock alone.
+    pub fn load_battery_save(&mut self, data: &[u8]) {
+        self.bus.load_cart_ram(data);
+        if let Some(rtc) = data.get(self.bus.cart_ram().len()..) {
+            let wall_time = (self.rtc_mode == RtcMode::WallClock).then(|| self.wall_time());
+            let now = self.rtc_time();
+            self.bus.mapper.load_ram_extra(rtc, now, wall_time);
+        }
+        self.bus.mark_cart_ram_saved();
+    }
+
+    // Start the counters over; the play time carries on
+    pub fn reset_stats(&mut self) {
+        let play_time = self.stats.play_time();
+        self.stats = StatsCounter::default();
+        self.stats.set_play_time(play_time);
+    }
+
+    pub fn timer(&self) -> &Timer {
+        self.bus.timer()
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    // Replace the real-time source (the system clock by default)
+    pub fn set_wall_clock(&mut self, wall_clock: impl WallClock + 'static) {
+        self.wall_clock = Box::new(wall_clock);
+        self.bus.mapper.restart_rtc(self.rtc_time());
+    }
+
+    // Real time, as time since the Unix epoch
+    pub fn wall_time(&self) -> Duration {
+        self.wall_clock.now()
+    }
+
+    pub fn rtc_mode(&self) -> RtcMode {
+        self.rtc_mode
+    }
+
+    pub fn set_rtc_mode(&mut self, mode: RtcMode) {
+        self.rtc_mode = mode;
+        self.bus.mapper.restart_rtc(self.rtc_time());
+    }
+
+    // The cartridge clock brought up to date, for carts with one
+    pub fn rtc(&mut self) -> Option<RtcReading> {
+        let now = self.rtc_time();
+        self.bus.mapper.update_rtc(now);
+        self.bus.mapper.rtc_reading()
+    }
+
+    // Move the cartridge clock forward by `seconds`, or back when negative, e.g. to reach a timed
+    // event or test a rollover. Returns the new reading; None for carts without a clock.
+    pub fn shift_rtc(&mut self, seconds: i64) -> Option<RtcReading> {
+        let now = self.rtc_time();
+        self.bus.mapper.update_rtc(now);
+        self.bus.mapper.shift_rtc(seconds);
+        self.bus.mapper.rtc_reading()
+    }
+
+    // Time as seen by a cartridge RTC. Only differences between two readings are meaningful:
+    // emulated time since power-on, or wall-clock time, depending on the RtcMode.
+    pub fn rtc_time(&self) -> Duration {
+        match self.rtc_mode {
+            RtcMode::Emulated => self.clock.elapsed(),
+            RtcMode::WallClock => self.wall_time(),
+        }
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn bus(&self) -> &MemoryBus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut MemoryBus {
+        &mut self.bus
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // M-cycles of one scanline
+    const LINE: u64 = 114;
+
+    #[test]
+    fn frame_generation_keeps_increasing_across_load_state() {
+        let mut emulator = Emulator::new(vec![0; 0x8000], Model::Dmg);
+        // From the top of a frame, where the next lines are drawn
+        emulator.run_until_frame();
+        let state = emulator.save_state();
+        emulator.run_for_m_cycles(10 * LINE);
+        let before = emulator.frame().generation;
+
+        emulator.load_state(&state).unwrap();
+        let loaded = emulator.frame().generation;
+        assert!(loaded > before);
+        emulator.run_for_m_cycles(10 * LINE);
+        assert!(emulator.frame().generation > loaded);
+    }
+
+    #[test]
+    fn load_rom_clears_cheats_and_freezes() {
+        let mut emulator = Emulator::new(vec![0; 0x8000], Model::Dmg);
+        emulator.cheats_mut().add("00A-17B-C49").unwrap();
+        emulator.freeze(0xC000, 0x12);
+        emulator.reset();
+        assert_eq!(emulator.cheats().list().len(), 1);
+        assert_eq!(emulator.cheats().freezes().len(), 1);
+
+        emulator.load_rom(vec![0; 0x8000]);
+        assert!(emulator.cheats().list().is_empty());
+        assert!(emulator.cheats().freezes().is_empty());
+    }
+}
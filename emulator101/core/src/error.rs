@@ -0,0 +1,94 @@
+// Emulation errors
+// Things a real Game Boy would do something undefined with (lock up, ignore, ...). The core
+// reports them instead of printing, and the embedder decides what happens via ErrorPolicy.
+
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::savestate::SaveStateError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum EmulatorError {
+    // One of the 11 unused opcodes; hardware locks up the CPU
+    #[error("illegal opcode {opcode:#04X} at {pc:#06X}")]
+    IllegalOpcode { opcode: u8, pc: u16 },
+
+    // 0xFEA0-0xFEFF
+    #[error("write of {value:#04X} to unusable memory at {addr:#06X}")]
+    UnusableWrite { addr: u16, value: u8 },
+
+    #[error("cartridge RAM access at {addr:#06X} but the cartridge has no RAM there")]
+    MissingCartRam { addr: u16 },
+}
+
+// What the emulator does when an EmulatorError occurs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    // Carry on silently: the offending opcode or access is skipped and nothing is reported
+    Ignore,
+    // Carry on, but send EmulatorEvent::Error to the event hook
+    #[default]
+    Report,
+    // Report the error and stop executing until reset() or load_state()
+    Stop,
+}
+
+// Problems setting up an emulator with EmulatorBuilder / Emulator::from_file
+#[derive(Debug, Error)]
+pub enum LoadError {
+    #[cfg(feature = "std")]
+    #[error("failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    // Too short to even contain a cartridge header (0x0100-0x014F)
+    #[error("ROM is only {0} bytes, too small to be a Game Boy cartridge")]
+    RomTooSmall(usize),
+
+    #[error("boot ROM is {found} bytes, expected {expected} for this model")]
+    BootRomSize { expected: usize, found: usize },
+}
+
+// Codes rejected by Cheats::add
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum CheatError {
+    #[error("invalid character '{0}' in cheat code")]
+    InvalidDigit(char),
+
+    #[error("cheat code has {0} digits, expected 6 or 9")]
+    InvalidLength(usize),
+
+    // Game Genie codes can only patch the cartridge ROM (0x0000-0x7FFF)
+    #[error("cheat code targets {0:#06X}, outside cartridge ROM")]
+    NotRomAddress(u16),
+}
+
+// Problems reading a movie file or starting its playback
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum MovieError {
+    #[error("not a movie file")]
+    BadMagic,
+
+    #[error("unsupported movie version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("movie is truncated")]
+    Truncated,
+
+    #[error("movie contains an invalid {0}")]
+    InvalidValue(&'static str),
+
+    #[error("movie was recorded with a different ROM")]
+    RomMismatch,
+
+    // The anchoring savestate could not be loaded
+    #[error("movie's starting state is unusable: {0}")]
+    Anchor(#[from] SaveStateError),
+}
@@ -0,0 +1,98 @@
+// Callback registration for embedders
+// Frontends register closures on the Emulator instead of polling PPU/bus internals.
+
+use alloc::boxed::Box;
+
+use crate::diagnostics::DiagnosticEvent;
+use crate::error::EmulatorError;
+use crate::mapper::MapperWrite;
+
+// Notable things that happened inside the emulator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmulatorEvent {
+    FrameCompleted,
+    // run_until_frame gave up after a frame's worth of cycles without a frame: the LCD is off,
+    // or the PPU never reached VBlank
+    FrameTimeout,
+    LcdEnabled,
+    LcdDisabled,
+    Reset,
+    StateLoaded,
+    // Only sent when the ErrorPolicy is Report or Stop
+    Error(EmulatorError),
+}
+
+pub type FrameHook = Box<dyn FnMut(&[u8]) + Send>;
+pub type SerialHook = Box<dyn FnMut(u8) + Send>;
+pub type AudioHook = Box<dyn FnMut(&[i16]) + Send>;
+pub type EventHook = Box<dyn FnMut(EmulatorEvent) + Send>;
+pub type DiagnosticHook = Box<dyn FnMut(DiagnosticEvent) + Send>;
+pub type RumbleHook = Box<dyn FnMut(bool) + Send>;
+pub type MapperWriteHook = Box<dyn FnMut(MapperWrite) + Send>;
+
+#[derive(Default)]
+pub struct Hooks {
+    pub(crate) frame: Option<FrameHook>,
+    pub(crate) serial: Option<SerialHook>,
+    pub(crate) audio: Option<AudioHook>,
+    pub(crate) event: Option<EventHook>,
+    pub(crate) diagnostic: Option<DiagnosticHook>,
+    pub(crate) rumble: Option<RumbleHook>,
+    pub(crate) mapper_write: Option<MapperWriteHook>,
+}
+
+impl Hooks {
+    pub(crate) fn frame(&mut self, frame: &[u8]) {
+        if let Some(hook) = self.frame.as_mut() {
+            hook(frame);
+        }
+    }
+
+    pub(crate) fn serial(&mut self, byte: u8) {
+        if let Some(hook) = self.serial.as_mut() {
+            hook(byte);
+        }
+    }
+
+    pub(crate) fn has_audio(&self) -> bool {
+        self.audio.is_some()
+    }
+
+    pub(crate) fn audio(&mut self, samples: &[i16]) {
+        if let Some(hook) = self.audio.as_mut() {
+            hook(samples);
+        }
+    }
+
+    pub(crate) fn event(&mut self, event: EmulatorEvent) {
+        if let Some(hook) = self.event.as_mut() {
+            hook(event);
+        }
+    }
+
+    pub(crate) fn has_diagnostic(&self) -> bool {
+        self.diagnostic.is_some()
+    }
+
+    pub(crate) fn diagnostic(&mut self, event: DiagnosticEvent) {
+        if let Some(hook) = self.diagnostic.as_mut() {
+            hook(event);
+        }
+    }
+
+    pub(crate) fn rumble(&mut self, on: bool) {
+        if let Some(hook) = self.rumble.as_mut() {
+            hook(on);
+        }
+    }
+
+    pub(crate) fn has_mapper_write(&self) -> bool {
+        self.mapper_write.is_some()
+    }
+
+    pub(crate) fn mapper_write(&mut self, write: MapperWrite) {
+        if let Some(hook) = self.mapper_write.as_mut() {
+            hook(write);
+        }
+    }
+}
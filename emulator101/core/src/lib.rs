@@ -0,0 +1,57 @@
+// Index-based pixel loops mirror the hardware descriptions and are kept on purpose
+#![allow(clippy::needless_range_loop)]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[macro_use]
+mod trace;
+
+pub mod accuracy;
+pub mod apu;
+pub mod builder;
+pub mod cartridge;
+pub mod cheats;
+pub mod clock;
+pub mod coverage;
+pub mod cpu;
+pub mod diagnostics;
+pub mod disasm;
+pub mod memory;
+pub mod model;
+pub mod interrupts;
+pub mod timer;
+pub mod ppu;
+pub mod savestate;
+pub mod stack_checks;
+pub mod emulator;
+pub mod error;
+pub mod hooks;
+pub mod link;
+pub mod mapper;
+pub mod movie;
+pub mod stats;
+
+pub use accuracy::{Accuracy, AccuracyProfile};
+pub use builder::EmulatorBuilder;
+pub use cartridge::{Cartridge, CgbSupport};
+pub use cheats::{Cheat, Cheats, Freeze, GameGenieCode};
+pub use clock::{Clock, FixedWallClock, RtcMode, WallClock};
+pub use coverage::OpcodeCoverage;
+pub use cpu::CpuMode;
+#[cfg(feature = "std")]
+pub use clock::SystemWallClock;
+pub use emulator::{Emulator, FrameBuffer};
+pub use error::{CheatError, EmulatorError, ErrorPolicy, LoadError, MovieError};
+pub use diagnostics::{Diagnostic, DiagnosticEvent, LockedMemory, Severity};
+pub use stack_checks::StackMisuse;
+pub use hooks::EmulatorEvent;
+pub use link::LinkPort;
+pub use mapper::{MappedBanks, MapperKind, MapperRegister, MapperWrite, RtcReading};
+#[cfg(feature = "std")]
+pub use link::{LocalLink, StreamLink, STREAM_REPLY_TIMEOUT};
+pub use memory::JoypadButton;
+pub use model::Model;
+pub use movie::{Greenzone, Movie};
+pub use ppu::{LcdMode, Palette, PpuState, SCREEN_WIDTH, SCREEN_HEIGHT};
+pub use stats::Stats;
@@ -0,0 +1,150 @@
+// Link cable
+// A serial transfer moves one byte each way: the side driving the clock (SC bit 0 set) shifts
+// its SB out while shifting the other side's SB in, and the other side, waiting with SC = 0x80,
+// finishes its transfer on the same clock. The cable is modelled a byte at a time: the clocking
+// side swaps bytes when it starts a transfer and the waiting side picks its byte up on its next
+// bus tick. Without a cable the clocking side shifts in 0xFF.
+//
+// The cables below need threads and locks, so they come with the std feature; without it the
+// embedder implements LinkPort over whatever its platform offers.
+
+#[cfg(feature = "std")]
+use std::io::{BufReader, Read, Write};
+#[cfg(feature = "std")]
+use std::sync::mpsc::{self, Receiver};
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "std")]
+use std::thread;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+// How long a byte clocked out over a stream waits for the peer's answer by default. Emulation
+// stops for that long, so it is kept to a few milliseconds: a byte takes about 1 ms on a real cable.
+#[cfg(feature = "std")]
+pub const STREAM_REPLY_TIMEOUT: Duration = Duration::from_millis(5);
+
+// One end of a link cable as seen by an emulator
+pub trait LinkPort: Send {
+    // This side started clocking out `byte`; returns what the other side had in SB, or 0xFF
+    // if it is not waiting for a transfer
+    fn exchange(&mut self, byte: u8) -> u8;
+    // This side waits on the external clock with `byte` in SB
+    fn offer(&mut self, byte: u8);
+    // The byte the other side clocked in since the last call
+    fn receive(&mut self) -> Option<u8>;
+}
+
+#[cfg(feature = "std")]
+#[derive(Default)]
+struct Wire {
+    // Byte each end has waiting in SB
+    offers: [Option<u8>; 2],
+    // Byte clocked in to each end, not picked up yet
+    incoming: [Option<u8>; 2],
+}
+
+// An in-process cable between two emulators, e.g. two players on one machine
+#[cfg(feature = "std")]
+pub struct LocalLink {
+    wire: Arc<Mutex<Wire>>,
+    end: usize,
+}
+
+#[cfg(feature = "std")]
+impl LocalLink {
+    // Both ends of a new cable
+    pub fn pair() -> (LocalLink, LocalLink) {
+        let wire = Arc::new(Mutex::new(Wire::default()));
+        (LocalLink { wire: wire.clone(), end: 0 }, LocalLink { wire, end: 1 })
+    }
+}
+
+#[cfg(feature = "std")]
+impl LinkPort for LocalLink {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        let mut wire = self.wire.lock().unwrap();
+        let other = 1 - self.end;
+        match wire.offers[other].take() {
+            Some(reply) => {
+                wire.incoming[other] = Some(byte);
+                reply
+            },
+            None => 0xFF,
+        }
+    }
+
+    fn offer(&mut self, byte: u8) {
+        self.wire.lock().unwrap().offers[self.end] = Some(byte);
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        self.wire.lock().unwrap().incoming[self.end].take()
+    }
+}
+
+// A cable to another program over a byte stream (stdin/stdout, a named pipe, a serial port),
+// so scripts and test harnesses can be the peer. Every byte this side clocks out is written to
+// the stream and the peer answers with one byte. A byte from the peer while this side waits on
+// the external clock is the peer clocking, and is answered with this side's SB.
+#[cfg(feature = "std")]
+pub struct StreamLink {
+    output: Box<dyn Write + Send>,
+    // Filled by a reader thread, so polling never blocks
+    incoming: Receiver<u8>,
+    offer: u8,
+    reply_timeout: Duration,
+}
+
+#[cfg(feature = "std")]
+impl StreamLink {
+    pub fn new(input: impl Read + Send + 'static, output: impl Write + Send + 'static) -> StreamLink {
+        let (sender, incoming) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(input).bytes() {
+                let Ok(byte) = byte else { break };
+                if sender.send(byte).is_err() {
+                    break;
+                }
+            }
+        });
+        StreamLink { output: Box::new(output), incoming, offer: 0xFF, reply_timeout: STREAM_REPLY_TIMEOUT }
+    }
+
+    // Wait this long for the peer's answer instead of STREAM_REPLY_TIMEOUT, for peers further
+    // away than a local pipe
+    pub fn with_reply_timeout(mut self, timeout: Duration) -> StreamLink {
+        self.reply_timeout = timeout;
+        self
+    }
+
+    // The peer on the other end of this process's stdin and stdout
+    pub fn stdio() -> StreamLink {
+        StreamLink::new(std::io::stdin(), std::io::stdout())
+    }
+
+    fn send(&mut self, byte: u8) -> bool {
+        self.output.write_all(&[byte]).and_then(|_| self.output.flush()).is_ok()
+    }
+}
+
+#[cfg(feature = "std")]
+impl LinkPort for StreamLink {
+    fn exchange(&mut self, byte: u8) -> u8 {
+        // A peer that went away or does not answer reads as an unplugged cable
+        if !self.send(byte) {
+            return 0xFF;
+        }
+        self.incoming.recv_timeout(self.reply_timeout).unwrap_or(0xFF)
+    }
+
+    fn offer(&mut self, byte: u8) {
+        self.offer = byte;
+    }
+
+    fn receive(&mut self) -> Option<u8> {
+        let byte = self.incoming.try_recv().ok()?;
+        self.send(self.offer);
+        Some(byte)
+    }
+}
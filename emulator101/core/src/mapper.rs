@@ -0,0 +1,1524 @@
+// Cartridge mappers
+// The mapper decides which part of the ROM the CPU sees at 0x0000-0x7FFF and takes the writes
+// to that range as its control registers. Cartridges without one show their first 32 KiB.
+//
+//   MBC1 (most early games, e.g. Super Mario Land, up to 2 MiB ROM and 32 KiB RAM): 16 KiB ROM
+//     banks at 0x4000-0x7FFF and four 8 KiB RAM banks.
+//       0x0000-0x1FFF RAM enable (0xA in the low nibble)
+//       0x2000-0x3FFF BANK1: ROM bank bits 0-4; 0 selects 1
+//       0x4000-0x5FFF BANK2: two more bits, ROM bank bits 5-6 or the RAM bank
+//       0x6000-0x7FFF mode: 0 uses BANK2 only for 0x4000-0x7FFF; 1 also applies it to
+//         0x0000-0x3FFF (banks 0x00/0x20/0x40/0x60) and to RAM
+//     Bank numbers wrap around the ROM and RAM sizes. MBC1 multicarts (MBC1M) are not supported.
+//   MBC3 (Pokemon Gold/Silver, Harvest Moon GB, ...): 16 KiB ROM banks at 0x4000-0x7FFF, four
+//     8 KiB RAM banks and, on the TIMER carts, a real-time clock.
+//       0x0000-0x1FFF RAM and clock enable (0xA in the low nibble)
+//       0x2000-0x3FFF ROM bank, 7 bits; 0 selects 1
+//       0x4000-0x5FFF 0x00-0x03 maps a RAM bank at 0xA000, 0x08-0x0C a clock register:
+//         seconds, minutes, hours, day bits 0-7, then day bit 8 (bit 0), halt (bit 6) and day
+//         counter carry (bit 7)
+//       0x6000-0x7FFF writing 0 then 1 latches the clock into the registers the CPU reads
+//     The clock counts seconds up to 511 days, driven by the RTC time like the HuC3's. It is
+//     kept in the battery save after the RAM in the 48-byte footer VBA and BGB use.
+//   MBC5 (most later games, up to 8 MiB ROM and 128 KiB RAM): 16 KiB ROM banks at 0x4000-0x7FFF,
+//     bank 0 included, and sixteen 8 KiB RAM banks.
+//       0x0000-0x1FFF RAM enable (0xA in the low nibble)
+//       0x2000-0x2FFF ROM bank bits 0-7
+//       0x3000-0x3FFF ROM bank bit 8
+//       0x4000-0x5FFF RAM bank; on RUMBLE carts bit 3 drives the motor instead, leaving 8 banks
+//   M161 (Mani 4 in 1 multicart): the first write anywhere in 0x0000-0x7FFF selects one of
+//     eight 32 KiB banks (bits 0-2 of the value) and locks it until the next reset
+//   Wisdom Tree: a write to 0x0000-0x3FFF selects the 32 KiB bank given by the low byte of the
+//     address; the value written doesn't matter
+//   HuC3 (Hudson, e.g. Robopon): 16 KiB ROM banks at 0x4000-0x7FFF and four 8 KiB RAM banks.
+//     0xA000-0xBFFF shows RAM or one of the registers, picked by the mode written to
+//     0x0000-0x1FFF:
+//       0x0 RAM, read only          0xA RAM, read/write
+//       0xB RTC command (write)     0xC RTC result (read)
+//       0xD RTC semaphore           0xE infrared port
+//     The clock counts minutes of the day and days, and is driven by the emulator's RTC time
+//     (see RtcMode). It is kept in the battery save after the RAM.
+//
+// Neither unlicensed cart describes itself properly in the header, so MapperKind::detect goes by
+// heuristics and embedders can pick the mapper explicitly (EmulatorBuilder::mapper).
+//
+// Each mapper implements Mbc, so the bus only forwards the ROM and RAM areas to it.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+use core::time::Duration;
+
+use crate::cartridge::Cartridge;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+const BANK_SIZE: usize = 0x8000;
+// ROM banks of the MBC1 and HuC3
+const ROM_BANK_SIZE: usize = 0x4000;
+// Cartridge RAM is banked in 8 KiB units at 0xA000-0xBFFF
+pub const RAM_BANK_SIZE: usize = 0x2000;
+const MINUTES_PER_DAY: u16 = 24 * 60;
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MapperKind {
+    #[default]
+    None,
+    Mbc1,
+    M161,
+    WisdomTree,
+    Huc3,
+    // After the others, which keep the numbers savestates store
+    Mbc3,
+    Mbc5,
+}
+
+impl MapperKind {
+    pub const ALL: [MapperKind; 7] = [MapperKind::None, MapperKind::Mbc1, MapperKind::Mbc3, MapperKind::Mbc5, MapperKind::M161, MapperKind::WisdomTree, MapperKind::Huc3];
+
+    // Best guess from the cartridge header
+    pub fn detect(rom: &[u8]) -> MapperKind {
+        let header = Cartridge::parse(rom);
+        let title = header.title.as_bytes();
+        if header.cartridge_type == 0xFE {
+            MapperKind::Huc3
+        } else if header.cartridge_type == 0x10 && title.starts_with(b"TETRIS SET") {
+            // The Mani 4 in 1 menu, which claims to be an MBC3 cart
+            MapperKind::M161
+        } else if title.windows(6).any(|window| window.eq_ignore_ascii_case(b"WISDOM"))
+            || (header.cartridge_type == 0x00 && header.rom_size == BANK_SIZE && rom.len() > BANK_SIZE)
+        {
+            // Wisdom Tree games claim to be plain 32 KiB carts
+            MapperKind::WisdomTree
+        } else if matches!(header.cartridge_type, 0x01..=0x03) {
+            MapperKind::Mbc1
+        } else if matches!(header.cartridge_type, 0x0F..=0x13) {
+            MapperKind::Mbc3
+        } else if matches!(header.cartridge_type, 0x19..=0x1E) {
+            MapperKind::Mbc5
+        } else {
+            MapperKind::None
+        }
+    }
+}
+
+impl fmt::Display for MapperKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MapperKind::None => "none",
+            MapperKind::Mbc1 => "mbc1",
+            MapperKind::Mbc3 => "mbc3",
+            MapperKind::Mbc5 => "mbc5",
+            MapperKind::M161 => "m161",
+            MapperKind::WisdomTree => "wisdom-tree",
+            MapperKind::Huc3 => "huc3",
+        })
+    }
+}
+
+impl FromStr for MapperKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MapperKind::ALL
+            .into_iter()
+            .find(|kind| kind.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown mapper '{}' (expected none, mbc1, mbc3, mbc5, m161, wisdom-tree or huc3)", s))
+    }
+}
+
+// What a cartridge clock shows (see Emulator::rtc). The HuC3 counts minutes, so its seconds are
+// always 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RtcReading {
+    pub days: u16,
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+}
+
+impl fmt::Display for RtcReading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "day {}, {:02}:{:02}:{:02}", self.days, self.hours, self.minutes, self.seconds)
+    }
+}
+
+// Register behind an address in 0x0000-0x7FFF, for the mapper write trace (see MapperWrite)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapperRegister {
+    RamEnable,
+    // The whole ROM bank number (MBC3, HuC3, the 32 KiB mappers)
+    RomBank,
+    // Part of it: MBC1 BANK1 and MBC5 bits 0-7, MBC5 bit 8
+    RomBankLow,
+    RomBankHigh,
+    // MBC1 BANK2: the RAM bank or ROM bank bits 5-6, depending on the mode
+    Bank2,
+    BankingMode,
+    RamBank,
+    // MBC3: a RAM bank or a clock register at 0xA000
+    RamBankOrClock,
+    ClockLatch,
+    // HuC3: what 0xA000-0xBFFF shows
+    Huc3Mode,
+    // Nothing there; the write has no effect
+    None,
+}
+
+impl fmt::Display for MapperRegister {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MapperRegister::RamEnable => "RAM enable",
+            MapperRegister::RomBank => "ROM bank",
+            MapperRegister::RomBankLow => "ROM bank low",
+            MapperRegister::RomBankHigh => "ROM bank high",
+            MapperRegister::Bank2 => "BANK2 (RAM bank / ROM bank high)",
+            MapperRegister::BankingMode => "banking mode",
+            MapperRegister::RamBank => "RAM bank",
+            MapperRegister::RamBankOrClock => "RAM bank / clock register",
+            MapperRegister::ClockLatch => "clock latch",
+            MapperRegister::Huc3Mode => "HuC3 mode",
+            MapperRegister::None => "no register",
+        })
+    }
+}
+
+// Banks the mapper shows (see Emulator::mapped_banks), after masking to the ROM size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedBanks {
+    // 16 KiB ROM banks at 0x0000 and 0x4000
+    pub rom0: usize,
+    pub romx: usize,
+    // RAM bank at 0xA000, None while a register is mapped there
+    pub ram: Option<usize>,
+    pub ram_enabled: bool,
+}
+
+// A write to the mapper's registers and the banks mapped after it (see
+// Emulator::on_mapper_write)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapperWrite {
+    // Address of the instruction that wrote
+    pub pc: u16,
+    pub addr: u16,
+    pub value: u8,
+    pub register: MapperRegister,
+    pub banks: MappedBanks,
+}
+
+// MBC3 clock registers, as the CPU sees them at 0x08-0x0C
+#[derive(Debug, Clone, Copy, Default)]
+struct Mbc3Time {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16, // 9 bits
+    halted: bool,
+    // The day counter went past 511
+    carry: bool,
+}
+
+impl Mbc3Time {
+    fn register(&self, index: u8) -> u8 {
+        match index {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days as u8,
+            _ => (self.days >> 8) as u8 | (self.halted as u8) << 6 | (self.carry as u8) << 7,
+        }
+    }
+
+    // Unused bits don't stick; out-of-range values do, as on the chip
+    fn set_register(&mut self, index: u8, value: u8) {
+        match index {
+            0x08 => self.seconds = value & 0x3F,
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | value as u16,
+            _ => {
+                self.days = (self.days & 0xFF) | (value as u16 & 0x01) << 8;
+                self.halted = value & 0x40 != 0;
+                self.carry = value & 0x80 != 0;
+            },
+        }
+    }
+
+    // Move by `seconds`, wrapping around the 512-day counter both ways; going past it forward
+    // sets the carry. Out-of-range counters are carried into the next one.
+    fn shift(&mut self, seconds: i64) {
+        let period = 512 * SECONDS_PER_DAY;
+        let total = self.days as i64 * SECONDS_PER_DAY + self.hours as i64 * 3600 + self.minutes as i64 * 60 + self.seconds as i64 + seconds;
+        if total >= period {
+            self.carry = true;
+        }
+        let total = total.rem_euclid(period);
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        self.days = (total / SECONDS_PER_DAY) as u16;
+    }
+
+    // Five 32-bit registers of the save footer
+    fn save(&self, w: &mut StateWriter) {
+        for index in 0x08..=0x0C {
+            w.write_u32(self.register(index) as u32);
+        }
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        for index in 0x08..=0x0C {
+            self.set_register(index, r.read_u32()? as u8);
+        }
+        Ok(())
+    }
+}
+
+// MBC3 real-time clock
+#[derive(Debug, Clone, Default)]
+struct Mbc3Clock {
+    time: Mbc3Time,
+    // Copy taken by the last latch, which is what the CPU reads
+    latched: Mbc3Time,
+    // 0 was written to the latch register, so a 1 latches
+    latch_armed: bool,
+    // RTC time the counters are up to date with, advanced in whole seconds
+    synced_at: Duration,
+    // RTC time of the last update (at most a frame ago), where writing the seconds restarts the
+    // second
+    now: Duration,
+}
+
+impl Mbc3Clock {
+    // Count the seconds passed since the last update; a halted clock skips them
+    fn advance(&mut self, now: Duration) {
+        self.now = now;
+        if now < self.synced_at || self.time.halted {
+            self.synced_at = now;
+            return;
+        }
+        let elapsed = (now - self.synced_at).as_secs();
+        if elapsed == 0 {
+            return;
+        }
+        self.synced_at += Duration::from_secs(elapsed);
+        self.time.shift(elapsed as i64);
+    }
+
+    fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.time;
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    // Writes go to the counters and the latched copy, so a read straight after sees them
+    fn set_register(&mut self, index: u8, value: u8) {
+        if index == 0x08 {
+            self.synced_at = self.now;
+        }
+        self.time.set_register(index, value);
+        self.latched.set_register(index, value);
+    }
+}
+
+// HuC3 real-time clock
+#[derive(Debug, Clone, Default)]
+struct Huc3Clock {
+    minutes: u16, // Minute of the day, 0-1439
+    days: u16,    // 12-bit day counter
+    alarm_minutes: u16,
+    alarm_days: u16,
+    alarm_enabled: bool,
+    // RTC time the counters are up to date with, advanced in whole minutes
+    synced_at: Duration,
+}
+
+impl Huc3Clock {
+    // Count the minutes passed since the last update
+    fn advance(&mut self, now: Duration) {
+        if now < self.synced_at {
+            // The time source went backwards (e.g. a new RtcMode); carry on from here
+            self.synced_at = now;
+            return;
+        }
+        let elapsed = (now - self.synced_at).as_secs() / 60;
+        if elapsed == 0 {
+            return;
+        }
+        self.synced_at += Duration::from_secs(elapsed * 60);
+        let total = self.minutes as u64 + elapsed;
+        self.minutes = (total % MINUTES_PER_DAY as u64) as u16;
+        self.days = (self.days as u64 + total / MINUTES_PER_DAY as u64) as u16 & 0x0FFF;
+    }
+
+    // Move the counters by whole minutes, wrapping around the 12-bit day counter both ways
+    fn shift(&mut self, minutes: i64) {
+        let per_day = MINUTES_PER_DAY as i64;
+        let total = (self.days as i64 * per_day + self.minutes as i64 + minutes).rem_euclid(0x1000 * per_day);
+        self.minutes = (total % per_day) as u16;
+        self.days = (total / per_day) as u16;
+    }
+
+    // Nibble `index` of the clock's register space
+    fn nibble(&self, index: u8) -> u8 {
+        let (value, shift) = match index {
+            0x00..=0x02 => (self.minutes, index * 4),
+            0x03..=0x06 => (self.days, (index - 0x03) * 4),
+            0x58..=0x5A => (self.alarm_minutes, (index - 0x58) * 4),
+            0x5B..=0x5E => (self.alarm_days, (index - 0x5B) * 4),
+            0x5F => (self.alarm_enabled as u16, 0),
+            _ => return 0,
+        };
+        (value >> shift) as u8 & 0x0F
+    }
+
+    fn set_nibble(&mut self, index: u8, nibble: u8) {
+        let (value, shift) = match index {
+            0x00..=0x02 => (&mut self.minutes, index * 4),
+            0x03..=0x06 => (&mut self.days, (index - 0x03) * 4),
+            0x58..=0x5A => (&mut self.alarm_minutes, (index - 0x58) * 4),
+            0x5B..=0x5E => (&mut self.alarm_days, (index - 0x5B) * 4),
+            0x5F => {
+                self.alarm_enabled = nibble & 1 != 0;
+                return;
+            },
+            _ => return,
+        };
+        *value = (*value & !(0x0F << shift)) | ((nibble as u16 & 0x0F) << shift);
+    }
+
+    fn save(&self, w: &mut StateWriter) {
+        w.write_u16(self.minutes);
+        w.write_u16(self.days);
+        w.write_u16(self.alarm_minutes);
+        w.write_u16(self.alarm_days);
+        w.write_bool(self.alarm_enabled);
+    }
+
+    fn load(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.minutes = r.read_u16()? % MINUTES_PER_DAY;
+        self.days = r.read_u16()? & 0x0FFF;
+        self.alarm_minutes = r.read_u16()?;
+        self.alarm_days = r.read_u16()?;
+        self.alarm_enabled = r.read_bool()?;
+        Ok(())
+    }
+}
+
+// A cartridge mapper. The bus keeps the ROM and the cartridge RAM and hands them in; the mapper
+// holds its registers and anything else on the cartridge, such as a clock. A new mapper needs a
+// MapperKind, an implementation of this trait and a line in `new`.
+pub(crate) trait Mbc: Send {
+    fn kind(&self) -> MapperKind;
+
+    // Byte the CPU reads at 0x0000-0x7FFF; past the end of the image reads open bus
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8;
+
+    // Write to 0x0000-0x7FFF, which the mapper takes as its control registers
+    fn write_rom(&mut self, addr: u16, value: u8);
+
+    // The register a write to `addr` goes to
+    fn register(&self, _addr: u16) -> MapperRegister {
+        MapperRegister::None
+    }
+
+    // 16 KiB ROM banks shown at 0x0000 and 0x4000
+    fn rom_banks(&self) -> (usize, usize) {
+        (0, 1)
+    }
+
+    // RAM bank mapped at 0xA000, or None while a register is mapped there instead
+    fn ram_bank(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    // Whether 0xA000-0xBFFF can be written
+    fn ram_enabled(&self) -> bool {
+        true
+    }
+
+    // Read of 0xA000-0xBFFF: cartridge RAM, a register, or open bus while RAM is disabled
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        ram_byte(ram, ram_offset(addr))
+    }
+
+    // Write to 0xA000-0xBFFF. Returns whether a battery save would change, or None when there is
+    // no RAM behind the address.
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> Option<bool> {
+        store(ram, ram_offset(addr), value)
+    }
+
+    // Contents of a battery save: the cartridge RAM, followed by whatever else the cartridge keeps
+    // powered (clock counters as of RTC time `now`, stamped with the wall-clock time)
+    fn save_ram(&self, ram: &[u8], _now: Duration, _wall_time: Duration) -> Vec<u8> {
+        ram.to_vec()
+    }
+
+    // Restore what save_ram put after the RAM, at RTC time `now`. With `wall_time` a clock also
+    // catches up on the time since the save was written. Incomplete data is ignored.
+    fn load_ram_extra(&mut self, _data: &[u8], _now: Duration, _wall_time: Option<Duration>) {}
+
+    fn has_rtc(&self) -> bool {
+        false
+    }
+
+    // Bring the clock up to date with the RTC time
+    fn update_rtc(&mut self, _now: Duration) {}
+
+    fn rtc_reading(&self) -> Option<RtcReading> {
+        None
+    }
+
+    // Move the clock by `seconds` (back when negative), as far as it can count them
+    fn shift_rtc(&mut self, _seconds: i64) {}
+
+    // Keep the clock's counters but start timing from `now`, for a new time source
+    fn restart_rtc(&mut self, _now: Duration) {}
+
+    // The cartridge's rumble motor is running
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    fn save_state(&self, w: &mut StateWriter);
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError>;
+}
+
+// The mapper for `kind` in its power-on state, for a ROM of `rom_size` bytes with `cartridge`'s
+// header
+pub(crate) fn new(kind: MapperKind, cartridge: &Cartridge, rom_size: usize) -> Box<dyn Mbc> {
+    match kind {
+        MapperKind::None => Box::new(NoMbc),
+        MapperKind::Mbc1 => Box::new(Mbc1::new(rom_size)),
+        MapperKind::Mbc3 => Box::new(Mbc3::new(rom_size, cartridge.has_timer())),
+        MapperKind::Mbc5 => Box::new(Mbc5::new(rom_size, cartridge.has_rumble())),
+        MapperKind::M161 => Box::new(M161::default()),
+        MapperKind::WisdomTree => Box::new(WisdomTree::default()),
+        MapperKind::Huc3 => Box::new(Huc3::new(rom_size)),
+    }
+}
+
+fn rom_byte(rom: &[u8], offset: usize) -> u8 {
+    rom.get(offset).copied().unwrap_or(0xFF)
+}
+
+// ROM size rounded up to whole 16 KiB banks, minus one: bank numbers are masked with it
+fn rom_bank_mask(rom_size: usize) -> usize {
+    rom_size.div_ceil(ROM_BANK_SIZE).max(1).next_power_of_two() - 1
+}
+
+// Offset of an access to 0xA000-0xBFFF within the bank mapped there
+fn ram_offset(addr: u16) -> usize {
+    (addr - 0xA000) as usize
+}
+
+// RAM smaller than the range the mapper addresses repeats: a 2 KiB chip shows up four times in
+// 0xA000-0xBFFF and bank numbers wrap around the RAM size. Without RAM the area reads open bus.
+fn ram_byte(ram: &[u8], offset: usize) -> u8 {
+    ram.get(offset.checked_rem(ram.len()).unwrap_or(0)).copied().unwrap_or(0xFF)
+}
+
+// Write cartridge RAM; whether the byte changed, or None when the cartridge has no RAM
+fn store(ram: &mut [u8], offset: usize, value: u8) -> Option<bool> {
+    let index = offset.checked_rem(ram.len())?;
+    let byte = &mut ram[index];
+    let changed = *byte != value;
+    *byte = value;
+    Some(changed)
+}
+
+// No mapper: the first 32 KiB and 8 KiB of RAM
+struct NoMbc;
+
+impl Mbc for NoMbc {
+    fn kind(&self) -> MapperKind {
+        MapperKind::None
+    }
+
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        rom_byte(rom, addr as usize)
+    }
+
+    fn write_rom(&mut self, _addr: u16, _value: u8) {}
+
+    fn save_state(&self, _w: &mut StateWriter) {}
+
+    fn load_state(&mut self, _r: &mut StateReader) -> Result<(), SaveStateError> {
+        Ok(())
+    }
+}
+
+struct Mbc1 {
+    // See rom_bank_mask
+    rom_bank_mask: usize,
+    ram_enabled: bool,
+    bank1: u8, // 5 bits, never 0
+    bank2: u8, // 2 bits
+    mode: bool,
+}
+
+impl Mbc1 {
+    fn new(rom_size: usize) -> Self {
+        Mbc1 { rom_bank_mask: rom_bank_mask(rom_size), ram_enabled: false, bank1: 1, bank2: 0, mode: false }
+    }
+}
+
+impl Mbc for Mbc1 {
+    fn kind(&self) -> MapperKind {
+        MapperKind::Mbc1
+    }
+
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF if self.mode => (self.bank2 as usize) << 5,
+            0x0000..=0x3FFF => 0,
+            _ => (self.bank2 as usize) << 5 | self.bank1 as usize,
+        };
+        rom_byte(rom, (bank & self.rom_bank_mask) * ROM_BANK_SIZE + (addr as usize & (ROM_BANK_SIZE - 1)))
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.bank1 = (value & 0x1F).max(1),
+            0x4000..=0x5FFF => self.bank2 = value & 0x03,
+            _ => self.mode = value & 0x01 != 0,
+        }
+    }
+
+    fn register(&self, addr: u16) -> MapperRegister {
+        match addr {
+            0x0000..=0x1FFF => MapperRegister::RamEnable,
+            0x2000..=0x3FFF => MapperRegister::RomBankLow,
+            0x4000..=0x5FFF => MapperRegister::Bank2,
+            _ => MapperRegister::BankingMode,
+        }
+    }
+
+    fn rom_banks(&self) -> (usize, usize) {
+        let high = (self.bank2 as usize) << 5;
+        let low = if self.mode { high } else { 0 };
+        (low & self.rom_bank_mask, (high | self.bank1 as usize) & self.rom_bank_mask)
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        Some(if self.mode { self.bank2 as usize } else { 0 })
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+
+    // Disabled RAM reads as open bus and ignores writes
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match self.ram_bank() {
+            Some(bank) if self.ram_enabled => ram_byte(ram, bank * RAM_BANK_SIZE + ram_offset(addr)),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> Option<bool> {
+        match self.ram_bank() {
+            Some(bank) if self.ram_enabled => store(ram, bank * RAM_BANK_SIZE + ram_offset(addr), value),
+            _ => Some(false),
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.ram_enabled);
+        w.write_u8(self.bank1);
+        w.write_u8(self.bank2);
+        w.write_bool(self.mode);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enabled = r.read_bool()?;
+        self.bank1 = (r.read_u8()? & 0x1F).max(1);
+        self.bank2 = r.read_u8()? & 0x03;
+        self.mode = r.read_bool()?;
+        Ok(())
+    }
+}
+
+struct Mbc3 {
+    // See rom_bank_mask
+    rom_bank_mask: usize,
+    ram_enabled: bool,
+    rom_bank: u8, // 7 bits, never 0
+    // RAM bank (0x00-0x03) or clock register (0x08-0x0C) mapped at 0xA000
+    ram_select: u8,
+    // The header says TIMER; without it the clock registers read open bus
+    timer: bool,
+    clock: Mbc3Clock,
+}
+
+impl Mbc3 {
+    fn new(rom_size: usize, timer: bool) -> Self {
+        Mbc3 { rom_bank_mask: rom_bank_mask(rom_size), ram_enabled: false, rom_bank: 1, ram_select: 0, timer, clock: Mbc3Clock::default() }
+    }
+
+    fn rtc_selected(&self) -> bool {
+        matches!(self.ram_select, 0x08..=0x0C)
+    }
+}
+
+impl Mbc for Mbc3 {
+    fn kind(&self) -> MapperKind {
+        MapperKind::Mbc3
+    }
+
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize & self.rom_bank_mask,
+        };
+        rom_byte(rom, bank * ROM_BANK_SIZE + (addr as usize & (ROM_BANK_SIZE - 1)))
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = (value & 0x7F).max(1),
+            0x4000..=0x5FFF => self.ram_select = value & 0x0F,
+            _ => self.clock.write_latch(value),
+        }
+    }
+
+    fn register(&self, addr: u16) -> MapperRegister {
+        match addr {
+            0x0000..=0x1FFF => MapperRegister::RamEnable,
+            0x2000..=0x3FFF => MapperRegister::RomBank,
+            0x4000..=0x5FFF => MapperRegister::RamBankOrClock,
+            _ => MapperRegister::ClockLatch,
+        }
+    }
+
+    fn rom_banks(&self) -> (usize, usize) {
+        (0, self.rom_bank as usize & self.rom_bank_mask)
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        (!self.rtc_selected()).then_some(self.ram_select as usize & 0x03)
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+
+    // Disabled RAM and clock read as open bus and ignore writes
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match self.ram_bank() {
+            _ if !self.ram_enabled => 0xFF,
+            Some(bank) => ram_byte(ram, bank * RAM_BANK_SIZE + ram_offset(addr)),
+            None if self.timer => self.clock.latched.register(self.ram_select),
+            None => 0xFF,
+        }
+    }
+
+    // Setting the clock changes the battery save too
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> Option<bool> {
+        match self.ram_bank() {
+            _ if !self.ram_enabled => Some(false),
+            Some(bank) => store(ram, bank * RAM_BANK_SIZE + ram_offset(addr), value),
+            None if self.timer => {
+                self.clock.set_register(self.ram_select, value);
+                Some(true)
+            },
+            None => Some(false),
+        }
+    }
+
+    // The clock follows the RAM: the counters, the latched copy and the wall-clock time in
+    // seconds, all little-endian, as VBA and BGB write it
+    fn save_ram(&self, ram: &[u8], now: Duration, wall_time: Duration) -> Vec<u8> {
+        if !self.timer {
+            return ram.to_vec();
+        }
+        let mut clock = self.clock.clone();
+        clock.advance(now);
+        let mut w = StateWriter::raw();
+        w.write_bytes(ram);
+        clock.time.save(&mut w);
+        clock.latched.save(&mut w);
+        w.write_u64(wall_time.as_secs());
+        w.finish()
+    }
+
+    // Older emulators wrote the time stamp in 32 bits, making a 44-byte footer
+    fn load_ram_extra(&mut self, data: &[u8], now: Duration, wall_time: Option<Duration>) {
+        if !self.timer {
+            return;
+        }
+        let mut clock = Mbc3Clock::default();
+        let mut r = StateReader::raw(data);
+        let stamp = clock.time.load(&mut r).and_then(|()| clock.latched.load(&mut r)).and_then(|()| match data.len() {
+            48.. => r.read_u64(),
+            _ => r.read_u32().map(u64::from),
+        });
+        let Ok(stamp) = stamp else {
+            return;
+        };
+        let away = wall_time.map_or(Duration::ZERO, |wall_time| wall_time.saturating_sub(Duration::from_secs(stamp)));
+        clock.synced_at = now.saturating_sub(away);
+        clock.now = now;
+        self.clock = clock;
+    }
+
+    fn has_rtc(&self) -> bool {
+        self.timer
+    }
+
+    fn update_rtc(&mut self, now: Duration) {
+        self.clock.advance(now);
+    }
+
+    fn rtc_reading(&self) -> Option<RtcReading> {
+        let time = &self.clock.time;
+        self.timer.then_some(RtcReading { days: time.days, hours: time.hours, minutes: time.minutes, seconds: time.seconds })
+    }
+
+    fn shift_rtc(&mut self, seconds: i64) {
+        self.clock.time.shift(seconds);
+    }
+
+    fn restart_rtc(&mut self, now: Duration) {
+        self.clock.synced_at = now;
+        self.clock.now = now;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.ram_enabled);
+        w.write_u8(self.rom_bank);
+        w.write_u8(self.ram_select);
+        self.clock.time.save(w);
+        self.clock.latched.save(w);
+        w.write_bool(self.clock.latch_armed);
+        w.write_u64(self.clock.synced_at.as_nanos() as u64);
+        w.write_u64(self.clock.now.as_nanos() as u64);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enabled = r.read_bool()?;
+        self.rom_bank = (r.read_u8()? & 0x7F).max(1);
+        self.ram_select = r.read_u8()? & 0x0F;
+        self.clock.time.load(r)?;
+        self.clock.latched.load(r)?;
+        self.clock.latch_armed = r.read_bool()?;
+        self.clock.synced_at = Duration::from_nanos(r.read_u64()?);
+        self.clock.now = Duration::from_nanos(r.read_u64()?);
+        Ok(())
+    }
+}
+
+struct Mbc5 {
+    // See rom_bank_mask
+    rom_bank_mask: usize,
+    ram_enabled: bool,
+    rom_bank: u16, // 9 bits, 0 allowed
+    ram_bank: u8,
+    // The header says RUMBLE: bit 3 of the RAM bank register is the motor
+    has_rumble: bool,
+    motor: bool,
+}
+
+impl Mbc5 {
+    fn new(rom_size: usize, has_rumble: bool) -> Self {
+        Mbc5 { rom_bank_mask: rom_bank_mask(rom_size), ram_enabled: false, rom_bank: 1, ram_bank: 0, has_rumble, motor: false }
+    }
+
+    fn write_ram_bank(&mut self, value: u8) {
+        if self.has_rumble {
+            self.motor = value & 0x08 != 0;
+            self.ram_bank = value & 0x07;
+        } else {
+            self.ram_bank = value & 0x0F;
+        }
+    }
+}
+
+impl Mbc for Mbc5 {
+    fn kind(&self) -> MapperKind {
+        MapperKind::Mbc5
+    }
+
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        let bank = match addr {
+            0x0000..=0x3FFF => 0,
+            _ => self.rom_bank as usize & self.rom_bank_mask,
+        };
+        rom_byte(rom, bank * ROM_BANK_SIZE + (addr as usize & (ROM_BANK_SIZE - 1)))
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => self.rom_bank = (self.rom_bank & 0xFF) | (value as u16 & 0x01) << 8,
+            0x4000..=0x5FFF => self.write_ram_bank(value),
+            _ => {},
+        }
+    }
+
+    fn register(&self, addr: u16) -> MapperRegister {
+        match addr {
+            0x0000..=0x1FFF => MapperRegister::RamEnable,
+            0x2000..=0x2FFF => MapperRegister::RomBankLow,
+            0x3000..=0x3FFF => MapperRegister::RomBankHigh,
+            0x4000..=0x5FFF => MapperRegister::RamBank,
+            _ => MapperRegister::None,
+        }
+    }
+
+    fn rom_banks(&self) -> (usize, usize) {
+        (0, self.rom_bank as usize & self.rom_bank_mask)
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        Some(self.ram_bank as usize)
+    }
+
+    fn ram_enabled(&self) -> bool {
+        self.ram_enabled
+    }
+
+    // Disabled RAM reads as open bus and ignores writes
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+        ram_byte(ram, self.ram_bank as usize * RAM_BANK_SIZE + ram_offset(addr))
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> Option<bool> {
+        if !self.ram_enabled {
+            return Some(false);
+        }
+        store(ram, self.ram_bank as usize * RAM_BANK_SIZE + ram_offset(addr), value)
+    }
+
+    fn rumble(&self) -> bool {
+        self.motor
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.ram_enabled);
+        w.write_u16(self.rom_bank);
+        w.write_u8(self.ram_bank);
+        w.write_bool(self.motor);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.ram_enabled = r.read_bool()?;
+        self.rom_bank = r.read_u16()? & 0x1FF;
+        self.ram_bank = r.read_u8()? & 0x0F;
+        self.motor = r.read_bool()? && self.has_rumble;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct M161 {
+    // 32 KiB bank
+    rom_bank: u8,
+    // A bank has been selected since reset
+    locked: bool,
+}
+
+impl Mbc for M161 {
+    fn kind(&self) -> MapperKind {
+        MapperKind::M161
+    }
+
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        rom_byte(rom, self.rom_bank as usize * BANK_SIZE + addr as usize)
+    }
+
+    fn write_rom(&mut self, _addr: u16, value: u8) {
+        if !self.locked {
+            self.rom_bank = value & 0x07;
+            self.locked = true;
+        }
+    }
+
+    fn register(&self, _addr: u16) -> MapperRegister {
+        if self.locked { MapperRegister::None } else { MapperRegister::RomBank }
+    }
+
+    fn rom_banks(&self) -> (usize, usize) {
+        (self.rom_bank as usize * 2, self.rom_bank as usize * 2 + 1)
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.rom_bank);
+        w.write_bool(self.locked);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_bank = r.read_u8()? & 0x07;
+        self.locked = r.read_bool()?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct WisdomTree {
+    // 32 KiB bank
+    rom_bank: u8,
+}
+
+impl Mbc for WisdomTree {
+    fn kind(&self) -> MapperKind {
+        MapperKind::WisdomTree
+    }
+
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        rom_byte(rom, self.rom_bank as usize * BANK_SIZE + addr as usize)
+    }
+
+    fn write_rom(&mut self, addr: u16, _value: u8) {
+        if addr < 0x4000 {
+            self.rom_bank = addr as u8;
+        }
+    }
+
+    fn register(&self, addr: u16) -> MapperRegister {
+        if addr < 0x4000 { MapperRegister::RomBank } else { MapperRegister::None }
+    }
+
+    fn rom_banks(&self) -> (usize, usize) {
+        (self.rom_bank as usize * 2, self.rom_bank as usize * 2 + 1)
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.rom_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_bank = r.read_u8()?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct Huc3 {
+    // See rom_bank_mask
+    rom_bank_mask: usize,
+    // 16 KiB bank at 0x4000, never 0
+    rom_bank: usize,
+    mode: u8,
+    ram_bank: usize,
+    // RTC register index used by the read/write commands
+    access_index: u8,
+    // Last argument of the extended command (0x6x)
+    access_flags: u8,
+    // Result of the last read command
+    result: u8,
+    // Infrared LED, switched by writes in mode 0xE
+    ir_led: bool,
+    clock: Huc3Clock,
+}
+
+impl Huc3 {
+    fn new(rom_size: usize) -> Self {
+        Huc3 { rom_bank_mask: rom_bank_mask(rom_size), rom_bank: 1, ..Huc3::default() }
+    }
+
+    // Run a command written in mode 0xB: high nibble command, low nibble argument
+    fn command(&mut self, value: u8) {
+        let argument = value & 0x0F;
+        match value >> 4 {
+            // Read the register at the index and move on
+            0x1 => {
+                self.result = self.clock.nibble(self.access_index);
+                self.access_index = self.access_index.wrapping_add(1);
+            },
+            // Write the register at the index; 0x3 also moves on
+            0x2 | 0x3 => {
+                self.clock.set_nibble(self.access_index, argument);
+                if value >> 4 == 0x3 {
+                    self.access_index = self.access_index.wrapping_add(1);
+                }
+            },
+            0x4 => self.access_index = (self.access_index & 0xF0) | argument,
+            0x5 => self.access_index = (self.access_index & 0x0F) | (argument << 4),
+            0x6 => self.access_flags = argument,
+            // The tone generator and anything else unknown
+            _ => {},
+        }
+    }
+}
+
+impl Mbc for Huc3 {
+    fn kind(&self) -> MapperKind {
+        MapperKind::Huc3
+    }
+
+    fn read_rom(&self, rom: &[u8], addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => rom_byte(rom, addr as usize),
+            _ => rom_byte(rom, (self.rom_bank & self.rom_bank_mask) * ROM_BANK_SIZE + (addr as usize & (ROM_BANK_SIZE - 1))),
+        }
+    }
+
+    fn write_rom(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.mode = value & 0x0F,
+            0x2000..=0x3FFF => self.rom_bank = ((value & 0x7F) as usize).max(1),
+            0x4000..=0x5FFF => self.ram_bank = (value & 0x03) as usize,
+            _ => {},
+        }
+    }
+
+    fn register(&self, addr: u16) -> MapperRegister {
+        match addr {
+            0x0000..=0x1FFF => MapperRegister::Huc3Mode,
+            0x2000..=0x3FFF => MapperRegister::RomBank,
+            0x4000..=0x5FFF => MapperRegister::RamBank,
+            _ => MapperRegister::None,
+        }
+    }
+
+    fn rom_banks(&self) -> (usize, usize) {
+        (0, self.rom_bank & self.rom_bank_mask)
+    }
+
+    fn ram_bank(&self) -> Option<usize> {
+        matches!(self.mode, 0x0 | 0xA).then_some(self.ram_bank)
+    }
+
+    // Mode 0x0 maps RAM read-only
+    fn ram_enabled(&self) -> bool {
+        self.mode == 0xA
+    }
+
+    fn read_ram(&self, ram: &[u8], addr: u16) -> u8 {
+        match self.mode {
+            0x0 | 0xA => ram_byte(ram, self.ram_bank * RAM_BANK_SIZE + ram_offset(addr)),
+            0xC if self.access_flags == 0x2 => 0x01,
+            0xC => self.result,
+            // The clock is always ready
+            0xD => 0x01,
+            // Bit 0 would be set by incoming light; nothing is on the other end
+            0xE => 0xC0,
+            _ => 0xFF,
+        }
+    }
+
+    // Setting the clock changes the battery save too
+    fn write_ram(&mut self, ram: &mut [u8], addr: u16, value: u8) -> Option<bool> {
+        match self.mode {
+            0x0 => return Some(false),
+            0xA => return store(ram, self.ram_bank * RAM_BANK_SIZE + ram_offset(addr), value),
+            0xB => self.command(value),
+            0xE => self.ir_led = value & 0x01 != 0,
+            _ => {},
+        }
+        Some(true)
+    }
+
+    // The clock follows the RAM
+    fn save_ram(&self, ram: &[u8], now: Duration, wall_time: Duration) -> Vec<u8> {
+        let mut clock = self.clock.clone();
+        clock.advance(now);
+        let mut w = StateWriter::raw();
+        w.write_bytes(ram);
+        clock.save(&mut w);
+        w.write_u64(wall_time.as_secs());
+        w.finish()
+    }
+
+    fn load_ram_extra(&mut self, data: &[u8], now: Duration, wall_time: Option<Duration>) {
+        let mut clock = Huc3Clock::default();
+        let mut r = StateReader::raw(data);
+        let Ok(stamp) = clock.load(&mut r).and_then(|()| r.read_u64()) else {
+            return;
+        };
+        let away = wall_time.map_or(Duration::ZERO, |wall_time| wall_time.saturating_sub(Duration::from_secs(stamp)));
+        clock.synced_at = now.saturating_sub(away);
+        self.clock = clock;
+    }
+
+    fn has_rtc(&self) -> bool {
+        true
+    }
+
+    fn update_rtc(&mut self, now: Duration) {
+        self.clock.advance(now);
+    }
+
+    fn rtc_reading(&self) -> Option<RtcReading> {
+        Some(RtcReading {
+            days: self.clock.days,
+            hours: (self.clock.minutes / 60) as u8,
+            minutes: (self.clock.minutes % 60) as u8,
+            seconds: 0,
+        })
+    }
+
+    fn shift_rtc(&mut self, seconds: i64) {
+        self.clock.shift(seconds / 60);
+    }
+
+    fn restart_rtc(&mut self, now: Duration) {
+        self.clock.synced_at = now;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.rom_bank as u8);
+        w.write_u8(self.mode);
+        w.write_u8(self.ram_bank as u8);
+        w.write_u8(self.access_index);
+        w.write_u8(self.access_flags);
+        w.write_u8(self.result);
+        w.write_bool(self.ir_led);
+        self.clock.save(w);
+        w.write_u64(self.clock.synced_at.as_nanos() as u64);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.rom_bank = ((r.read_u8()? & 0x7F) as usize).max(1);
+        self.mode = r.read_u8()? & 0x0F;
+        self.ram_bank = (r.read_u8()? & 0x03) as usize;
+        self.access_index = r.read_u8()?;
+        self.access_flags = r.read_u8()?;
+        self.result = r.read_u8()?;
+        self.ir_led = r.read_bool()?;
+        self.clock.load(r)?;
+        self.clock.synced_at = Duration::from_nanos(r.read_u64()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `banks` 16 KiB banks, every byte holding its bank's number
+    fn numbered_rom(banks: usize) -> Vec<u8> {
+        (0..banks).flat_map(|bank| [bank as u8; ROM_BANK_SIZE]).collect()
+    }
+
+    // Bank numbers read at 0x0000 and 0x4000
+    fn mapped(mbc: &dyn Mbc, rom: &[u8]) -> (u8, u8) {
+        (mbc.read_rom(rom, 0x0000), mbc.read_rom(rom, 0x4000))
+    }
+
+    #[test]
+    fn mbc1_bank1_zero_maps_bank_1() {
+        let rom = numbered_rom(128);
+        let mut mbc = Mbc1::new(rom.len());
+        // Only the low five bits are written, and 0 in them counts as 1
+        for value in [0x00, 0x20, 0x40, 0x60] {
+            mbc.write_rom(0x2000, value);
+            assert_eq!(mapped(&mbc, &rom), (0x00, 0x01), "0x2000 = {:#04X}", value);
+        }
+        mbc.write_rom(0x2000, 0x21);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x01));
+        // With BANK2 set, 0x20, 0x40 and 0x60 can't be reached at 0x4000
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x21));
+    }
+
+    #[test]
+    fn mbc1_mode_1_maps_bank2_at_0000() {
+        let rom = numbered_rom(64);
+        let mut mbc = Mbc1::new(rom.len());
+        mbc.write_rom(0x4000, 0x01);
+        mbc.write_rom(0x2000, 0x02);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x22));
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mapped(&mbc, &rom), (0x20, 0x22));
+        assert_eq!(mbc.rom_banks(), (0x20, 0x22));
+        // A 1 MiB ROM has no bank 0x40: BANK2 = 2 wraps to the first half
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x02));
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x4000, 0x01);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x22));
+    }
+
+    #[test]
+    fn mbc1_banks_wrap_around_a_small_rom() {
+        let rom = numbered_rom(16);
+        let mut mbc = Mbc1::new(rom.len());
+        mbc.write_rom(0x2000, 0x12);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x02));
+        mbc.write_rom(0x2000, 0x10);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x00));
+        assert_eq!(mbc.rom_banks(), (0x00, 0x00));
+    }
+
+    #[test]
+    fn mbc1_ram_banks_wrap_around_a_small_ram() {
+        let mut ram = vec![0; RAM_BANK_SIZE];
+        let mut mbc = Mbc1::new(ROM_BANK_SIZE * 64);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x6000, 0x01);
+        mbc.write_rom(0x4000, 0x02);
+        assert_eq!(mbc.write_ram(&mut ram, 0xA123, 0x5A), Some(true));
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA123), 0x5A);
+        // A 2 KiB chip repeats within the bank
+        let small: Vec<u8> = (0..0x800).map(|offset| offset as u8).collect();
+        assert_eq!(mbc.read_ram(&small, 0xB805), 0x05);
+    }
+
+    // MBC3 clock register `index` as the CPU reads it
+    fn mbc3_register(mbc: &mut Mbc3, index: u8) -> u8 {
+        mbc.write_rom(0x4000, index);
+        mbc.read_ram(&[], 0xA000)
+    }
+
+    fn set_mbc3_register(mbc: &mut Mbc3, index: u8, value: u8) {
+        mbc.write_rom(0x4000, index);
+        mbc.write_ram(&mut [], 0xA000, value);
+    }
+
+    // An MBC3 with a clock, RAM enabled and the clock at day 0x12C 05:15:30
+    fn mbc3_with_time() -> Mbc3 {
+        let mut mbc = Mbc3::new(ROM_BANK_SIZE * 4, true);
+        mbc.write_rom(0x0000, 0x0A);
+        for (index, value) in [(0x08, 30), (0x09, 15), (0x0A, 5), (0x0B, 0x2C), (0x0C, 0x01)] {
+            set_mbc3_register(&mut mbc, index, value);
+        }
+        mbc
+    }
+
+    #[test]
+    fn mbc3_rtc_footer_round_trips() {
+        let ram = vec![0x42; RAM_BANK_SIZE];
+        let mbc = mbc3_with_time();
+        let save = mbc.save_ram(&ram, Duration::ZERO, Duration::from_secs(1_000_000));
+        assert_eq!(save.len(), RAM_BANK_SIZE + 48);
+        assert_eq!(&save[..RAM_BANK_SIZE], &ram[..]);
+        // Seconds of the counters, then of the latched copy, then the time stamp
+        assert_eq!(&save[RAM_BANK_SIZE..RAM_BANK_SIZE + 4], &[30, 0, 0, 0]);
+        assert_eq!(&save[RAM_BANK_SIZE + 20..RAM_BANK_SIZE + 24], &[30, 0, 0, 0]);
+        assert_eq!(&save[RAM_BANK_SIZE + 40..], &1_000_000u64.to_le_bytes());
+
+        // Loaded 90 seconds of wall-clock time later, the clock has caught up on them
+        let now = Duration::from_secs(500);
+        let mut loaded = Mbc3::new(ROM_BANK_SIZE * 4, true);
+        loaded.load_ram_extra(&save[RAM_BANK_SIZE..], now, Some(Duration::from_secs(1_000_090)));
+        loaded.update_rtc(now);
+        assert_eq!(loaded.rtc_reading(), Some(RtcReading { days: 0x12C, hours: 5, minutes: 17, seconds: 0 }));
+        // The latched copy is as saved
+        loaded.write_rom(0x0000, 0x0A);
+        assert_eq!(mbc3_register(&mut loaded, 0x08), 30);
+        assert_eq!(mbc3_register(&mut loaded, 0x0C), 0x01);
+
+        // Without a wall-clock time nothing is caught up
+        let mut loaded = Mbc3::new(ROM_BANK_SIZE * 4, true);
+        loaded.load_ram_extra(&save[RAM_BANK_SIZE..], now, None);
+        loaded.update_rtc(now);
+        assert_eq!(loaded.rtc_reading(), Some(RtcReading { days: 0x12C, hours: 5, minutes: 15, seconds: 30 }));
+    }
+
+    #[test]
+    fn mbc3_loads_the_44_byte_footer() {
+        let mut footer = Vec::new();
+        for value in [30u32, 15, 5, 0x2C, 0x01, 10, 0, 0, 0, 0, 1_000_000] {
+            footer.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(footer.len(), 44);
+        let now = Duration::from_secs(500);
+        let mut mbc = Mbc3::new(ROM_BANK_SIZE * 4, true);
+        mbc.load_ram_extra(&footer, now, Some(Duration::from_secs(1_000_060)));
+        mbc.update_rtc(now);
+        assert_eq!(mbc.rtc_reading(), Some(RtcReading { days: 0x12C, hours: 5, minutes: 16, seconds: 30 }));
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(mbc3_register(&mut mbc, 0x08), 10);
+
+        // A footer cut short is ignored
+        let mut mbc = mbc3_with_time();
+        mbc.load_ram_extra(&footer[..40], now, None);
+        assert_eq!(mbc.rtc_reading(), Some(RtcReading { days: 0x12C, hours: 5, minutes: 15, seconds: 30 }));
+    }
+
+    #[test]
+    fn mbc3_reads_the_clock_latched_by_writing_0_then_1() {
+        let mut mbc = mbc3_with_time();
+        mbc.update_rtc(Duration::from_secs(5));
+        assert_eq!(mbc.rtc_reading().map(|time| time.seconds), Some(35));
+        assert_eq!(mbc3_register(&mut mbc, 0x08), 30);
+        // 1 alone doesn't latch
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc3_register(&mut mbc, 0x08), 30);
+        mbc.write_rom(0x6000, 0x00);
+        mbc.write_rom(0x6000, 0x01);
+        assert_eq!(mbc3_register(&mut mbc, 0x08), 35);
+        // The latched copy stays put while the clock runs on
+        mbc.update_rtc(Duration::from_secs(8));
+        assert_eq!(mbc3_register(&mut mbc, 0x08), 35);
+
+        // Disabled, the clock reads open bus
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc3_register(&mut mbc, 0x08), 0xFF);
+        // So does a cartridge without one
+        let mut mbc = Mbc3::new(ROM_BANK_SIZE * 4, false);
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(mbc3_register(&mut mbc, 0x08), 0xFF);
+        assert_eq!(mbc.rtc_reading(), None);
+    }
+
+    #[test]
+    fn mbc3_halt_bit_stops_the_clock() {
+        let mut mbc = mbc3_with_time();
+        set_mbc3_register(&mut mbc, 0x0C, 0x41);
+        mbc.update_rtc(Duration::from_secs(10));
+        assert_eq!(mbc.rtc_reading().map(|time| time.seconds), Some(30));
+        assert_eq!(mbc3_register(&mut mbc, 0x0C), 0x41);
+
+        // It counts again from the moment the bit is cleared
+        set_mbc3_register(&mut mbc, 0x0C, 0x01);
+        mbc.update_rtc(Duration::from_secs(15));
+        assert_eq!(mbc.rtc_reading().map(|time| time.seconds), Some(35));
+    }
+
+    #[test]
+    fn mbc5_rom_bank_is_9_bits_and_0_is_allowed() {
+        let mut mbc = Mbc5::new(ROM_BANK_SIZE * 512, false);
+        mbc.write_rom(0x2000, 0x05);
+        mbc.write_rom(0x3000, 0x01);
+        assert_eq!(mbc.rom_banks(), (0, 0x105));
+        // Each half is written on its own; only bit 0 of the high register counts
+        mbc.write_rom(0x2000, 0xFF);
+        assert_eq!(mbc.rom_banks(), (0, 0x1FF));
+        mbc.write_rom(0x3000, 0xFE);
+        assert_eq!(mbc.rom_banks(), (0, 0x0FF));
+
+        let rom = numbered_rom(8);
+        let mut mbc = Mbc5::new(rom.len(), false);
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x00));
+        // Wrapped around the ROM size
+        mbc.write_rom(0x2000, 0x0B);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x03));
+    }
+
+    #[test]
+    fn mbc5_selects_one_of_16_ram_banks() {
+        let mut ram = vec![0; RAM_BANK_SIZE * 16];
+        let mut mbc = Mbc5::new(ROM_BANK_SIZE * 4, false);
+        assert_eq!(mbc.write_ram(&mut ram, 0xA000, 0x11), Some(false));
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0F);
+        assert_eq!(mbc.ram_bank(), Some(15));
+        assert_eq!(mbc.write_ram(&mut ram, 0xA010, 0x22), Some(true));
+        assert_eq!(ram[15 * RAM_BANK_SIZE + 0x10], 0x22);
+        mbc.write_rom(0x4000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA010), 0x00);
+        assert!(!mbc.rumble());
+    }
+
+    #[test]
+    fn mbc5_rumble_bit_drives_the_motor() {
+        let mut ram = vec![0; RAM_BANK_SIZE * 8];
+        let mut mbc = Mbc5::new(ROM_BANK_SIZE * 4, true);
+        mbc.write_rom(0x0000, 0x0A);
+        mbc.write_rom(0x4000, 0x0B);
+        assert!(mbc.rumble());
+        assert_eq!(mbc.ram_bank(), Some(3));
+        mbc.write_ram(&mut ram, 0xA000, 0x33);
+        assert_eq!(ram[3 * RAM_BANK_SIZE], 0x33);
+        mbc.write_rom(0x4000, 0x03);
+        assert!(!mbc.rumble());
+        assert_eq!(mbc.ram_bank(), Some(3));
+    }
+
+    #[test]
+    fn huc3_rom_banks_wrap_around_the_rom_size() {
+        let rom = numbered_rom(8);
+        let mut mbc = Huc3::new(rom.len());
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x01));
+        mbc.write_rom(0x2000, 0x0A);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x02));
+        assert_eq!(mbc.rom_banks(), (0x00, 0x02));
+        // 0 selects 1, before the mask
+        mbc.write_rom(0x2000, 0x00);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x01));
+        mbc.write_rom(0x2000, 0x08);
+        assert_eq!(mapped(&mbc, &rom), (0x00, 0x00));
+    }
+
+    #[test]
+    fn huc3_mode_picks_what_0xa000_shows() {
+        let mut ram = vec![0; RAM_BANK_SIZE * 4];
+        let mut mbc = Huc3::new(ROM_BANK_SIZE * 4);
+        mbc.write_rom(0x4000, 0x02);
+        // 0x0: RAM read-only
+        assert_eq!(mbc.write_ram(&mut ram, 0xA000, 0x11), Some(false));
+        assert_eq!(ram[2 * RAM_BANK_SIZE], 0x00);
+        // 0xA: RAM read/write
+        mbc.write_rom(0x0000, 0x0A);
+        assert_eq!(mbc.write_ram(&mut ram, 0xA000, 0x11), Some(true));
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x11);
+        mbc.write_rom(0x0000, 0x00);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x11);
+        assert_eq!(mbc.ram_bank(), Some(2));
+        // 0xD: the semaphore always reads ready
+        mbc.write_rom(0x0000, 0x0D);
+        assert_eq!(mbc.ram_bank(), None);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0x01);
+        // 0xE: the infrared port sees no light; writes switch the LED
+        mbc.write_rom(0x0000, 0x0E);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xC0);
+        mbc.write_ram(&mut ram, 0xA000, 0x01);
+        assert!(mbc.ir_led);
+        mbc.write_ram(&mut ram, 0xA000, 0x00);
+        assert!(!mbc.ir_led);
+        // Unknown modes read open bus and leave RAM alone
+        mbc.write_rom(0x0000, 0x05);
+        assert_eq!(mbc.read_ram(&ram, 0xA000), 0xFF);
+        mbc.write_ram(&mut ram, 0xA000, 0x22);
+        assert_eq!(ram[2 * RAM_BANK_SIZE], 0x11);
+    }
+
+    #[test]
+    fn huc3_rtc_commands_read_and_write_nibbles() {
+        let mut mbc = Huc3::new(ROM_BANK_SIZE * 4);
+        let command = |mbc: &mut Huc3, value: u8| {
+            mbc.write_rom(0x0000, 0x0B);
+            mbc.write_ram(&mut [], 0xA000, value);
+        };
+        let result = |mbc: &mut Huc3| {
+            mbc.write_rom(0x0000, 0x0C);
+            mbc.read_ram(&[], 0xA000)
+        };
+
+        // Index 0, then write minutes 0x015 a nibble at a time and day 3 at index 3
+        command(&mut mbc, 0x40);
+        command(&mut mbc, 0x50);
+        for nibble in [0x35, 0x31, 0x30] {
+            command(&mut mbc, nibble);
+        }
+        command(&mut mbc, 0x23);
+        assert_eq!(mbc.rtc_reading(), Some(RtcReading { days: 3, hours: 0, minutes: 21, seconds: 0 }));
+
+        // Read them back from index 0
+        command(&mut mbc, 0x40);
+        command(&mut mbc, 0x10);
+        assert_eq!(result(&mut mbc), 0x05);
+        command(&mut mbc, 0x10);
+        assert_eq!(result(&mut mbc), 0x01);
+        command(&mut mbc, 0x10);
+        command(&mut mbc, 0x10);
+        assert_eq!(result(&mut mbc), 0x03);
+
+        // Extended command 0x62 makes the result read 1
+        command(&mut mbc, 0x62);
+        assert_eq!(result(&mut mbc), 0x01);
+
+        // The minutes roll over into the days as RTC time passes
+        mbc.update_rtc(Duration::from_secs(60 * (24 * 60 - 21)));
+        assert_eq!(mbc.rtc_reading(), Some(RtcReading { days: 4, hours: 0, minutes: 0, seconds: 0 }));
+    }
+}
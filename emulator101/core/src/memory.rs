@@ -0,0 +1,1018 @@
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+use crate::accuracy::Accuracy;
+use crate::apu::Apu;
+use crate::cartridge::Cartridge;
+use crate::cheats::Cheats;
+use crate::interrupts::{InterruptController, InterruptType};
+use crate::link::LinkPort;
+use crate::mapper::{self, MappedBanks, MapperKind, MapperWrite, Mbc};
+use crate::timer::{DivObserver, FRAME_SEQUENCER_BIT, Timer};
+use crate::ppu::Ppu;
+use crate::model::Model;
+use crate::error::EmulatorError;
+use crate::diagnostics::{Diagnostic, DiagnosticEvent, LockedMemory};
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+// Joypad button enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoypadButton {
+    // D-pad
+    Right,
+    Left,
+    Up,
+    Down,
+    
+    // Buttons
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl JoypadButton {
+    pub const ALL: [JoypadButton; 8] = [
+        JoypadButton::Right, JoypadButton::Left, JoypadButton::Up, JoypadButton::Down,
+        JoypadButton::A, JoypadButton::B, JoypadButton::Select, JoypadButton::Start,
+    ];
+
+    // The button's bit in a `MemoryBus::buttons()` mask
+    pub fn mask(self) -> u8 {
+        match self {
+            JoypadButton::Right => 0x01,
+            JoypadButton::Left => 0x02,
+            JoypadButton::Up => 0x04,
+            JoypadButton::Down => 0x08,
+            JoypadButton::A => 0x10,
+            JoypadButton::B => 0x20,
+            JoypadButton::Select => 0x40,
+            JoypadButton::Start => 0x80,
+        }
+    }
+}
+
+impl core::str::FromStr for JoypadButton {
+    type Err = String;
+
+    // "a", "b", "select", "start", "up", "down", "left" or "right", in any case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        JoypadButton::ALL
+            .into_iter()
+            .find(|button| format!("{:?}", button).eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown button '{}' (expected a, b, select, start, up, down, left or right)", s))
+    }
+}
+
+// I/O addresses with a register behind them, counting CGB registers the emulator doesn't
+// implement yet
+fn is_io_register(addr: u16, cgb: bool) -> bool {
+    match addr {
+        0xFF00..=0xFF02 | 0xFF04..=0xFF07 | 0xFF0F => true,
+        0xFF10..=0xFF14 | 0xFF16..=0xFF1E | 0xFF20..=0xFF26 | 0xFF30..=0xFF3F => true,
+        0xFF40..=0xFF4B | 0xFF50 => true,
+        0xFF4D | 0xFF4F | 0xFF51..=0xFF56 | 0xFF68..=0xFF6C | 0xFF70 => cgb,
+        _ => false,
+    }
+}
+
+pub struct MemoryBus {
+    // Basic memory regions
+    wram: [u8; 0x2000],       // 8KB Working RAM (0xC000-0xDFFF)
+    hram: [u8; 0x7F],         // High RAM (0xFF80-0xFFFE)
+    io_registers: [u8; 0x80],  // I/O registers (0xFF00-0xFF7F)
+    ie_register: u8,           // Interrupt Enable register (0xFFFF)
+    
+    // ROM and external RAM - these would be in the cartridge
+    rom: Vec<u8>,             // ROM data
+    cartridge: Cartridge,     // The ROM's header
+    pub(crate) mapper: Box<dyn Mbc>, // ROM and RAM banking
+    eram: Vec<u8>,            // External RAM
+    eram_dirty: bool,         // Cartridge RAM or clock written since the last battery save
+    pub(crate) cheats: Cheats, // Game Genie codes patching ROM reads
+    
+    // Interrupt controller
+    int_ctrl: InterruptController,
+
+    // Timer component
+    timer: Timer,
+    // Falling edges of DIV bit 4, which clock the APU's frame sequencer
+    frame_sequencer: DivObserver,
+
+    // APU component
+    pub apu: Apu,
+
+    // PPU component
+    pub ppu: Ppu,
+
+    // Joypad state
+    joypad_select: u8,  // Joypad selection (buttons or d-pad)
+    joypad_buttons: u8, // State of buttons (A, B, Select, Start)
+    joypad_dpad: u8,    // State of D-pad (Right, Left, Up, Down)
+    last_joypad_state: u8, // P1 as of the last line check, for falling-edge detection
+    joypad_debounce_counter: u8,
+    joypad_debounce_delay: u8,
+    
+    // Serial output for tests
+    serial_data: u8,           // SB register (0xFF01)
+    serial_control: u8,        // SC register (0xFF02)
+    serial_transfer_active: bool,
+    serial_bit_counter: u8,
+    serial_clock_counter: u16,
+    serial_out: Option<u8>,    // Byte shifted out by the last internal-clock transfer start
+    serial_in: u8,             // Byte being shifted in by an internal-clock transfer
+    // Link cable, if one is plugged in; a setting rather than machine state, like cheats
+    pub(crate) link: Option<Box<dyn LinkPort>>,
+    // Also a setting; not part of savestates
+    accuracy: Accuracy,
+
+    // Optional boot ROM, overlaid on the cartridge until the game writes to 0xFF50
+    boot_rom: Option<Vec<u8>>,
+    boot_rom_mapped: bool,
+
+    // STOP mode (see Cpu::stop): DIV and the timer don't count. The PPU keeps going; games turn
+    // the LCD off first, as on hardware it misbehaves.
+    stopped: bool,
+
+    // Errors raised since the emulator last collected them
+    errors: Vec<EmulatorError>,
+    // Diagnostics raised since then; the emulator fills in the PC
+    diagnostics: Vec<DiagnosticEvent>,
+    // Mapper register writes since then, also waiting for their PC. Only recorded while
+    // something listens for them (Emulator::on_mapper_write).
+    mapper_writes: Vec<MapperWrite>,
+    pub(crate) record_mapper_writes: bool,
+    // Echo RAM address read during the current step. Reads can't raise diagnostics themselves,
+    // so take_diagnostics turns this into one.
+    echo_read: Cell<Option<u16>>,
+}
+
+impl MemoryBus {
+    pub fn new(rom: Vec<u8>, model: Model) -> Self {
+        let cartridge = Cartridge::parse(&rom);
+        let mapper = mapper::new(MapperKind::detect(&rom), &cartridge, rom.len());
+        let eram = vec![0; cartridge.ram_size]; // As much external RAM as the header declares
+        let mut timer = Timer::new(model);
+        let frame_sequencer = timer.add_div_observer(FRAME_SEQUENCER_BIT);
+        let mut mmu = Self {
+            wram: [0; 0x2000],
+            hram: [0; 0x7F],
+            io_registers: [0; 0x80],
+            ie_register: 0,
+            rom,
+            cartridge,
+            mapper,
+            eram,
+            eram_dirty: false,
+            cheats: Cheats::default(),
+            int_ctrl: InterruptController::new(),
+            timer,
+            frame_sequencer,
+            apu: Apu::new(model),
+            ppu: Ppu::new(model),
+            joypad_select: 0xCF, // Both button and direction selected (P14 and P15 high)
+            joypad_buttons: 0x0F, // All buttons released
+            joypad_dpad: 0x0F,    // All d-pad released
+            last_joypad_state: 0xCF,
+            joypad_debounce_counter: 0,
+            joypad_debounce_delay: 1,
+            serial_data: 0,
+            serial_control: 0x7E,
+            serial_transfer_active: false,
+            serial_bit_counter: 0,
+            serial_clock_counter: 0,
+            serial_out: None,
+            serial_in: 0xFF,
+            link: None,
+            accuracy: Accuracy::default(),
+            boot_rom: None,
+            boot_rom_mapped: false,
+            stopped: false,
+            errors: Vec::new(),
+            diagnostics: Vec::new(),
+            mapper_writes: Vec::new(),
+            record_mapper_writes: false,
+            echo_read: Cell::new(None),
+        };
+        mmu.io_registers[0x0F] = 0xE1; // Set if register to post boot value
+        mmu
+    }
+
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.accuracy = accuracy;
+        self.ppu.set_accuracy(accuracy);
+    }
+
+    pub fn timer(&self) -> &Timer {
+        &self.timer
+    }
+
+    // ROM contents, used to tie savestates to the game they were made with
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    pub fn cartridge(&self) -> &Cartridge {
+        &self.cartridge
+    }
+
+    pub fn mapper(&self) -> MapperKind {
+        self.mapper.kind()
+    }
+
+    // Replace the detected mapper; only meant for a freshly powered-on bus
+    pub(crate) fn set_mapper(&mut self, kind: MapperKind) {
+        self.mapper = mapper::new(kind, &self.cartridge, self.rom.len());
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.cartridge.has_battery()
+    }
+
+    // External (cartridge) RAM, what a battery save holds besides any clock data
+    pub fn cart_ram(&self) -> &[u8] {
+        &self.eram
+    }
+
+    // Restore cartridge RAM from a battery save; extra bytes are ignored and missing ones left as
+    // they are
+    pub fn load_cart_ram(&mut self, data: &[u8]) {
+        let len = data.len().min(self.eram.len());
+        self.eram[..len].copy_from_slice(&data[..len]);
+    }
+
+    // Overwrite cartridge RAM starting at `offset` (debugger edits, imported banks); bytes past the
+    // end are dropped
+    pub fn write_cart_ram(&mut self, offset: usize, data: &[u8]) {
+        let end = offset.saturating_add(data.len()).min(self.eram.len());
+        if offset < end {
+            self.eram[offset..end].copy_from_slice(&data[..end - offset]);
+            self.eram_dirty = true;
+        }
+    }
+
+    // Whether the cartridge RAM or clock changed since mark_cart_ram_saved, so a battery save
+    // would differ from the last one written
+    pub fn cart_ram_dirty(&self) -> bool {
+        self.eram_dirty
+    }
+
+    pub fn mark_cart_ram_saved(&mut self) {
+        self.eram_dirty = false;
+    }
+
+    // RAM bank mapped at 0xA000, or None while the mapper shows a register there
+    pub fn cart_ram_bank(&self) -> Option<usize> {
+        self.mapper.ram_bank()
+    }
+
+    pub fn mapped_banks(&self) -> MappedBanks {
+        let (rom0, romx) = self.mapper.rom_banks();
+        MappedBanks { rom0, romx, ram: self.mapper.ram_bank(), ram_enabled: self.mapper.ram_enabled() }
+    }
+
+    // Start from power-on instead of the post-boot state: map the boot ROM over the cartridge
+    // and put the hardware in the state the boot ROM expects
+    pub fn map_boot_rom(&mut self, boot_rom: Vec<u8>) {
+        self.boot_rom = Some(boot_rom);
+        self.boot_rom_mapped = true;
+        self.timer.set_div(0);
+        self.ppu.write_register(0xFF40, 0x00);
+        self.apu = Apu::powered_off(self.ppu.model());
+        self.io_registers[0x0F] = 0xE0;
+    }
+
+    // DMG boot ROMs cover 0x0000-0x00FF; CGB ones also 0x0200-0x08FF (0x0100-0x01FF stays the header)
+    fn boot_rom_byte(&self, addr: u16) -> Option<u8> {
+        if !self.boot_rom_mapped || (0x0100..0x0200).contains(&addr) {
+            return None;
+        }
+        self.boot_rom.as_ref()?.get(addr as usize).copied()
+    }
+
+    // Move the ROM out (leaving the bus empty) so it can be handed to a freshly built bus
+    pub(crate) fn take_rom(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.rom)
+    }
+
+    pub(crate) fn set_rom(&mut self, rom: Vec<u8>) {
+        self.rom = rom;
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.wram);
+        w.write_bytes(&self.hram);
+        w.write_bytes(&self.io_registers);
+        w.write_u8(self.ie_register);
+        w.write_u32(self.eram.len() as u32);
+        w.write_bytes(&self.eram);
+        w.write_u8(self.mapper.kind() as u8);
+        self.mapper.save_state(w);
+
+        w.write_u8(self.joypad_select);
+        w.write_u8(self.joypad_buttons);
+        w.write_u8(self.joypad_dpad);
+        w.write_u8(self.last_joypad_state);
+        w.write_u8(self.joypad_debounce_counter);
+
+        w.write_u8(self.serial_data);
+        w.write_u8(self.serial_control);
+        w.write_bool(self.serial_transfer_active);
+        w.write_u8(self.serial_bit_counter);
+        w.write_u16(self.serial_clock_counter);
+        w.write_u8(self.serial_in);
+
+        w.write_bool(self.boot_rom_mapped);
+        w.write_bool(self.stopped);
+
+        self.timer.save_state(w);
+        self.ppu.save_state(w);
+        self.apu.save_state(w);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        r.read_into(&mut self.wram)?;
+        r.read_into(&mut self.hram)?;
+        r.read_into(&mut self.io_registers)?;
+        self.ie_register = r.read_u8()?;
+        let eram_len = r.read_u32()? as usize;
+        if eram_len != self.eram.len() {
+            return Err(SaveStateError::InvalidValue("external RAM size"));
+        }
+        r.read_into(&mut self.eram)?;
+        self.eram_dirty = true;
+        if r.read_u8()? != self.mapper.kind() as u8 {
+            return Err(SaveStateError::InvalidValue("mapper"));
+        }
+        self.mapper.load_state(r)?;
+
+        self.joypad_select = r.read_u8()?;
+        self.joypad_buttons = r.read_u8()?;
+        self.joypad_dpad = r.read_u8()?;
+        self.last_joypad_state = r.read_u8()?;
+        self.joypad_debounce_counter = r.read_u8()?;
+
+        self.serial_data = r.read_u8()?;
+        self.serial_control = r.read_u8()?;
+        self.serial_transfer_active = r.read_bool()?;
+        self.serial_bit_counter = r.read_u8()?;
+        self.serial_clock_counter = r.read_u16()?;
+        self.serial_in = r.read_u8()?;
+
+        // Only meaningful if this emulator has a boot ROM as well
+        self.boot_rom_mapped = r.read_bool()? && self.boot_rom.is_some();
+        self.stopped = r.read_bool()?;
+
+        self.timer.load_state(r)?;
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)
+    }
+
+    // Write every frozen value, e.g. after RAM was reset or loaded from a savestate
+    pub(crate) fn apply_freezes(&mut self) {
+        for freeze in self.cheats.freezes().to_vec() {
+            self.write_byte(freeze.address, freeze.value);
+        }
+    }
+
+    // Advance every component by the given number of T-cycles.
+    // Rather than stepping each component on every T-cycle, jump straight to the next cycle on
+    // which one of them has something to do (a TIMA edge, a serial bit, a DMA byte). Cycles in
+    // between only move counters, so the result is the same as stepping one cycle at a time, in
+    // the same component order. The PPU goes further and only catches up when it reaches a mode
+    // change or something writes to it (see Ppu::add_dots). Accuracy::per_cycle_ticks turns the
+    // jumping off, as a reference to check it against.
+    pub fn tick(&mut self, cycles: u8) {
+        let mut remaining = cycles as u32;
+        while remaining > 0 {
+            let step = if self.accuracy.per_cycle_ticks { 1 } else { remaining.min(self.cycles_until_event()) };
+
+            // Update timer
+            if !self.stopped && self.timer.advance(step) {
+                self.request_interrupt(InterruptType::Timer);
+            }
+
+            // Update APU
+            self.apu.advance(step);
+            for _ in 0..self.timer.take_div_edges(self.frame_sequencer) {
+                self.apu.clock_frame_sequencer();
+            }
+
+            // Update PPU
+            if let Some(interrupt) = self.ppu.add_dots(step) {
+                self.request_interrupt(interrupt);
+            }
+
+            // Update serial
+            if self.advance_serial(step) {
+                self.request_interrupt(InterruptType::Serial);
+            }
+
+            // Update joypad
+            self.joypad_debounce_counter = self.joypad_debounce_counter.saturating_sub(step.min(0xFF) as u8);
+
+            // Process DMA transfers (one byte per M-cycle; steps are single cycles while it runs)
+            if self.ppu.oam_dma_busy() {
+                self.process_dma_cycle();
+            }
+
+            remaining -= step;
+        }
+
+        self.poll_link();
+    }
+
+    // Finish a transfer waiting on the external clock once the other end of the cable has
+    // clocked a byte in
+    fn poll_link(&mut self) {
+        if !self.serial_transfer_active || self.serial_control & 0x01 != 0 {
+            return;
+        }
+        if let Some(byte) = self.link.as_mut().and_then(|link| link.receive()) {
+            self.serial_data = byte;
+            self.serial_transfer_active = false;
+            self.serial_bit_counter = 0;
+            self.serial_control &= 0x7F;
+            self.request_interrupt(InterruptType::Serial);
+        }
+    }
+
+    // The next cycle any component has work on (at least 1)
+    fn cycles_until_event(&self) -> u32 {
+        if self.ppu.oam_dma_busy() {
+            return 1;
+        }
+        // The PPU stops for its own events inside add_dots
+        self.timer.cycles_until_event().min(self.serial_cycles_until_event())
+    }
+
+    fn serial_cycles_until_event(&self) -> u32 {
+        if !self.serial_transfer_active || self.serial_control & 0x01 == 0 {
+            return u32::MAX;
+        }
+        512u32.saturating_sub(self.serial_clock_counter as u32).max(1)
+    }
+
+    // Same as `cycles` calls to update_serial_cycle, up to the next bit
+    fn advance_serial(&mut self, cycles: u32) -> bool {
+        if cycles < self.serial_cycles_until_event() {
+            if self.serial_transfer_active && self.serial_control & 0x01 != 0 {
+                self.serial_clock_counter += cycles as u16;
+            }
+            false
+        } else {
+            self.serial_clock_counter += (cycles - 1) as u16;
+            self.update_serial_cycle()
+        }
+    }
+
+    // Called by the CPU before a 16-bit register inc/dec; the DMG's increment unit drives the
+    // address bus, so pointing into OAM can trigger the OAM corruption bug
+    pub fn oam_bug_idu(&mut self, addr: u16) {
+        if (0xFE00..=0xFEFF).contains(&addr) {
+            self.ppu.oam_bug_write();
+        }
+    }
+
+    // Record an error for the emulator to handle according to its ErrorPolicy
+    pub fn report(&mut self, error: EmulatorError) {
+        self.errors.push(error);
+        self.diagnose(Diagnostic::Error(error));
+    }
+
+    pub fn take_errors(&mut self) -> Vec<EmulatorError> {
+        core::mem::take(&mut self.errors)
+    }
+
+    pub(crate) fn diagnose(&mut self, diagnostic: Diagnostic) {
+        debug!(%diagnostic, ly = self.ppu.ly, "diagnostic");
+        self.diagnostics.push(DiagnosticEvent { diagnostic, pc: 0, ly: self.ppu.ly });
+    }
+
+    fn diagnose_locked_write(&mut self, memory: LockedMemory, addr: u16, value: u8) {
+        let mode = self.ppu.mode();
+        self.diagnose(Diagnostic::LockedWrite { memory, addr, value, mode });
+    }
+
+    // Mapper writes since the last call; the emulator fills in the PC
+    pub(crate) fn drain_mapper_writes(&mut self) -> impl Iterator<Item = MapperWrite> + '_ {
+        self.mapper_writes.drain(..)
+    }
+
+    pub(crate) fn take_diagnostics(&mut self) -> Vec<DiagnosticEvent> {
+        if let Some(addr) = self.echo_read.take() {
+            self.diagnose(Diagnostic::EchoRamAccess { addr, write: false });
+        }
+        core::mem::take(&mut self.diagnostics)
+    }
+
+    // Forget echo RAM reads made outside the CPU (debuggers, scripts) before the next step
+    pub(crate) fn clear_echo_read(&self) {
+        self.echo_read.set(None);
+    }
+
+    // Byte sent by a transfer started since the last call (what test ROMs print to)
+    pub fn take_serial_byte(&mut self) -> Option<u8> {
+        self.serial_out.take()
+    }
+
+    // Update timer for a single cycle
+    pub fn update_timer_cycle(&mut self) -> bool {
+        self.timer.update_cycle()
+    }
+    
+    // Update PPU for a single cycle
+    pub fn update_ppu_cycle(&mut self) -> Option<InterruptType> {
+        self.ppu.update_cycle()
+    }
+    
+    // Update serial for a single cycle
+    pub fn update_serial_cycle(&mut self) -> bool {
+        // Skip if transfer not active
+        if !self.serial_transfer_active {
+            return false;
+        }
+        
+        // Only handle internal clock (bit 0 of SC set)
+        if self.serial_control & 0x01 != 0 {
+            // Update clock counter
+            self.serial_clock_counter = self.serial_clock_counter.wrapping_add(1);
+            
+            // Each bit takes 512 T-cycles at normal speed
+            if self.serial_clock_counter == 512 {
+                self.serial_clock_counter -= 512;
+                
+                // Shift out a bit, shifting in the other side's (1s with no cable connected)
+                let bit = (self.serial_in >> (7 - self.serial_bit_counter)) & 0x01;
+                self.serial_bit_counter += 1;
+                self.serial_data = (self.serial_data << 1) | bit;
+                
+                // After 8 bits, transfer is complete
+                if self.serial_bit_counter == 8 {
+                    // Reset transfer
+                    self.serial_transfer_active = false;
+                    self.serial_bit_counter = 0;
+                    
+                    // Clear transfer bit (7) in SC
+                    self.serial_control &= 0x7F;
+                    
+                    // Request serial interrupt
+                    return true;
+                }
+            }
+        }
+        
+        false
+    }
+    
+    // Update joypad for a single cycle
+    pub fn update_joypad_cycle(&mut self) -> bool {
+        if self.joypad_debounce_counter > 0 {
+            self.joypad_debounce_counter -= 1;
+        }
+        
+        // The lines only move on button changes and select writes, which check them right
+        // away, so this finds an edge only if something changed the state behind our back
+        self.joypad_line_fell()
+    }
+
+    // P10-P13 as the CPU sees them, 0 = pressed. Each selected group pulls the lines of its
+    // pressed buttons low, so with both selected a line reads low if either button is held.
+    fn joypad_lines(&self) -> u8 {
+        let mut lines = 0x0F;
+        if self.joypad_select & 0x20 == 0 {
+            lines &= self.joypad_buttons;
+        }
+        if self.joypad_select & 0x10 == 0 {
+            lines &= self.joypad_dpad;
+        }
+        lines
+    }
+
+    // Some button in a selected group is held, pulling an input line low
+    pub fn joypad_held(&self) -> bool {
+        self.joypad_lines() != 0x0F
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    pub(crate) fn set_stopped(&mut self, stopped: bool) {
+        self.stopped = stopped;
+    }
+
+    pub(crate) fn reset_div(&mut self) {
+        self.timer.set_div(0);
+    }
+
+    // KEY1 bit 0: the game asked for a speed switch on the next STOP (CGB only)
+    pub fn speed_switch_prepared(&self) -> bool {
+        self.ppu.model().is_cgb() && self.io_registers[0x4D] & 0x01 != 0
+    }
+
+    // Flip KEY1's current-speed bit and clear the request. Only the flag changes: double speed
+    // itself isn't emulated, so the CPU keeps running at normal speed.
+    pub(crate) fn switch_speed(&mut self) {
+        self.io_registers[0x4D] = (self.io_registers[0x4D] ^ 0x80) & 0x80;
+    }
+
+    // The joypad interrupt fires when an input line goes from high to low, whether that is a
+    // button being pressed in a selected group or a group with a held button being selected
+    fn joypad_line_fell(&mut self) -> bool {
+        let lines = self.joypad_lines();
+        let fell = self.last_joypad_state & !lines & 0x0F != 0;
+        self.last_joypad_state = 0xC0 | (self.joypad_select & 0x30) | lines;
+        fell
+    }
+    
+    // Process one DMA cycle
+    pub fn process_dma_cycle(&mut self) {
+        if !self.ppu.dma_cycle() {
+            return;
+        }
+        
+        // Get current byte position
+        let byte_pos = self.ppu.get_dma_byte();
+        
+        // Calculate actual memory address
+        let addr = self.ppu.get_dma_source() + (byte_pos as u16);
+        
+        // Read the byte from memory
+        let value = self.read_byte(addr);
+        
+        // Process the DMA byte (write to OAM)
+        self.ppu.process_dma_byte(value);
+    }
+
+    pub fn read_byte(&self, addr: u16) -> u8 {
+        if let Some(value) = self.boot_rom_byte(addr) {
+            return value;
+        }
+
+        match addr {
+            // ROM (0x0000-0x7FFF), banked by the mapper; past the end of the image reads open bus
+            0x0000..=0x7FFF => self.cheats.patch_rom(addr, self.mapper.read_rom(&self.rom, addr)),
+            // VRAM (0x8000-0x9FFF)
+            0x8000..=0x9FFF => self.ppu.read_vram(addr),
+            // External RAM (0xA000-0xBFFF), or a mapper register
+            0xA000..=0xBFFF => self.mapper.read_ram(&self.eram, addr),
+            // Working RAM (0xC000-0xDFFF)
+            0xC000..=0xDFFF => self.wram[(addr - 0xC000) as usize],
+            
+            // Echo RAM (0xE000-0xFDFF): mirrors 0xC000-0xDDFF on every model, but Nintendo
+            // forbids using it
+            0xE000..=0xFDFF => {
+                self.echo_read.set(Some(addr));
+                self.wram[(addr - 0xE000) as usize]
+            },
+
+            // OAM (0xFE00-0xFE9F)
+            0xFE00..=0xFE9F => self.ppu.read_oam(addr),
+            
+            // I/O Registers (0xFF00-0xFF7F)
+            0xFF00..=0xFF7F => self.read_io(addr),
+            
+            // High RAM (0xFF80-0xFFFE)
+            0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize],
+            
+            // Interrupt Enable
+            0xFFFF => self.get_ie(),
+
+            // Unusable region (0xFEA0-0xFEFF): open bus while the PPU has OAM locked, otherwise
+            // 0x00 on DMG-family models and the high nibble of the low address byte twice on
+            // CGB (revision E) and AGB
+            0xFEA0..=0xFEFF if self.accuracy.strict_echo_ram => {
+                let model = self.ppu.model();
+                if self.ppu.oam_locked() {
+                    0xFF
+                } else if model.is_cgb() {
+                    let nibble = (addr as u8) >> 4;
+                    nibble << 4 | nibble
+                } else {
+                    0x00
+                }
+            },
+            
+            // Unused memory regions
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write_byte(&mut self, addr: u16, value: u8) {
+        let value = self.cheats.frozen_value(addr, value);
+        match addr {
+            // ROM area: mapper control registers
+            0x0000..=0x7FFF => {
+                if self.mapper.kind() == MapperKind::None && matches!(self.rom.get(0x0147), Some(0x00 | 0x08 | 0x09)) {
+                    self.diagnose(Diagnostic::RomWriteWithoutMbc { addr, value });
+                }
+                trace!(addr, value, "mapper write");
+                let register = self.mapper.register(addr);
+                self.mapper.write_rom(addr, value);
+                if self.record_mapper_writes {
+                    self.mapper_writes.push(MapperWrite { pc: 0, addr, value, register, banks: self.mapped_banks() });
+                }
+            },
+
+            // VRAM (0x8000-0x9FFF)
+            0x8000..=0x9FFF => {
+                // Only reported when the PPU really drops it (see Ppu::write_vram)
+                if self.accuracy.memory_locking && self.ppu.vram_locked() {
+                    self.diagnose_locked_write(LockedMemory::Vram, addr, value);
+                }
+                self.ppu.write_vram(addr, value);
+            },
+
+            // External RAM
+            0xA000..=0xBFFF => match self.mapper.write_ram(&mut self.eram, addr, value) {
+                Some(changed) => self.eram_dirty |= changed,
+                None => self.report(EmulatorError::MissingCartRam { addr }),
+            },
+            
+            // Working RAM
+            0xC000..=0xDFFF => self.wram[(addr - 0xC000) as usize] = value,
+            
+            // Echo RAM
+            0xE000..=0xFDFF => {
+                self.diagnose(Diagnostic::EchoRamAccess { addr, write: true });
+                self.wram[(addr - 0xE000) as usize] = value;
+            },
+
+            // OAM (0xFE00-0xFE9F)
+            0xFE00..=0xFE9F => {
+                if self.accuracy.memory_locking && self.ppu.oam_locked() {
+                    self.diagnose_locked_write(LockedMemory::Oam, addr, value);
+                }
+                self.ppu.write_oam(addr, value);
+            },
+            
+            // I/O Registers
+            0xFF00..=0xFF7F => self.write_io(addr, value),
+            
+            // High RAM
+            0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize] = value,
+            
+            // Interrupt Enable
+            0xFFFF => self.set_ie(value),
+            
+            // Unusable region (0xFEA0-0xFEFF)
+            _ => self.report(EmulatorError::UnusableWrite { addr, value }),
+        }
+    }
+
+    fn read_io(&self, addr: u16) -> u8 {
+        match addr {
+            // Joypad
+            0xFF00 => 0xC0 | (self.joypad_select & 0x30) | self.joypad_lines(),
+            // Serial Transfer Data
+            0xFF01 => self.serial_data,
+            
+            // Serial Transfer Control
+            0xFF02 => self.serial_control,
+
+            // Timer registers
+            0xFF04 => self.timer.get_div(),
+            0xFF05 => self.timer.get_tima(),
+            0xFF06 => self.timer.get_tma(),
+            0xFF07 => self.timer.get_tac(),
+
+            // APU registers and wave RAM
+            0xFF10..=0xFF3F => self.apu.read_register(addr),
+            
+            // Interrupt Flag (0xFF0F)
+            0xFF0F => self.get_if(),
+
+            // PPU registers
+            0xFF40..=0xFF4B => self.ppu.read_register(addr),
+
+            // KEY1: current speed (bit 7) and speed switch request (bit 0)
+            0xFF4D if self.ppu.model().is_cgb() => 0x7E | self.io_registers[0x4D],
+            
+            // Other I/O registers
+            _ => self.io_registers[(addr - 0xFF00) as usize],
+        }
+    }
+
+    fn write_io(&mut self, addr: u16, value: u8) {
+        trace!(addr, value, "io write");
+        if !is_io_register(addr, self.ppu.model().is_cgb()) {
+            self.diagnose(Diagnostic::UnknownIoWrite { addr, value });
+        } else if addr == 0xFF46 && value >= 0xE0 {
+            self.diagnose(Diagnostic::OddDmaSource { source: (value as u16) << 8 });
+        }
+        match addr {
+            // Joypad
+            0xFF00 => {
+                // Only bits 4-5 are writable (selection bits)
+                self.joypad_select = 0xC0 | (value & 0x30) | (self.joypad_select & 0xF); // bit 7 and 6 unused and always 1
+                if self.joypad_line_fell() {
+                    self.request_interrupt(InterruptType::Joypad);
+                }
+            },
+            // Serial Transfer Data
+            0xFF01 => {
+                self.serial_data = value;
+                // A transfer waiting on the other side's clock sends whatever SB holds by then
+                if self.serial_transfer_active && self.serial_control & 0x01 == 0
+                    && let Some(link) = &mut self.link
+                {
+                    link.offer(value);
+                }
+            },
+            
+            // Serial Transfer Control
+            0xFF02 => {
+                self.serial_control = value & 0x83; // Only bits 0, 1, and 7 are writable
+                
+                // Check if transfer start requested (bit 7 changed from 0 to 1)
+                if self.serial_control & 0x80 != 0 {
+                    // Start a new transfer
+                    self.serial_transfer_active = true;
+                    self.serial_bit_counter = 0;
+                    self.serial_clock_counter = 0;
+
+                    if self.serial_control & 0x01 != 0 {
+                        self.serial_out = Some(self.serial_data);
+                        self.serial_in = self.link.as_mut().map_or(0xFF, |link| link.exchange(self.serial_data));
+                    } else if let Some(link) = &mut self.link {
+                        link.offer(self.serial_data);
+                    }
+                }
+            },
+
+            // Timer registers
+            0xFF04 => self.timer.set_div(value),
+            0xFF05 => self.timer.set_tima(value),
+            0xFF06 => self.timer.set_tma(value),
+            0xFF07 => self.timer.set_tac(value),
+
+            // APU registers and wave RAM
+            0xFF10..=0xFF3F => self.apu.write_register(addr, value),
+            
+            // Interrupt Flag (0xFF0F)
+            0xFF0F => self.set_if(value), // Only bits 0-4 are used
+
+            // Boot ROM disable (can't be re-enabled)
+            0xFF50 => {
+                if value != 0 {
+                    self.boot_rom_mapped = false;
+                }
+            },
+
+            // PPU registers
+            0xFF41 if self.ppu.stat_write_triggers_interrupt() => {
+                self.ppu.write_register(addr, value);
+                self.request_interrupt(InterruptType::LcdStat);
+            },
+            0xFF40..=0xFF4B => self.ppu.write_register(addr, value),
+
+            // KEY1: only the request bit is writable
+            0xFF4D if self.ppu.model().is_cgb() => {
+                self.io_registers[0x4D] = (self.io_registers[0x4D] & 0x80) | (value & 0x01);
+            },
+            
+            // Other I/O registers
+            _ => self.io_registers[(addr - 0xFF00) as usize] = value,
+        }
+    }
+
+    // Methods for interrupt handling
+    pub fn request_interrupt(&mut self, interrupt: InterruptType) {
+        self.int_ctrl.request_interrupt(&mut self.io_registers[0x0F], interrupt);
+    }
+
+    pub fn clear_interrupt(&mut self, interrupt: InterruptType) {
+        self.int_ctrl.clear_interrupt(&mut self.io_registers[0x0F], interrupt);
+    }
+
+    /*
+    The key insight is that on the original Game Boy hardware,
+    the unused bits (5-7) of the IE register at address 0xFFFF always read as "1".
+    */
+
+    pub fn set_if(&mut self, value: u8) {
+        self.io_registers[0x0F] = (value & 0x1F) | 0xE0; // Only bits 0-4 are writable, bits 5-7 always 1
+    }
+
+    pub fn set_ie(&mut self, value: u8) {
+        self.ie_register = (value & 0x1F) | 0xE0; // Only bits 0-4 are writable, bits 5-7 always 1
+    }
+
+    pub fn get_ie(&self) -> u8 {
+        self.ie_register | 0xE0  // Ensure bits 5-7 always read as 1
+    }
+    
+    pub fn get_if(&self) -> u8 {
+        self.io_registers[0x0F]
+    }
+
+    // Held buttons as a bitmask, one bit per JoypadButton in declaration order (Right = bit 0)
+    pub fn buttons(&self) -> u8 {
+        (!self.joypad_dpad & 0x0F) | (!self.joypad_buttons & 0x0F) << 4
+    }
+
+    // Set every button at once from a `buttons()` bitmask. Unlike set_button there is no
+    // debouncing, so a recorded input always replays exactly.
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.joypad_dpad = !buttons & 0x0F;
+        self.joypad_buttons = !buttons >> 4;
+        if self.joypad_line_fell() {
+            self.request_interrupt(InterruptType::Joypad);
+        }
+    }
+
+    // Update the state of a single button (pressed = true means held down)
+    pub fn set_button(&mut self, button: JoypadButton, pressed: bool) {
+        // Skip rapid repeat inputs via debouncing for press events (not release)
+        if pressed && self.joypad_debounce_counter > 0 {
+            return;
+        }
+
+        if pressed {
+            self.press_button(button);
+            self.joypad_debounce_counter = self.joypad_debounce_delay;
+        } else {
+            self.release_button(button);
+        }
+    }
+
+    // Press a button (set bit to 0)
+    fn press_button(&mut self, button: JoypadButton) {
+        match button {
+            // D-pad
+            JoypadButton::Right => self.joypad_dpad &= !0x01,
+            JoypadButton::Left => self.joypad_dpad &= !0x02,
+            JoypadButton::Up => self.joypad_dpad &= !0x04,
+            JoypadButton::Down => self.joypad_dpad &= !0x08,
+            
+            // Buttons
+            JoypadButton::A => self.joypad_buttons &= !0x01,
+            JoypadButton::B => self.joypad_buttons &= !0x02,
+            JoypadButton::Select => self.joypad_buttons &= !0x04,
+            JoypadButton::Start => self.joypad_buttons &= !0x08,
+        }
+        
+        // Only a button in a selected group pulls its line low
+        if self.joypad_line_fell() {
+            self.request_interrupt(InterruptType::Joypad);
+        }
+    }
+    
+    // Release a button (set bit to 1)
+    fn release_button(&mut self, button: JoypadButton) {
+        match button {
+            // D-pad
+            JoypadButton::Right => self.joypad_dpad |= 0x01,
+            JoypadButton::Left => self.joypad_dpad |= 0x02,
+            JoypadButton::Up => self.joypad_dpad |= 0x04,
+            JoypadButton::Down => self.joypad_dpad |= 0x08,
+            
+            // Buttons
+            JoypadButton::A => self.joypad_buttons |= 0x01,
+            JoypadButton::B => self.joypad_buttons |= 0x02,
+            JoypadButton::Select => self.joypad_buttons |= 0x04,
+            JoypadButton::Start => self.joypad_buttons |= 0x08,
+        }
+        self.joypad_line_fell();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::accuracy::AccuracyProfile;
+
+    // A bus with the LCD on, stepped into mode 3 of the first line
+    fn bus_in_mode_3(profile: AccuracyProfile) -> MemoryBus {
+        let mut bus = MemoryBus::new(vec![0; 0x8000], Model::Dmg);
+        bus.set_accuracy(profile.settings());
+        bus.write_byte(0xFF40, 0x91);
+        while !(bus.ppu.vram_locked() && bus.ppu.oam_locked()) {
+            bus.tick(4);
+        }
+        bus
+    }
+
+    #[test]
+    fn locked_writes_are_reported_only_when_they_are_dropped() {
+        let mut bus = bus_in_mode_3(AccuracyProfile::Balanced);
+        bus.write_byte(0x8000, 0x12);
+        bus.write_byte(0xFE00, 0x34);
+        let diagnostics = bus.take_diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|event| matches!(event.diagnostic, Diagnostic::LockedWrite { .. })));
+
+        // Without memory locking the writes land and there is nothing to report
+        let mut bus = bus_in_mode_3(AccuracyProfile::Fast);
+        bus.write_byte(0x8000, 0x12);
+        bus.write_byte(0xFE00, 0x34);
+        assert!(bus.take_diagnostics().is_empty());
+        assert_eq!(bus.read_byte(0x8000), 0x12);
+        assert_eq!(bus.read_byte(0xFE00), 0x34);
+    }
+}
\ No newline at end of file
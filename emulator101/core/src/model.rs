@@ -0,0 +1,101 @@
+// Hardware models
+// The emulator always starts from the post-boot-ROM state. Each model's boot ROM leaves the
+// registers differently, and a few hardware bugs were fixed in later revisions.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use core::fmt;
+use core::str::FromStr;
+
+use crate::cartridge::{Cartridge, CgbSupport};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Model {
+    #[default]
+    Dmg, // Original Game Boy
+    Mgb, // Game Boy Pocket / Light
+    Sgb, // Super Game Boy
+    Cgb, // Game Boy Color
+    Agb, // Game Boy Advance running a Game Boy / Color game
+}
+
+// CPU registers as left by the boot ROM: (AF, BC, DE, HL)
+pub struct BootRegisters {
+    pub af: u16,
+    pub bc: u16,
+    pub de: u16,
+    pub hl: u16,
+}
+
+impl Model {
+    pub const ALL: [Model; 5] = [Model::Dmg, Model::Mgb, Model::Sgb, Model::Cgb, Model::Agb];
+
+    // Pick a model for a ROM from its header. Only CGB-only carts ask for a Color; dual-mode
+    // carts run as DMG since no CGB hardware is emulated yet.
+    pub fn from_header(rom: &[u8]) -> Self {
+        match Cartridge::parse(rom).cgb {
+            CgbSupport::Only => Model::Cgb,
+            CgbSupport::Dual | CgbSupport::None => Model::Dmg,
+        }
+    }
+
+    pub fn boot_registers(self) -> BootRegisters {
+        match self {
+            Model::Dmg => BootRegisters { af: 0x01B0, bc: 0x0013, de: 0x00D8, hl: 0x014D },
+            Model::Mgb => BootRegisters { af: 0xFFB0, bc: 0x0013, de: 0x00D8, hl: 0x014D },
+            Model::Sgb => BootRegisters { af: 0x0100, bc: 0x0014, de: 0x0000, hl: 0xC060 },
+            Model::Cgb => BootRegisters { af: 0x1180, bc: 0x0000, de: 0xFF56, hl: 0x000D },
+            // Same as CGB, but B = 1 lets games detect the GBA
+            Model::Agb => BootRegisters { af: 0x1100, bc: 0x0100, de: 0xFF56, hl: 0x000D },
+        }
+    }
+
+    // Internal 16-bit DIV counter at 0x0100. The SGB and CGB boot ROMs run for a variable
+    // amount of time, so their values are approximations.
+    pub fn div_seed(self) -> u16 {
+        match self {
+            Model::Dmg | Model::Mgb => 0xABCC,
+            Model::Sgb => 0xD85C,
+            Model::Cgb | Model::Agb => 0x1EA0,
+        }
+    }
+
+    // Whether the machine can run in CGB mode at all
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    // 16-bit inc/dec and OAM accesses during mode 2 corrupt OAM (fixed on CGB)
+    pub fn has_oam_bug(self) -> bool {
+        !self.is_cgb()
+    }
+
+    // Writing STAT outside mode 2/3 briefly enables every STAT source and raises an interrupt
+    pub fn has_stat_write_bug(self) -> bool {
+        !self.is_cgb()
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Model::Dmg => "dmg",
+            Model::Mgb => "mgb",
+            Model::Sgb => "sgb",
+            Model::Cgb => "cgb",
+            Model::Agb => "agb",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Model::ALL
+            .into_iter()
+            .find(|model| model.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown model '{}' (expected dmg, mgb, sgb, cgb or agb)", s))
+    }
+}
@@ -0,0 +1,237 @@
+// Input movies
+// A movie records the buttons held on every frame from a known starting point, so a run (a TAS,
+// a bug reproduction) replays identically and can be picked up again later. File layout, all
+// little-endian:
+//
+//   "GBMV", version u32, ROM hash u64, model u8, seed u64, re-record count u32,
+//   anchor savestate length u32 + bytes (length 0: the movie starts at power-on),
+//   frame count u32, then one byte per frame with the held buttons (Emulator::buttons)
+//
+// The seed is the wall-clock time, in seconds since the epoch, that the recording started at.
+// Starting a movie pins the wall clock to it and runs the RTC on emulated time, so nothing from
+// the host leaks into the run. The re-record count goes up every time recording resumes from
+// an earlier frame, as TAS tools traditionally report.
+//
+// For editing, a Greenzone keeps savestates taken at regular frames while a movie runs, so
+// seeking to any recorded frame only re-emulates the few frames since the closest one.
+
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use crate::clock::{FixedWallClock, RtcMode};
+use crate::emulator::Emulator;
+use crate::error::MovieError;
+use crate::model::Model;
+use crate::savestate::{self, SaveStateError, StateReader, StateWriter};
+
+const MAGIC: &[u8; 4] = b"GBMV";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    pub model: Model,
+    pub seed: u64,
+    pub rerecords: u32,
+    // Savestate the movie starts from; None starts it at power-on
+    pub anchor: Option<Vec<u8>>,
+    frames: Vec<u8>,
+}
+
+impl Movie {
+    // An empty movie for the emulator's game, starting from its current state when `anchored`
+    // and from power-on otherwise
+    pub fn new(emulator: &Emulator, anchored: bool) -> Movie {
+        Movie {
+            rom_hash: savestate::rom_hash(emulator.bus().rom()),
+            model: emulator.model(),
+            seed: emulator.wall_time().as_secs(),
+            rerecords: 0,
+            anchor: anchored.then(|| emulator.save_state()),
+            frames: Vec::new(),
+        }
+    }
+
+    // Put the emulator at the movie's first frame: same model, power-on (with blank cartridge
+    // RAM, so a battery save can't change the run) or the anchoring state, and the host clock
+    // pinned to the seed
+    pub fn start(&self, emulator: &mut Emulator) -> Result<(), MovieError> {
+        if savestate::rom_hash(emulator.bus().rom()) != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        emulator.set_model(self.model);
+        emulator.set_rtc_mode(RtcMode::Emulated);
+        emulator.set_wall_clock(FixedWallClock(Duration::from_secs(self.seed)));
+        emulator.reset();
+        emulator.load_battery_save(&vec![0; emulator.battery_save().len()]);
+        if let Some(anchor) = &self.anchor {
+            emulator.load_state(anchor)?;
+        }
+        emulator.set_buttons(self.input(0).unwrap_or(0));
+        Ok(())
+    }
+
+    // Number of recorded frames
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // Buttons held during `frame`, if it was recorded
+    pub fn input(&self, frame: usize) -> Option<u8> {
+        self.frames.get(frame).copied()
+    }
+
+    pub fn inputs(&self) -> &[u8] {
+        &self.frames
+    }
+
+    // Record the buttons held during the next frame
+    pub fn push(&mut self, buttons: u8) {
+        self.frames.push(buttons);
+    }
+
+    // Drop everything from `frame` on to record it again, counting a re-record
+    pub fn rerecord(&mut self, frame: usize) {
+        self.frames.truncate(frame);
+        self.rerecords += 1;
+    }
+
+    // Put the emulator at the start of `frame` (before its input is applied), from the closest
+    // greenzone state at or before it and replaying the recorded inputs from there. Frames past
+    // the end of the recording can't be reached; returns the frame actually sought to. At least
+    // one frame is replayed, as savestates don't hold the picture.
+    pub fn seek(&self, emulator: &mut Emulator, greenzone: &mut Greenzone, frame: usize) -> Result<usize, MovieError> {
+        let frame = frame.min(self.len());
+        let mut current = match frame.checked_sub(1).and_then(|before| greenzone.nearest(before)) {
+            Some((start, state)) => {
+                emulator.load_state(state)?;
+                start
+            },
+            None => {
+                self.start(emulator)?;
+                0
+            },
+        };
+        while current < frame {
+            greenzone.capture(current, emulator);
+            emulator.set_buttons(self.frames[current]);
+            emulator.run_until_frame();
+            current += 1;
+        }
+        Ok(frame)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::raw();
+        w.write_bytes(MAGIC);
+        w.write_u32(VERSION);
+        w.write_u64(self.rom_hash);
+        w.write_u8(Model::ALL.iter().position(|&model| model == self.model).unwrap_or(0) as u8);
+        w.write_u64(self.seed);
+        w.write_u32(self.rerecords);
+        let anchor = self.anchor.as_deref().unwrap_or(&[]);
+        w.write_u32(anchor.len() as u32);
+        w.write_bytes(anchor);
+        w.write_u32(self.frames.len() as u32);
+        w.write_bytes(&self.frames);
+        w.finish()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Movie, MovieError> {
+        let truncated = |e: SaveStateError| match e {
+            SaveStateError::UnexpectedEof => MovieError::Truncated,
+            e => MovieError::Anchor(e),
+        };
+        let mut r = StateReader::raw(data);
+        if r.read_bytes(4).map_err(truncated)? != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+        let version = r.read_u32().map_err(truncated)?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let rom_hash = r.read_u64().map_err(truncated)?;
+        let model = *Model::ALL.get(r.read_u8().map_err(truncated)? as usize)
+            .ok_or(MovieError::InvalidValue("model"))?;
+        let seed = r.read_u64().map_err(truncated)?;
+        let rerecords = r.read_u32().map_err(truncated)?;
+        let anchor_len = r.read_u32().map_err(truncated)? as usize;
+        let anchor = r.read_bytes(anchor_len).map_err(truncated)?;
+        let frame_count = r.read_u32().map_err(truncated)? as usize;
+        let frames = r.read_bytes(frame_count).map_err(truncated)?.to_vec();
+        Ok(Movie {
+            rom_hash,
+            model,
+            seed,
+            rerecords,
+            anchor: (!anchor.is_empty()).then(|| anchor.to_vec()),
+            frames,
+        })
+    }
+}
+
+// Savestates of a running movie, one every `interval` frames, kept in frame order. Once
+// `capacity` states are stored, or they take more than the byte budget, the oldest are dropped,
+// like a rewind buffer.
+pub struct Greenzone {
+    interval: usize,
+    capacity: usize,
+    budget: usize,
+    // Bytes taken by `states`
+    size: usize,
+    states: VecDeque<(usize, Vec<u8>)>,
+}
+
+impl Greenzone {
+    pub fn new(interval: usize, capacity: usize) -> Greenzone {
+        Greenzone { interval: interval.max(1), capacity: capacity.max(1), budget: usize::MAX, size: 0, states: VecDeque::new() }
+    }
+
+    // Also keep the states under `bytes` in total. States compress well, but how well depends on
+    // the game (CGB games fill more RAM), so a count alone doesn't bound memory. The latest state
+    // is always kept.
+    pub fn with_budget(mut self, bytes: usize) -> Greenzone {
+        self.budget = bytes;
+        self
+    }
+
+    // Bytes the stored states take
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Called at the start of every frame, before its input is applied
+    pub fn capture(&mut self, frame: usize, emulator: &Emulator) {
+        if !frame.is_multiple_of(self.interval) || self.states.back().is_some_and(|&(last, _)| last >= frame) {
+            return;
+        }
+        let state = emulator.save_state();
+        self.size += state.len();
+        self.states.push_back((frame, state));
+        while self.states.len() > 1 && (self.states.len() > self.capacity || self.size > self.budget) {
+            if let Some((_, dropped)) = self.states.pop_front() {
+                self.size -= dropped.len();
+            }
+        }
+    }
+
+    // Forget the states after `frame`, whose inputs are about to change
+    pub fn truncate(&mut self, frame: usize) {
+        while self.states.back().is_some_and(|&(last, _)| last > frame) {
+            if let Some((_, dropped)) = self.states.pop_back() {
+                self.size -= dropped.len();
+            }
+        }
+    }
+
+    // The latest state at or before `frame`
+    fn nearest(&self, frame: usize) -> Option<(usize, &[u8])> {
+        self.states.iter().rev().find(|&&(start, _)| start <= frame).map(|(start, state)| (*start, state.as_slice()))
+    }
+}
@@ -0,0 +1,1803 @@
+// Pixel Processing Unit (PPU) module
+// The PPU is responsible for rendering the graphics of the Game
+
+// TODO: FIX STAT INTERRUPTS
+// TODO: FIX SLOW FRAME RATE CAUSED BY BUSY WAITING
+// TODO: FIX PPU SO THAT IT PASSES DMG-ACID TESTS and MOONEYE TESTS
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::accuracy::Accuracy;
+use crate::interrupts::InterruptType;
+use crate::model::Model;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+// Bytes in an RGBA32 frame
+pub const FRAME_BUFFER_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT * 4;
+// Tiles in the tile data area (0x8000-0x97FF)
+pub const TILE_COUNT: usize = 384;
+
+// A decoded tile: 2-bit color indices by row, then column (leftmost pixel first)
+pub type Tile = [[u8; 8]; 8];
+
+// RGBA for each of the four color indices under one palette register
+type ColorLut = [[u8; 4]; 4];
+
+// LCD Registers
+const LCDC: u16 = 0xFF40; // LCD Control
+const STAT: u16 = 0xFF41; // LCDC Status
+const SCY: u16 = 0xFF42;  // Scroll Y
+const SCX: u16 = 0xFF43;  // Scroll X
+const LY: u16 = 0xFF44;   // LCD Y-Coordinate
+const LYC: u16 = 0xFF45;  // LY Compare
+const DMA: u16 = 0xFF46;  // DMA Transfer (Using OAM RAM)
+const BGP: u16 = 0xFF47;  // BG Palette Data
+const OBP0: u16 = 0xFF48; // Object Palette 0 Data
+const OBP1: u16 = 0xFF49; // Object Palette 1 Data
+const WY: u16 = 0xFF4A;   // Window Y Position
+const WX: u16 = 0xFF4B;   // Window X Position
+
+// T-cycles from a write to DMA until the transfer (re)starts: one M-cycle of startup, taking
+// over at the start of the cycle after it. A running transfer keeps copying until then.
+const OAM_DMA_START_DELAY: u8 = 5;
+
+// Progress of an OAM DMA transfer, for debug tools. While a transfer runs, CPU reads of OAM
+// return 0xFF and writes are dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OamDmaProgress {
+    // Source of the running transfer, or of the requested one if none is running yet
+    pub source: u16,
+    pub bytes_transferred: u8,
+    // T-cycles until OAM is released
+    pub remaining_cycles: u32,
+    // Written to DMA but still in the startup delay (a running transfer is about to restart)
+    pub starting: bool,
+}
+
+// How the window was drawn over a frame, for debug tools
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WindowStats {
+    pub lines_drawn: u8,
+    // Lines after the WY condition was met that showed no window (LCDC bit 5 cleared
+    // mid-frame, ...); a frame with only skipped lines is a missing HUD bar
+    pub lines_skipped: u8,
+}
+
+// Mode 3 of one visible scanline, for timing tests and tools
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mode3Timing {
+    // Length of mode 3 in dots, penalty included
+    pub dots: u16,
+    // Sprites selected during the OAM scan
+    pub sprites: u8,
+    // Dots the sprite fetches added
+    pub sprite_penalty: u16,
+}
+
+// LCD Mode
+// RGB colors for the four DMG shades, lightest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette(pub [[u8; 3]; 4]);
+
+impl Palette {
+    // Green-tinted palette resembling the original screen
+    pub const GREEN: Palette = Palette([[224, 248, 208], [136, 192, 112], [52, 104, 86], [8, 24, 32]]);
+    pub const GRAYSCALE: Palette = Palette([[255, 255, 255], [170, 170, 170], [85, 85, 85], [0, 0, 0]]);
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::GREEN
+    }
+}
+
+// Palette register a pixel's color number went through
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelPalette {
+    #[default]
+    Bgp,
+    Obp0,
+    Obp1,
+}
+
+impl fmt::Display for PixelPalette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PixelPalette::Bgp => "BGP",
+            PixelPalette::Obp0 => "OBP0",
+            PixelPalette::Obp1 => "OBP1",
+        })
+    }
+}
+
+// Layer that produced a pixel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelLayer {
+    // Also color 0 when the background is off
+    #[default]
+    Background,
+    Window,
+    // Sprite with this OAM index (0-39)
+    Sprite(u8),
+}
+
+impl fmt::Display for PixelLayer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PixelLayer::Background => f.write_str("BG"),
+            PixelLayer::Window => f.write_str("WIN"),
+            PixelLayer::Sprite(index) => write!(f, "OBJ#{}", index),
+        }
+    }
+}
+
+// What a pixel of the last frame was drawn from, for debugging tools
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PixelSource {
+    pub layer: PixelLayer,
+    // 2-bit color number from the tile, before the palette
+    pub color_index: u8,
+    pub palette: PixelPalette,
+}
+
+impl PixelSource {
+    const BACKGROUND_0: PixelSource = PixelSource { layer: PixelLayer::Background, color_index: 0, palette: PixelPalette::Bgp };
+
+    fn background(color_index: u8) -> Self {
+        PixelSource { layer: PixelLayer::Background, color_index, palette: PixelPalette::Bgp }
+    }
+
+    fn window(color_index: u8) -> Self {
+        PixelSource { layer: PixelLayer::Window, color_index, palette: PixelPalette::Bgp }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LcdMode {
+    HBlank = 0,		// Horizontal blanking (mode 0)
+    VBlank = 1,		// Vertical blanking (mode 1)
+    OamScan = 2,	// OAM RAM access (mode 2)
+    Drawing = 3,	// Pixel transfer (mode 3)
+}
+
+impl LcdMode {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(LcdMode::HBlank),
+            1 => Some(LcdMode::VBlank),
+            2 => Some(LcdMode::OamScan),
+            3 => Some(LcdMode::Drawing),
+            _ => None,
+        }
+    }
+}
+
+// Where the PPU's state machine is, without VRAM, OAM or the pictures: enough to put a PPU at
+// an exact point of a line in a test, and the timing part of a savestate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PpuState {
+    pub mode: LcdMode,
+    // Dots spent in the current mode
+    pub dots: u32,
+    pub ly: u8,
+    // Window line counter and the WY match of this frame
+    pub window_line: u8,
+    pub wy_triggered: bool,
+    pub window_active: bool,
+    // Scroll the current line is drawn with; the line is drawn at the end of mode 3, so this is
+    // SCX/SCY as they are at that point
+    pub scx: u8,
+    pub scy: u8,
+}
+
+impl PpuState {
+    pub fn save(&self, w: &mut StateWriter) {
+        w.write_u8(self.mode as u8);
+        w.write_u32(self.dots);
+        w.write_u8(self.ly);
+        w.write_u8(self.window_line);
+        w.write_bool(self.wy_triggered);
+        w.write_bool(self.window_active);
+        w.write_u8(self.scx);
+        w.write_u8(self.scy);
+    }
+
+    pub fn load(r: &mut StateReader) -> Result<Self, SaveStateError> {
+        let mode = LcdMode::from_bits(r.read_u8()?).ok_or(SaveStateError::InvalidValue("LCD mode"))?;
+        let dots = r.read_u32()?;
+        let ly = r.read_u8()?;
+        if ly > 153 {
+            return Err(SaveStateError::InvalidValue("LY"));
+        }
+        Ok(Self {
+            mode,
+            dots,
+            ly,
+            window_line: r.read_u8()?,
+            wy_triggered: r.read_bool()?,
+            window_active: r.read_bool()?,
+            scx: r.read_u8()?,
+            scy: r.read_u8()?,
+        })
+    }
+}
+
+// OAM Entry (Sprite Attributes)
+#[derive(Clone, Copy, Debug)]
+pub struct OamEntry {
+    pub y_pos: u8,      // Y position on screen (minus 16)
+    pub x_pos: u8,      // X position on screen (minus 8)
+    pub tile_idx: u8,   // Tile index from 0x8000
+    pub attributes: u8,  // Sprite attributes (flip, priority, palette)
+}
+
+impl OamEntry {
+    fn new() -> Self {
+        Self {
+            y_pos: 0,
+            x_pos: 0,
+            tile_idx: 0,
+            attributes: 0,
+        }
+    }
+
+    fn from_bytes(bytes: &[u8; 4]) -> Self {
+        Self {
+            y_pos: bytes[0],
+            x_pos: bytes[1],
+            tile_idx: bytes[2],
+            attributes: bytes[3],
+        }
+    }
+
+    // Is sprite on current scanline?
+    fn is_on_scanline(&self, ly: u8, sprite_size: u8) -> bool {
+        let sprite_y = self.y_pos.wrapping_sub(16);
+        ly >= sprite_y && ly < sprite_y.wrapping_add(sprite_size)
+    }
+
+    // Get priority flag (0 = Above BG, 1 = Behind non-zero BG)
+    fn has_priority(&self) -> bool {
+        self.attributes & 0x80 != 0
+    }
+
+    // Get Y-flip flag
+    fn is_y_flipped(&self) -> bool {
+        self.attributes & 0x40 != 0
+    }
+
+    // Get X-flip flag
+    fn is_x_flipped(&self) -> bool {
+        self.attributes & 0x20 != 0
+    }
+
+    // Get palette (0 = OBP0, 1 = OBP1)
+    fn palette(&self) -> u8 {
+        if self.attributes & 0x10 != 0 { 1 } else { 0 }
+    }
+}
+
+#[derive(Clone)]
+pub struct Ppu {
+    // Decides which hardware bugs are emulated
+    model: Model,
+    // Switches off the OAM bug and VRAM/OAM locking for the fast profile
+    accuracy: Accuracy,
+    // Colors used for the frame buffer
+    palette: Palette,
+    // BGP, OBP0 and OBP1 resolved through `palette`; rebuilt when any of them changes
+    bg_colors: ColorLut,
+    obj_colors: [ColorLut; 2],
+	// RGBA32, boxed so neither the PPU nor anything holding it keeps ~92KB on the stack
+	frame_buffer: Box<[u8; FRAME_BUFFER_SIZE]>,
+    // Color number and palette behind each pixel of frame_buffer
+    pixel_sources: Box<[PixelSource; SCREEN_WIDTH * SCREEN_HEIGHT]>,
+	// VRMA
+	vram: [u8; 0x2000],
+    // Tile data decoded from the two bitplanes. A write to tile data only marks the tile in
+    // dirty_tiles; it is decoded again before the next scanline is rendered.
+    tiles: Box<[Tile; TILE_COUNT]>,
+    dirty_tiles: [u64; TILE_COUNT / 64],
+	// OAM
+	oam: [u8; 0xA0],
+    // Parsed OAM entries for quick access
+    pub oam_entries: [OamEntry; 40],
+    // Current scanline sprites (max 10 per line)
+    scanline_sprites: Vec<(usize, OamEntry)>, // (index, entry) pairs
+	// LCD Registers
+	pub lcdc: u8,
+	pub stat: u8,
+	pub scy: u8,
+	pub scx: u8,
+	pub ly: u8,
+	pub lyc: u8,
+	pub dma: u8,
+	pub bgp: u8,
+	pub obp0: u8,
+	pub obp1: u8,
+	pub wy: u8,
+    wy_triggered: bool,
+	pub wx: u8,
+
+    // Window internal position counter
+    window_line: u8, // Current line in the window, separate from LY
+
+	// PPU Mode
+	mode: LcdMode,
+	mode_cycles: u32,
+    // Cycles the PPU has been told about but not run yet (see add_dots). They are all within the
+    // current mode, so only mode_cycles is behind.
+    pending_dots: u32,
+
+    // Access control flags
+    vram_accessible: bool,
+    oam_accessible: bool,
+
+	// For tracking when the frame is ready
+	pub frame_ready: bool,
+    // Scanlines written to frame_buffer since power-on, so frontends can tell the picture changed
+    lines_drawn: u64,
+
+    // For tracking OAM Corruption
+    pub oam_dma_active: bool,
+    // High byte of the running transfer's source (DMA may already hold the next one)
+    oam_dma_source: u8,
+    oam_dma_byte: u8,
+    // T-cycles into the current byte (one byte per M-cycle)
+    oam_dma_cycles: u8,
+    // T-cycles until a requested transfer starts, 0 if none is pending
+    oam_dma_start_delay: u8,
+    last_frame_window_active: bool,
+    // Window lines of the frame being drawn, and of the last complete one
+    frame_window_stats: WindowStats,
+    last_window_stats: WindowStats,
+    // Mode 3 of each line of the frame being drawn, and of the last complete one
+    frame_mode3: [Mode3Timing; SCREEN_HEIGHT],
+    last_mode3: [Mode3Timing; SCREEN_HEIGHT],
+    
+    // LY=LYC interrupt already triggered for this line
+    lyc_interrupt_triggered: bool,
+    
+    // CPU last read/write a locked area
+    cpu_vram_bus_conflict: bool,
+    cpu_oam_bus_conflict: bool,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new(Model::default())
+    }
+}
+
+impl Ppu {
+	pub fn new(model: Model) -> Self {
+		let mut ppu = Self {
+            model,
+            accuracy: Accuracy::default(),
+            palette: Palette::default(),
+            bg_colors: [[0; 4]; 4],
+            obj_colors: [[[0; 4]; 4]; 2],
+			frame_buffer: vec![0xFF; FRAME_BUFFER_SIZE].into_boxed_slice().try_into().unwrap(), // Initialize with white
+            pixel_sources: vec![PixelSource::default(); SCREEN_WIDTH * SCREEN_HEIGHT].into_boxed_slice().try_into().unwrap(),
+			vram: [0; 0x2000],
+            tiles: vec![[[0; 8]; 8]; TILE_COUNT].into_boxed_slice().try_into().unwrap(),
+            dirty_tiles: [u64::MAX; TILE_COUNT / 64],
+			oam: [0; 0xA0],
+            oam_entries: [OamEntry::new(); 40],
+            scanline_sprites: Vec::with_capacity(10),
+			lcdc: 0x91, // LCD & PPU are on by default
+			stat: 0x85, // Set STAT to mode 2
+			scy: 0,
+			scx: 0,
+			ly: 0,
+            lyc: 0,
+            dma: 0xFF,
+            bgp: 0xFC,
+            obp0: 0xFF,
+            obp1: 0xFF,
+            wy: 0,
+            wx: 0,
+            wy_triggered: false,
+            window_line: 0,
+            mode: LcdMode::VBlank,
+            mode_cycles: 0,
+            pending_dots: 0,
+            vram_accessible: true,
+            oam_accessible: true,
+            frame_ready: false,
+            lines_drawn: 0,
+            oam_dma_active: false,
+            oam_dma_source: 0,
+            oam_dma_byte: 0,
+            oam_dma_cycles: 0,
+            oam_dma_start_delay: 0,
+            last_frame_window_active: false,
+            frame_window_stats: WindowStats::default(),
+            last_window_stats: WindowStats::default(),
+            frame_mode3: [Mode3Timing::default(); SCREEN_HEIGHT],
+            last_mode3: [Mode3Timing::default(); SCREEN_HEIGHT],
+            lyc_interrupt_triggered: false,
+            cpu_vram_bus_conflict: false,
+            cpu_oam_bus_conflict: false,
+		};
+        // Initialize OAM entries from initial OAM data
+        ppu.update_oam_entries();
+        ppu.update_color_luts();
+        ppu
+	}
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn set_accuracy(&mut self, accuracy: Accuracy) {
+        self.accuracy = accuracy;
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.update_color_luts();
+    }
+
+    fn update_color_luts(&mut self) {
+        let lut = |register: u8| {
+            core::array::from_fn(|color_idx| {
+                let [r, g, b] = self.palette.0[self.get_color(color_idx as u8, register) as usize];
+                [r, g, b, 255]
+            })
+        };
+        let (bg_colors, obj_colors) = (lut(self.bgp), [lut(self.obp0), lut(self.obp1)]);
+        self.bg_colors = bg_colors;
+        self.obj_colors = obj_colors;
+    }
+
+    // Last rendered frame as RGBA32, SCREEN_WIDTH * SCREEN_HEIGHT pixels
+    pub fn frame(&self) -> &[u8] {
+        &self.frame_buffer[..]
+    }
+
+    // Bumped by every scanline written to frame()
+    pub fn lines_drawn(&self) -> u64 {
+        self.lines_drawn
+    }
+
+    // Where each pixel of frame() came from, row by row
+    pub fn pixel_sources(&self) -> &[PixelSource] {
+        &self.pixel_sources[..]
+    }
+
+    // Update OAM entries from raw OAM data
+    fn update_oam_entries(&mut self) {
+        for i in 0..40 {
+            let start = i * 4;
+            let mut bytes = [0u8; 4];
+            bytes.copy_from_slice(&self.oam[start..start + 4]);
+            self.oam_entries[i] = OamEntry::from_bytes(&bytes);
+        }
+    }
+
+    // The state machine's position, with the banked dots settled
+    pub fn state(&self) -> PpuState {
+        PpuState {
+            mode: self.mode,
+            dots: self.mode_cycles + self.pending_dots,
+            ly: self.ly,
+            window_line: self.window_line,
+            wy_triggered: self.wy_triggered,
+            window_active: self.last_frame_window_active,
+            scx: self.scx,
+            scy: self.scy,
+        }
+    }
+
+    // Put the state machine at `state`; STAT's mode bits follow
+    pub fn set_state(&mut self, state: PpuState) {
+        self.mode = state.mode;
+        self.mode_cycles = state.dots;
+        self.pending_dots = 0;
+        self.ly = state.ly;
+        self.window_line = state.window_line;
+        self.wy_triggered = state.wy_triggered;
+        self.last_frame_window_active = state.window_active;
+        self.scx = state.scx;
+        self.scy = state.scy;
+        self.stat = (self.stat & 0xFC) | (self.mode as u8 & 0x3);
+    }
+
+    // The pictures (frame() and pixel_sources()) are not part of a savestate: they are redrawn
+    // by the next frame
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.vram);
+        w.write_bytes(&self.oam);
+
+        w.write_u8(self.scanline_sprites.len() as u8);
+        for &(idx, entry) in self.scanline_sprites.iter() {
+            w.write_u8(idx as u8);
+            w.write_bytes(&[entry.y_pos, entry.x_pos, entry.tile_idx, entry.attributes]);
+        }
+
+        for value in [self.lcdc, self.stat, self.lyc, self.dma, self.bgp, self.obp0, self.obp1,
+                      self.wy, self.wx] {
+            w.write_u8(value);
+        }
+        self.state().save(w);
+        w.write_bool(self.vram_accessible);
+        w.write_bool(self.oam_accessible);
+        w.write_bool(self.frame_ready);
+        w.write_bool(self.oam_dma_active);
+        w.write_u8(self.oam_dma_source);
+        w.write_u8(self.oam_dma_byte);
+        w.write_u8(self.oam_dma_cycles);
+        w.write_u8(self.oam_dma_start_delay);
+        w.write_bool(self.lyc_interrupt_triggered);
+        w.write_bool(self.cpu_vram_bus_conflict);
+        w.write_bool(self.cpu_oam_bus_conflict);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        r.read_into(&mut self.vram)?;
+        self.dirty_tiles = [u64::MAX; TILE_COUNT / 64];
+        r.read_into(&mut self.oam)?;
+        self.update_oam_entries();
+
+        // Sprites selected during the OAM scan of the current line; up to all 40 without the limit
+        let sprite_count = r.read_u8()?;
+        if sprite_count > 40 {
+            return Err(SaveStateError::InvalidValue("sprite count"));
+        }
+        self.scanline_sprites.clear();
+        for _ in 0..sprite_count {
+            let idx = r.read_u8()? as usize;
+            if idx >= 40 {
+                return Err(SaveStateError::InvalidValue("sprite index"));
+            }
+            let mut bytes = [0u8; 4];
+            r.read_into(&mut bytes)?;
+            self.scanline_sprites.push((idx, OamEntry::from_bytes(&bytes)));
+        }
+
+        self.lcdc = r.read_u8()?;
+        self.stat = r.read_u8()?;
+        self.lyc = r.read_u8()?;
+        self.dma = r.read_u8()?;
+        self.bgp = r.read_u8()?;
+        self.obp0 = r.read_u8()?;
+        self.obp1 = r.read_u8()?;
+        self.wy = r.read_u8()?;
+        self.wx = r.read_u8()?;
+        self.update_color_luts();
+        self.set_state(PpuState::load(r)?);
+        self.vram_accessible = r.read_bool()?;
+        self.oam_accessible = r.read_bool()?;
+        self.frame_ready = r.read_bool()?;
+        self.oam_dma_active = r.read_bool()?;
+        self.oam_dma_source = r.read_u8()?;
+        self.oam_dma_byte = r.read_u8()?;
+        if self.oam_dma_byte >= 160 {
+            return Err(SaveStateError::InvalidValue("OAM DMA byte"));
+        }
+        self.oam_dma_cycles = r.read_u8()? & 0x03;
+        self.oam_dma_start_delay = r.read_u8()?.min(OAM_DMA_START_DELAY);
+        self.lyc_interrupt_triggered = r.read_bool()?;
+        self.cpu_vram_bus_conflict = r.read_bool()?;
+        self.cpu_oam_bus_conflict = r.read_bool()?;
+        Ok(())
+    }
+
+	// Read from VRAM. Only the low 13 bits of the address are used, so any address is safe.
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        if self.accuracy.memory_locking && self.vram_locked() {
+            return 0xFF;
+        }
+        self.vram[(addr & 0x1FFF) as usize]
+    }
+
+    // Write to VRAM
+    pub fn write_vram(&mut self, addr: u16, value: u8) {
+        if self.accuracy.memory_locking && self.vram_locked() {
+            self.cpu_vram_bus_conflict = true;
+            return;
+        }
+        let offset = (addr & 0x1FFF) as usize;
+        if self.vram[offset] != value && offset < TILE_COUNT * 16 {
+            self.dirty_tiles[offset / 16 / 64] |= 1 << (offset / 16 % 64);
+        }
+        self.vram[offset] = value;
+    }
+
+    // Copies of VRAM and OAM as they are, whatever mode the PPU is in, for viewers, scripts and
+    // tests; read_vram/read_oam return 0xFF while the CPU is locked out
+    pub fn vram_snapshot(&self) -> [u8; 0x2000] {
+        self.vram
+    }
+
+    pub fn oam_snapshot(&self) -> [u8; 0xA0] {
+        self.oam
+    }
+
+    // Tile `index` of the tile data area (0..TILE_COUNT), ignoring VRAM access locks
+    pub fn tile(&self, index: usize) -> Tile {
+        if self.dirty_tiles[index / 64] & (1 << (index % 64)) != 0 {
+            self.decode_tile(index)
+        } else {
+            self.tiles[index]
+        }
+    }
+
+    fn decode_tile(&self, index: usize) -> Tile {
+        let data = &self.vram[index * 16..index * 16 + 16];
+        let mut tile = [[0; 8]; 8];
+        for (row, pixels) in tile.iter_mut().enumerate() {
+            let (low, high) = (data[row * 2], data[row * 2 + 1]);
+            for (col, pixel) in pixels.iter_mut().enumerate() {
+                let bit = 7 - col;
+                *pixel = ((high >> bit) & 0x01) << 1 | ((low >> bit) & 0x01);
+            }
+        }
+        tile
+    }
+
+    // Decode the tiles written since the last call
+    fn refresh_tiles(&mut self) {
+        for word in 0..self.dirty_tiles.len() {
+            while self.dirty_tiles[word] != 0 {
+                let bit = self.dirty_tiles[word].trailing_zeros() as usize;
+                self.dirty_tiles[word] &= !(1 << bit);
+                let index = word * 64 + bit;
+                self.tiles[index] = self.decode_tile(index);
+            }
+        }
+    }
+
+    // Tile number for a tile map entry; with signed addressing, entries 0-127 use 0x9000-0x97FF
+    // and 128-255 use 0x8800-0x8FFF
+    fn tile_number(tile_index: u8, signed: bool) -> usize {
+        if signed {
+            (256 + tile_index as i8 as i16) as usize
+        } else {
+            tile_index as usize
+        }
+    }
+
+    pub fn get_dma_source(&self) -> u16 {
+        (self.oam_dma_source as u16) << 8
+    }
+
+    // The OAM DMA transfer in flight, if any
+    pub fn oam_dma(&self) -> Option<OamDmaProgress> {
+        let full = 160 * 4;
+        if self.oam_dma_start_delay > 0 {
+            // The new transfer counts its first cycle when it takes over
+            Some(OamDmaProgress {
+                source: if self.oam_dma_active { self.get_dma_source() } else { (self.dma as u16) << 8 },
+                bytes_transferred: if self.oam_dma_active { self.oam_dma_byte } else { 0 },
+                remaining_cycles: self.oam_dma_start_delay as u32 - 1 + full,
+                starting: true,
+            })
+        } else if self.oam_dma_active {
+            Some(OamDmaProgress {
+                source: self.get_dma_source(),
+                bytes_transferred: self.oam_dma_byte,
+                remaining_cycles: full - self.oam_dma_byte as u32 * 4 - self.oam_dma_cycles as u32,
+                starting: false,
+            })
+        } else {
+            None
+        }
+    }
+
+    // Window line counter: the window row drawn next, separate from LY
+    pub fn window_line(&self) -> u8 {
+        self.window_line
+    }
+
+    // WY has matched LY this frame while the window was enabled
+    pub fn window_triggered(&self) -> bool {
+        self.wy_triggered
+    }
+
+    // The window has been active this frame
+    pub fn window_active(&self) -> bool {
+        self.last_frame_window_active
+    }
+
+    // Window lines of the last complete frame
+    pub fn window_stats(&self) -> WindowStats {
+        self.last_window_stats
+    }
+
+    pub(crate) fn model(&self) -> Model {
+        self.model
+    }
+
+    // STAT mode bits (0-3); 0 while the LCD is off
+    pub fn mode(&self) -> u8 {
+        if self.lcdc & 0x80 == 0 { 0 } else { self.mode as u8 }
+    }
+
+    // The CPU can't write VRAM right now (mode 3)
+    pub fn vram_locked(&self) -> bool {
+        !self.vram_accessible && self.lcdc & 0x80 != 0
+    }
+
+    // The CPU can't write OAM right now (modes 2 and 3)
+    pub fn oam_locked(&self) -> bool {
+        !self.oam_accessible && self.lcdc & 0x80 != 0
+    }
+
+    // Mode 3 of every line of the last complete frame; lines the LCD did not draw are zero
+    pub fn mode3_timings(&self) -> &[Mode3Timing; SCREEN_HEIGHT] {
+        &self.last_mode3
+    }
+
+    // Where the PPU is in the frame, for debug overlays: LY and the screen column being drawn,
+    // interpolated over mode 3 (0 before it, SCREEN_WIDTH after it)
+    pub fn beam_position(&self) -> (u8, usize) {
+        let dots = self.mode_cycles + self.pending_dots;
+        let x = match self.mode {
+            LcdMode::OamScan => 0,
+            LcdMode::Drawing => {
+                let drawing_time = 172 + self.sprite_penalty();
+                (dots.min(drawing_time) as usize * SCREEN_WIDTH) / drawing_time as usize
+            },
+            LcdMode::HBlank | LcdMode::VBlank => SCREEN_WIDTH,
+        };
+        (self.ly, x)
+    }
+
+    // A transfer is running or about to start
+    pub(crate) fn oam_dma_busy(&self) -> bool {
+        self.oam_dma_active || self.oam_dma_start_delay > 0
+    }
+
+    // Advance OAM DMA by one T-cycle; returns true when the running transfer copies its next
+    // byte on this cycle (see process_dma_byte)
+    pub(crate) fn dma_cycle(&mut self) -> bool {
+        if self.oam_dma_start_delay > 0 {
+            self.oam_dma_start_delay -= 1;
+            if self.oam_dma_start_delay == 0 {
+                // A new transfer replaces the running one from its first byte
+                self.oam_dma_active = true;
+                self.oam_dma_source = self.dma;
+                self.oam_dma_byte = 0;
+                self.oam_dma_cycles = 0;
+            }
+        }
+        if !self.oam_dma_active {
+            return false;
+        }
+        self.oam_dma_cycles = (self.oam_dma_cycles + 1) % 4;
+        self.oam_dma_cycles == 0
+    }
+    
+    pub fn get_dma_byte(&self) -> u8 {
+        self.oam_dma_byte
+    }
+    
+    pub fn process_dma_byte(&mut self, value: u8) {
+        if !self.oam_dma_active {
+            return;
+        }
+        
+        // Write to OAM directly (bypassing access check)
+        self.oam[self.oam_dma_byte as usize] = value;
+        
+        // Update OAM entry if it's a 4-byte boundary
+        if self.oam_dma_byte % 4 == 3 {
+            let entry_idx = (self.oam_dma_byte / 4) as usize;
+            if entry_idx < 40 {  // Safety check
+                let start = entry_idx * 4;
+                let mut bytes = [0u8; 4];
+                bytes.copy_from_slice(&self.oam[start..start + 4]);
+                self.oam_entries[entry_idx] = OamEntry::from_bytes(&bytes);
+            }
+        }
+        
+        self.oam_dma_byte += 1;
+        
+        // Check if DMA is complete
+        if self.oam_dma_byte >= 160 {
+            self.oam_dma_active = false;
+            self.oam_dma_byte = 0;
+            // Update all OAM entries after DMA completes
+            self.update_oam_entries();
+        }
+    }
+    
+    // Read from OAM
+    pub fn read_oam(&self, addr: u16) -> u8 {
+        let oam_addr = addr.wrapping_sub(0xFE00) as usize;
+        if oam_addr >= 0xA0 {
+            return 0xFF; // Out of bounds
+        }
+        
+        // Check if OAM is accessible based on the current mode
+        if self.accuracy.memory_locking && self.oam_locked() {
+            // During modes 2 & 3 (OAM scan & pixel transfer), OAM is inaccessible
+            return 0xFF;
+        }
+        
+        // Simulate OAM corruption during DMA
+        if self.oam_dma_active {
+            // OAM corruption - complex bug, simplified simulation 
+            return 0xFF; // Corrupted read during DMA
+        }
+        
+        self.oam[oam_addr]
+    }
+    
+    // Write to OAM
+    pub fn write_oam(&mut self, addr: u16, value: u8) {
+        let oam_addr = addr.wrapping_sub(0xFE00) as usize;
+        if oam_addr >= 0xA0 {
+            return; // Out of bounds
+        }
+        
+        // Check if OAM is accessible based on the current mode
+        if self.accuracy.memory_locking && self.oam_locked() {
+            self.cpu_oam_bus_conflict = true;
+            self.oam_bug_write();
+            return;
+        }
+        
+        // Simulate OAM corruption during DMA
+        if self.oam_dma_active {
+            // OAM is locked during DMA
+            return;
+        }
+        
+        self.oam[oam_addr] = value;
+        
+        // Update the corresponding OAM entry
+        let entry_idx = oam_addr / 4;
+        let byte_idx = oam_addr % 4;
+        
+        match byte_idx {
+            0 => self.oam_entries[entry_idx].y_pos = value,
+            1 => self.oam_entries[entry_idx].x_pos = value,
+            2 => self.oam_entries[entry_idx].tile_idx = value,
+            3 => self.oam_entries[entry_idx].attributes = value,
+            _ => unreachable!(),
+        }
+    }
+    
+    // DMG OAM corruption bug (write variant): while the PPU scans OAM in mode 2, a write or a
+    // 16-bit inc/dec of an address in 0xFE00-0xFEFF garbles the 8-byte row being read.
+    pub fn oam_bug_write(&mut self) {
+        self.catch_up();
+        if !self.model.has_oam_bug() || !self.accuracy.oam_bug || self.lcdc & 0x80 == 0 || self.mode != LcdMode::OamScan {
+            return;
+        }
+
+        // One row (two entries) is scanned per M-cycle; the first row is never affected
+        let row = (self.mode_cycles as usize / 4).min(19);
+        if row == 0 {
+            return;
+        }
+
+        let base = row * 8;
+        let prev = base - 8;
+        let word = |oam: &[u8; 0xA0], at: usize| u16::from_le_bytes([oam[at], oam[at + 1]]);
+        let a = word(&self.oam, base);
+        let b = word(&self.oam, prev);
+        let c = word(&self.oam, prev + 4);
+        let corrupted = ((a ^ c) & (b ^ c)) ^ c;
+
+        self.oam[base..base + 2].copy_from_slice(&corrupted.to_le_bytes());
+        self.oam.copy_within(prev + 2..prev + 8, base + 2);
+        self.update_oam_entries();
+    }
+
+    // DMG STAT write bug: writing STAT while in HBlank/VBlank (or while LY=LYC) raises a STAT
+    // interrupt as if every source was enabled for a moment
+    pub fn stat_write_triggers_interrupt(&self) -> bool {
+        self.model.has_stat_write_bug()
+            && self.lcdc & 0x80 != 0
+            && (matches!(self.mode, LcdMode::HBlank | LcdMode::VBlank) || self.ly == self.lyc)
+    }
+
+    // Begin DMA transfer
+    // Writing DMA (re)starts a transfer after the startup delay; one already running carries on
+    // until then, so OAM stays blocked across a restart
+    fn begin_oam_dma(&mut self, value: u8) {
+        trace!(source = (value as u16) << 8, "oam dma");
+        self.dma = value;
+        self.oam_dma_start_delay = OAM_DMA_START_DELAY;
+    }
+
+	// Read from a PPU register
+    pub fn read_register(&self, addr: u16) -> u8 {
+        match addr {
+            LCDC => self.lcdc,
+            STAT => {
+                // Combine STAT register with current mode
+                let mode_bits = self.mode as u8;
+                let lyc_flag = if self.ly == self.lyc { 0x04 } else { 0x00 };
+                0x80 | (self.stat & 0x78) | lyc_flag | mode_bits
+            },
+            SCY => self.scy,
+            SCX => self.scx,
+            LY => self.ly,
+            LYC => self.lyc,
+            DMA => self.dma,
+            BGP => self.bgp,
+            OBP0 => self.obp0,
+            OBP1 => self.obp1,
+            WY => self.wy,
+            WX => self.wx,
+            _ => 0xFF, // Should not happen
+        }
+    }
+    
+    // Write to a PPU register
+    pub fn write_register(&mut self, addr: u16, value: u8) {
+        self.catch_up();
+        match addr {
+            LCDC => {
+                let old_lcd_enable = self.lcdc & 0x80 != 0;
+                let new_lcd_enable = value & 0x80 != 0;
+                
+                // Store the old value to detect changes
+                let old_lcdc = self.lcdc;
+                self.lcdc = value;
+                
+                // Turning LCD off
+                if old_lcd_enable && !new_lcd_enable {
+                    self.ly = 0;
+                    self.mode = LcdMode::HBlank;
+                    self.mode_cycles = 0;
+                    self.vram_accessible = true;
+                    self.oam_accessible = true;
+                    self.window_line = 0;
+                    debug!(ly = self.ly, "lcd off");
+                } else if !old_lcd_enable && new_lcd_enable {
+                    // LCD turned on - initialize state
+                    self.mode_cycles = 0;
+                    self.mode = LcdMode::OamScan;
+                    debug!("lcd on");
+                }
+                
+                // Handle window enable/disable
+                if (old_lcdc & 0x20) != (value & 0x20) {
+                    // Window was toggled, make sure state is consistent
+                    if value & 0x20 == 0 {
+                        // Window disabled mid-frame
+                        // Don't reset window_line here!
+                    }
+                }
+            },
+            STAT => {
+                // Only bits 3-6 are writable, bit 7 always reads as 1
+                let old_stat = self.stat;
+                self.stat = 0x80 | (value & 0x78) | (self.stat & 0x07);
+                
+                // Check if LYC=LY interrupt was just enabled and condition is true
+                if (old_stat & 0x40) == 0 && (value & 0x40) != 0 && (self.stat & 0x04) != 0 {
+                    self.lyc_interrupt_triggered = true;
+                }
+            },
+            SCY => self.scy = value,
+            SCX => self.scx = value,
+            LY => {}, // LY is read-only
+            LYC => {
+                let old_lyc = self.lyc;
+                self.lyc = value;
+                
+                // Update coincidence flag immediately
+                if self.ly == value {
+                    self.stat |= 0x04; // Set coincidence flag
+                    
+                    // If coincidence interrupt enabled and LYC changed to match LY
+                    if (self.stat & 0x40) != 0 && old_lyc != value {
+                        self.lyc_interrupt_triggered = true;
+                    }
+                } else {
+                    self.stat &= !0x04; // Clear coincidence flag
+                }
+            },
+            DMA => self.begin_oam_dma(value),
+            BGP => {
+                self.bgp = value;
+                self.update_color_luts();
+            },
+            OBP0 => {
+                self.obp0 = value;
+                self.update_color_luts();
+            },
+            OBP1 => {
+                self.obp1 = value;
+                self.update_color_luts();
+            },
+            WY => self.wy = value,
+            WX => self.wx = value,
+            _ => {}, // Should not happen
+        }
+    }
+
+    #[allow(dead_code)]
+    fn debugging(&self, out: &mut impl fmt::Write) -> fmt::Result {
+        writeln!(out)?;
+        writeln!(out, "LCDC: {:#04X}", self.lcdc)?;
+        writeln!(out, "STAT: {:#04X}", self.stat)?;
+        writeln!(out, "SCY: {:#04X}", self.scy)?;
+        writeln!(out, "SCX: {:#04X}", self.scx)?;
+        writeln!(out, "LY: {:#04X}", self.ly)?;
+        writeln!(out, "LYC: {:#04X}", self.lyc)?;
+        writeln!(out, "BGP: {:#04X}", self.bgp)?;
+        writeln!(out, "OBP0: {:#04X}", self.obp0)?;
+        writeln!(out, "OBP1: {:#04X}", self.obp1)?;
+        writeln!(out, "WY: {:#04X}", self.wy)?;
+        writeln!(out, "WX: {:#04X}", self.wx)?;
+        writeln!(out, "Window Line: {:#04X}", self.window_line)?;
+        writeln!(out, "MODE: {:?}", self.mode)?;
+        writeln!(out, "MODE CYCLES: {}", self.mode_cycles + self.pending_dots)?;
+        Ok(())
+    }
+
+    // Dots the sprite fetches add to mode 3 of the current line
+    fn sprite_penalty(&self) -> u32 {
+        (self.scanline_sprites.len() as u32 * 6).min(60)
+    }
+
+    // Length of the current mode in T-cycles
+    fn mode_length(&self) -> u32 {
+        let sprite_penalty = self.sprite_penalty();
+        match self.mode {
+            LcdMode::OamScan => 80,
+            LcdMode::Drawing => 172 + sprite_penalty,
+            LcdMode::HBlank => 456 - (80 + 172 + sprite_penalty),
+            LcdMode::VBlank => 456,
+        }
+    }
+
+    // T-cycles until the next mode change (at least 1), u32::MAX while the LCD is off
+    pub(crate) fn cycles_until_event(&self) -> u32 {
+        if self.lcdc & 0x80 == 0 {
+            return u32::MAX;
+        }
+        self.mode_length().saturating_sub(self.mode_cycles + self.pending_dots).max(1)
+    }
+
+    // Let `dots` T-cycles pass. The PPU only really runs up to its mode changes; cycles within a
+    // mode are banked in pending_dots and settled by catch_up the next time something needs
+    // the exact position (an event, a register write, the OAM bug or a savestate).
+    pub(crate) fn add_dots(&mut self, dots: u32) -> Option<InterruptType> {
+        let mut dots = dots;
+        let mut interrupt = None;
+        while dots > 0 {
+            let until = self.cycles_until_event();
+            if dots < until {
+                self.defer(dots);
+                break;
+            }
+            interrupt = interrupt.or(self.advance(until));
+            dots -= until;
+        }
+        interrupt
+    }
+
+    // Bank cycles within the current mode. The first batch after the PPU was caught up applies
+    // the mode's access flags and window check; later ones would only repeat them.
+    fn defer(&mut self, dots: u32) {
+        // update_cycle does nothing while the LCD is off
+        if self.lcdc & 0x80 == 0 {
+            return;
+        }
+        if self.pending_dots == 0 {
+            self.apply_mode();
+        }
+        self.pending_dots += dots;
+    }
+
+    // Run the banked cycles
+    fn catch_up(&mut self) {
+        self.mode_cycles += self.pending_dots;
+        self.pending_dots = 0;
+    }
+
+    // Same as `cycles` calls to update_cycle; `cycles` must not exceed cycles_until_event()
+    pub(crate) fn advance(&mut self, cycles: u32) -> Option<InterruptType> {
+        if cycles < self.cycles_until_event() {
+            self.skip(cycles);
+            None
+        } else {
+            self.skip(cycles - 1);
+            self.update_cycle()
+        }
+    }
+
+    // Cycles within a mode: only the mode counter moves. The rest of what update_cycle does on
+    // every cycle of a mode gives the same result each time, so doing it once is enough.
+    fn skip(&mut self, cycles: u32) {
+        if cycles == 0 || self.lcdc & 0x80 == 0 {
+            return;
+        }
+        self.mode_cycles += cycles;
+        self.apply_mode();
+    }
+
+    // The part of update_cycle that only depends on the current mode
+    fn apply_mode(&mut self) {
+        match self.mode {
+            LcdMode::OamScan => {
+                self.oam_accessible = false;
+                self.vram_accessible = true;
+                if self.ly == self.wy && (self.lcdc & 0x20) != 0 {
+                    self.wy_triggered = true;
+                    self.last_frame_window_active = true;
+                }
+            },
+            LcdMode::Drawing => {
+                self.oam_accessible = false;
+                self.vram_accessible = false;
+            },
+            LcdMode::HBlank | LcdMode::VBlank => {
+                self.oam_accessible = true;
+                self.vram_accessible = true;
+            },
+        }
+        self.stat = (self.stat & 0xFC) | (self.mode as u8 & 0x3);
+    }
+
+	// Update the PPU for a single cycle
+    pub fn update_cycle(&mut self) -> Option<InterruptType> {
+        // Skip if LCD is off
+        if self.lcdc & 0x80 == 0 {
+            return None;
+        }
+        self.catch_up();
+        
+        // Store old state for edge detection
+        let _old_mode = self.mode;
+        let _old_ly = self.ly;
+        
+        // Initialize interrupt to None
+        let mut interrupt = None;
+        
+        // Add cycles to mode counter
+        self.mode_cycles += 1;
+        
+        // PPU state machine
+        match self.mode {
+            LcdMode::OamScan => { // Mode 2
+                // OAM scan mode - OAM locked, VRAM accessible
+                self.oam_accessible = false;
+                self.vram_accessible = true;
+                
+                // Check WY condition at the start of Mode 2 (OAM Scan)
+                if self.ly == self.wy && (self.lcdc & 0x20) != 0 {
+                    self.wy_triggered = true;
+                    self.last_frame_window_active = true;
+                }
+                
+                // Mode 2 (OAM scan) takes 80 cycles
+                if self.mode_cycles >= 80 {
+                    // Move to Mode 3 (Drawing)
+                    self.mode = LcdMode::Drawing;
+                    self.mode_cycles -= 80;
+                    self.vram_accessible = false;
+                    
+                    // Prepare sprites for this scanline
+                    self.prepare_sprites_for_scanline();
+                }
+            },
+            
+            LcdMode::Drawing => { // Mode 3
+                // Drawing mode - both OAM and VRAM locked
+                self.oam_accessible = false;
+                self.vram_accessible = false;
+                
+                // Calculate Mode 3 length based on sprites
+                let sprite_penalty = self.sprite_penalty();
+                let drawing_time = 172 + sprite_penalty;
+                
+                if self.mode_cycles >= drawing_time {
+                    // Move to Mode 0 (HBlank)
+                    self.mode = LcdMode::HBlank;
+                    self.mode_cycles -= drawing_time;
+                    self.vram_accessible = true;
+                    self.oam_accessible = true;
+                    if let Some(timing) = self.frame_mode3.get_mut(self.ly as usize) {
+                        *timing = Mode3Timing {
+                            dots: drawing_time as u16,
+                            sprites: self.scanline_sprites.len() as u8,
+                            sprite_penalty: sprite_penalty as u16,
+                        };
+                    }
+                    
+                    // Render this scanline
+                    self.render_scanline();
+                    trace!(ly = self.ly, mode3_dots = drawing_time, sprites = self.scanline_sprites.len(), "scanline");
+                    
+                    // Update window line counter after rendering
+                    if self.wy_triggered && self.ly >= self.wy {
+                        self.window_line = self.window_line.wrapping_add(1);
+                    }
+                }
+            },
+            
+            LcdMode::HBlank => { // Mode 0
+                // HBlank mode - both OAM and VRAM accessible
+                self.oam_accessible = true;
+                self.vram_accessible = true;
+                
+                // Calculate HBlank duration
+                let sprite_penalty = self.sprite_penalty();
+                let hblank_time = 456 - (80 + 172 + sprite_penalty);
+                
+                if self.mode_cycles >= hblank_time {
+                    self.mode_cycles -= hblank_time;
+                    
+                    // Increment LY
+                    self.ly = (self.ly + 1) % 154;
+                    
+                    // Check window activation on LY change
+                    if self.ly == self.wy && (self.lcdc & 0x20) != 0 {
+                        self.wy_triggered = true;
+                        self.last_frame_window_active = true;
+                    }
+                    
+                    // Check if we've reached the end of visible screen
+                    if self.ly == 144 {
+                        // Enter VBlank (Mode 1)
+                        self.mode = LcdMode::VBlank;
+                        self.frame_ready = true;
+                        trace!("vblank");
+                        self.last_window_stats = core::mem::take(&mut self.frame_window_stats);
+                        self.last_mode3 = core::mem::replace(&mut self.frame_mode3, [Mode3Timing::default(); SCREEN_HEIGHT]);
+                        
+                        // VBlank interrupt is always generated
+                        interrupt = Some(InterruptType::VBlank);
+                    } else {
+                        // Start next scanline with OAM scan (Mode 2)
+                        self.mode = LcdMode::OamScan;
+                    }
+                }
+            },
+            
+            LcdMode::VBlank => { // Mode 1
+                // VBlank mode - both OAM and VRAM accessible
+                self.oam_accessible = true;
+                self.vram_accessible = true;
+                
+                // Each scanline in VBlank still takes 456 cycles
+                if self.mode_cycles >= 456 {
+                    self.mode_cycles -= 456;
+                    
+                    // Increment LY
+                    self.ly = (self.ly + 1) % 154;
+                    
+                    // Check for end of VBlank
+                    if self.ly == 0 {
+                        // Always reset window line counter at frame start
+                        self.window_line = 0;
+                        self.last_frame_window_active = false;
+                        self.wy_triggered = false;
+                        
+                        // Start new frame with OAM scan (Mode 2)
+                        self.mode = LcdMode::OamScan;
+                    }
+                }
+            },
+        }
+        
+        // Update STAT register with current mode
+        let mode_bits = self.mode as u8;
+        self.stat = (self.stat & 0xFC) | (mode_bits & 0x3);
+        
+        interrupt
+    }
+
+    // Prepare sprites for the current scanline (OAM scan)
+    fn prepare_sprites_for_scanline(&mut self) {
+        self.scanline_sprites.clear();
+        
+        // If objects are disabled, don't collect any sprites
+        if self.lcdc & 0x02 == 0 {
+            return;
+        }
+        
+        // Determine sprite size based on LCDC bit 2
+        let sprite_size = if self.lcdc & 0x04 != 0 { 16 } else { 8 };
+        
+        // First pass: collect all sprites visible on this scanline
+        for (idx, sprite) in self.oam_entries.iter().enumerate() {
+            // Check if sprite is on the current scanline
+            if sprite.is_on_scanline(self.ly, sprite_size) {
+                // Include all sprites in range, even those with X=0
+                // They count toward the 10 sprite limit even if not rendered
+                self.scanline_sprites.push((idx, *sprite));
+            }
+        }
+        
+        // Sort sprites according to DMG priority rules:
+        // 1. Lower X-coordinate has higher priority
+        // 2. If X-coordinates are equal, lower OAM index has higher priority
+        self.scanline_sprites.sort_by(|(idx_a, sprite_a), (idx_b, sprite_b)| {
+            sprite_a.x_pos.cmp(&sprite_b.x_pos)
+                .then_with(|| idx_a.cmp(idx_b))
+        });
+        
+        // Limit to 10 sprites per scanline (DMG hardware limitation), unless lifted as an enhancement
+        if self.accuracy.sprite_limit && self.scanline_sprites.len() > 10 {
+            self.scanline_sprites.truncate(10);
+        }
+        
+        // Reverse the array so we can process from highest priority to lowest
+        // This makes the rendering code cleaner as earlier sprites overwrite later ones
+        self.scanline_sprites.reverse();
+    }
+
+	// Render a single scanline to the frame buffer
+    fn render_scanline(&mut self) {
+        // Only render if LCD is enabled
+        if self.lcdc & 0x80 == 0 {
+            return;
+        }
+        
+        self.refresh_tiles();
+
+        // Create a scanline buffer for priority handling
+        let mut scanline_buffer = [([0u8; 4], false, PixelSource::BACKGROUND_0); SCREEN_WIDTH];
+        
+        // Background
+        if self.lcdc & 0x01 != 0 { // BG enabled
+            self.render_background(&mut scanline_buffer);
+        } else {
+            // If background is disabled, fill with color 0
+            let [r, g, b] = self.palette.0[0];
+            for x in 0..SCREEN_WIDTH {
+                scanline_buffer[x] = ([r, g, b, 255], false, PixelSource::BACKGROUND_0);
+            }
+        }
+        
+        // Window
+        /*if self.lcdc & 0x20 != 0 { // Window enabled
+            self.render_window(&mut scanline_buffer);
+        }*/
+
+        let window_drawn = self.lcdc & 0x20 != 0 && self.last_frame_window_active // Window enabled
+            && self.render_window(&mut scanline_buffer);
+        if window_drawn {
+            self.frame_window_stats.lines_drawn = self.frame_window_stats.lines_drawn.saturating_add(1);
+        } else if self.wy_triggered {
+            self.frame_window_stats.lines_skipped = self.frame_window_stats.lines_skipped.saturating_add(1);
+        }
+        
+        // Sprites
+        if self.lcdc & 0x02 != 0 { // Sprites enabled
+            self.render_sprites(&mut scanline_buffer);
+        }
+        
+        // Now transfer scanline buffer to frame buffer
+        self.finalize_scanline(&scanline_buffer);
+    }
+
+	// Render the background for the current scanline
+    fn render_background(&mut self, scanline_buffer: &mut [([u8; 4], bool, PixelSource)]) {
+        // Get tile map address based on LCDC bit 3
+        let tile_map_addr = if self.lcdc & 0x08 != 0 { 0x9C00 } else { 0x9800 };
+        
+        // Get tile data addressing mode based on LCDC bit 4
+        let tile_data_signed = self.lcdc & 0x10 == 0;
+        
+        // Calculate y position within background
+        let y_pos = self.ly.wrapping_add(self.scy);
+        
+        // Calculate which tile row we're on
+        let tile_row = (y_pos / 8) as u16;
+        
+        // Calculate which pixel row within the tile
+        let tile_y = (y_pos % 8) as usize;
+        
+        // Walk the line a tile at a time: look up the map entry and tile row once, then emit
+        // its pixels. Only the first tile can start part way in (SCX % 8).
+        let mut x = 0;
+        let mut x_pos = self.scx;
+        while x < SCREEN_WIDTH {
+            // Get the tile index from the tile map
+            let tile_map_index = tile_map_addr + tile_row * 32 + (x_pos / 8) as u16;
+            let tile = Self::tile_number(self.read_vram(tile_map_index), tile_data_signed);
+            let pixels = &self.tiles[tile][tile_y][(x_pos % 8) as usize..];
+            let count = pixels.len().min(SCREEN_WIDTH - x);
+            
+            // Map to real colors and mark non-zero pixels for sprite priority
+            for (slot, &color_idx) in scanline_buffer[x..x + count].iter_mut().zip(pixels) {
+                *slot = (self.bg_colors[color_idx as usize], color_idx > 0, PixelSource::background(color_idx));
+            }
+            x += count;
+            x_pos = x_pos.wrapping_add(count as u8);
+        }
+    }
+    
+    // Render the window for the current scanline
+    /*fn render_window(&mut self, scanline_buffer: &mut [(u8, bool)]) {
+        // Check if window is disabled by LCDC bit 5
+        if self.lcdc & 0x20 == 0 {
+            return;
+        }
+        
+        // In DMG mode, window is also disabled if BG is disabled (LCDC bit 0)
+        if self.lcdc & 0x01 == 0 {
+            return;
+        }
+        
+        // Check if WY condition was triggered for this frame
+        if !self.wy_triggered {
+            return;
+        }
+        
+        // Check if window X position is valid
+        // WX=7 puts the window at the left edge of the screen
+        // WX>=167 means window is not visible on this scanline
+        if self.wx > 166 {
+            return;
+        }
+        
+        // Get window tile map address based on LCDC bit 6
+        let tile_map_addr = if self.lcdc & 0x40 != 0 { 0x9C00 } else { 0x9800 };
+        
+        // Get tile data address based on LCDC bit 4
+        let tile_data_signed = self.lcdc & 0x10 == 0;
+        let tile_data_addr = if !tile_data_signed { 0x8000 } else { 0x8800 };
+        
+        // Use internal window line counter
+        let window_y = self.window_line;
+        
+        // Calculate which tile row we're on
+        let tile_row = (window_y / 8) as u16;
+        
+        // Calculate which pixel row within the tile
+        let tile_y = (window_y % 8) as u16;
+        
+        // Flag to track if we actually rendered any window pixels
+        let mut rendered = false;
+        
+        // For each pixel in the scanline
+        for x in 0..SCREEN_WIDTH {
+            // Skip pixels that are before the window's X position
+            // WX-7 is the actual starting X position on the screen
+            let wx_adjusted = if self.wx < 7 { 0 } else { self.wx - 7 };
+            if (x as u8) < wx_adjusted {
+                continue;
+            }
+            
+            rendered = true;
+            
+            // Calculate X position within window
+            let window_x = (x as u8).wrapping_sub(wx_adjusted);
+            
+            // Calculate which tile column we're on
+            let tile_col = (window_x / 8) as u16;
+            
+            // Calculate which pixel column within the tile
+            let tile_x = (window_x % 8) as u16;
+            
+            // Calculate tile index address in the tile map
+            let tile_map_index = tile_map_addr + tile_row * 32 + tile_col;
+            
+            // Get the tile index from the tile map
+            let tile_index = self.read_vram(tile_map_index);
+            
+            // Calculate tile data address
+            let tile_data_index = if !tile_data_signed {
+                tile_data_addr + (tile_index as u16) * 16
+            } else {
+                // $8800 addressing uses signed tile indices
+                tile_data_addr + ((tile_index as i8 as i16 + 128) as u16) * 16
+            };
+            
+            // Read the two bytes of tile data for this row
+            let tile_data_low = self.read_vram(tile_data_index + tile_y * 2);
+            let tile_data_high = self.read_vram(tile_data_index + tile_y * 2 + 1);
+            
+            // Calculate the bit position within the tile data
+            let bit_pos = 7 - tile_x;
+            
+            // Get the pixel color (2 bits, one from each byte)
+            let color_bit_low = (tile_data_low >> bit_pos) & 0x01;
+            let color_bit_high = (tile_data_high >> bit_pos) & 0x01;
+            let color_idx = (color_bit_high << 1) | color_bit_low;
+            
+            // Map to real color from the palette
+            let color = self.get_color(color_idx, self.bgp);
+            
+            // Store in the scanline buffer
+            scanline_buffer[x] = (color, color_idx > 0);
+        }
+        
+        // Only increment window line counter if we actually rendered any window pixels
+        if rendered {
+            self.window_line += 1;
+            //self.last_frame_window_active = true;
+        }
+    }*/
+
+    // Returns whether the window was drawn on this line
+    fn render_window(&mut self, scanline_buffer: &mut [([u8; 4], bool, PixelSource)]) -> bool {
+        // Should we be checkin wy or wx ?
+        if self.lcdc & 0x20 == 0 || self.wy > 143 || !self.wy_triggered {
+            return false;
+        }
+
+        let wx_adj = self.wx.saturating_sub(7);
+        let tile_map_addr = if self.lcdc & 0x40 != 0 { 0x9C00 } else { 0x9800 };
+        let signed_tiles = (self.lcdc & 0x10) == 0;
+
+        let window_y = self.window_line;
+        let tile_row = (window_y / 8) as u16;
+        let tile_y = (window_y % 8) as usize;
+
+        for pixel_x in 0..SCREEN_WIDTH {
+            let wx_start = wx_adj as i16;
+            let x_start = wx_start.clamp(0, 159) as usize;
+            if pixel_x < x_start {
+                continue;
+            }
+
+            let window_x = (pixel_x - x_start) as u16;
+            let tile_col = window_x / 8;
+            let tile_x = (window_x % 8) as usize;
+            
+            let tile_map_index = tile_map_addr + tile_row * 32 + tile_col;
+            let tile_index = self.read_vram(tile_map_index);
+            let tile = Self::tile_number(tile_index, signed_tiles);
+            let color_idx = self.tiles[tile][tile_y][tile_x];
+            let color = self.bg_colors[color_idx as usize];
+            
+            scanline_buffer[pixel_x] = (color, color_idx > 0, PixelSource::window(color_idx));
+        }
+        self.last_frame_window_active = true;
+        true
+    }
+    
+    // Render the sprites for the current scanline
+    fn render_sprites(&mut self, scanline_buffer: &mut [([u8; 4], bool, PixelSource)]) {
+        // Skip sprite rendering entirely if sprites are disabled
+        if self.lcdc & 0x02 == 0 {
+            return;
+        }
+    
+        // Get sprite size (8x8 or 8x16)
+        let sprite_size = if self.lcdc & 0x04 != 0 { 16 } else { 8 };
+        
+        // Process the sprites that were found during OAM scan
+        // Important: DMG renders sprites from lowest X-coordinate to highest
+        // with OAM index as tie-breaker, so we should process in reverse order
+        // since our prepare_sprites_for_scanline sorts by X and then OAM index
+        for &(oam_index, sprite) in self.scanline_sprites.iter() {
+            let sprite_y = sprite.y_pos.wrapping_sub(16);
+            let sprite_x = sprite.x_pos.wrapping_sub(8);
+            
+            // Skip sprites with X=0 (these count toward the 10 sprite limit but aren't rendered)
+            if sprite.x_pos == 0 {
+                continue;
+            }
+            
+            // Get sprite attributes
+            let priority = sprite.has_priority(); // OBJ-to-BG Priority (bit 7)
+            let flip_y = sprite.is_y_flipped();
+            let flip_x = sprite.is_x_flipped();
+            let colors = self.obj_colors[sprite.palette() as usize];
+            let palette = if sprite.palette() == 0 { PixelPalette::Obp0 } else { PixelPalette::Obp1 };
+            
+            // Calculate the correct tile index for the sprite
+            let mut tile_idx = sprite.tile_idx as usize;
+            
+            // For 8x16 sprites, bit 0 of the tile index is ignored
+            if sprite_size == 16 {
+                tile_idx &= 0xFE; // Clear bit 0
+            }
+            
+            // Calculate the y offset within the sprite
+            let mut y_offset = (self.ly - sprite_y) as usize;
+            if flip_y {
+                y_offset = sprite_size - 1 - y_offset;
+            }
+            
+            // For 8x16 sprites, determine if we're in the bottom tile
+            if sprite_size == 16 && y_offset >= 8 {
+                tile_idx += 1; // Use next tile for bottom half
+                y_offset -= 8; // Adjust offset for the second tile
+            }
+            
+            // Sprites always use $8000 addressing mode
+            let tile_row = self.tiles[tile_idx][y_offset];
+            
+            // For each pixel in the sprite's width
+            for x_offset in 0..8 {
+                // Calculate the screen X position
+                let screen_x = sprite_x.wrapping_add(x_offset);
+                
+                // Skip if outside screen bounds
+                if screen_x >= SCREEN_WIDTH as u8 {
+                    continue;
+                }
+                
+                // Pick the tile column based on flip status
+                let column = if flip_x { 7 - x_offset } else { x_offset };
+                let color_idx = tile_row[column as usize];
+                
+                // Color 0 is transparent for sprites - skip this pixel
+                if color_idx == 0 {
+                    continue;
+                }
+                
+                // Map to actual color using the appropriate palette
+                let color = colors[color_idx as usize];
+                
+                // Get the background pixel color and priority flag
+                let x = screen_x as usize;
+                let (_, bg_color_nonzero, _) = scanline_buffer[x];
+                let source = PixelSource { layer: PixelLayer::Sprite(oam_index as u8), color_index: color_idx, palette };
+                
+                // Priority rules:
+                // 1. If BG color is 0, sprite always shows
+                // 2. Otherwise, if sprite priority bit is 0, sprite shows
+                // 3. Otherwise, if BG is enabled (LCDC.0) and BG pixel is non-zero, BG shows
+                
+                if !bg_color_nonzero || !priority {
+                    // Either BG is color 0 or sprite has priority over BG
+                    scanline_buffer[x] = (color, false, source);
+                } else if self.lcdc & 0x01 == 0 {
+                    // Background is disabled, so draw sprite regardless of priority
+                    scanline_buffer[x] = (color, false, source);
+                }
+                // Otherwise, BG has priority, so keep the background pixel
+            }
+        }
+    }
+
+    // Transfer the scanline buffer to the frame buffer with color mapping
+    fn finalize_scanline(&mut self, scanline_buffer: &[([u8; 4], bool, PixelSource)]) {
+        let ly = self.ly as usize;
+        if ly >= SCREEN_HEIGHT {
+            return; // Safety check
+        }
+        
+        // Pixels were already resolved to RGBA through the palette LUTs
+        let row = &mut self.frame_buffer[ly * SCREEN_WIDTH * 4..(ly + 1) * SCREEN_WIDTH * 4];
+        for (pixel, (color, _, _)) in row.chunks_exact_mut(4).zip(scanline_buffer) {
+            pixel.copy_from_slice(color);
+        }
+        let sources = &mut self.pixel_sources[ly * SCREEN_WIDTH..(ly + 1) * SCREEN_WIDTH];
+        for (source, &(_, _, from)) in sources.iter_mut().zip(scanline_buffer) {
+            *source = from;
+        }
+        self.lines_drawn += 1;
+    }
+    
+    // Get a color from a palette
+    fn get_color(&self, color_idx: u8, palette: u8) -> u8 {
+        let idx = 2 * color_idx;
+        
+        (palette >> idx) & 0x03
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LCD and background on, no sprites, so mode 3 is the minimum 172 dots
+    fn ppu_at(mode: LcdMode, dots: u32, ly: u8) -> Ppu {
+        let mut ppu = Ppu::new(Model::Dmg);
+        ppu.write_register(LCDC, 0x91);
+        ppu.set_state(PpuState { mode, dots, ly, window_line: 0, wy_triggered: false, window_active: false, scx: 0, scy: 0 });
+        ppu
+    }
+
+    #[test]
+    fn snapshots_ignore_access_locks() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        ppu.write_vram(0x9800, 0x12);
+        ppu.write_oam(0xFE00, 0x34);
+        ppu.write_register(LCDC, 0x91);
+        ppu.set_state(PpuState { mode: LcdMode::OamScan, dots: 79, ly: 0, window_line: 0, wy_triggered: false, window_active: false, scx: 0, scy: 0 });
+        ppu.add_dots(1);
+        assert_eq!((ppu.read_vram(0x9800), ppu.read_oam(0xFE00)), (0xFF, 0xFF));
+        assert_eq!((ppu.vram_snapshot()[0x1800], ppu.oam_snapshot()[0]), (0x12, 0x34));
+    }
+
+    #[test]
+    fn sprite_limit_can_be_lifted() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        for sprite in 0..12u16 {
+            ppu.write_oam(0xFE00 + sprite * 4, 16);
+            ppu.write_oam(0xFE01 + sprite * 4, 8 + sprite as u8);
+        }
+        ppu.write_register(LCDC, 0x93);
+        ppu.prepare_sprites_for_scanline();
+        assert_eq!(ppu.scanline_sprites.len(), 10);
+        ppu.set_accuracy(Accuracy { sprite_limit: false, ..Accuracy::default() });
+        ppu.prepare_sprites_for_scanline();
+        assert_eq!(ppu.scanline_sprites.len(), 12);
+    }
+
+    #[test]
+    fn oam_scan_lasts_80_dots() {
+        let mut ppu = ppu_at(LcdMode::OamScan, 0, 0);
+        ppu.add_dots(79);
+        assert_eq!((ppu.state().mode, ppu.state().dots), (LcdMode::OamScan, 79));
+        assert!(ppu.oam_locked() && !ppu.vram_locked());
+        ppu.add_dots(1);
+        assert_eq!((ppu.state().mode, ppu.state().dots), (LcdMode::Drawing, 0));
+        assert!(ppu.oam_locked() && ppu.vram_locked());
+    }
+
+    #[test]
+    fn line_is_456_dots() {
+        let mut ppu = ppu_at(LcdMode::OamScan, 0, 5);
+        ppu.add_dots(80 + 172);
+        assert_eq!((ppu.state().mode, ppu.state().ly), (LcdMode::HBlank, 5));
+        ppu.add_dots(456 - 80 - 172);
+        assert_eq!(ppu.state(), ppu_at(LcdMode::OamScan, 0, 6).state());
+        assert_eq!(ppu.read_register(STAT) & 0x03, LcdMode::OamScan as u8);
+    }
+
+    #[test]
+    fn vblank_starts_after_line_143() {
+        let mut ppu = ppu_at(LcdMode::HBlank, 203, 143);
+        assert!(matches!(ppu.add_dots(1), Some(InterruptType::VBlank)));
+        assert_eq!((ppu.state().mode, ppu.state().ly), (LcdMode::VBlank, 144));
+
+        let mut ppu = ppu_at(LcdMode::VBlank, 455, 153);
+        assert!(ppu.add_dots(1).is_none());
+        assert_eq!((ppu.state().mode, ppu.state().ly), (LcdMode::OamScan, 0));
+    }
+
+    #[test]
+    fn window_line_resets_at_frame_start() {
+        let mut ppu = ppu_at(LcdMode::VBlank, 0, 153);
+        ppu.set_state(PpuState { window_line: 40, wy_triggered: true, window_active: true, ..ppu.state() });
+        ppu.add_dots(456);
+        let state = ppu.state();
+        assert_eq!((state.window_line, state.wy_triggered, state.window_active), (0, false, false));
+    }
+
+    #[test]
+    fn pixel_sources_name_the_layer() {
+        let mut ppu = Ppu::new(Model::Dmg);
+        ppu.write_register(LCDC, 0x00);
+        // Tile 1 is solid color 3; the background map is tile 0 and the window map tile 1
+        for addr in 0x8010..0x8020 {
+            ppu.write_vram(addr, 0xFF);
+        }
+        for addr in 0x9C00..0x9C20 {
+            ppu.write_vram(addr, 0x01);
+        }
+        // Sprite 4 at screen x 8-15, window from x 80
+        for (offset, value) in [16, 16, 1, 0].into_iter().enumerate() {
+            ppu.write_oam(0xFE10 + offset as u16, value);
+        }
+        ppu.write_register(WY, 0);
+        ppu.write_register(WX, 87);
+        ppu.write_register(LCDC, 0xF3);
+        ppu.set_state(ppu_at(LcdMode::OamScan, 0, 0).state());
+        ppu.add_dots(80 + 172 + 8);
+
+        let sources = ppu.pixel_sources();
+        assert_eq!(sources[0], PixelSource::background(0));
+        assert_eq!(sources[10], PixelSource { layer: PixelLayer::Sprite(4), color_index: 3, palette: PixelPalette::Obp0 });
+        assert_eq!(sources[100], PixelSource::window(3));
+        assert_eq!(sources[SCREEN_WIDTH + 10], PixelSource::default());
+    }
+
+    #[test]
+    fn state_round_trip() {
+        let state = PpuState { mode: LcdMode::Drawing, dots: 100, ly: 77, window_line: 12, wy_triggered: true, window_active: true, scx: 3, scy: 200 };
+        let mut w = StateWriter::raw();
+        state.save(&mut w);
+        let data = w.finish();
+        assert_eq!(PpuState::load(&mut StateReader::raw(&data)), Ok(state));
+
+        let mut bad = data.clone();
+        bad[5] = 154;
+        assert_eq!(PpuState::load(&mut StateReader::raw(&bad)), Err(SaveStateError::InvalidValue("LY")));
+    }
+}
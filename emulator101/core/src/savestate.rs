@@ -0,0 +1,242 @@
+// Savestate serialization
+// A savestate is a flat little-endian byte stream: a small header followed by the
+// state of every component in a fixed order (CPU, bus, timer, PPU, clock). The header also
+// describes the state for a slot picker (see StateInfo), so that can be read without loading it.
+//
+// Everything after the fixed header fields (the thumbnail and the components) is one LZ4 block,
+// preceded by its uncompressed size. Most of a state is RAM that is zero or repeats, so this
+// brings a DMG state from about 60 KiB down to a few KiB and keeps quick saves and rewind
+// buffers small; LZ4 is fast enough to compress a state every frame. The ROM is never part of a
+// state, only its hash, which ties the state to the game it was made with.
+
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use thiserror::Error;
+
+use crate::ppu::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+const MAGIC: &[u8; 4] = b"GBSS";
+const VERSION: u32 = 18;
+
+// Largest uncompressed body accepted, so a corrupt size can't ask for gigabytes
+const MAX_BODY_SIZE: usize = 16 << 20;
+
+// Size of the screenshot in a savestate: the screen at half size
+pub const THUMBNAIL_WIDTH: usize = SCREEN_WIDTH / 2;
+pub const THUMBNAIL_HEIGHT: usize = SCREEN_HEIGHT / 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum SaveStateError {
+    #[error("not a savestate file")]
+    BadMagic,
+    #[error("unsupported savestate version {0}")]
+    UnsupportedVersion(u32),
+    #[error("savestate was created with a different ROM")]
+    RomMismatch,
+    #[error("savestate is truncated")]
+    UnexpectedEof,
+    #[error("savestate contains an invalid {0}")]
+    InvalidValue(&'static str),
+    #[error("savestate data is corrupt")]
+    Corrupt,
+}
+
+// Simple hash of the ROM contents so a state is never loaded into the wrong game (FNV-1a)
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for &byte in rom {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash
+}
+
+// What a savestate says about itself (see info)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateInfo {
+    pub rom_hash: u64,
+    // Wall-clock time it was made, since the Unix epoch
+    pub saved_at: Duration,
+    // Emulated time since power-on
+    pub play_time: Duration,
+    // The screen at the time, RGBA32, THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT pixels
+    pub thumbnail: Vec<u8>,
+}
+
+impl StateInfo {
+    // Average each 2x2 block of an RGBA32 frame of SCREEN_WIDTH * SCREEN_HEIGHT pixels
+    pub fn thumbnail_of(frame: &[u8]) -> Vec<u8> {
+        let mut thumbnail = Vec::with_capacity(THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 4);
+        for y in 0..THUMBNAIL_HEIGHT {
+            for x in 0..THUMBNAIL_WIDTH {
+                for channel in 0..4 {
+                    let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)].iter()
+                        .map(|(dx, dy)| frame[((y * 2 + dy) * SCREEN_WIDTH + x * 2 + dx) * 4 + channel] as u32)
+                        .sum();
+                    thumbnail.push((sum / 4) as u8);
+                }
+            }
+        }
+        thumbnail
+    }
+}
+
+// Read the header of a savestate, from any ROM
+pub fn info(data: &[u8]) -> Result<StateInfo, SaveStateError> {
+    unpack_any(data).map(|(info, _)| info)
+}
+
+// Check that a savestate was made with the ROM of `rom_hash` and decompress its components, to
+// be read with StateReader::raw
+pub fn unpack(data: &[u8], rom_hash: u64) -> Result<Vec<u8>, SaveStateError> {
+    let (info, components) = unpack_any(data)?;
+    if info.rom_hash != rom_hash {
+        return Err(SaveStateError::RomMismatch);
+    }
+    Ok(components)
+}
+
+fn unpack_any(data: &[u8]) -> Result<(StateInfo, Vec<u8>), SaveStateError> {
+    let mut r = StateReader::raw(data);
+    if r.read_bytes(4)? != MAGIC {
+        return Err(SaveStateError::BadMagic);
+    }
+    let version = r.read_u32()?;
+    if version != VERSION {
+        return Err(SaveStateError::UnsupportedVersion(version));
+    }
+    let rom_hash = r.read_u64()?;
+    let saved_at = Duration::from_secs(r.read_u64()?);
+    let play_time = Duration::from_nanos(r.read_u64()?);
+    let size = r.read_u32()? as usize;
+    if size > MAX_BODY_SIZE {
+        return Err(SaveStateError::Corrupt);
+    }
+    let mut body = lz4_flex::block::decompress(&data[r.pos..], size).map_err(|_| SaveStateError::Corrupt)?;
+    if body.len() != size {
+        return Err(SaveStateError::Corrupt);
+    }
+    let thumbnail_size = THUMBNAIL_WIDTH * THUMBNAIL_HEIGHT * 4;
+    if body.len() < thumbnail_size {
+        return Err(SaveStateError::UnexpectedEof);
+    }
+    let components = body.split_off(thumbnail_size);
+    Ok((StateInfo { rom_hash, saved_at, play_time, thumbnail: body }, components))
+}
+
+pub struct StateWriter {
+    buf: Vec<u8>,
+    // Where the compressed body starts, for savestates
+    body_start: Option<usize>,
+}
+
+impl StateWriter {
+    pub fn new(info: &StateInfo) -> Self {
+        let mut writer = Self::raw();
+        writer.write_bytes(MAGIC);
+        writer.write_u32(VERSION);
+        writer.write_u64(info.rom_hash);
+        writer.write_u64(info.saved_at.as_secs());
+        writer.write_u64(info.play_time.as_nanos() as u64);
+        writer.body_start = Some(writer.buf.len());
+        writer.write_bytes(&info.thumbnail);
+        writer
+    }
+
+    // No savestate header or compression, for other formats built from the same pieces
+    pub fn raw() -> Self {
+        Self { buf: Vec::new(), body_start: None }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        let Some(start) = self.body_start else {
+            return self.buf;
+        };
+        let compressed = lz4_flex::block::compress(&self.buf[start..]);
+        let size = (self.buf.len() - start) as u32;
+        self.buf.truncate(start);
+        self.write_u32(size);
+        self.buf.extend_from_slice(&compressed);
+        self.buf
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    // Read data without a savestate header, such as the components from unpack (see
+    // StateWriter::raw)
+    pub fn raw(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, SaveStateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, SaveStateError> {
+        let mut bytes = [0u8; 2];
+        bytes.copy_from_slice(self.read_bytes(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, SaveStateError> {
+        let mut bytes = [0u8; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, SaveStateError> {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.pos + len > self.data.len() {
+            return Err(SaveStateError::UnexpectedEof);
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    // Fill a fixed-size buffer (VRAM, WRAM, ...) from the stream
+    pub fn read_into(&mut self, dest: &mut [u8]) -> Result<(), SaveStateError> {
+        let bytes = self.read_bytes(dest.len())?;
+        dest.copy_from_slice(bytes);
+        Ok(())
+    }
+}
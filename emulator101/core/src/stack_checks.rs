@@ -0,0 +1,71 @@
+// Stack misuse heuristics
+// Optional checks on the stack pointer after every instruction, for bugs that are otherwise
+// silent: a stack in ROM or VRAM (pushes are lost or land in tile data), a stack grown down over
+// the OAM DMA routine in high RAM (the next DMA jumps into garbage), and pops past 0xFFFE that
+// wrap SP around to the bottom of memory. Each finding becomes a Warning diagnostic, reported
+// when SP first gets into that state rather than after every instruction.
+
+use core::fmt;
+
+use crate::memory::MemoryBus;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StackMisuse {
+    InRom,
+    InVram,
+    OverDmaRoutine,
+    Underflow,
+}
+
+impl fmt::Display for StackMisuse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StackMisuse::InRom => "stack pointer in ROM",
+            StackMisuse::InVram => "stack pointer in VRAM",
+            StackMisuse::OverDmaRoutine => "stack grew over the OAM DMA routine",
+            StackMisuse::Underflow => "stack underflow past 0xFFFE",
+        })
+    }
+}
+
+// Bytes around the `LDH (0x46),A` that make up a typical DMA routine:
+// LD A,n / LDH (0x46),A / LD A,0x28 / DEC A / JR NZ,-3 / RET
+const DMA_ROUTINE_BEFORE: u16 = 2;
+const DMA_ROUTINE_AFTER: u16 = 8;
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StackChecks {
+    // High RAM the game runs its DMA routine from, once it has been seen
+    dma_routine: Option<(u16, u16)>,
+    // What SP was last reported for, until it is fine again
+    reported: Option<StackMisuse>,
+}
+
+impl StackChecks {
+    // Check the instruction at `pc` that moved SP from `old_sp` to `sp`; returns a newly found misuse
+    pub(crate) fn check(&mut self, bus: &MemoryBus, pc: u16, old_sp: u16, sp: u16) -> Option<StackMisuse> {
+        if (0xFF80..0xFFFE).contains(&pc) && bus.read_byte(pc) == 0xE0 && bus.read_byte(pc + 1) == 0x46 {
+            let start = pc.saturating_sub(DMA_ROUTINE_BEFORE).max(0xFF80);
+            self.dma_routine = Some((start, pc.saturating_add(DMA_ROUTINE_AFTER).min(0xFFFE)));
+        }
+
+        let misuse = if old_sp >= 0xFF00 && sp < 0x0100 && sp < old_sp {
+            Some(StackMisuse::Underflow)
+        } else if sp < 0x8000 {
+            Some(StackMisuse::InRom)
+        } else if sp < 0xA000 {
+            Some(StackMisuse::InVram)
+        } else if self.dma_routine.is_some_and(|(start, end)| (start..end).contains(&sp)) {
+            Some(StackMisuse::OverDmaRoutine)
+        } else {
+            None
+        };
+        // Once wrapped around, SP sits in ROM: that is still the same underflow
+        if self.reported == Some(StackMisuse::Underflow) && misuse == Some(StackMisuse::InRom) {
+            return None;
+        }
+        let new = misuse.filter(|&misuse| self.reported != Some(misuse));
+        self.reported = misuse;
+        new
+    }
+}
@@ -0,0 +1,85 @@
+// Performance counters
+// Running totals for a frontend's performance HUD. They describe the emulator session rather
+// than the emulated machine, so they keep counting across resets and state loads and are never
+// written to savestates.
+
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+use crate::clock::Clock;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    // Frames completed by the PPU
+    pub frames: u64,
+    // Emulated M-cycles
+    pub m_cycles: u64,
+    pub instructions: u64,
+    // Emulated time per host time between the last two frames (1.0 = full speed). Host timing
+    // needs the std feature; without it this and last_frame_time stay 0.
+    pub speed: f64,
+    // Host time spent inside the last run_until_frame call
+    pub last_frame_time: Duration,
+    // Host time since the first frame, and emulated minus host time over it in seconds: negative
+    // once emulation has fallen behind real time (or was paused), positive when it runs ahead
+    pub host_time: Duration,
+    pub drift: f64,
+    // Audio samples produced but not taken with pull_audio yet
+    pub audio_buffered: usize,
+    // Emulated time played with the current ROM: what Emulator::set_play_time was given (time
+    // from earlier sessions, kept by the frontend) plus the M-cycles run since
+    pub play_time: Duration,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StatsCounter {
+    pub(crate) stats: Stats,
+    // When the first and the previous frame completed, and the cycle totals at those points
+    #[cfg(feature = "std")]
+    first_frame_at: Option<(Instant, u64)>,
+    #[cfg(feature = "std")]
+    last_frame_at: Option<(Instant, u64)>,
+    // Play time before the cycle total `played_from`
+    played_before: Duration,
+    played_from: u64,
+}
+
+impl StatsCounter {
+    pub(crate) fn add_step(&mut self, m_cycles: u64, instructions: u64) {
+        self.stats.m_cycles += m_cycles;
+        self.stats.instructions += instructions;
+    }
+
+    pub(crate) fn set_play_time(&mut self, play_time: Duration) {
+        self.played_before = play_time;
+        self.played_from = self.stats.m_cycles;
+    }
+
+    pub(crate) fn play_time(&self) -> Duration {
+        self.played_before + Clock::duration_of(self.stats.m_cycles - self.played_from)
+    }
+
+    pub(crate) fn frame_completed(&mut self) {
+        self.stats.frames += 1;
+
+        #[cfg(feature = "std")]
+        self.measure_speed();
+    }
+
+    #[cfg(feature = "std")]
+    fn measure_speed(&mut self) {
+        let now = Instant::now();
+        if let Some((at, m_cycles)) = self.last_frame_at {
+            let host = now.duration_since(at).as_secs_f64();
+            if host > 0.0 {
+                self.stats.speed = Clock::duration_of(self.stats.m_cycles - m_cycles).as_secs_f64() / host;
+            }
+        }
+        self.last_frame_at = Some((now, self.stats.m_cycles));
+
+        let (start, start_cycles) = *self.first_frame_at.get_or_insert((now, self.stats.m_cycles));
+        self.stats.host_time = now.duration_since(start);
+        self.stats.drift = Clock::duration_of(self.stats.m_cycles - start_cycles).as_secs_f64() - self.stats.host_time.as_secs_f64();
+    }
+}
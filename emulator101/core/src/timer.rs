@@ -0,0 +1,452 @@
+// Timer (DIV, TIMA, TMA, TAC)
+// TIMA counts falling edges of one DIV counter bit (selected by TAC). When it overflows it
+// reads 0 for one M-cycle (cycle A), then is reloaded from TMA and requests the interrupt; the
+// M-cycle of the reload is cycle B. Writes interact with that window the way hardware does:
+//
+//   TIMA write in cycle A   replaces the reload: TIMA takes the written value, no interrupt
+//   TIMA write in cycle B   ignored, TIMA keeps the value loaded from TMA
+//   TMA write in cycle B    also goes to TIMA
+//
+// Other DIV-derived clocks (the APU frame sequencer, ...) register a DIV observer for the
+// counter bit they are driven by and collect its falling edges, so everything runs off this one
+// counter, including the resets caused by writing DIV.
+
+use alloc::vec::Vec;
+
+use crate::model::Model;
+use crate::savestate::{SaveStateError, StateReader, StateWriter};
+
+// DIV counter bit that clocks the APU frame sequencer (bit 4 of DIV, 512 Hz)
+pub const FRAME_SEQUENCER_BIT: u8 = 12;
+
+// Handle returned by Timer::add_div_observer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DivObserver(usize);
+
+// Falling edges of one DIV counter bit not collected yet
+struct DivTap {
+    bit: u8,
+    edges: u32,
+}
+
+pub struct Timer {
+    // The internal 16-bit DIV counter
+    div_counter: u16,
+    
+    // Timer registers
+    tima: u8,   // Timer counter (0xFF05)
+    tma: u8,    // Timer modulo (0xFF06)
+    tac: u8,    // Timer control (0xFF07)
+    
+    // State for edge detection
+    previous_and_result: bool,
+    
+    // State for TIMA overflow handling (cycle A)
+    tima_overflow: bool,
+    tima_overflow_cycles: u8,
+    // The M-cycle in which TIMA was reloaded from TMA (cycle B)
+    tima_reloading: bool,
+    tima_reload_cycles: u8,
+
+    // DIV observers and the OR of the bits they watch
+    taps: Vec<DivTap>,
+    tap_mask: u16,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new(Model::default())
+    }
+}
+
+impl Timer {
+    pub fn new(model: Model) -> Self {
+        Self {
+            div_counter: model.div_seed(),
+            tima: 0,
+            tma: 0,
+            tac: 0xF8,
+            previous_and_result: false,
+            tima_overflow: false,
+            tima_overflow_cycles: 0,
+            tima_reloading: false,
+            tima_reload_cycles: 0,
+            taps: Vec::new(),
+            tap_mask: 0,
+        }
+    }
+
+    // Start counting falling edges of DIV counter bit `bit` (0-15)
+    pub fn add_div_observer(&mut self, bit: u8) -> DivObserver {
+        assert!(bit < 16, "DIV counter bit out of range");
+        self.taps.push(DivTap { bit, edges: 0 });
+        self.tap_mask |= 1 << bit;
+        DivObserver(self.taps.len() - 1)
+    }
+
+    // Falling edges seen by `observer` since the last call
+    pub fn take_div_edges(&mut self, observer: DivObserver) -> u32 {
+        core::mem::take(&mut self.taps[observer.0].edges)
+    }
+
+    // Every change of the DIV counter goes through here so observers see each falling edge
+    fn set_div_counter(&mut self, value: u16) {
+        let fell = self.div_counter & !value & self.tap_mask;
+        if fell != 0 {
+            for tap in &mut self.taps {
+                if fell & (1 << tap.bit) != 0 {
+                    tap.edges += 1;
+                }
+            }
+        }
+        self.div_counter = value;
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.div_counter);
+        w.write_u8(self.tima);
+        w.write_u8(self.tma);
+        w.write_u8(self.tac);
+        w.write_bool(self.previous_and_result);
+        w.write_bool(self.tima_overflow);
+        w.write_u8(self.tima_overflow_cycles);
+        w.write_bool(self.tima_reloading);
+        w.write_u8(self.tima_reload_cycles);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), SaveStateError> {
+        self.div_counter = r.read_u16()?;
+        self.tima = r.read_u8()?;
+        self.tma = r.read_u8()?;
+        self.tac = r.read_u8()?;
+        self.previous_and_result = r.read_bool()?;
+        self.tima_overflow = r.read_bool()?;
+        self.tima_overflow_cycles = r.read_u8()?;
+        self.tima_reloading = r.read_bool()?;
+        self.tima_reload_cycles = r.read_u8()?;
+        Ok(())
+    }
+
+    // T-cycles until the next one that does more than count: a TIMA increment or a step of the
+    // overflow/reload sequence. At least 1; u32::MAX if nothing is pending.
+    pub(crate) fn cycles_until_event(&self) -> u32 {
+        if self.tima_overflow || self.tima_reloading {
+            return 1;
+        }
+        if !self.enabled() {
+            return u32::MAX;
+        }
+        // The selected bit falls when the counter reaches a multiple of twice its value
+        let period = 2u32 << self.selected_bit();
+        period - (self.div_counter as u32 & (period - 1))
+    }
+
+    // Same as `cycles` calls to update_cycle; `cycles` must not exceed cycles_until_event()
+    pub(crate) fn advance(&mut self, cycles: u32) -> bool {
+        if cycles < self.cycles_until_event() {
+            self.skip(cycles);
+            false
+        } else {
+            self.skip(cycles - 1);
+            self.update_cycle()
+        }
+    }
+
+    // Count `cycles` T-cycles on which nothing but the counter changes
+    fn skip(&mut self, cycles: u32) {
+        if cycles == 0 {
+            return;
+        }
+        let old = self.div_counter as u32;
+        let new = old + cycles;
+        for tap in &mut self.taps {
+            tap.edges += (new >> (tap.bit + 1)) - (old >> (tap.bit + 1));
+        }
+        self.div_counter = new as u16;
+        self.previous_and_result = self.enabled() && self.div_counter & (1 << self.selected_bit()) != 0;
+    }
+
+    pub fn update_cycle(&mut self) -> bool {
+        let mut interrupt_requested = false;
+        
+        // Increment the 16-bit DIV counter
+        self.set_div_counter(self.div_counter.wrapping_add(1));
+        
+        // Get the bit position to check based on TAC clock select
+        let bit_position = self.selected_bit();
+        
+        // Extract the bit from DIV counter at the specified position
+        let bit_value = (self.div_counter & (1 << bit_position)) != 0;
+        
+        // Check if timer is enabled
+        let timer_enabled = self.enabled();
+        
+        // Calculate current AND result
+        let current_and_result = bit_value && timer_enabled;
+        
+        // Check for falling edge (1->0)
+        if self.previous_and_result && !current_and_result {
+            // Increment TIMA on falling edge
+            if !self.tima_overflow {
+                let current_tima = self.tima;
+                self.tima = self.tima.wrapping_add(1);
+                
+                // Check for overflow (when TIMA goes from 0xFF to 0x00)
+                if current_tima == 0xFF {
+                    self.tima_overflow = true;
+                    self.tima_overflow_cycles = 0;
+                    // Keep TIMA at zero during the delay period
+                    self.tima = 0;
+                }
+            }
+        }
+        
+        // Update the previous AND result for next cycle
+        self.previous_and_result = current_and_result;
+        
+        // The reload window (cycle B) lasts one M-cycle
+        if self.tima_reloading {
+            self.tima_reload_cycles += 1;
+            if self.tima_reload_cycles == 4 {
+                self.tima_reloading = false;
+                self.tima_reload_cycles = 0;
+            }
+        }
+
+        // Handle TIMA overflow (if active)
+        if self.tima_overflow {
+            self.tima_overflow_cycles += 1;
+            
+            // TIMA reads as 0 during overflow
+            self.tima = 0;
+            
+            if self.tima_overflow_cycles == 4 {
+                // Reload from TMA and trigger the interrupt; cycle B starts
+                self.tima = self.tma;
+                self.tima_overflow = false;
+                self.tima_overflow_cycles = 0;
+                self.tima_reloading = true;
+                self.tima_reload_cycles = 0;
+                interrupt_requested = true;
+            }
+        }
+        
+        interrupt_requested
+    }
+    
+    // Read-only view of the internals, for debuggers
+
+    // The full 16-bit divider counter; DIV (0xFF04) is its upper byte
+    pub fn div_counter(&self) -> u16 {
+        self.div_counter
+    }
+
+    // DIV counter bit whose falling edge clocks TIMA, as selected by TAC
+    pub fn selected_bit(&self) -> u8 {
+        match self.tac & 0x03 {
+            0 => 9, // 4096HZ (check bit 9)
+            1 => 3, // 262144HZ (check bit 3)
+            2 => 5, // 65536HZ (check bit 5)
+            3 => 7, // 16384HZ (check bit 7)
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.tac & 0x04 != 0
+    }
+
+    // Selected bit AND timer enable; TIMA increments when this goes from true to false
+    pub fn input_signal(&self) -> bool {
+        self.previous_and_result
+    }
+
+    // TIMA overflowed and reads 0 until the reload from TMA (cycle A)
+    pub fn overflow_pending(&self) -> bool {
+        self.tima_overflow
+    }
+
+    // TIMA was just reloaded from TMA (cycle B)
+    pub fn reloading(&self) -> bool {
+        self.tima_reloading
+    }
+
+    // Getters and setters for timer registers
+    
+    pub fn get_div(&self) -> u8 {
+        // DIV register is the upper 8 bits of the 16-bit counter
+        (self.div_counter >> 8) as u8
+    }
+    
+    pub fn set_div(&mut self, _value: u8) {
+        // Save the old DIV value to check for falling edge
+        let old_div_counter = self.div_counter;
+        
+        // Writing to DIV resets the entire 16-bit counter to 0
+        self.set_div_counter(0);
+        
+        // This can trigger a TIMA increment if it causes a falling edge!
+        let bit_position = self.selected_bit();
+        
+        // Check if the relevant bit was high in the old counter
+        let old_bit_value = (old_div_counter & (1 << bit_position)) != 0;
+        let timer_enabled = self.enabled();
+        let old_and_result = old_bit_value && timer_enabled;
+        
+        // After reset, all bits of DIV are 0
+        let new_bit_value = false;
+        let new_and_result = new_bit_value && timer_enabled;
+        
+        // Check for falling edge caused by DIV reset
+        if old_and_result && !new_and_result {
+            // Increment TIMA if it's not already in overflow state
+            if !self.tima_overflow {
+                let (new_tima, overflow) = self.tima.overflowing_add(1);
+                self.tima = new_tima;
+                
+                if overflow {
+                    self.tima_overflow = true;
+                    self.tima_overflow_cycles = 0;
+                }
+            }
+        }
+        
+        self.previous_and_result = new_and_result;
+    }
+    
+    pub fn get_tima(&self) -> u8 {
+        self.tima
+    }
+    
+    pub fn set_tima(&mut self, value: u8) {
+        if self.tima_reloading {
+            // Cycle B: the value from TMA wins
+            return;
+        }
+        if self.tima_overflow {
+            // Cycle A: the write cancels the pending reload and interrupt
+            self.tima_overflow = false;
+            self.tima_overflow_cycles = 0;
+        }
+        self.tima = value;
+    }
+    
+    pub fn get_tma(&self) -> u8 {
+        self.tma
+    }
+    
+    pub fn set_tma(&mut self, value: u8) {
+        self.tma = value;
+        if self.tima_reloading {
+            // Cycle B: TIMA is still being loaded from TMA
+            self.tima = value;
+        }
+    }
+    
+    pub fn get_tac(&self) -> u8 {
+        self.tac
+    }
+    
+    pub fn set_tac(&mut self, value: u8) {
+        // Only bits 0-2 are used
+        let old_tac = self.tac;
+        self.tac = value & 0x07;
+        
+        // Changing TAC can trigger a TIMA increment if it causes a falling edge!
+        if old_tac != self.tac {
+            let bit_position = self.selected_bit();
+            
+            let bit_value = (self.div_counter & (1 << bit_position)) != 0;
+            let timer_enabled = self.enabled();
+            let current_and_result = bit_value && timer_enabled;
+            
+            // Check for falling edge caused by TAC change
+            if self.previous_and_result && !current_and_result {
+                // Increment TIMA if it's not already in overflow state
+                if !self.tima_overflow {
+                    let (new_tima, overflow) = self.tima.overflowing_add(1);
+                    self.tima = new_tima;
+                    
+                    if overflow {
+                        self.tima_overflow = true;
+                        self.tima_overflow_cycles = 0;
+                    }
+                }
+            }
+            
+            self.previous_and_result = current_and_result;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A timer whose TIMA just overflowed (cycle A), counting every 16 T-cycles with `tma` in TMA
+    fn overflowed(tma: u8) -> Timer {
+        let mut timer = Timer::new(Model::Dmg);
+        timer.set_div(0);
+        timer.set_tac(0x05);
+        timer.set_tma(tma);
+        timer.set_tima(0xFF);
+        while !timer.overflow_pending() {
+            assert!(!timer.update_cycle());
+        }
+        timer
+    }
+
+    // Run T-cycles until TIMA is reloaded from TMA (cycle B starts)
+    fn run_to_reload(timer: &mut Timer) {
+        for _ in 0..4 {
+            if timer.update_cycle() {
+                assert!(timer.reloading());
+                return;
+            }
+        }
+        panic!("no reload within an M-cycle of the overflow");
+    }
+
+    #[test]
+    fn overflow_reloads_from_tma_after_an_m_cycle() {
+        let mut timer = overflowed(0x80);
+        assert_eq!(timer.get_tima(), 0x00);
+        run_to_reload(&mut timer);
+        assert_eq!(timer.get_tima(), 0x80);
+    }
+
+    #[test]
+    fn tma_write_during_reload_goes_to_tima() {
+        let mut timer = overflowed(0x80);
+        run_to_reload(&mut timer);
+        timer.set_tma(0x42);
+        assert_eq!(timer.get_tima(), 0x42);
+        // Once cycle B is over TMA writes leave TIMA alone
+        for _ in 0..4 {
+            timer.update_cycle();
+        }
+        assert!(!timer.reloading());
+        timer.set_tma(0x13);
+        assert_eq!(timer.get_tima(), 0x42);
+    }
+
+    #[test]
+    fn tima_write_in_overflow_cycle_cancels_reload_and_interrupt() {
+        let mut timer = overflowed(0x80);
+        timer.set_tima(0x10);
+        assert!(!timer.overflow_pending());
+        for _ in 0..8 {
+            assert!(!timer.update_cycle(), "interrupt requested after the cancelled reload");
+        }
+        assert!(!timer.reloading());
+        assert_eq!(timer.get_tima(), 0x10);
+    }
+
+    #[test]
+    fn tima_write_during_reload_is_ignored() {
+        let mut timer = overflowed(0x80);
+        run_to_reload(&mut timer);
+        timer.set_tima(0x10);
+        assert_eq!(timer.get_tima(), 0x80);
+    }
+}
@@ -0,0 +1,38 @@
+// Structured logging
+// With the `tracing` feature these forward to the tracing crate, so an embedder's subscriber can
+// enable each subsystem separately by module path (emulator101_core::cpu, ::ppu, ::memory, ...).
+// Without it they compile to nothing and their arguments are never evaluated.
+
+#[cfg(feature = "tracing")]
+macro_rules! trace {
+    ($($arg:tt)*) => { tracing::trace!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace {
+    ($($arg:tt)*) => {};
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! debug {
+    ($($arg:tt)*) => { tracing::debug!($($arg)*) };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! debug {
+    ($($arg:tt)*) => {};
+}
+
+// A debug-level span, entered until the returned guard is dropped
+#[cfg(feature = "tracing")]
+macro_rules! span {
+    ($($arg:tt)*) => { tracing::debug_span!($($arg)*).entered() };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! span {
+    ($($arg:tt)*) => { $crate::trace::NoSpan };
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
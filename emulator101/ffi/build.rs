@@ -0,0 +1,20 @@
+// Regenerates include/emulator101.h from the extern "C" API on every build
+
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).expect("invalid cbindgen.toml");
+
+    match cbindgen::generate_with_config(&crate_dir, config) {
+        Ok(bindings) => {
+            bindings.write_to_file(crate_dir.join("include").join("emulator101.h"));
+        },
+        // Keep building with the checked-in header if the source can't be parsed (e.g. mid-edit)
+        Err(e) => println!("cargo:warning=failed to generate C header: {}", e),
+    }
+
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
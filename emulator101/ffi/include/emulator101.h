@@ -0,0 +1,143 @@
+#ifndef EMULATOR101_H
+#define EMULATOR101_H
+
+/* Generated by cbindgen from emulator101/ffi. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Width of the frame buffer in pixels.
+#define GB_SCREEN_WIDTH 160
+
+// Height of the frame buffer in pixels.
+#define GB_SCREEN_HEIGHT 144
+
+// Hardware model. GB_MODEL_AUTO picks one from the cartridge header.
+typedef enum GbModel {
+  GB_MODEL_AUTO,
+  GB_MODEL_DMG,
+  GB_MODEL_MGB,
+  GB_MODEL_SGB,
+  GB_MODEL_CGB,
+  GB_MODEL_AGB,
+} GbModel;
+
+typedef enum GbButton {
+  GB_BUTTON_RIGHT,
+  GB_BUTTON_LEFT,
+  GB_BUTTON_UP,
+  GB_BUTTON_DOWN,
+  GB_BUTTON_A,
+  GB_BUTTON_B,
+  GB_BUTTON_SELECT,
+  GB_BUTTON_START,
+} GbButton;
+
+// Opaque emulator handle.
+typedef struct GbEmulator GbEmulator;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Create an emulator running the given ROM. Returns NULL if `rom` is NULL.
+//
+// # Safety
+// `rom` must point to `rom_len` readable bytes.
+struct GbEmulator *gb_create(const uint8_t *rom, size_t rom_len, enum GbModel model);
+
+// Destroy an emulator created by gb_create. Passing NULL is a no-op.
+//
+// # Safety
+// `emu` must be NULL or a handle from gb_create that has not been destroyed yet.
+void gb_destroy(struct GbEmulator *emu);
+
+// Swap in a different ROM and reset the machine, clearing cheats and frozen addresses. Returns
+// false if `rom` is NULL.
+//
+// # Safety
+// `emu` must be a live handle and `rom` must point to `rom_len` readable bytes.
+bool gb_load_rom(struct GbEmulator *emu, const uint8_t *rom, size_t rom_len, enum GbModel model);
+
+// Reset the machine, keeping the loaded ROM.
+//
+// # Safety
+// `emu` must be a live handle.
+void gb_reset(struct GbEmulator *emu);
+
+// Run until the next frame is complete.
+//
+// # Safety
+// `emu` must be a live handle.
+void gb_run_frame(struct GbEmulator *emu);
+
+// Call `callback` with `user_data` at the end of every gb_run_frame, e.g. to run
+// rc_client_do_frame for achievements. The callback may read the emulator (gb_read_memory,
+// gb_framebuffer) but not run or reset it. NULL removes it.
+//
+// # Safety
+// `emu` must be a live handle; `user_data` is passed through untouched.
+void gb_set_frame_callback(struct GbEmulator *emu,
+                           void (*callback)(void *user_data),
+                           void *user_data);
+
+// Copy `num_bytes` of memory starting at `address` into `buffer`, in the address space
+// rcheevos uses for the Game Boy (0x0000-0xFFFF as the CPU sees it), without side effects.
+// Returns the number of bytes copied, so it can back an rc_client_read_memory_func_t.
+//
+// # Safety
+// `emu` must be a live handle and `buffer` must have room for `num_bytes` bytes.
+uint32_t gb_read_memory(const struct GbEmulator *emu,
+                        uint32_t address,
+                        uint8_t *buffer,
+                        uint32_t num_bytes);
+
+// Pointer to the last frame as RGBA32, GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT pixels, rows top to
+// bottom. Valid until the next call that runs or resets the emulator.
+//
+// # Safety
+// `emu` must be a live handle.
+const uint8_t *gb_framebuffer(const struct GbEmulator *emu);
+
+// Generation of the frame behind gb_framebuffer: it changes whenever the pixels do, so a
+// frontend that sees the same value as last time can skip uploading the frame again. 0 for a
+// NULL handle.
+//
+// # Safety
+// `emu` must be a live handle.
+uint64_t gb_frame_generation(const struct GbEmulator *emu);
+
+// Press or release a button.
+//
+// # Safety
+// `emu` must be a live handle.
+void gb_set_button(struct GbEmulator *emu, enum GbButton button, bool pressed);
+
+// Serialize the machine. The returned buffer holds `*out_len` bytes and must be released with
+// gb_free_state. Returns NULL if `emu` or `out_len` is NULL.
+//
+// # Safety
+// `emu` must be a live handle and `out_len` must be writable.
+uint8_t *gb_save_state(const struct GbEmulator *emu, size_t *out_len);
+
+// Release a buffer returned by gb_save_state. Passing NULL is a no-op.
+//
+// # Safety
+// `state` and `len` must come from the same gb_save_state call, and be freed only once.
+void gb_free_state(uint8_t *state, size_t len);
+
+// Restore a state produced by gb_save_state. Returns false (leaving the machine untouched) if
+// the data is invalid or was made with a different ROM.
+//
+// # Safety
+// `emu` must be a live handle and `state` must point to `len` readable bytes.
+bool gb_load_state(struct GbEmulator *emu, const uint8_t *state, size_t len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* EMULATOR101_H */
@@ -0,0 +1,266 @@
+// C API for the emulator core
+// Every function takes the handle returned by gb_create. The handle owns a copy of the ROM, so
+// callers may free their buffers as soon as a call returns. See include/emulator101.h.
+
+use std::ffi::c_void;
+use std::ptr;
+use std::slice;
+
+use emulator101_core::{Emulator, JoypadButton, Model, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// Width of the frame buffer in pixels.
+pub const GB_SCREEN_WIDTH: u32 = 160;
+/// Height of the frame buffer in pixels.
+pub const GB_SCREEN_HEIGHT: u32 = 144;
+
+// Literals above so cbindgen can emit them; make sure they match the core
+const _: () = assert!(GB_SCREEN_WIDTH as usize == SCREEN_WIDTH && GB_SCREEN_HEIGHT as usize == SCREEN_HEIGHT);
+
+/// Opaque emulator handle.
+pub struct GbEmulator {
+    emulator: Emulator,
+    // Called at the end of every gb_run_frame, with its user data
+    frame_callback: Option<(extern "C" fn(*mut c_void), *mut c_void)>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum GbButton {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl From<GbButton> for JoypadButton {
+    fn from(button: GbButton) -> Self {
+        match button {
+            GbButton::Right => JoypadButton::Right,
+            GbButton::Left => JoypadButton::Left,
+            GbButton::Up => JoypadButton::Up,
+            GbButton::Down => JoypadButton::Down,
+            GbButton::A => JoypadButton::A,
+            GbButton::B => JoypadButton::B,
+            GbButton::Select => JoypadButton::Select,
+            GbButton::Start => JoypadButton::Start,
+        }
+    }
+}
+
+/// Hardware model. GB_MODEL_AUTO picks one from the cartridge header.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub enum GbModel {
+    Auto,
+    Dmg,
+    Mgb,
+    Sgb,
+    Cgb,
+    Agb,
+}
+
+impl GbModel {
+    fn resolve(self, rom: &[u8]) -> Model {
+        match self {
+            GbModel::Auto => Model::from_header(rom),
+            GbModel::Dmg => Model::Dmg,
+            GbModel::Mgb => Model::Mgb,
+            GbModel::Sgb => Model::Sgb,
+            GbModel::Cgb => Model::Cgb,
+            GbModel::Agb => Model::Agb,
+        }
+    }
+}
+
+// Copy a caller-owned buffer, treating (NULL, 0) as empty
+unsafe fn copy_buffer(data: *const u8, len: usize) -> Option<Vec<u8>> {
+    if data.is_null() {
+        return (len == 0).then(Vec::new);
+    }
+    // SAFETY: the caller guarantees `data` points to `len` readable bytes
+    Some(unsafe { slice::from_raw_parts(data, len) }.to_vec())
+}
+
+/// Create an emulator running the given ROM. Returns NULL if `rom` is NULL.
+///
+/// # Safety
+/// `rom` must point to `rom_len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_create(rom: *const u8, rom_len: usize, model: GbModel) -> *mut GbEmulator {
+    let Some(rom) = (unsafe { copy_buffer(rom, rom_len) }) else {
+        return ptr::null_mut();
+    };
+    let model = model.resolve(&rom);
+    Box::into_raw(Box::new(GbEmulator { emulator: Emulator::new(rom, model), frame_callback: None }))
+}
+
+/// Destroy an emulator created by gb_create. Passing NULL is a no-op.
+///
+/// # Safety
+/// `emu` must be NULL or a handle from gb_create that has not been destroyed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_destroy(emu: *mut GbEmulator) {
+    if !emu.is_null() {
+        // SAFETY: the handle came from Box::into_raw in gb_create
+        drop(unsafe { Box::from_raw(emu) });
+    }
+}
+
+/// Swap in a different ROM and reset the machine, clearing cheats and frozen addresses. Returns
+/// false if `rom` is NULL.
+///
+/// # Safety
+/// `emu` must be a live handle and `rom` must point to `rom_len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_load_rom(emu: *mut GbEmulator, rom: *const u8, rom_len: usize, model: GbModel) -> bool {
+    let (Some(emu), Some(rom)) = (unsafe { emu.as_mut() }, unsafe { copy_buffer(rom, rom_len) }) else {
+        return false;
+    };
+    emu.emulator.set_model(model.resolve(&rom));
+    emu.emulator.load_rom(rom);
+    true
+}
+
+/// Reset the machine, keeping the loaded ROM.
+///
+/// # Safety
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_reset(emu: *mut GbEmulator) {
+    if let Some(emu) = unsafe { emu.as_mut() } {
+        emu.emulator.reset();
+    }
+}
+
+/// Run until the next frame is complete.
+///
+/// # Safety
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_run_frame(emu: *mut GbEmulator) {
+    if let Some(emu) = unsafe { emu.as_mut() } {
+        emu.emulator.run_until_frame();
+        if let Some((callback, user_data)) = emu.frame_callback {
+            callback(user_data);
+        }
+    }
+}
+
+/// Call `callback` with `user_data` at the end of every gb_run_frame, e.g. to run
+/// rc_client_do_frame for achievements. The callback may read the emulator (gb_read_memory,
+/// gb_framebuffer) but not run or reset it. NULL removes it.
+///
+/// # Safety
+/// `emu` must be a live handle; `user_data` is passed through untouched.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_set_frame_callback(emu: *mut GbEmulator, callback: Option<extern "C" fn(user_data: *mut c_void)>, user_data: *mut c_void) {
+    if let Some(emu) = unsafe { emu.as_mut() } {
+        emu.frame_callback = callback.map(|callback| (callback, user_data));
+    }
+}
+
+/// Copy `num_bytes` of memory starting at `address` into `buffer`, in the address space
+/// rcheevos uses for the Game Boy (0x0000-0xFFFF as the CPU sees it), without side effects.
+/// Returns the number of bytes copied, so it can back an rc_client_read_memory_func_t.
+///
+/// # Safety
+/// `emu` must be a live handle and `buffer` must have room for `num_bytes` bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_read_memory(emu: *const GbEmulator, address: u32, buffer: *mut u8, num_bytes: u32) -> u32 {
+    let Some(emu) = (unsafe { emu.as_ref() }) else {
+        return 0;
+    };
+    if buffer.is_null() {
+        return 0;
+    }
+    // SAFETY: the caller guarantees `buffer` has room for `num_bytes` bytes
+    let buffer = unsafe { slice::from_raw_parts_mut(buffer, num_bytes as usize) };
+    emu.emulator.peek_memory(address, buffer) as u32
+}
+
+/// Pointer to the last frame as RGBA32, GB_SCREEN_WIDTH * GB_SCREEN_HEIGHT pixels, rows top to
+/// bottom. Valid until the next call that runs or resets the emulator.
+///
+/// # Safety
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_framebuffer(emu: *const GbEmulator) -> *const u8 {
+    match unsafe { emu.as_ref() } {
+        Some(emu) => emu.emulator.frame().pixels.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Generation of the frame behind gb_framebuffer: it changes whenever the pixels do, so a
+/// frontend that sees the same value as last time can skip uploading the frame again. 0 for a
+/// NULL handle.
+///
+/// # Safety
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_frame_generation(emu: *const GbEmulator) -> u64 {
+    match unsafe { emu.as_ref() } {
+        Some(emu) => emu.emulator.frame().generation,
+        None => 0,
+    }
+}
+
+/// Press or release a button.
+///
+/// # Safety
+/// `emu` must be a live handle.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_set_button(emu: *mut GbEmulator, button: GbButton, pressed: bool) {
+    if let Some(emu) = unsafe { emu.as_mut() } {
+        emu.emulator.set_button(button.into(), pressed);
+    }
+}
+
+/// Serialize the machine. The returned buffer holds `*out_len` bytes and must be released with
+/// gb_free_state. Returns NULL if `emu` or `out_len` is NULL.
+///
+/// # Safety
+/// `emu` must be a live handle and `out_len` must be writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_save_state(emu: *const GbEmulator, out_len: *mut usize) -> *mut u8 {
+    let (Some(emu), Some(out_len)) = (unsafe { emu.as_ref() }, unsafe { out_len.as_mut() }) else {
+        return ptr::null_mut();
+    };
+    let state = emu.emulator.save_state().into_boxed_slice();
+    *out_len = state.len();
+    Box::into_raw(state) as *mut u8
+}
+
+/// Release a buffer returned by gb_save_state. Passing NULL is a no-op.
+///
+/// # Safety
+/// `state` and `len` must come from the same gb_save_state call, and be freed only once.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_free_state(state: *mut u8, len: usize) {
+    if !state.is_null() {
+        // SAFETY: rebuilds the boxed slice leaked by gb_save_state
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(state, len)) });
+    }
+}
+
+/// Restore a state produced by gb_save_state. Returns false (leaving the machine untouched) if
+/// the data is invalid or was made with a different ROM.
+///
+/// # Safety
+/// `emu` must be a live handle and `state` must point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gb_load_state(emu: *mut GbEmulator, state: *const u8, len: usize) -> bool {
+    let Some(emu) = (unsafe { emu.as_mut() }) else {
+        return false;
+    };
+    if state.is_null() {
+        return false;
+    }
+    // SAFETY: the caller guarantees `state` points to `len` readable bytes
+    let data = unsafe { slice::from_raw_parts(state, len) };
+    emu.emulator.load_state(data).is_ok()
+}
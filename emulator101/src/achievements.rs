@@ -0,0 +1,329 @@
+// Achievements
+// A local client for RetroAchievements-style achievements, built with the `achievements`
+// feature. Achievements come from a TOML file given with --achievements:
+//
+//   [[achievement]]
+//   title = "First steps"
+//   trigger = "0xHd35e=1_0xHd35d>=3"
+//
+// Triggers use the rcheevos condition syntax, read through Emulator::peek_memory each frame:
+//   operands  0xH1234 (8-bit), 0x1234 (16-bit), 0xX1234 (32-bit), 0xL / 0xU (low / high nibble),
+//             0xM-0xT (bits 0-7), with a `d` prefix for last frame's value; constants are
+//             decimal or `h` + hex
+//   compare   = != < <= > >=
+//   flags     R: resets hit counts and holds the achievement, P: pauses its group
+//   hits      .N. after a condition: it must have been true on N frames
+//   groups    core conditions joined by `_`, then `S`-separated alternates (one must hold)
+// An achievement that is already true when the game starts waits until it is false once, as
+// rcheevos does. Logging in to RetroAchievements.org is not part of this client; embedders
+// wanting the official runtime link rcheevos against the C API (gb_read_memory and
+// gb_set_frame_callback).
+
+use std::path::Path;
+
+use emulator101_core::Emulator;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    achievement: Vec<Definition>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Definition {
+    title: String,
+    trigger: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Size {
+    Bit(u8),
+    LowNibble,
+    HighNibble,
+    Byte,
+    Word,
+    Dword,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Operand {
+    Value(u32),
+    Memory { address: u32, size: Size, delta: bool },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Flag {
+    None,
+    ResetIf,
+    PauseIf,
+}
+
+#[derive(Debug)]
+struct Condition {
+    flag: Flag,
+    left: Operand,
+    compare: Compare,
+    right: Operand,
+    // Frames it must have been true on (0: just now)
+    required_hits: u32,
+    hits: u32,
+}
+
+struct Achievement {
+    title: String,
+    // Core group first, then the alternates
+    groups: Vec<Vec<Condition>>,
+    // True when the game started; held until it has been false once
+    waiting: bool,
+    unlocked: bool,
+}
+
+pub struct Achievements {
+    achievements: Vec<Achievement>,
+    // Every memory operand used, read once per frame like rcheevos' memrefs, with this and the
+    // previous frame's values (the same on the first frame)
+    memrefs: Vec<(u32, Size)>,
+    values: Vec<u32>,
+    previous: Vec<u32>,
+    first_frame: bool,
+}
+
+impl Achievements {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file: File = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let achievements: Vec<Achievement> = file
+            .achievement
+            .into_iter()
+            .map(|definition| {
+                let groups = parse_trigger(&definition.trigger)
+                    .map_err(|e| format!("{}: \"{}\": {}", path.display(), definition.title, e))?;
+                Ok(Achievement { title: definition.title, groups, waiting: false, unlocked: false })
+            })
+            .collect::<Result<_, String>>()?;
+        let mut memrefs = Vec::new();
+        for condition in achievements.iter().flat_map(|achievement| achievement.groups.iter().flatten()) {
+            for operand in [condition.left, condition.right] {
+                if let Operand::Memory { address, size, .. } = operand
+                    && !memrefs.contains(&(address, size))
+                {
+                    memrefs.push((address, size));
+                }
+            }
+        }
+        Ok(Achievements { achievements, memrefs, values: Vec::new(), previous: Vec::new(), first_frame: true })
+    }
+
+    pub fn count(&self) -> usize {
+        self.achievements.len()
+    }
+
+    // Evaluate every locked achievement after a frame; returns the titles unlocked by it
+    pub fn do_frame(&mut self, emulator: &Emulator) -> Vec<String> {
+        let values: Vec<u32> = self.memrefs.iter().map(|&(address, size)| read(emulator, address, size)).collect();
+        self.previous = std::mem::replace(&mut self.values, values);
+        if self.first_frame {
+            self.previous = self.values.clone();
+        }
+        let memory = Memory { memrefs: &self.memrefs, values: &self.values, previous: &self.previous };
+
+        let mut unlocked = Vec::new();
+        for achievement in self.achievements.iter_mut().filter(|achievement| !achievement.unlocked) {
+            let triggered = evaluate(&mut achievement.groups, &memory);
+            if self.first_frame && triggered {
+                achievement.waiting = true;
+            } else if achievement.waiting {
+                achievement.waiting = triggered;
+            } else if triggered {
+                achievement.unlocked = true;
+                unlocked.push(achievement.title.clone());
+            }
+        }
+        self.first_frame = false;
+        unlocked
+    }
+}
+
+fn read(emulator: &Emulator, address: u32, size: Size) -> u32 {
+    let mut bytes = [0u8; 4];
+    let count = match size {
+        Size::Word => 2,
+        Size::Dword => 4,
+        _ => 1,
+    };
+    emulator.peek_memory(address, &mut bytes[..count]);
+    let value = u32::from_le_bytes(bytes);
+    match size {
+        Size::Bit(bit) => (value >> bit) & 1,
+        Size::LowNibble => value & 0x0F,
+        Size::HighNibble => (value >> 4) & 0x0F,
+        _ => value,
+    }
+}
+
+// The memory values of one frame
+struct Memory<'a> {
+    memrefs: &'a [(u32, Size)],
+    values: &'a [u32],
+    previous: &'a [u32],
+}
+
+impl Memory<'_> {
+    fn value(&self, operand: Operand) -> u32 {
+        match operand {
+            Operand::Value(value) => value,
+            Operand::Memory { address, size, delta } => {
+                let index = self.memrefs.iter().position(|&memref| memref == (address, size)).unwrap_or_default();
+                if delta { self.previous[index] } else { self.values[index] }
+            },
+        }
+    }
+}
+
+// Whether the trigger holds this frame, updating hit counts
+fn evaluate(groups: &mut [Vec<Condition>], memory: &Memory) -> bool {
+    let mut reset = false;
+    let mut results = Vec::with_capacity(groups.len());
+    for group in groups.iter_mut() {
+        // A true PauseIf freezes the group: no hits are counted and it can't hold
+        let paused = group
+            .iter()
+            .filter(|condition| condition.flag == Flag::PauseIf)
+            .any(|condition| condition.holds(memory));
+        if paused {
+            results.push(false);
+            continue;
+        }
+        let mut all = true;
+        for condition in group.iter_mut() {
+            match condition.flag {
+                Flag::PauseIf => {},
+                Flag::ResetIf => reset |= condition.holds(memory),
+                Flag::None => {
+                    let holds = condition.holds(memory);
+                    if condition.required_hits == 0 {
+                        all &= holds;
+                    } else {
+                        if holds && condition.hits < condition.required_hits {
+                            condition.hits += 1;
+                        }
+                        all &= condition.hits >= condition.required_hits;
+                    }
+                },
+            }
+        }
+        results.push(all);
+    }
+    if reset {
+        for condition in groups.iter_mut().flatten() {
+            condition.hits = 0;
+        }
+        return false;
+    }
+    let (core, alternates) = results.split_first().unwrap_or((&true, &[]));
+    *core && (alternates.is_empty() || alternates.iter().any(|&holds| holds))
+}
+
+impl Condition {
+    fn holds(&self, memory: &Memory) -> bool {
+        let (left, right) = (memory.value(self.left), memory.value(self.right));
+        match self.compare {
+            Compare::Equal => left == right,
+            Compare::NotEqual => left != right,
+            Compare::Less => left < right,
+            Compare::LessEqual => left <= right,
+            Compare::Greater => left > right,
+            Compare::GreaterEqual => left >= right,
+        }
+    }
+}
+
+fn parse_trigger(trigger: &str) -> Result<Vec<Vec<Condition>>, String> {
+    // `S` separates groups, except right after `0x` where it is the bit 6 size
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for (index, c) in trigger.char_indices() {
+        if c == 'S' && !trigger[..index].ends_with("0x") && !trigger[..index].ends_with("0X") {
+            groups.push(&trigger[start..index]);
+            start = index + 1;
+        }
+    }
+    groups.push(&trigger[start..]);
+    groups.into_iter().map(|group| group.split('_').map(parse_condition).collect()).collect()
+}
+
+fn parse_condition(text: &str) -> Result<Condition, String> {
+    let (flag, rest) = match text.split_once(':') {
+        Some(("R", rest)) => (Flag::ResetIf, rest),
+        Some(("P", rest)) => (Flag::PauseIf, rest),
+        Some((flag, _)) => return Err(format!("unsupported condition flag '{}:'", flag)),
+        None => (Flag::None, text),
+    };
+    // Hit count suffix: ".N."
+    let (rest, required_hits) = match rest.strip_suffix('.').and_then(|rest| rest.rsplit_once('.')) {
+        Some((rest, hits)) => (rest, hits.parse().map_err(|_| format!("invalid hit count in '{}'", text))?),
+        None => (rest, 0),
+    };
+    const COMPARES: [(&str, Compare); 6] = [
+        ("!=", Compare::NotEqual),
+        ("<=", Compare::LessEqual),
+        (">=", Compare::GreaterEqual),
+        ("=", Compare::Equal),
+        ("<", Compare::Less),
+        (">", Compare::Greater),
+    ];
+    let (left, compare, right) = COMPARES
+        .iter()
+        .find_map(|&(symbol, compare)| rest.split_once(symbol).map(|(left, right)| (left, compare, right)))
+        .ok_or_else(|| format!("no comparison in '{}'", text))?;
+    Ok(Condition {
+        flag,
+        left: parse_operand(left)?,
+        compare,
+        right: parse_operand(right)?,
+        required_hits,
+        hits: 0,
+    })
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let invalid = || format!("invalid operand '{}'", text);
+    let (delta, rest) = match text.strip_prefix('d') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    let Some(memory) = rest.strip_prefix("0x").or_else(|| rest.strip_prefix("0X")) else {
+        if delta {
+            return Err(invalid());
+        }
+        let value = match text.strip_prefix('h') {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => text.parse(),
+        };
+        return value.map(Operand::Value).map_err(|_| invalid());
+    };
+    let (size, address) = match memory.chars().next() {
+        Some(c @ 'M'..='T') => (Size::Bit(c as u8 - b'M'), &memory[1..]),
+        Some('H') => (Size::Byte, &memory[1..]),
+        Some('X') => (Size::Dword, &memory[1..]),
+        Some('L') => (Size::LowNibble, &memory[1..]),
+        Some('U') => (Size::HighNibble, &memory[1..]),
+        Some(' ') => (Size::Word, &memory[1..]),
+        _ => (Size::Word, memory),
+    };
+    let address = u32::from_str_radix(address, 16).map_err(|_| invalid())?;
+    Ok(Operand::Memory { address, size, delta })
+}
@@ -0,0 +1,91 @@
+// Sound output
+// The front emulator's samples go through a lock-free ring buffer to an SDL playback device, so
+// neither the emulation thread nor the audio callback ever waits on the other. Emulation is paced
+// by frames rather than by the sound card, so the ring is kept short: when the game runs ahead
+// (fast-forward, the flat 60 Hz pacing) samples that don't fit are dropped, and when it falls
+// behind or is paused the device plays silence.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use emulator101_core::apu::SAMPLE_RATE;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+// 100 ms of stereo frames
+const CAPACITY: usize = SAMPLE_RATE as usize / 10;
+
+struct Ring {
+    // One left/right pair per slot: left in the low half, right in the high half
+    frames: Box<[AtomicU32]>,
+    // Frames written and read so far. Only the producer stores `written` and only the consumer
+    // `read`; the slots between them are the queued samples.
+    written: AtomicUsize,
+    read: AtomicUsize,
+}
+
+// Samples waiting to be played; clones refer to the same ring. There must only be one producer
+// at a time: the tab in front, the ones in the background being paused.
+#[derive(Clone)]
+pub struct AudioQueue {
+    ring: Arc<Ring>,
+}
+
+impl AudioQueue {
+    // Interleaved stereo, as the emulator produces it
+    pub fn push(&self, samples: &[i16]) {
+        let ring = &*self.ring;
+        let mut written = ring.written.load(Ordering::Relaxed);
+        let read = ring.read.load(Ordering::Acquire);
+        for pair in samples.chunks_exact(2) {
+            if written.wrapping_sub(read) == CAPACITY {
+                break;
+            }
+            let frame = pair[0] as u16 as u32 | (pair[1] as u16 as u32) << 16;
+            ring.frames[written % CAPACITY].store(frame, Ordering::Relaxed);
+            written = written.wrapping_add(1);
+        }
+        // Publishes the slots stored above
+        ring.written.store(written, Ordering::Release);
+    }
+}
+
+struct Playback {
+    queue: AudioQueue,
+}
+
+impl AudioCallback for Playback {
+    type Channel = i16;
+
+    fn callback(&mut self, out: &mut [i16]) {
+        let ring = &*self.queue.ring;
+        let mut read = ring.read.load(Ordering::Relaxed);
+        let written = ring.written.load(Ordering::Acquire);
+        for pair in out.chunks_exact_mut(2) {
+            if read == written {
+                pair.fill(0);
+                continue;
+            }
+            let frame = ring.frames[read % CAPACITY].load(Ordering::Relaxed);
+            pair[0] = frame as i16;
+            pair[1] = (frame >> 16) as i16;
+            read = read.wrapping_add(1);
+        }
+        // Hands the slots read above back to the producer
+        ring.read.store(read, Ordering::Release);
+    }
+}
+
+// Open the default playback device; the device plays for as long as it is kept
+pub fn open(sdl_context: &sdl2::Sdl) -> Result<(AudioDevice<impl AudioCallback>, AudioQueue), String> {
+    let ring = Ring {
+        frames: (0..CAPACITY).map(|_| AtomicU32::new(0)).collect(),
+        written: AtomicUsize::new(0),
+        read: AtomicUsize::new(0),
+    };
+    let queue = AudioQueue { ring: Arc::new(ring) };
+    let desired = AudioSpecDesired { freq: Some(SAMPLE_RATE as i32), channels: Some(2), samples: Some(1024) };
+    let playback = Playback { queue: queue.clone() };
+    let device = sdl_context.audio()?.open_playback(None, &desired, |_| playback)?;
+    device.resume();
+    Ok((device, queue))
+}
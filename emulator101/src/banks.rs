@@ -0,0 +1,109 @@
+// Mapper write trace
+// `emulator101 banks <rom> [--state <file>] [--frames <count>] [--log]` runs the game headless
+// from power-on (or a savestate) and prints which banks the mapper showed in each frame, to track
+// down a wrong bank mapped by the game or by the emulator. Frames that saw the same banks are
+// merged into one row; a frame that switched banks lists them in the order they were mapped:
+//
+//   frames       0000         4000                 RAM
+//   0-41         00           01                   off
+//   42           00           01 05 01             off 0
+//   43-599       00           01                   0
+//
+// RAM shows the bank at 0xA000, `off` while it is disabled and `reg` while a clock or other
+// register is mapped there. `--log` also prints every write to 0x0000-0x7FFF as it happens, with
+// the register it went to and the banks it left mapped.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use emulator101_core::{EmulatorBuilder, MappedBanks, MapperWrite};
+
+pub struct Options {
+    pub state: Option<PathBuf>,
+    pub frames: u64,
+    pub log: bool,
+}
+
+// Banks seen during one frame, each column without repeats in a row
+#[derive(PartialEq)]
+struct FrameBanks {
+    rom0: Vec<usize>,
+    romx: Vec<usize>,
+    ram: Vec<String>,
+}
+
+impl FrameBanks {
+    fn new(banks: MappedBanks) -> Self {
+        FrameBanks { rom0: vec![banks.rom0], romx: vec![banks.romx], ram: vec![ram_label(banks)] }
+    }
+
+    fn push(&mut self, banks: MappedBanks) {
+        push_new(&mut self.rom0, banks.rom0);
+        push_new(&mut self.romx, banks.romx);
+        push_new(&mut self.ram, ram_label(banks));
+    }
+}
+
+fn push_new<T: PartialEq>(column: &mut Vec<T>, value: T) {
+    if column.last() != Some(&value) {
+        column.push(value);
+    }
+}
+
+fn ram_label(banks: MappedBanks) -> String {
+    match banks.ram {
+        _ if !banks.ram_enabled => "off".to_string(),
+        Some(bank) => bank.to_string(),
+        None => "reg".to_string(),
+    }
+}
+
+fn hex_banks(banks: &[usize]) -> String {
+    banks.iter().map(|bank| format!("{:02X}", bank)).collect::<Vec<_>>().join(" ")
+}
+
+fn describe(write: &MapperWrite) -> String {
+    let banks = write.banks;
+    format!(
+        "PC {:04X}: [{:04X}] = {:02X}  {:<32} ROM {:02X}/{:02X}, RAM {}",
+        write.pc, write.addr, write.value, write.register.to_string(), banks.rom0, banks.romx, ram_label(banks)
+    )
+}
+
+pub fn run(rom_path: &str, options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut emulator = EmulatorBuilder::new().build_from_file(rom_path)?;
+    if let Some(path) = &options.state {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        emulator.load_state(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    let writes = Arc::new(Mutex::new(Vec::new()));
+    let sink = writes.clone();
+    emulator.on_mapper_write(move |write| sink.lock().unwrap().push(write));
+
+    // Rows of (first frame, last frame, banks)
+    let mut rows: Vec<(u64, u64, FrameBanks)> = Vec::new();
+    for frame in 0..options.frames {
+        let mut banks = FrameBanks::new(emulator.mapped_banks());
+        emulator.run_until_frame();
+        for write in writes.lock().unwrap().drain(..) {
+            if options.log {
+                println!("frame {:<6} {}", frame, describe(&write));
+            }
+            banks.push(write.banks);
+        }
+        match rows.last_mut() {
+            Some((_, last, previous)) if *previous == banks => *last = frame,
+            _ => rows.push((frame, frame, banks)),
+        }
+    }
+
+    if options.log {
+        println!();
+    }
+    println!("{:<12} {:<12} {:<20} RAM", "frames", "0000", "4000");
+    for (first, last, banks) in &rows {
+        let frames = if first == last { first.to_string() } else { format!("{}-{}", first, last) };
+        println!("{:<12} {:<12} {:<20} {}", frames, hex_banks(&banks.rom0), hex_banks(&banks.romx), banks.ram.join(" "));
+    }
+    Ok(())
+}
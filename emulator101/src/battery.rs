@@ -0,0 +1,105 @@
+// Battery saves
+// Cartridge RAM of battery-backed games, and the clock of carts with one, is kept in a .sav file
+// next to the ROM: read when the game is loaded, written when the emulator stops or switches
+// games, and in between every --save-interval seconds and with each savestate if the game changed
+// it (Emulator::battery_dirty), so a crash loses little progress. Before an existing save is first
+// replaced it is copied to a timestamped backup (`game.sav.<unix time>.bak`), keeping the newest
+// few; the periodic writes don't add more, so the backups stay from before each session. The new
+// save is written to a temporary file first and renamed over the old one, so neither a crash
+// mid-write nor an in-game corruption can take the only copy.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use emulator101_core::Emulator;
+
+pub const DEFAULT_BACKUPS: usize = 3;
+// Seconds between writes of a changed save while playing
+pub const DEFAULT_FLUSH_INTERVAL: u64 = 30;
+
+pub struct BatterySave {
+    path: PathBuf,
+    // Number of backups kept; 0 disables them
+    backups: usize,
+    // The save this one replaces has been backed up already
+    backed_up: bool,
+}
+
+impl BatterySave {
+    // The save file of the ROM at `rom_path`: same name with a .sav extension
+    pub fn for_rom(rom_path: impl AsRef<Path>, backups: usize) -> Self {
+        BatterySave { path: rom_path.as_ref().with_extension("sav"), backups, backed_up: false }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Load the save into the emulator's cartridge RAM; Ok(false) when there is none yet
+    pub fn load(&self, emulator: &mut Emulator) -> io::Result<bool> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                emulator.load_battery_save(&data);
+                Ok(true)
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    // Write the cartridge RAM out, backing up the previous save if it differs and this is the
+    // first write
+    pub fn store(&mut self, ram: &[u8]) -> io::Result<()> {
+        match fs::read(&self.path) {
+            Ok(previous) if previous == ram => return Ok(()),
+            Ok(_) if self.backups > 0 && !self.backed_up => {
+                let stamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+                fs::copy(&self.path, self.backup_path(stamp))?;
+                self.prune_backups()?;
+                self.backed_up = true;
+            },
+            Ok(_) => {},
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+        let temp = self.sibling("tmp");
+        fs::write(&temp, ram)?;
+        fs::rename(&temp, &self.path)
+    }
+
+    fn backup_path(&self, stamp: u64) -> PathBuf {
+        self.sibling(&format!("{}.bak", stamp))
+    }
+
+    // `game.sav` -> `game.sav.<suffix>`
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        let mut name = self.path.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(suffix);
+        self.path.with_file_name(name)
+    }
+
+    // Delete all but the newest `backups` backups
+    fn prune_backups(&self) -> io::Result<()> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let prefix = format!("{}.", self.path.file_name().unwrap_or_default().to_string_lossy());
+        let mut stamps: Vec<u64> = fs::read_dir(dir)?
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.strip_prefix(&prefix)?.strip_suffix(".bak")?.parse().ok()
+            })
+            .collect();
+        stamps.sort_unstable();
+        let excess = stamps.len().saturating_sub(self.backups);
+        for &stamp in &stamps[..excess] {
+            fs::remove_file(self.backup_path(stamp))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+// Raster beam indicator
+// A debug overlay for pausing and stepping: the scanline the PPU is on gets a faint tint and the
+// column it has drawn up to (interpolated over mode 3) a brighter tick, so a frame stopped
+// mid-way shows which part is new and which is still the previous frame. The CPU's mode is shown
+// next to the position unless it is simply running.
+
+use emulator101_core::CpuMode;
+
+use crate::font;
+
+const TINT: [u8; 3] = [0xFF, 0x30, 0x30];
+// Share of the tint mixed into the scanline and into the tick, out of 256
+const LINE_ALPHA: u32 = 64;
+const TICK_ALPHA: u32 = 192;
+// Rows the tick reaches above and below the scanline
+const TICK_REACH: usize = 2;
+
+// Draw the marker for beam position (LY, column) onto an RGBA32 frame
+pub fn draw(pixels: &mut [u8], width: usize, (ly, x): (u8, usize), mode: CpuMode) {
+    let height = pixels.len() / (width * 4);
+    let ly = ly as usize;
+    if ly < height {
+        for column in 0..width {
+            blend(pixels, (ly * width + column) * 4, LINE_ALPHA);
+        }
+        if x < width {
+            for row in ly.saturating_sub(TICK_REACH)..(ly + TICK_REACH + 1).min(height) {
+                blend(pixels, (row * width + x) * 4, TICK_ALPHA);
+            }
+        }
+    }
+    let mut text = if x < width { format!("LY {} X {}", ly, x) } else { format!("LY {}", ly) };
+    match mode {
+        CpuMode::Running => {},
+        CpuMode::Halted => text.push_str(" HALT"),
+        CpuMode::Stopped => text.push_str(" STOP"),
+        CpuMode::SpeedSwitch(cycles) => text.push_str(&format!(" SPEED {}", cycles)),
+    }
+    let y = (height - font::GLYPH_HEIGHT - 2) as i32;
+    font::draw_text_rgba(pixels, width, 3, y + 1, &text, [0x00, 0x00, 0x00, 0xFF]);
+    font::draw_text_rgba(pixels, width, 2, y, &text, [0xFF, 0xFF, 0xFF, 0xFF]);
+}
+
+fn blend(pixels: &mut [u8], offset: usize, alpha: u32) {
+    for (channel, tint) in pixels[offset..offset + 3].iter_mut().zip(TINT) {
+        *channel = ((*channel as u32 * (256 - alpha) + tint as u32 * alpha) / 256) as u8;
+    }
+}
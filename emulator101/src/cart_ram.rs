@@ -0,0 +1,254 @@
+// Cartridge RAM editor
+// Hex view of the external RAM one 8 KiB bank at a time, whichever bank the mapper currently
+// shows at 0xA000. Bytes can be edited in place, and a bank can be exported to or imported from
+// a file next to the ROM (`game.gb` -> `game.ram<bank>.bin`) for save hacking.
+//
+// Keys (while this window has focus):
+//   , / .        previous / next bank    Arrows      move the cursor
+//   PageUp/Down  scroll a page           0-9, A-F    type a byte (two digits) at the cursor
+//   S            export the bank         L           import the bank
+
+use std::path::{Path, PathBuf};
+
+use emulator101_core::mapper::RAM_BANK_SIZE;
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::font;
+use crate::worker::{Command, EmulatorThread};
+
+const WINDOW_WIDTH: u32 = 360;
+const WINDOW_HEIGHT: u32 = 420;
+const LINE_HEIGHT: i32 = 12;
+const BYTES_PER_ROW: usize = 16;
+// Hex rows shown at once
+const VISIBLE_ROWS: usize = 28;
+// x of the first hex column: after "A000  "
+const HEX_X: i32 = 5 + 6 * font::ADVANCE as i32;
+
+const TEXT: Color = Color::RGB(0, 0, 0);
+const DIM: Color = Color::RGB(110, 110, 110);
+const SELECTED: Color = Color::RGB(200, 240, 200);
+
+pub struct CartRamEditor {
+    canvas: Canvas<Window>,
+    is_open: bool,
+    // Latest cartridge RAM capture and the bank mapped at 0xA000
+    ram: Vec<u8>,
+    mapped_bank: Option<usize>,
+    bank: usize,
+    // Offset of the cursor within the bank
+    cursor: usize,
+    first_row: usize,
+    // First hex digit typed at the cursor
+    pending_digit: Option<u8>,
+    // Exported banks go next to this file
+    rom_path: PathBuf,
+    status: String,
+}
+
+impl CartRamEditor {
+    pub fn new(sdl_context: &sdl2::Sdl, rom_path: &str) -> Result<Self, String> {
+        let video_subsystem = sdl_context.video()?;
+        let window = video_subsystem
+            .window("Cartridge RAM", WINDOW_WIDTH, WINDOW_HEIGHT)
+            .position_centered()
+            .hidden() // Start hidden
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        Ok(CartRamEditor {
+            canvas,
+            is_open: false,
+            ram: Vec::new(),
+            mapped_bank: None,
+            bank: 0,
+            cursor: 0,
+            first_row: 0,
+            pending_digit: None,
+            rom_path: PathBuf::from(rom_path),
+            status: String::new(),
+        })
+    }
+
+    pub fn toggle(&mut self) {
+        self.is_open = !self.is_open;
+        if self.is_open {
+            self.canvas.window_mut().show();
+        } else {
+            self.canvas.window_mut().hide();
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    // A different ROM was loaded: its banks are exported next to it
+    pub fn set_rom_path(&mut self, rom_path: &str) {
+        self.rom_path = PathBuf::from(rom_path);
+        self.bank = 0;
+    }
+
+    // Handle events aimed at this window; returns true if the event was used
+    pub fn handle_event(&mut self, event: &Event, emulator: &EmulatorThread) -> bool {
+        if !self.is_open || event.get_window_id() != Some(self.canvas.window().id()) {
+            return false;
+        }
+
+        match event {
+            Event::KeyDown { keycode: Some(key), .. } => {
+                self.handle_key(*key, emulator);
+                true
+            },
+            Event::Window { win_event: sdl2::event::WindowEvent::Close, .. } => {
+                self.toggle();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn bank_count(&self) -> usize {
+        self.ram.len().div_ceil(RAM_BANK_SIZE)
+    }
+
+    fn handle_key(&mut self, key: Keycode, emulator: &EmulatorThread) {
+        match key {
+            Keycode::Comma => self.bank = self.bank.saturating_sub(1),
+            Keycode::Period => self.bank = (self.bank + 1).min(self.bank_count().saturating_sub(1)),
+            Keycode::Left => self.cursor = self.cursor.saturating_sub(1),
+            Keycode::Right => self.cursor += 1,
+            Keycode::Up => self.cursor = self.cursor.saturating_sub(BYTES_PER_ROW),
+            Keycode::Down => self.cursor += BYTES_PER_ROW,
+            Keycode::PageUp => self.cursor = self.cursor.saturating_sub(BYTES_PER_ROW * VISIBLE_ROWS),
+            Keycode::PageDown => self.cursor += BYTES_PER_ROW * VISIBLE_ROWS,
+            Keycode::S => self.export(),
+            Keycode::L => self.import(emulator),
+            _ => {
+                let name = key.name();
+                let digit = name.strip_prefix("Keypad ").unwrap_or(&name);
+                if let Ok(digit) = u8::from_str_radix(digit, 16)
+                    && digit < 0x10
+                {
+                    self.type_digit(digit, emulator);
+                }
+                return;
+            },
+        }
+        self.pending_digit = None;
+        self.cursor = self.cursor.min(RAM_BANK_SIZE - 1);
+        // Keep the cursor on screen
+        let row = self.cursor / BYTES_PER_ROW;
+        self.first_row = self.first_row.clamp(row.saturating_sub(VISIBLE_ROWS - 1), row);
+    }
+
+    // The second digit of a byte writes it and moves to the next one
+    fn type_digit(&mut self, digit: u8, emulator: &EmulatorThread) {
+        let Some(high) = self.pending_digit.take() else {
+            self.pending_digit = Some(digit);
+            return;
+        };
+        let offset = self.bank * RAM_BANK_SIZE + self.cursor;
+        if offset < self.ram.len() {
+            let value = (high << 4) | digit;
+            self.ram[offset] = value;
+            emulator.send(Command::WriteCartRam(offset, vec![value]));
+            self.handle_key(Keycode::Right, emulator);
+        }
+    }
+
+    fn bank_path(&self) -> PathBuf {
+        let stem = self.rom_path.file_stem().unwrap_or_default().to_string_lossy();
+        self.rom_path.with_file_name(format!("{}.ram{}.bin", stem, self.bank))
+    }
+
+    fn bank_data(&self) -> &[u8] {
+        let start = (self.bank * RAM_BANK_SIZE).min(self.ram.len());
+        let end = (start + RAM_BANK_SIZE).min(self.ram.len());
+        &self.ram[start..end]
+    }
+
+    fn export(&mut self) {
+        let path = self.bank_path();
+        self.status = match std::fs::write(&path, self.bank_data()) {
+            Ok(()) => format!("Exported {}", display_name(&path)),
+            Err(e) => format!("Export failed: {}", e),
+        };
+        eprintln!("{}", self.status);
+    }
+
+    fn import(&mut self, emulator: &EmulatorThread) {
+        let path = self.bank_path();
+        self.status = match std::fs::read(&path) {
+            Ok(data) if data.len() > self.bank_data().len() => {
+                format!("{} is larger than the bank ({} bytes)", display_name(&path), data.len())
+            },
+            Ok(data) => {
+                let offset = self.bank * RAM_BANK_SIZE;
+                self.ram[offset..offset + data.len()].copy_from_slice(&data);
+                emulator.send(Command::WriteCartRam(offset, data));
+                format!("Imported {}", display_name(&path))
+            },
+            Err(e) => format!("Import failed: {}", e),
+        };
+        eprintln!("{}", self.status);
+    }
+
+    pub fn update(&mut self, ram: Vec<u8>, mapped_bank: Option<usize>) -> Result<(), String> {
+        if !self.is_open {
+            return Ok(());
+        }
+        self.ram = ram;
+        self.mapped_bank = mapped_bank;
+        self.bank = self.bank.min(self.bank_count().saturating_sub(1));
+
+        let mapped = match mapped_bank {
+            Some(bank) if bank == self.bank => "mapped at A000",
+            Some(_) => "not mapped",
+            None => "register mapped at A000",
+        };
+        let mut lines = vec![
+            (format!("Bank {}/{}  {}", self.bank, self.bank_count(), mapped), TEXT),
+            (", . bank  S export  L import  0-F edit".to_string(), DIM),
+            (String::new(), TEXT),
+        ];
+        let bank = self.bank_data();
+        for row in self.first_row..(self.first_row + VISIBLE_ROWS).min(bank.len().div_ceil(BYTES_PER_ROW)) {
+            let bytes = &bank[row * BYTES_PER_ROW..((row + 1) * BYTES_PER_ROW).min(bank.len())];
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            lines.push((format!("{:04X}  {}", 0xA000 + row * BYTES_PER_ROW, hex.join(" ")), TEXT));
+        }
+        let has_ram = !bank.is_empty();
+        if !has_ram {
+            lines.push(("The cartridge has no RAM".to_string(), TEXT));
+        }
+
+        self.canvas.set_draw_color(Color::RGB(240, 240, 240));
+        self.canvas.clear();
+        // Cursor cell
+        let row = self.cursor / BYTES_PER_ROW;
+        if has_ram && (self.first_row..self.first_row + VISIBLE_ROWS).contains(&row) {
+            let x = HEX_X + (self.cursor % BYTES_PER_ROW * 3 * font::ADVANCE) as i32;
+            let y = 5 + LINE_HEIGHT * (3 + (row - self.first_row) as i32);
+            self.canvas.set_draw_color(SELECTED);
+            self.canvas.fill_rect(Rect::new(x - 1, y - 2, 2 * font::ADVANCE as u32 + 1, LINE_HEIGHT as u32))?;
+        }
+        let mut y = 5;
+        for (text, color) in &lines {
+            font::draw_text(&mut self.canvas, text, 5, y, *color)?;
+            y += LINE_HEIGHT;
+        }
+        font::draw_text(&mut self.canvas, &self.status, 5, WINDOW_HEIGHT as i32 - LINE_HEIGHT - 5, DIM)?;
+        self.canvas.present();
+        Ok(())
+    }
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name().unwrap_or_default().to_string_lossy().into_owned()
+}
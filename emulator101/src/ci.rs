@@ -0,0 +1,132 @@
+// Headless runs for CI
+// `run <rom> --frames N` (or any of the options below) runs the ROM without a window and exits
+// with 0 when it passed and 1 when it failed, for regression pipelines:
+//   --frames N             frames to run at most (default 7200, two minutes); with nothing else
+//                          to wait for, running them all is a pass
+//   --expect-serial TEXT   pass once the serial output contains TEXT (Blargg's "Passed")
+//   --until-ld-b-b         stop at LD B,B; pass if B, C, D, E, H, L hold 3, 5, 8, 13, 21, 34
+//                          (the Mooneye convention), fail otherwise
+//   --screenshot FILE      write the last frame to a PNG
+// An --input-script plays its buttons here too, so a bug scenario can run unattended. The
+// serial output goes to stdout, the outcome and any --diagnostics to stderr.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use emulator101_core::emulator::CYCLES_PER_FRAME;
+use emulator101_core::{Emulator, Severity, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+use crate::input_script::InputScript;
+
+const DEFAULT_FRAMES: u64 = 2 * 60 * 60;
+const LD_B_B: u8 = 0x40;
+// B, C, D, E, H, L of a passing Mooneye test
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+#[derive(Default)]
+pub struct Automation {
+    pub frames: Option<u64>,
+    pub expect_serial: Option<String>,
+    pub until_ld_b_b: bool,
+    pub screenshot: Option<PathBuf>,
+}
+
+// Run until an exit condition; Ok(true) when the ROM passed
+pub fn run(mut emulator: Emulator, automation: &Automation, diagnostics: Option<Severity>, input_script: Option<&InputScript>) -> Result<bool, Box<dyn std::error::Error>> {
+    if let Some(least) = diagnostics {
+        emulator.on_diagnostic(move |event| {
+            if event.severity() >= least {
+                eprintln!("Diagnostic ({}): {}", event.severity(), event);
+            }
+        });
+    }
+    let (serial_tx, serial_rx) = mpsc::channel();
+    emulator.on_serial_byte(move |byte| {
+        let _ = serial_tx.send(byte);
+    });
+
+    let waits = automation.expect_serial.is_some() || automation.until_ld_b_b;
+    let limit = automation.frames.unwrap_or(DEFAULT_FRAMES);
+    let mut serial = Vec::new();
+    let mut stdout = std::io::stdout();
+    // Frames run, counting a frame's worth of cycles with the LCD off as one
+    let mut frames = 0;
+    let outcome = loop {
+        if frames >= limit {
+            break if waits { Err(format!("no result after {} frames", frames)) } else { Ok(format!("ran {} frames", frames)) };
+        }
+        if let Some(script) = input_script
+            && frames <= script.length()
+        {
+            emulator.set_buttons(script.buttons(frames));
+        }
+        if automation.until_ld_b_b {
+            if let Some(registers) = run_frame_until_ld_b_b(&mut emulator) {
+                break if registers == MOONEYE_PASS {
+                    Ok(format!("LD B,B after {} frames with the passing registers", frames))
+                } else {
+                    Err(format!("LD B,B after {} frames with B C D E H L = {:?}", frames, registers))
+                };
+            }
+        } else {
+            emulator.run_until_frame();
+        }
+        frames += 1;
+        if let Some(error) = emulator.fault() {
+            break Err(format!("stopped by an error: {}", error));
+        }
+
+        let start = serial.len();
+        serial.extend(serial_rx.try_iter());
+        stdout.write_all(&serial[start..])?;
+        if let Some(expected) = &automation.expect_serial
+            && String::from_utf8_lossy(&serial).contains(expected.as_str())
+        {
+            break Ok(format!("serial output matched after {} frames", frames));
+        }
+    };
+    stdout.write_all(&serial_rx.try_iter().collect::<Vec<_>>())?;
+    stdout.flush()?;
+
+    if let Some(path) = &automation.screenshot {
+        write_png(path, emulator.frame().pixels).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    match outcome {
+        Ok(message) => {
+            eprintln!("Passed: {}", message);
+            Ok(true)
+        },
+        Err(message) => {
+            eprintln!("Failed: {}", message);
+            Ok(false)
+        },
+    }
+}
+
+// Run one frame (as run_until_frame does) instruction by instruction; the registers if it
+// reached LD B,B
+fn run_frame_until_ld_b_b(emulator: &mut Emulator) -> Option<[u8; 6]> {
+    let frames = emulator.stats().frames;
+    let deadline = emulator.clock().m_cycles() + CYCLES_PER_FRAME as u64 / 4;
+    while emulator.stats().frames == frames && !emulator.clock().reached(deadline) && emulator.fault().is_none() {
+        let cpu = emulator.cpu();
+        if emulator.bus().read_byte(cpu.pc()) == LD_B_B {
+            let [b, c] = cpu.bc().to_be_bytes();
+            let [d, e] = cpu.de().to_be_bytes();
+            let [h, l] = cpu.hl().to_be_bytes();
+            return Some([b, c, d, e, h, l]);
+        }
+        emulator.step();
+    }
+    None
+}
+
+fn write_png(path: &Path, pixels: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)?;
+    Ok(())
+}
@@ -0,0 +1,147 @@
+// Frontend settings file
+// `emulator101.toml` in the working directory, or the file given with --config. Every entry is
+// optional; keys are SDL key names ("Z", "Return", "Left Shift", "Keypad 5", ...):
+//
+//   accuracy = "balanced"   # fast, balanced or cycle-accurate; --accuracy overrides it
+//   sprite_limit = false    # draw every sprite on a line instead of the first 10
+//   show_play_time = true   # the game's total play time in the title bar (see play_time.rs)
+//
+//   [keys]            # joypad in the main window (linked play keeps its fixed layout)
+//   a = "Z"
+//   b = "X"
+//   select = "Space"
+//   start = "Return"
+//   up = "Up"         # likewise down, left, right
+//
+//   [vram_viewer]     # shortcuts while the VRAM viewer has focus
+//   grid = "G"
+//   palettes = "P"
+//   map = "M"
+//
+//   [discord]         # Rich Presence, in builds with the `discord` feature
+//   client_id = "123456789012345678"
+//   enabled = true
+
+use std::io;
+use std::path::Path;
+
+use emulator101_core::{AccuracyProfile, JoypadButton};
+use sdl2::keyboard::Keycode;
+use serde::Deserialize;
+
+pub const DEFAULT_PATH: &str = "emulator101.toml";
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct File {
+    accuracy: Option<String>,
+    sprite_limit: Option<bool>,
+    show_play_time: bool,
+    keys: JoypadNames,
+    vram_viewer: ViewerNames,
+    discord: DiscordNames,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct JoypadNames {
+    a: Option<String>,
+    b: Option<String>,
+    select: Option<String>,
+    start: Option<String>,
+    up: Option<String>,
+    down: Option<String>,
+    left: Option<String>,
+    right: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ViewerNames {
+    grid: Option<String>,
+    palettes: Option<String>,
+    map: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DiscordNames {
+    enabled: bool,
+    client_id: Option<String>,
+}
+
+impl Default for DiscordNames {
+    fn default() -> Self {
+        DiscordNames { enabled: true, client_id: None }
+    }
+}
+
+pub struct Settings {
+    pub accuracy: Option<AccuracyProfile>,
+    // False to lift the 10 sprites per line limit
+    pub sprite_limit: bool,
+    pub show_play_time: bool,
+    pub joypad: JoypadKeys,
+    pub viewer: ViewerKeys,
+    // Discord application ID, None when Rich Presence is off
+    #[cfg_attr(not(feature = "discord"), allow(dead_code))]
+    pub discord: Option<String>,
+}
+
+pub struct JoypadKeys {
+    bindings: [(Keycode, JoypadButton); 8],
+}
+
+impl JoypadKeys {
+    pub fn button(&self, key: Keycode) -> Option<JoypadButton> {
+        self.bindings.iter().find(|(bound, _)| *bound == key).map(|&(_, button)| button)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ViewerKeys {
+    pub grid: Keycode,
+    pub palettes: Keycode,
+    pub map: Keycode,
+}
+
+impl Settings {
+    // Read the settings file; a missing default file just means the default settings
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let file_path = path.unwrap_or(Path::new(DEFAULT_PATH));
+        let text = match std::fs::read_to_string(file_path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && path.is_none() => String::new(),
+            Err(e) => return Err(format!("failed to read {}: {}", file_path.display(), e)),
+        };
+        let file: File = toml::from_str(&text).map_err(|e| format!("{}: {}", file_path.display(), e))?;
+
+        let key = |name: &Option<String>, default: Keycode| match name {
+            Some(name) => Keycode::from_name(name).ok_or_else(|| format!("unknown key \"{}\"", name)),
+            None => Ok(default),
+        };
+        let keys = &file.keys;
+        let joypad = JoypadKeys {
+            bindings: [
+                (key(&keys.right, Keycode::Right)?, JoypadButton::Right),
+                (key(&keys.left, Keycode::Left)?, JoypadButton::Left),
+                (key(&keys.up, Keycode::Up)?, JoypadButton::Up),
+                (key(&keys.down, Keycode::Down)?, JoypadButton::Down),
+                (key(&keys.a, Keycode::Z)?, JoypadButton::A),
+                (key(&keys.b, Keycode::X)?, JoypadButton::B),
+                (key(&keys.select, Keycode::Space)?, JoypadButton::Select),
+                (key(&keys.start, Keycode::Return)?, JoypadButton::Start),
+            ],
+        };
+        let viewer = &file.vram_viewer;
+        let viewer = ViewerKeys {
+            grid: key(&viewer.grid, Keycode::G)?,
+            palettes: key(&viewer.palettes, Keycode::P)?,
+            map: key(&viewer.map, Keycode::M)?,
+        };
+        let accuracy = file.accuracy.as_deref().map(str::parse).transpose()?;
+        let discord = file.discord.client_id.filter(|_| file.discord.enabled);
+        let sprite_limit = file.sprite_limit.unwrap_or(true);
+        Ok(Settings { accuracy, sprite_limit, show_play_time: file.show_play_time, joypad, viewer, discord })
+    }
+}
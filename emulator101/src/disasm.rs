@@ -0,0 +1,224 @@
+// ROM listings
+// `emulator101 disasm <rom> [--bank N] [--sym <file.sym>] [--entry <address>]...` prints one
+// ROM bank as RGBDS source that assembles back to the same bytes. There is no code/data log,
+// so code is found by following jumps and calls from entry points: the interrupt vectors and
+// $0100 in bank 0, the places bank 0 jumps or calls into $4000-$7FFF for the other banks, and
+// any --entry addresses. Everything not reached is written as `db`. Labels come from an RGBDS
+// .sym file (`game.sym` next to the ROM is picked up on its own); other jump and call targets
+// in the bank get `jump_BBB_AAAA` / `call_BBB_AAAA` names.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+
+use emulator101_core::disasm::{self, Flow, Instruction};
+
+const BANK_SIZE: usize = 0x4000;
+// Interrupt vectors and the cartridge entry point
+const HOME_ENTRIES: [u16; 6] = [0x0040, 0x0048, 0x0050, 0x0058, 0x0060, 0x0100];
+const BYTES_PER_DB: usize = 8;
+// Column the address comments start at
+const COMMENT_COLUMN: usize = 40;
+
+pub struct Options {
+    pub bank: usize,
+    pub sym: Option<String>,
+    pub entries: Vec<u16>,
+}
+
+// A hex address, written `4A00`, `$4A00` or `0x4A00`
+pub fn parse_address(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", text))
+}
+
+// Labels from an RGBDS .sym file: `BB:AAAA Name` lines, `;` comments
+struct Symbols {
+    labels: HashMap<(usize, u16), String>,
+}
+
+impl Symbols {
+    fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut labels = HashMap::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let parsed = line.split_once(' ').and_then(|(location, name)| {
+                let (bank, address) = location.split_once(':')?;
+                Some(((usize::from_str_radix(bank, 16).ok()?, u16::from_str_radix(address, 16).ok()?), name.trim().to_string()))
+            });
+            let (location, name) = parsed.ok_or_else(|| format!("{}:{}: expected `BB:AAAA Name`", path.display(), number + 1))?;
+            labels.entry(location).or_insert(name);
+        }
+        Ok(Symbols { labels })
+    }
+
+    // Name of an address as seen from code in `bank`: ROMX addresses are in that bank, the rest
+    // usually in bank 0 (WRAMX and the like may be listed under another)
+    fn name(&self, bank: usize, address: u16) -> Option<&str> {
+        let home = if (0x4000..0x8000).contains(&address) { bank } else { 0 };
+        if let Some(name) = self.labels.get(&(home, address)) {
+            return Some(name);
+        }
+        if address < 0x8000 {
+            return None;
+        }
+        self.labels.iter().find(|((_, label_address), _)| *label_address == address).map(|(_, name)| name.as_str())
+    }
+}
+
+// Follow the code from `entries` through the bank mapped at `base`; returns the instructions
+// found by address. Flow leaving the bank is not followed.
+fn trace(data: &[u8], base: u16, entries: impl IntoIterator<Item = u16>) -> BTreeMap<u16, Instruction> {
+    let end = base as usize + data.len();
+    let mut found = BTreeMap::new();
+    let mut covered = vec![false; data.len()];
+    let mut pending: Vec<u16> = entries.into_iter().collect();
+    while let Some(mut address) = pending.pop() {
+        loop {
+            let offset = address as usize;
+            if offset < base as usize || offset >= end || covered[offset - base as usize] {
+                break;
+            }
+            let Some(instruction) = disasm::decode(&data[offset - base as usize..], address) else { break };
+            let length = instruction.length as usize;
+            if covered[offset - base as usize..(offset + length).min(end) - base as usize].iter().any(|&c| c) {
+                break; // Runs into code already decoded from another starting point
+            }
+            covered[offset - base as usize..offset + length - base as usize].fill(true);
+            let flow = instruction.flow;
+            found.insert(address, instruction);
+            match flow {
+                Flow::Continue => {},
+                Flow::Jump(target) => {
+                    pending.push(target);
+                    break;
+                },
+                Flow::Branch(target) | Flow::Call(target) => pending.push(target),
+                Flow::End => break,
+            }
+            address = address.wrapping_add(length as u16);
+        }
+    }
+    found
+}
+
+pub fn run(rom_path: &str, options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let rom = std::fs::read(rom_path).map_err(|e| format!("{}: {}", rom_path, e))?;
+    let banks = rom.len().div_ceil(BANK_SIZE);
+    let bank = options.bank;
+    if bank >= banks {
+        return Err(format!("{} has {} banks (0-{})", rom_path, banks, banks - 1).into());
+    }
+    let sym_path = match &options.sym {
+        Some(path) => Some(Path::new(path).to_path_buf()),
+        None => Some(Path::new(rom_path).with_extension("sym")).filter(|path| path.exists()),
+    };
+    let symbols = match &sym_path {
+        Some(path) => Symbols::load(path)?,
+        None => Symbols { labels: HashMap::new() },
+    };
+
+    let home = &rom[..BANK_SIZE.min(rom.len())];
+    let home_entries = options.entries.iter().copied().filter(|_| bank == 0);
+    let home_code = trace(home, 0x0000, HOME_ENTRIES.into_iter().chain(home_entries));
+    let (data, base, code) = if bank == 0 {
+        (home, 0x0000u16, home_code)
+    } else {
+        let data = &rom[bank * BANK_SIZE..((bank + 1) * BANK_SIZE).min(rom.len())];
+        let into_bank = home_code.values().filter_map(|instruction| match instruction.flow {
+            Flow::Jump(target) | Flow::Branch(target) | Flow::Call(target) if target >= 0x4000 => Some(target),
+            _ => None,
+        });
+        (data, 0x4000u16, trace(data, 0x4000, into_bank.chain(options.entries.iter().copied())))
+    };
+    let end = base as usize + data.len();
+
+    // Labels for this bank: the .sym file's, then generated ones for code that is jumped to
+    let mut labels: BTreeMap<u16, String> = symbols
+        .labels
+        .iter()
+        .filter(|((label_bank, address), _)| *label_bank == bank && (base as usize..end).contains(&(*address as usize)))
+        .map(|((_, address), name)| (*address, name.clone()))
+        .collect();
+    for instruction in code.values() {
+        let (target, kind) = match instruction.flow {
+            Flow::Jump(target) | Flow::Branch(target) => (target, "jump"),
+            Flow::Call(target) => (target, "call"),
+            _ => continue,
+        };
+        if code.contains_key(&target) && symbols.name(bank, target).is_none() {
+            labels.entry(target).or_insert_with(|| format!("{}_{:03X}_{:04X}", kind, bank, target));
+        }
+    }
+    // A label can only go between instructions; any others become constants
+    let inside: Vec<u16> = labels.keys().copied().filter(|&address| is_inside_instruction(&code, address)).collect();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "; Bank ${:02X} of {}, disassembled by emulator101", bank, rom_path);
+    let _ = writeln!(out, "; Code was found by following jumps and calls; the rest is data (db).");
+    out.push('\n');
+    for address in &inside {
+        let _ = writeln!(out, "DEF {} EQU ${:04X}", labels[address], address);
+    }
+    if !inside.is_empty() {
+        out.push('\n');
+    }
+    match bank {
+        0 => out.push_str("SECTION \"ROM Bank $000\", ROM0[$0000]\n"),
+        _ => {
+            let _ = writeln!(out, "SECTION \"ROM Bank ${:03X}\", ROMX[$4000], BANK[${:X}]", bank, bank);
+        },
+    }
+
+    let label_for = |address: u16| -> Option<String> {
+        symbols.name(bank, address).map(str::to_string).or_else(|| {
+            let in_bank = (base as usize..end).contains(&(address as usize));
+            in_bank.then(|| labels.get(&address).cloned()).flatten()
+        })
+    };
+    let mut address = base as usize;
+    while address < end {
+        if let Some(name) = labels.get(&(address as u16)).filter(|_| !inside.contains(&(address as u16))) {
+            let _ = writeln!(out, "\n{}:", name);
+        }
+        let bytes_at = |length: usize| &data[address - base as usize..address - base as usize + length];
+        let (text, length) = match code.get(&(address as u16)) {
+            Some(instruction) => {
+                let mut text = instruction.text.clone();
+                if let Some(name) = instruction.address.and_then(label_for) {
+                    text = text.replace(&format!("${:04X}", instruction.address.unwrap_or_default()), &name);
+                }
+                (text, instruction.length as usize)
+            },
+            None => {
+                // Data up to the next label, instruction or line's worth
+                let mut length = 1;
+                while length < BYTES_PER_DB
+                    && address + length < end
+                    && !code.contains_key(&((address + length) as u16))
+                    && !labels.contains_key(&((address + length) as u16))
+                {
+                    length += 1;
+                }
+                let values: Vec<String> = bytes_at(length).iter().map(|byte| format!("${:02X}", byte)).collect();
+                (format!("db {}", values.join(", ")), length)
+            },
+        };
+        let hex: Vec<String> = bytes_at(length).iter().map(|byte| format!("{:02X}", byte)).collect();
+        let line = format!("    {}", text);
+        let _ = writeln!(out, "{:<width$} ; ${:04X}: {}", line, address, hex.join(" "), width = COMMENT_COLUMN);
+        address += length;
+    }
+    std::io::Write::write_all(&mut std::io::stdout().lock(), out.as_bytes())?;
+    Ok(())
+}
+
+fn is_inside_instruction(code: &BTreeMap<u16, Instruction>, address: u16) -> bool {
+    code.range(..address)
+        .next_back()
+        .is_some_and(|(&start, instruction)| start as usize + instruction.length as usize > address as usize)
+}
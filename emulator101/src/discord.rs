@@ -0,0 +1,166 @@
+// Discord Rich Presence
+// Built with the `discord` feature. Shows the game's title from the ROM header, how long it has
+// been played and whether it is paused on the player's Discord profile. It needs the ID of a
+// Discord application, set in the config file:
+//
+//   [discord]
+//   client_id = "123456789012345678"
+//   enabled = true    # false turns it off without removing the ID
+//
+// Discord is reached over its local IPC socket (`discord-ipc-N` in the runtime directory on
+// Unix, a named pipe of the same name on Windows): frames of a little-endian opcode and length
+// followed by JSON. A background thread owns the connection, so a missing or slow Discord never
+// holds up the UI; it retries every RETRY_INTERVAL and resends the current activity on connect.
+
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use emulator101_core::Cartridge;
+use serde_json::json;
+
+const RETRY_INTERVAL: Duration = Duration::from_secs(15);
+const OP_HANDSHAKE: u32 = 0;
+const OP_FRAME: u32 = 1;
+const OP_CLOSE: u32 = 2;
+
+#[derive(Clone)]
+struct Activity {
+    title: String,
+    state: &'static str,
+    // Unix time play started, shown as elapsed time
+    started: u64,
+}
+
+pub struct Presence {
+    updates: Sender<Activity>,
+    activity: Activity,
+}
+
+impl Presence {
+    pub fn start(client_id: String, title: String) -> Self {
+        let (updates, receiver) = mpsc::channel::<Activity>();
+        let activity = Activity { title, state: "Playing", started: now() };
+        let mut current = Some(activity.clone());
+        thread::spawn(move || {
+            let mut connection: Option<Connection> = None;
+            let mut nonce = 0u64;
+            loop {
+                if connection.is_none() {
+                    connection = Connection::open(&client_id).ok();
+                }
+                if let (Some(conn), Some(activity)) = (&mut connection, &current) {
+                    nonce += 1;
+                    if conn.set_activity(activity, nonce).is_err() {
+                        connection = None;
+                    }
+                }
+                match receiver.recv_timeout(RETRY_INTERVAL) {
+                    Ok(activity) => current = Some(activity),
+                    // Retry the connection, or just wait for the next change
+                    Err(RecvTimeoutError::Timeout) if connection.is_none() => {},
+                    Err(RecvTimeoutError::Timeout) => current = None,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+        Presence { updates, activity }
+    }
+
+    // A different game was loaded: its title, with the play time starting over
+    pub fn set_game(&mut self, title: String) {
+        self.activity.title = title;
+        self.activity.started = now();
+        let _ = self.updates.send(self.activity.clone());
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.activity.state = if paused { "Paused" } else { "Playing" };
+        let _ = self.updates.send(self.activity.clone());
+    }
+}
+
+// Title from the cartridge header, or "Unknown game" when it is blank
+pub fn game_title(rom: &[u8]) -> String {
+    match Cartridge::parse(rom).title {
+        title if title.is_empty() => "Unknown game".to_string(),
+        title => title,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+type Stream = std::fs::File;
+
+struct Connection {
+    stream: Stream,
+}
+
+impl Connection {
+    fn open(client_id: &str) -> io::Result<Self> {
+        let stream = (0..10).find_map(connect).ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Discord is not running"))?;
+        let mut connection = Connection { stream };
+        connection.send(OP_HANDSHAKE, &json!({ "v": 1, "client_id": client_id }))?;
+        connection.receive()?;
+        Ok(connection)
+    }
+
+    fn set_activity(&mut self, activity: &Activity, nonce: u64) -> io::Result<()> {
+        let payload = json!({
+            "cmd": "SET_ACTIVITY",
+            "args": {
+                "pid": std::process::id(),
+                "activity": {
+                    "details": activity.title,
+                    "state": activity.state,
+                    "timestamps": { "start": activity.started },
+                },
+            },
+            "nonce": nonce.to_string(),
+        });
+        self.send(OP_FRAME, &payload)?;
+        self.receive()
+    }
+
+    fn send(&mut self, opcode: u32, payload: &serde_json::Value) -> io::Result<()> {
+        let body = payload.to_string();
+        let mut frame = Vec::with_capacity(8 + body.len());
+        frame.extend_from_slice(&opcode.to_le_bytes());
+        frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        frame.extend_from_slice(body.as_bytes());
+        self.stream.write_all(&frame)
+    }
+
+    // Read (and drop) one reply; a close frame means Discord turned us away
+    fn receive(&mut self) -> io::Result<()> {
+        let mut header = [0u8; 8];
+        self.stream.read_exact(&mut header)?;
+        let opcode = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        let mut body = vec![0; length as usize];
+        self.stream.read_exact(&mut body)?;
+        if opcode == OP_CLOSE {
+            return Err(io::Error::new(io::ErrorKind::ConnectionRefused, String::from_utf8_lossy(&body).into_owned()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn connect(index: u32) -> Option<Stream> {
+    let dirs = ["XDG_RUNTIME_DIR", "TMPDIR", "TMP", "TEMP"].iter().filter_map(|name| std::env::var(name).ok());
+    dirs.chain(["/tmp".to_string()])
+        .flat_map(|dir| ["", "app/com.discordapp.Discord/", "snap.discord/"].map(|sub| format!("{}/{}discord-ipc-{}", dir, sub, index)))
+        .find_map(|path| Stream::connect(path).ok())
+}
+
+#[cfg(windows)]
+fn connect(index: u32) -> Option<Stream> {
+    std::fs::OpenOptions::new().read(true).write(true).open(format!(r"\\.\pipe\discord-ipc-{}", index)).ok()
+}
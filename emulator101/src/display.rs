@@ -0,0 +1,76 @@
+// HiDPI window scaling
+// The main window is SCALE times the Game Boy screen times the desktop's scale factor, taken from
+// the display's DPI (96 DPI is 100%) or given with --ui-scale, so it can be fractional: 1.25,
+// 1.5, ... macOS already sizes windows in points, so there only the Retina backing store is
+// used. The picture itself is still scaled by a whole number: each screen is blown up with
+// nearest-neighbor to the largest integer multiple that fits, and only the step from there to
+// the window size is filtered. Pixels stay even and sharp instead of alternating between two
+// widths.
+
+use sdl2::VideoSubsystem;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+// DPI desktops treat as a scale of 1
+const BASE_DPI: f32 = 96.0;
+
+// Desktop scale factor of the primary display, in quarter steps; 1 when it can't be read
+pub fn detect_scale(video: &VideoSubsystem) -> f32 {
+    if cfg!(target_os = "macos") {
+        return 1.0;
+    }
+    match video.display_dpi(0) {
+        Ok((_, hdpi, _)) if hdpi > 0.0 => ((hdpi / BASE_DPI * 4.0).round() / 4.0).max(1.0),
+        _ => 1.0,
+    }
+}
+
+// Window size for `width`x`height` at `scale`, shrunk (never below 1) to fit the display
+pub fn window_size(video: &VideoSubsystem, width: u32, height: u32, scale: f32) -> (u32, u32) {
+    let scale = match video.display_usable_bounds(0) {
+        Ok(bounds) => scale.min((bounds.width() as f32 / width as f32).min(bounds.height() as f32 / height as f32).max(1.0)),
+        Err(_) => scale,
+    };
+    ((width as f32 * scale).round() as u32, (height as f32 * scale).round() as u32)
+}
+
+// Draws screen textures at any size with an integer prescale
+pub struct SharpScaler<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    // Render target holding the integer-scaled screen, filtered when copied to the window
+    prescaled: Option<Texture<'a>>,
+}
+
+impl<'a> SharpScaler<'a> {
+    pub fn new(texture_creator: &'a TextureCreator<WindowContext>) -> Self {
+        SharpScaler { texture_creator, prescaled: None }
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>, screen: &Texture, dest: Rect) -> Result<(), String> {
+        let query = screen.query();
+        let factor = (dest.width() / query.width).min(dest.height() / query.height).max(1);
+        let (width, height) = (query.width * factor, query.height * factor);
+        if (width, height) == dest.size() {
+            return canvas.copy(screen, None, dest);
+        }
+        let stale = self.prescaled.as_ref().is_none_or(|texture| {
+            let query = texture.query();
+            (query.width, query.height) != (width, height)
+        });
+        if stale {
+            // The scale quality hint is read when a texture is created
+            sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "linear");
+            let texture = self.texture_creator.create_texture_target(None, width, height);
+            sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+            self.prescaled = Some(texture.map_err(|e| e.to_string())?);
+        }
+        let Some(prescaled) = self.prescaled.as_mut() else { return Ok(()) };
+        let mut copied = Ok(());
+        canvas
+            .with_texture_canvas(prescaled, |target| copied = target.copy(screen, None, None))
+            .map_err(|e| e.to_string())?;
+        copied?;
+        canvas.copy(prescaled, None, dest)
+    }
+}
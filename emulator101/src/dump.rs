@@ -0,0 +1,117 @@
+// Machine state as JSON
+// `emulator101 dump <rom> [--state <file>] [--frames <count>] [--output <file.json>]` runs the
+// game headless from power-on (or a savestate) and prints the CPU registers, interrupt flags,
+// timer, PPU counters and every I/O register as JSON, for bug reports and for diffing against
+// another emulator or build at the same point. `F12` writes the same dump of the running game
+// next to the ROM. Values are plain integers and I/O registers are keyed by address, so two
+// dumps line up key for key.
+
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use emulator101_core::{Emulator, EmulatorBuilder};
+
+pub struct Options {
+    pub state: Option<PathBuf>,
+    pub frames: u64,
+    // Printed to stdout without one
+    pub output: Option<PathBuf>,
+}
+
+// `{ "key": value, ... }` indented `depth` levels, values already in JSON
+fn object<K: Display>(fields: impl IntoIterator<Item = (K, String)>, depth: usize) -> String {
+    let indent = "  ".repeat(depth + 1);
+    let fields: Vec<String> = fields.into_iter().map(|(key, value)| format!("{}\"{}\": {}", indent, key, value)).collect();
+    format!("{{\n{}\n{}}}", fields.join(",\n"), "  ".repeat(depth))
+}
+
+// A JSON string; the cartridge title can hold quotes and backslashes
+fn string(value: impl Display) -> String {
+    let mut json = String::from("\"");
+    for c in value.to_string().chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+pub fn to_json(emulator: &Emulator) -> String {
+    let cpu = emulator.cpu();
+    let bus = emulator.bus();
+    let timer = emulator.timer();
+    let ppu = emulator.ppu().state();
+
+    let cpu = object([
+        ("af", cpu.af().to_string()),
+        ("bc", cpu.bc().to_string()),
+        ("de", cpu.de().to_string()),
+        ("hl", cpu.hl().to_string()),
+        ("sp", cpu.sp().to_string()),
+        ("pc", cpu.pc().to_string()),
+        ("ime", cpu.ime().to_string()),
+        ("ime_pending", cpu.ime_pending().to_string()),
+        ("mode", string(emulator.cpu_mode())),
+        ("instructions", cpu.instruction_count.to_string()),
+    ], 1);
+    let interrupts = object([
+        ("ie", bus.get_ie().to_string()),
+        ("if", bus.get_if().to_string()),
+    ], 1);
+    let timer = object([
+        ("div_counter", timer.div_counter().to_string()),
+        ("div", timer.get_div().to_string()),
+        ("tima", timer.get_tima().to_string()),
+        ("tma", timer.get_tma().to_string()),
+        ("tac", timer.get_tac().to_string()),
+        ("overflow_pending", timer.overflow_pending().to_string()),
+        ("reloading", timer.reloading().to_string()),
+    ], 1);
+    let ppu = object([
+        ("mode", (ppu.mode as u8).to_string()),
+        ("dots", ppu.dots.to_string()),
+        ("ly", ppu.ly.to_string()),
+        ("window_line", ppu.window_line.to_string()),
+        ("wy_triggered", ppu.wy_triggered.to_string()),
+        ("window_active", ppu.window_active.to_string()),
+    ], 1);
+    let cartridge = object([
+        ("title", string(&emulator.cartridge().title)),
+        ("type", emulator.cartridge().cartridge_type.to_string()),
+        ("mapper", string(emulator.mapper())),
+        ("ram_bank", bus.cart_ram_bank().map_or("null".to_string(), |bank| bank.to_string())),
+    ], 1);
+    let io = object((0xFF00..=0xFF7Fu16).chain([0xFFFF]).map(|addr| (format!("{:04X}", addr), bus.read_byte(addr).to_string())), 1);
+
+    object([
+        ("model", string(emulator.model())),
+        ("m_cycles", emulator.clock().m_cycles().to_string()),
+        ("cpu", cpu),
+        ("interrupts", interrupts),
+        ("timer", timer),
+        ("ppu", ppu),
+        ("cartridge", cartridge),
+        ("io", io),
+    ], 0) + "\n"
+}
+
+pub fn run(rom_path: &str, options: Options) -> Result<(), Box<dyn std::error::Error>> {
+    let mut emulator = EmulatorBuilder::new().build_from_file(rom_path)?;
+    if let Some(path) = &options.state {
+        let data = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        emulator.load_state(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    for _ in 0..options.frames {
+        emulator.run_until_frame();
+    }
+    let json = to_json(&emulator);
+    match &options.output {
+        Some(path) => std::fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))?,
+        None => print!("{}", json),
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+// Bitmap font
+// A simple 5x7 font shared by the debug windows and the on-screen display. Lowercase letters
+// are drawn with their uppercase glyphs; unknown characters render as blanks.
+
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+pub const GLYPH_WIDTH: usize = 5;
+pub const GLYPH_HEIGHT: usize = 7;
+// Horizontal distance between characters (5 pixels + 1 spacing)
+pub const ADVANCE: usize = 6;
+
+const FONT: &[(char, [u8; GLYPH_HEIGHT])] = &[
+    // Each value represents a row of 5 pixels (1=on, 0=off)
+    ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b00000]),
+    ('B', [0b11110, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110, 0b00000]),
+    ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10001, 0b01110, 0b00000]),
+    ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110, 0b00000]),
+    ('E', [0b11111, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111, 0b00000]),
+    ('F', [0b11111, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000, 0b00000]),
+    ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b01111, 0b00000]),
+    ('H', [0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001, 0b00000]),
+    ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000]),
+    ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100, 0b00000]),
+    ('K', [0b10001, 0b10010, 0b11100, 0b10010, 0b10001, 0b10001, 0b00000]),
+    ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111, 0b00000]),
+    ('M', [0b10001, 0b11011, 0b10101, 0b10001, 0b10001, 0b10001, 0b00000]),
+    ('N', [0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001, 0b00000]),
+    ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110, 0b00000]),
+    ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b00000]),
+    ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10011, 0b01111, 0b00000]),
+    ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10010, 0b10001, 0b00000]),
+    ('S', [0b01111, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110, 0b00000]),
+    ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000]),
+    ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110, 0b00000]),
+    ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00000]),
+    ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b11011, 0b10001, 0b00000]),
+    ('X', [0b10001, 0b01010, 0b00100, 0b00100, 0b01010, 0b10001, 0b00000]),
+    ('Y', [0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000]),
+    ('Z', [0b11111, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111, 0b00000]),
+    ('0', [0b01110, 0b10011, 0b10101, 0b10101, 0b11001, 0b01110, 0b00000]),
+    ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000]),
+    ('2', [0b01110, 0b10001, 0b00010, 0b00100, 0b01000, 0b11111, 0b00000]),
+    ('3', [0b01110, 0b10001, 0b00010, 0b00110, 0b10001, 0b01110, 0b00000]),
+    ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00000]),
+    ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b10001, 0b01110, 0b00000]),
+    ('6', [0b01110, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110, 0b00000]),
+    ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b00000]),
+    ('8', [0b01110, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110, 0b00000]),
+    ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110, 0b00000]),
+    (':', [0b00000, 0b00100, 0b00000, 0b00000, 0b00100, 0b00000, 0b00000]),
+    (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00100, 0b00000]),
+    (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b00100, 0b00100, 0b01000]),
+    ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b00100, 0b00010, 0b00000]),
+    (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00100, 0b01000, 0b00000]),
+    ('[', [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110, 0b00000]),
+    (']', [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110, 0b00000]),
+    ('+', [0b00000, 0b00100, 0b01110, 0b00100, 0b00000, 0b00000, 0b00000]),
+    ('-', [0b00000, 0b00000, 0b01110, 0b00000, 0b00000, 0b00000, 0b00000]),
+    ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
+    ('\\', [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000]),
+    ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
+    ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111, 0b00000]),
+    ('#', [0b01010, 0b11111, 0b01010, 0b01010, 0b11111, 0b01010, 0b00000]),
+    ('>', [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000]),
+    ('x', [0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b00000]),
+    ('a', [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b01111, 0b00000]),
+    ('b', [0b10000, 0b10000, 0b11110, 0b10001, 0b10001, 0b11110, 0b00000]),
+    ('c', [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b01110, 0b00000]),
+    ('d', [0b00001, 0b00001, 0b01111, 0b10001, 0b10001, 0b01111, 0b00000]),
+    ('e', [0b00000, 0b00000, 0b01110, 0b10001, 0b11110, 0b01111, 0b00000]),
+    ('f', [0b00110, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000, 0b00000]),
+    ('g', [0b00000, 0b00000, 0b01111, 0b10001, 0b01111, 0b00001, 0b01110]),
+    ('h', [0b10000, 0b10000, 0b11110, 0b10001, 0b10001, 0b10001, 0b00000]),
+    ('i', [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b01110, 0b00000]),
+    ('j', [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100]),
+    ('k', [0b10000, 0b10000, 0b10010, 0b11100, 0b10010, 0b10001, 0b00000]),
+    ('l', [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000]),
+    ('m', [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b00000]),
+    ('n', [0b00000, 0b00000, 0b11110, 0b10001, 0b10001, 0b10001, 0b00000]),
+    ('o', [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b01110, 0b00000]),
+    ('p', [0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000]),
+    ('q', [0b00000, 0b00000, 0b01111, 0b10001, 0b01111, 0b00001, 0b00001]),
+    ('r', [0b00000, 0b00000, 0b10110, 0b11000, 0b10000, 0b10000, 0b00000]),
+    ('s', [0b00000, 0b00000, 0b01111, 0b10000, 0b01110, 0b11110, 0b00000]),
+    ('t', [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b00110, 0b00000]),
+    ('u', [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01111, 0b00000]),
+    ('v', [0b00000, 0b00000, 0b10001, 0b10001, 0b01010, 0b00100, 0b00000]),
+    ('w', [0b00000, 0b00000, 0b10001, 0b10101, 0b10101, 0b01010, 0b00000]),
+    ('y', [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01000, 0b10000]),
+    ('z', [0b00000, 0b00000, 0b11111, 0b00010, 0b01100, 0b11111, 0b00000]),
+];
+
+// Rows of the glyph for `c`, most significant of the low 5 bits is the leftmost pixel
+pub fn glyph(c: char) -> &'static [u8; GLYPH_HEIGHT] {
+    let c = c.to_ascii_uppercase();
+    FONT.iter()
+        .find(|(glyph_char, _)| *glyph_char == c)
+        .map(|(_, rows)| rows)
+        .unwrap_or(&[0; GLYPH_HEIGHT])
+}
+
+// Draw text onto a debug window, one point per lit pixel
+pub fn draw_text(canvas: &mut Canvas<Window>, text: &str, x: i32, y: i32, color: Color) -> Result<(), String> {
+    canvas.set_draw_color(color);
+    let mut cursor_x = x;
+    for c in text.chars() {
+        for (row, &bitmap_row) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if (bitmap_row >> (GLYPH_WIDTH - 1 - col)) & 0x01 == 1 {
+                    canvas.draw_point((cursor_x + col as i32, y + row as i32))?;
+                }
+            }
+        }
+        cursor_x += ADVANCE as i32;
+    }
+    Ok(())
+}
+
+// Draw text into an RGBA32 buffer `width` pixels wide, clipping at the edges
+#[cfg_attr(not(feature = "lua"), allow(dead_code))]
+pub fn draw_text_rgba(buffer: &mut [u8], width: usize, x: i32, y: i32, text: &str, color: [u8; 4]) {
+    let height = buffer.len() / (width * 4);
+    let mut cursor_x = x;
+    for c in text.chars() {
+        for (row, &bitmap_row) in glyph(c).iter().enumerate() {
+            for col in 0..GLYPH_WIDTH {
+                if (bitmap_row >> (GLYPH_WIDTH - 1 - col)) & 0x01 == 0 {
+                    continue;
+                }
+                let px = cursor_x + col as i32;
+                let py = y + row as i32;
+                if px < 0 || py < 0 || px as usize >= width || py as usize >= height {
+                    continue;
+                }
+                let offset = (py as usize * width + px as usize) * 4;
+                buffer[offset..offset + 4].copy_from_slice(&color);
+            }
+        }
+        cursor_x += ADVANCE as i32;
+    }
+}
@@ -0,0 +1,65 @@
+// Gamma and brightness correction
+// A final stage applied to each RGBA32 frame before it is shown, so the dark DMG palette stays
+// readable on modern displays. Every channel goes through one 256-entry lookup table:
+// out = brightness * (in / 255) ^ (1 / gamma), so gamma above 1 lifts the dark shades.
+
+use crate::font;
+
+const GAMMA_RANGE: (f32, f32) = (0.5, 3.0);
+const BRIGHTNESS_RANGE: (f32, f32) = (0.5, 2.0);
+// Change per key press
+pub const STEP: f32 = 0.1;
+
+pub struct ColorCorrection {
+    gamma: f32,
+    brightness: f32,
+    lut: [u8; 256],
+    identity: bool,
+}
+
+impl ColorCorrection {
+    pub fn new(gamma: f32, brightness: f32) -> Self {
+        let mut correction = ColorCorrection { gamma: 1.0, brightness: 1.0, lut: [0; 256], identity: true };
+        correction.set(gamma, brightness);
+        correction
+    }
+
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    // Values are clamped to the supported range and rounded to the step size
+    pub fn set(&mut self, gamma: f32, brightness: f32) {
+        let snap = |value: f32, (min, max): (f32, f32)| ((value / STEP).round() * STEP).clamp(min, max);
+        self.gamma = snap(gamma, GAMMA_RANGE);
+        self.brightness = snap(brightness, BRIGHTNESS_RANGE);
+        for (value, out) in self.lut.iter_mut().enumerate() {
+            let level = (value as f32 / 255.0).powf(1.0 / self.gamma) * self.brightness;
+            *out = (level * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+        self.identity = self.lut.iter().enumerate().all(|(value, &out)| value == out as usize);
+    }
+
+    // Correct the color channels of an RGBA32 buffer in place (alpha is left alone)
+    pub fn apply(&self, pixels: &mut [u8]) {
+        if self.identity {
+            return;
+        }
+        for pixel in pixels.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel = self.lut[*channel as usize];
+            }
+        }
+    }
+
+    // Current settings as a line of on-screen text
+    pub fn draw_osd(&self, pixels: &mut [u8], width: usize) {
+        let text = format!("GAMMA {:.1} BRIGHT {:.1}", self.gamma, self.brightness);
+        font::draw_text_rgba(pixels, width, 3, 4, &text, [0x00, 0x00, 0x00, 0xFF]);
+        font::draw_text_rgba(pixels, width, 2, 3, &text, [0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+}
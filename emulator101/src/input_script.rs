@@ -0,0 +1,99 @@
+// Input scripts
+// `--input-script <file.toml>` plays button presses from launch, for kiosk demos and for
+// reproducing a bug the same way every time without recording a movie. Frames count from the
+// start; while the script runs it owns the joypad, and once it is over everything is released
+// and the keyboard takes over.
+//
+//   [[input]]
+//   frame = 120              # press Start at frame 120 (for PRESS_FRAMES frames)
+//   press = "start"
+//
+//   [[input]]
+//   frame = 200              # hold Right and B for a second
+//   hold = ["right", "b"]
+//   frames = 60
+//
+// Button names are a, b, select, start, up, down, left and right; `press` and `hold` take one
+// name or a list. Inputs may overlap, in which case their buttons are held together.
+
+use std::path::Path;
+
+use emulator101_core::JoypadButton;
+use serde::Deserialize;
+
+// How long `press` holds its buttons: long enough for games that poll the joypad once a frame
+// or debounce it
+const PRESS_FRAMES: u64 = 6;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct File {
+    #[serde(default)]
+    input: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    frame: u64,
+    press: Option<Buttons>,
+    hold: Option<Buttons>,
+    frames: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Buttons {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Buttons {
+    fn mask(&self) -> Result<u8, String> {
+        let names = match self {
+            Buttons::One(name) => std::slice::from_ref(name),
+            Buttons::Many(names) => names.as_slice(),
+        };
+        names.iter().try_fold(0, |mask, name| Ok(mask | name.parse::<JoypadButton>()?.mask()))
+    }
+}
+
+pub struct InputScript {
+    // (first frame, frames held, buttons), in file order
+    inputs: Vec<(u64, u64, u8)>,
+    // First frame after the last input
+    end: u64,
+}
+
+impl InputScript {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let file: File = toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut inputs = Vec::new();
+        for (index, entry) in file.input.iter().enumerate() {
+            let context = |e: String| format!("{}: input {} (frame {}): {}", path.display(), index + 1, entry.frame, e);
+            let (buttons, frames) = match (&entry.press, &entry.hold, entry.frames) {
+                (Some(buttons), None, frames) => (buttons, frames.unwrap_or(PRESS_FRAMES)),
+                (None, Some(buttons), Some(frames)) => (buttons, frames),
+                (None, Some(_), None) => return Err(context("`hold` needs `frames`".to_string())),
+                _ => return Err(context("expected one of `press` or `hold`".to_string())),
+            };
+            inputs.push((entry.frame, frames, buttons.mask().map_err(context)?));
+        }
+        let end = inputs.iter().map(|&(frame, frames, _)| frame + frames).max().unwrap_or(0);
+        Ok(InputScript { inputs, end })
+    }
+
+    // Frames the script runs for
+    pub fn length(&self) -> u64 {
+        self.end
+    }
+
+    // Buttons to hold on `frame`; nothing held once the script is over
+    pub fn buttons(&self, frame: u64) -> u8 {
+        self.inputs
+            .iter()
+            .filter(|&&(start, frames, _)| (start..start + frames).contains(&frame))
+            .fold(0, |mask, &(_, _, buttons)| mask | buttons)
+    }
+}
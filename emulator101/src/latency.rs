@@ -0,0 +1,72 @@
+// Input-to-photon latency test
+// With --latency-test the frontend sends a synthetic input once a second. The emulation thread
+// answers it by publishing a white frame, and the time from the input to that frame being
+// presented is split into the stages of the host pipeline: waiting for and running emulation,
+// uploading the texture and presenting (which includes waiting for vsync when enabled).
+
+use std::time::{Duration, Instant};
+
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+// Samples between reports
+const REPORT_EVERY: usize = 10;
+
+// Times of one probe through the pipeline
+pub struct Sample {
+    pub sent: Instant,
+    pub published: Instant,
+    pub uploaded: Instant,
+    pub presented: Instant,
+}
+
+pub struct LatencyTest {
+    next_probe: Instant,
+    // (emulation, upload, present, total) of every sample so far
+    samples: Vec<[Duration; 4]>,
+}
+
+impl LatencyTest {
+    pub fn new() -> Self {
+        LatencyTest { next_probe: Instant::now() + PROBE_INTERVAL, samples: Vec::new() }
+    }
+
+    // The time to stamp a new probe with, if one is due
+    pub fn probe_due(&mut self) -> Option<Instant> {
+        let now = Instant::now();
+        if now < self.next_probe {
+            return None;
+        }
+        self.next_probe = now + PROBE_INTERVAL;
+        Some(now)
+    }
+
+    // Add a sample; returns a report every REPORT_EVERY samples
+    pub fn record(&mut self, sample: Sample) -> Option<String> {
+        self.samples.push([
+            sample.published - sample.sent,
+            sample.uploaded - sample.published,
+            sample.presented - sample.uploaded,
+            sample.presented - sample.sent,
+        ]);
+        self.samples.len().is_multiple_of(REPORT_EVERY).then(|| self.report())
+    }
+
+    // Min / average / 95th percentile / max of each stage over all samples
+    pub fn report(&self) -> String {
+        if self.samples.is_empty() {
+            return "no samples".to_string();
+        }
+        let stages = ["emulation", "upload", "present", "total"];
+        let mut lines = vec![format!("{} samples, ms min/avg/p95/max:", self.samples.len())];
+        for (stage, name) in stages.iter().enumerate() {
+            let mut times: Vec<f64> = self.samples.iter().map(|sample| sample[stage].as_secs_f64() * 1000.0).collect();
+            times.sort_by(f64::total_cmp);
+            let average = times.iter().sum::<f64>() / times.len() as f64;
+            let p95 = times[(times.len() * 95 / 100).min(times.len() - 1)];
+            lines.push(format!(
+                "  {:<9} {:6.2} {:6.2} {:6.2} {:6.2}",
+                name, times[0], average, p95, times[times.len() - 1]
+            ));
+        }
+        lines.join("\n")
+    }
+}
@@ -1,6 +0,0 @@
-pub mod cpu;
-pub mod memory;
-pub mod interrupts;
-pub mod timer;
-pub mod ppu;
-pub mod vram_viewer;
\ No newline at end of file
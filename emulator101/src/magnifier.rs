@@ -0,0 +1,143 @@
+// Pixel magnifier
+// F3 toggles a zoomed view of the pixels around the mouse on the game screen, with the hovered
+// pixel's position, its color number and the palette register it went through (BGP for the
+// background and window, OBP0/OBP1 for sprites). Meant for chasing a single wrong pixel
+// alongside the VRAM viewer; with a link cable only the first player's screen is inspected.
+
+use emulator101_core::ppu::PixelSource;
+use emulator101_core::{SCREEN_WIDTH, SCREEN_HEIGHT};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::font;
+
+// Pixels shown either side of the hovered one
+const RADIUS: i32 = 5;
+const SPAN: u32 = RADIUS as u32 * 2 + 1;
+// Size of a magnified pixel, in screen pixels
+const ZOOM: u32 = 3;
+// Distance from the mouse to the box, in screen pixels
+const GAP: i32 = 4;
+const READOUT_LINES: u32 = 3;
+
+pub struct Magnifier {
+    enabled: bool,
+    // Hovered pixel of the first screen
+    hovered: Option<(usize, usize)>,
+    // Last frame, after color correction and before any overlay
+    pixels: Vec<u8>,
+    sources: Vec<PixelSource>,
+}
+
+impl Magnifier {
+    pub fn new() -> Self {
+        Magnifier { enabled: false, hovered: None, pixels: Vec::new(), sources: Vec::new() }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Follow the mouse over the first of `screens` side by side in `window`. Returns true for
+    // events it used up.
+    pub fn handle_event(&mut self, event: &Event, window: &Window, screens: u32) -> bool {
+        match *event {
+            Event::MouseMotion { window_id, x, y, .. } if window_id == window.id() => {
+                // Mouse coordinates are in window points, which the screens fill
+                let (width, height) = window.size();
+                let screen_width = (width / screens).max(1);
+                let (x, y) = (x.max(0) as u32, y.max(0) as u32);
+                self.hovered = (x < screen_width && y < height).then(|| {
+                    ((x * SCREEN_WIDTH as u32 / screen_width) as usize, (y * SCREEN_HEIGHT as u32 / height.max(1)) as usize)
+                });
+                true
+            },
+            Event::Window { window_id, win_event: WindowEvent::Leave, .. } if window_id == window.id() => {
+                self.hovered = None;
+                false
+            },
+            _ => false,
+        }
+    }
+
+    // Keep the frame to zoom into; `sources` stays empty until the emulation thread sends them
+    pub fn capture(&mut self, pixels: &[u8], sources: &[PixelSource]) {
+        self.pixels.clear();
+        self.pixels.extend_from_slice(pixels);
+        if !sources.is_empty() {
+            self.sources.clear();
+            self.sources.extend_from_slice(sources);
+        }
+    }
+
+    // Draw the box beside the mouse; `screen` is where the first screen is on the canvas
+    pub fn draw(&self, canvas: &mut Canvas<Window>, screen: Rect) -> Result<(), String> {
+        let Some((hovered_x, hovered_y)) = self.hovered.filter(|_| self.enabled && !self.pixels.is_empty()) else {
+            return Ok(());
+        };
+        let scale = (screen.width() / SCREEN_WIDTH as u32).max(1);
+        let cell = scale * ZOOM;
+        let size = SPAN * cell;
+        let readout_height = READOUT_LINES * (font::GLYPH_HEIGHT as u32 + 2) * scale;
+
+        // Below and right of the mouse, flipped to the other side near the edges
+        let (canvas_width, canvas_height) = canvas.output_size()?;
+        let mouse_x = screen.x() + (hovered_x as u32 * screen.width() / SCREEN_WIDTH as u32) as i32;
+        let mouse_y = screen.y() + (hovered_y as u32 * screen.height() / SCREEN_HEIGHT as u32) as i32;
+        let gap = GAP * scale as i32;
+        let mut left = mouse_x + gap + scale as i32;
+        if left + size as i32 > canvas_width as i32 {
+            left = mouse_x - gap - size as i32;
+        }
+        let mut top = mouse_y + gap + scale as i32;
+        if top + (size + readout_height) as i32 > canvas_height as i32 {
+            top = mouse_y - gap - (size + readout_height) as i32;
+        }
+
+        canvas.set_draw_color(Color::RGB(0x20, 0x20, 0x20));
+        canvas.fill_rect(Rect::new(left - 1, top - 1, size + 2, size + readout_height + 2))?;
+        for row in 0..SPAN as i32 {
+            for column in 0..SPAN as i32 {
+                let x = hovered_x as i32 + column - RADIUS;
+                let y = hovered_y as i32 + row - RADIUS;
+                if !(0..SCREEN_WIDTH as i32).contains(&x) || !(0..SCREEN_HEIGHT as i32).contains(&y) {
+                    continue;
+                }
+                let offset = (y as usize * SCREEN_WIDTH + x as usize) * 4;
+                let [r, g, b, _] = self.pixels[offset..offset + 4] else { continue };
+                canvas.set_draw_color(Color::RGB(r, g, b));
+                canvas.fill_rect(Rect::new(left + column * cell as i32, top + row * cell as i32, cell, cell))?;
+            }
+        }
+        // Outline the hovered pixel in black and white so it shows on any shade
+        let center = Rect::new(left + RADIUS * cell as i32, top + RADIUS * cell as i32, cell, cell);
+        canvas.set_draw_color(Color::RGB(0x00, 0x00, 0x00));
+        canvas.draw_rect(center)?;
+        canvas.set_draw_color(Color::RGB(0xFF, 0xFF, 0xFF));
+        canvas.draw_rect(Rect::new(center.x() - 1, center.y() - 1, cell + 2, cell + 2))?;
+
+        // The readout is drawn at the screen's scale, so it reads like the rest of the screen
+        let offset = (hovered_y * SCREEN_WIDTH + hovered_x) * 4;
+        let [r, g, b] = [self.pixels[offset], self.pixels[offset + 1], self.pixels[offset + 2]];
+        let source = match self.sources.get(hovered_y * SCREEN_WIDTH + hovered_x) {
+            Some(PixelSource { layer, color_index, palette }) => format!("{} {} {}", layer, color_index, palette),
+            None => String::new(),
+        };
+        let lines = [format!("{},{}", hovered_x, hovered_y), source, format!("RGB {:02X}{:02X}{:02X}", r, g, b)];
+        canvas.set_scale(scale as f32, scale as f32)?;
+        let text_x = (left + 2 * scale as i32) / scale as i32;
+        let mut text_y = (top + size as i32 + scale as i32) / scale as i32;
+        for line in &lines {
+            font::draw_text(canvas, line, text_x, text_y, Color::RGB(0xFF, 0xFF, 0xFF))?;
+            text_y += font::GLYPH_HEIGHT as i32 + 2;
+        }
+        canvas.set_scale(1.0, 1.0)
+    }
+}
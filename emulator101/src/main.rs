@@ -1,85 +1,680 @@
 use std::fs::File;
 use std::io::Read;
-use std::time::Duration;
-use std::time::Instant;
-use std::thread::sleep;
+use std::time::{Duration, Instant};
 use std::env;
+use std::path::{Path, PathBuf};
 
-use emulator101::cpu::Cpu;
-use emulator101::memory::MemoryBus;
-use emulator101::ppu::{SCREEN_WIDTH, SCREEN_HEIGHT};
-use emulator101::vram_viewer::VramViewer;
-use emulator101::interrupts::InterruptType;
+use emulator101_core::{AccuracyProfile, Cartridge, Emulator, EmulatorBuilder, ErrorPolicy, JoypadButton, LocalLink, MapperKind, Model, Movie, Palette, RtcMode, Severity, Stats, StreamLink, STREAM_REPLY_TIMEOUT, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+mod font;
+mod battery;
+#[cfg(feature = "achievements")]
+mod achievements;
+mod audio;
+mod banks;
+mod beam;
+mod cart_ram;
+mod ci;
+mod config;
+#[cfg(feature = "discord")]
+mod discord;
+mod disasm;
+mod display;
+mod dump;
+mod gamma;
+mod input_script;
+mod latency;
+mod magnifier;
+mod play_time;
+mod ram_search;
+mod rtc;
+mod rumble;
+mod scale;
+mod sync;
+mod spectate;
+mod splash;
+mod state_picker;
+mod tabs;
+#[cfg(feature = "lua")]
+mod scripting;
+mod triple_buffer;
+mod vram_viewer;
+mod worker;
+mod zip;
+use battery::BatterySave;
+use cart_ram::CartRamEditor;
+use config::Settings;
+use gamma::ColorCorrection;
+use input_script::InputScript;
+use latency::LatencyTest;
+use magnifier::Magnifier;
+use play_time::PlayTimes;
+use ram_search::RamSearch;
+use rumble::Rumble;
+use scale::Filter;
+use sync::SyncStats;
+use spectate::{SpectatorClient, SpectatorServer};
+use state_picker::StatePicker;
+use tabs::{Tab, Tabs};
+use vram_viewer::VramViewer;
+use worker::{Command, Config, EmulatorThread, MovieFile, Pacing, Reply, Step};
 
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::PixelFormatEnum;
 use sdl2::rect::Rect;
 
 const SCALE: u32 = 3;
+const WINDOW_TITLE: &str = "Game Boy Emulator";
+// How often the performance numbers in the title bar are refreshed
+const HUD_INTERVAL: Duration = Duration::from_secs(1);
+// How long the gamma/brightness settings stay on screen after being changed
+const OSD_DURATION: Duration = Duration::from_secs(2);
+
+// Settings from the command line
+struct RunOptions {
+    model: Option<Model>, // None: auto-detect from the cartridge header
+    mapper: Option<MapperKind>, // None: auto-detect from the cartridge header
+    error_policy: ErrorPolicy,
+    boot_rom: Option<PathBuf>,
+    palette: Palette,
+    accuracy: Option<AccuracyProfile>, // None: the config file's profile, else balanced
+    strict_echo_ram: bool, // On top of the profile
+    no_sprite_limit: bool, // Likewise
+    script: Option<PathBuf>,
+    cheats: Vec<String>, // Game Genie codes
+    link: Option<String>, // Second ROM to play against over a link cable
+    tabs: Vec<String>, // More ROMs, each in a background tab (Ctrl+Tab)
+    serial: Option<String>, // Link cable peer: "stdio" or a pipe/device path
+    serial_timeout: Option<u64>, // Milliseconds to wait for the peer's answer, None for the default
+    spectate: Option<String>, // Address to serve the screens to spectators on
+    record: Option<PathBuf>, // New input movie to record
+    play: Option<PathBuf>, // Input movie to play back (and continue recording)
+    opcode_coverage: Option<PathBuf>, // Where to write the opcode coverage report on exit
+    save_backups: usize, // Backups kept of each battery save
+    save_interval: u64, // Seconds between writes of a changed battery save, 0 for only on exit
+    latency_test: bool,
+    achievements: Option<PathBuf>,
+    input_script: Option<PathBuf>, // Buttons to play from launch
+    // Seconds to move the cartridge clock by once the save is loaded
+    rtc_shift: Option<i64>,
+    // Headless CI run instead of the window (see ci.rs)
+    automation: Option<ci::Automation>,
+    // Pace to the hardware's 59.7275 Hz instead of 60 Hz
+    exact_frame_rate: bool,
+    diagnostics: Option<Severity>, // Least severe diagnostic reported, None for none
+    stack_checks: bool,
+    gamma: f32,
+    brightness: f32,
+    filter: Filter,
+    // Window scale on top of SCALE; None detects the desktop's
+    ui_scale: Option<f32>,
+    config: Option<PathBuf>, // Settings file other than the default (see config.rs)
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            model: None,
+            mapper: None,
+            error_policy: ErrorPolicy::Report,
+            boot_rom: None,
+            palette: Palette::default(),
+            accuracy: None,
+            strict_echo_ram: false,
+            no_sprite_limit: false,
+            script: None,
+            cheats: Vec::new(),
+            link: None,
+            tabs: Vec::new(),
+            serial: None,
+            serial_timeout: None,
+            spectate: None,
+            record: None,
+            play: None,
+            opcode_coverage: None,
+            save_backups: battery::DEFAULT_BACKUPS,
+            save_interval: battery::DEFAULT_FLUSH_INTERVAL,
+            latency_test: false,
+            exact_frame_rate: false,
+            automation: None,
+            rtc_shift: None,
+            achievements: None,
+            input_script: None,
+            diagnostics: None,
+            stack_checks: false,
+            gamma: 1.0,
+            brightness: 1.0,
+            filter: Filter::Nearest,
+            ui_scale: None,
+            config: None,
+        }
+    }
+}
 
 fn read_rom(path: &str) -> Result<Vec<u8>, std::io::Error> {
     let mut rom_data = Vec::new();
     let mut file = File::open(path)?;
     file.read_to_end(&mut rom_data)?;
+    if zip::is_zip(&rom_data) {
+        return zip::extract_rom(&rom_data).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e));
+    }
     Ok(rom_data)
 }
 
+// Window title with the game's name from its header, and the file it came from when there are
+// tabs to tell apart (two revisions of a game usually share a title)
+fn window_title(title: &str, rom_path: &str, tabbed: bool) -> String {
+    match (title, tabbed) {
+        (_, true) => format!("{} - {} ({})", WINDOW_TITLE, title, tabs::name(rom_path)),
+        ("", false) => WINDOW_TITLE.to_string(),
+        (_, false) => format!("{} - {}", WINDOW_TITLE, title),
+    }
+}
+
+// Window title with emulation performance: frames per second over the last interval, speed
+// relative to hardware and the host time the last frame took to emulate, and the game's play
+// time when asked for
+fn hud_title(title: &str, stats: &Stats, fps: f64, show_play_time: bool) -> String {
+    let title = format!(
+        "{} - {:.1} fps | {:.0}% speed | {:.2} ms/frame",
+        title,
+        fps,
+        stats.speed * 100.0,
+        stats.last_frame_time.as_secs_f64() * 1000.0,
+    );
+    if show_play_time {
+        return format!("{} | {} played", title, play_time::format(stats.play_time));
+    }
+    title
+}
+
+// Linked play, as (player, button): player 1 (left screen) uses WASD with G for A, F for B,
+// Left Shift for Select and Tab for Start; player 2 (right screen) uses the arrows with K for A,
+// J for B, Right Shift for Select and Return for Start
+fn map_linked_key(key: Keycode) -> Option<(usize, JoypadButton)> {
+    match key {
+        Keycode::D => Some((0, JoypadButton::Right)),
+        Keycode::A => Some((0, JoypadButton::Left)),
+        Keycode::W => Some((0, JoypadButton::Up)),
+        Keycode::S => Some((0, JoypadButton::Down)),
+        Keycode::G => Some((0, JoypadButton::A)),
+        Keycode::F => Some((0, JoypadButton::B)),
+        Keycode::LShift => Some((0, JoypadButton::Select)),
+        Keycode::Tab => Some((0, JoypadButton::Start)),
+        Keycode::Right => Some((1, JoypadButton::Right)),
+        Keycode::Left => Some((1, JoypadButton::Left)),
+        Keycode::Up => Some((1, JoypadButton::Up)),
+        Keycode::Down => Some((1, JoypadButton::Down)),
+        Keycode::K => Some((1, JoypadButton::A)),
+        Keycode::J => Some((1, JoypadButton::B)),
+        Keycode::RShift => Some((1, JoypadButton::Select)),
+        Keycode::Return => Some((1, JoypadButton::Start)),
+        _ => None,
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> 
 {
+    // Verbose logging per subsystem, e.g. RUST_LOG=emulator101_core::ppu=trace (errors only by default)
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::IsTerminal::is_terminal(&std::io::stderr()))
+        .init();
+
     // Get command line arguments
     let args: Vec<String> = env::args().collect();
+    // Started without arguments, from a file manager say: wait for a ROM to be dropped on the
+    // window. Without a display there is nothing to show but the usage.
+    if args.len() == 1 {
+        match splash::wait_for_rom(None, None) {
+            Ok(Some(rom_path)) => run_emulator(&rom_path, RunOptions::default())?,
+            Ok(None) => {},
+            Err(_) => print_usage(),
+        }
+        return Ok(());
+    }
     if args.len() < 3 {
-        println!("Usage: emulator101 [run <rom_path>]");
+        print_usage();
         return Ok(());
     }
     
     if args[1] == "run" {
-        run_emulator(&args[2])?;
+        let mut run_options = RunOptions::default();
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            if option == "--latency-test" {
+                run_options.latency_test = true;
+                continue;
+            }
+            if option == "--until-ld-b-b" {
+                run_options.automation.get_or_insert_with(Default::default).until_ld_b_b = true;
+                continue;
+            }
+            if option == "--strict-echo-ram" {
+                run_options.strict_echo_ram = true;
+                continue;
+            }
+            if option == "--no-sprite-limit" {
+                run_options.no_sprite_limit = true;
+                continue;
+            }
+            if option == "--stack-checks" {
+                run_options.stack_checks = true;
+                continue;
+            }
+            match (option.as_str(), options.next().map(String::as_str)) {
+                ("--on-error", Some("ignore")) => run_options.error_policy = ErrorPolicy::Ignore,
+                ("--on-error", Some("log")) => run_options.error_policy = ErrorPolicy::Report,
+                ("--on-error", Some("stop")) => run_options.error_policy = ErrorPolicy::Stop,
+                ("--model", Some("auto")) => run_options.model = None,
+                ("--model", Some(name)) => run_options.model = Some(name.parse::<Model>()?),
+                ("--mapper", Some("auto")) => run_options.mapper = None,
+                ("--mapper", Some(name)) => run_options.mapper = Some(name.parse::<MapperKind>()?),
+                ("--bootrom", Some(path)) => run_options.boot_rom = Some(PathBuf::from(path)),
+                ("--palette", Some("green")) => run_options.palette = Palette::GREEN,
+                ("--palette", Some("grayscale")) => run_options.palette = Palette::GRAYSCALE,
+                ("--accuracy", Some(name)) => run_options.accuracy = Some(name.parse::<AccuracyProfile>()?),
+                ("--script", Some(path)) => run_options.script = Some(PathBuf::from(path)),
+                ("--cheat", Some(code)) => run_options.cheats.push(code.to_string()),
+                ("--link", Some(path)) => run_options.link = Some(path.to_string()),
+                ("--tab", Some(path)) => run_options.tabs.push(path.to_string()),
+                ("--serial", Some(peer)) => run_options.serial = Some(peer.to_string()),
+                ("--serial-timeout", Some(millis)) => run_options.serial_timeout = Some(millis.parse()?),
+                ("--spectate", Some(address)) => run_options.spectate = Some(address.to_string()),
+                ("--record", Some(path)) => run_options.record = Some(PathBuf::from(path)),
+                ("--play", Some(path)) => run_options.play = Some(PathBuf::from(path)),
+                ("--opcode-coverage", Some(path)) => run_options.opcode_coverage = Some(PathBuf::from(path)),
+                ("--save-backups", Some(count)) => run_options.save_backups = count.parse()?,
+                ("--save-interval", Some(seconds)) => run_options.save_interval = seconds.parse()?,
+                ("--gamma", Some(value)) => run_options.gamma = value.parse::<f32>()?,
+                ("--brightness", Some(value)) => run_options.brightness = value.parse::<f32>()?,
+                ("--diagnostics", Some("off")) => run_options.diagnostics = None,
+                ("--diagnostics", Some("info")) => run_options.diagnostics = Some(Severity::Info),
+                ("--diagnostics", Some("warning")) => run_options.diagnostics = Some(Severity::Warning),
+                ("--config", Some(path)) => run_options.config = Some(PathBuf::from(path)),
+                ("--achievements", Some(path)) => run_options.achievements = Some(PathBuf::from(path)),
+                ("--input-script", Some(path)) => run_options.input_script = Some(PathBuf::from(path)),
+                ("--rtc-shift", Some(amount)) => run_options.rtc_shift = Some(rtc::parse_shift(amount)?),
+                ("--frames", Some(count)) => run_options.automation.get_or_insert_with(Default::default).frames = Some(count.parse()?),
+                ("--expect-serial", Some(text)) => run_options.automation.get_or_insert_with(Default::default).expect_serial = Some(text.to_string()),
+                ("--screenshot", Some(path)) => run_options.automation.get_or_insert_with(Default::default).screenshot = Some(PathBuf::from(path)),
+                ("--frame-rate", Some("60")) => run_options.exact_frame_rate = false,
+                ("--frame-rate", Some("exact")) => run_options.exact_frame_rate = true,
+                ("--ui-scale", Some("auto")) => run_options.ui_scale = None,
+                ("--ui-scale", Some(value)) => run_options.ui_scale = Some(value.parse::<f32>()?.clamp(1.0, 4.0)),
+                ("--filter", Some("nearest")) => run_options.filter = Filter::Nearest,
+                ("--filter", Some("scale2x")) => run_options.filter = Filter::Scale2x,
+                ("--filter", Some("scale3x")) => run_options.filter = Filter::Scale3x,
+                _ => return Err(format!("invalid option {}", option).into()),
+            }
+        }
+        run_emulator(&args[2], run_options)?;
+    } else if args[1] == "watch" {
+        let player = match (args.get(3).map(String::as_str), args.get(4).map(String::as_str)) {
+            (None, _) => 0,
+            (Some("--player"), Some("1")) => 0,
+            (Some("--player"), Some("2")) => 1,
+            _ => return Err("usage: emulator101 watch <address> [--player 1|2]".into()),
+        };
+        watch(&args[2], player)?;
+    } else if args[1] == "rtc" {
+        let shift = args.get(3).map(|amount| rtc::parse_shift(amount)).transpose()?;
+        let emulator = EmulatorBuilder::new().rtc_mode(RtcMode::WallClock).build_from_file(&args[2])?;
+        rtc::run(emulator, &args[2], shift)?;
+    } else if args[1] == "disasm" {
+        let mut listing = disasm::Options { bank: 0, sym: None, entries: Vec::new() };
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            match (option.as_str(), options.next().map(String::as_str)) {
+                ("--bank", Some(bank)) => listing.bank = bank.parse()?,
+                ("--sym", Some(path)) => listing.sym = Some(path.to_string()),
+                ("--entry", Some(address)) => listing.entries.push(disasm::parse_address(address)?),
+                _ => return Err(format!("invalid option {}", option).into()),
+            }
+        }
+        disasm::run(&args[2], listing)?;
+    } else if args[1] == "dump" {
+        let mut dump_options = dump::Options { state: None, frames: 0, output: None };
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            match (option.as_str(), options.next().map(String::as_str)) {
+                ("--state", Some(path)) => dump_options.state = Some(PathBuf::from(path)),
+                ("--frames", Some(count)) => dump_options.frames = count.parse()?,
+                ("--output", Some(path)) => dump_options.output = Some(PathBuf::from(path)),
+                _ => return Err(format!("invalid option {}", option).into()),
+            }
+        }
+        dump::run(&args[2], dump_options)?;
+    } else if args[1] == "banks" {
+        let mut trace_options = banks::Options { state: None, frames: 600, log: false };
+        let mut options = args[3..].iter();
+        while let Some(option) = options.next() {
+            if option == "--log" {
+                trace_options.log = true;
+                continue;
+            }
+            match (option.as_str(), options.next().map(String::as_str)) {
+                ("--state", Some(path)) => trace_options.state = Some(PathBuf::from(path)),
+                ("--frames", Some(count)) => trace_options.frames = count.parse()?,
+                _ => return Err(format!("invalid option {}", option).into()),
+            }
+        }
+        banks::run(&args[2], trace_options)?;
     } else {
-        println!("Usage: emulator101 [test|run <rom_path>]");
+        print_usage();
     }
 
     Ok(())
 }
 
-fn run_emulator(rom_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+fn print_usage() {
+    println!("Usage: emulator101 [run <rom_path> [--model auto|dmg|mgb|sgb|cgb|agb] [--mapper auto|none|mbc1|mbc3|mbc5|m161|wisdom-tree|huc3] [--on-error ignore|log|stop] [--bootrom <file>] [--palette green|grayscale] [--accuracy fast|balanced|cycle-accurate] [--strict-echo-ram] [--no-sprite-limit] [--script <file.lua>] [--cheat <game genie code>]... [--link <second_rom_path>] [--tab <rom_path>]... [--serial stdio|<pipe_path>] [--serial-timeout <ms>] [--gamma <0.5-3.0>] [--brightness <0.5-2.0>] [--spectate <address>] [--record <movie>|--play <movie>] [--latency-test] [--diagnostics off|info|warning] [--stack-checks] [--opcode-coverage <report_file>] [--save-backups <count>] [--save-interval <seconds>] [--filter nearest|scale2x|scale3x] [--config <file.toml>] [--ui-scale auto|<1.0-4.0>] [--frame-rate 60|exact] [--frames <count>] [--expect-serial <text>] [--until-ld-b-b] [--screenshot <file.png>] [--rtc-shift <+24h|-30m|...>] [--achievements <file.toml>] [--input-script <file.toml>]]");
+    println!("       emulator101 watch <address> [--player 1|2]");
+    println!("       emulator101 rtc <rom_path> [<+24h|-30m|...>]");
+    println!("       emulator101 disasm <rom_path> [--bank <number>] [--sym <file.sym>] [--entry <address>]...");
+    println!("       emulator101 dump <rom_path> [--state <file>] [--frames <count>] [--output <file.json>]");
+    println!("       emulator101 banks <rom_path> [--state <file>] [--frames <count>] [--log]");
+}
+
+fn run_emulator(rom_path: &str, options: RunOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let settings = Settings::load(options.config.as_deref())?;
+    let mut accuracy = options.accuracy.or(settings.accuracy).unwrap_or_default().settings();
+    accuracy.strict_echo_ram |= options.strict_echo_ram;
+    accuracy.sprite_limit &= settings.sprite_limit && !options.no_sprite_limit;
+
     // Load the ROM
-    let rom_data = read_rom(rom_path)?;
+    let load = |path: &str| -> Result<Emulator, Box<dyn std::error::Error>> {
+        let mut builder = EmulatorBuilder::new()
+            .error_policy(options.error_policy)
+            .palette(options.palette)
+            .accuracy(accuracy)
+            // Cartridge clocks keep real time, including while the emulator is closed
+            .rtc_mode(RtcMode::WallClock);
+        if let Some(model) = options.model {
+            builder = builder.model(model);
+        }
+        if let Some(mapper) = options.mapper {
+            builder = builder.mapper(mapper);
+        }
+        if let Some(path) = &options.boot_rom {
+            builder = builder.bootrom_file(path);
+        }
+        let rom = read_rom(path).map_err(|e| format!("failed to read {}: {}", path, e))?;
+        Ok(builder.build(rom)?)
+    };
+    // A ROM that fails to load is reported in the window, which then takes another one
+    let mut rom_path = rom_path.to_string();
+    let mut emulator = loop {
+        match load(&rom_path) {
+            Ok(emulator) => break emulator,
+            Err(e) if options.automation.is_some() => return Err(e),
+            Err(e) => {
+                eprintln!("Failed to load {}: {}", rom_path, e);
+                let failure = splash::LoadFailure { rom_path: &rom_path, error: e.to_string() };
+                match splash::wait_for_rom(Some(failure), options.ui_scale)? {
+                    Some(path) => rom_path = path,
+                    None => return Ok(()),
+                }
+            },
+        }
+    };
+    let rom_path = rom_path.as_str();
+    let input_script = options.input_script.as_deref().map(InputScript::load).transpose()?;
+    if input_script.is_some() && options.play.is_some() {
+        return Err("--input-script and --play can't be used together".into());
+    }
+    for code in &options.cheats {
+        emulator.cheats_mut().add(code).map_err(|e| format!("{}: {}", code, e))?;
+    }
+    eprintln!("Cartridge: {}", emulator.cartridge());
+    eprintln!("Running as {}", emulator.model());
+    if emulator.mapper() != MapperKind::None {
+        eprintln!("Using the {} mapper", emulator.mapper());
+    }
+
+    // Headless: no window, saves or movies, just a pass or fail exit code
+    if let Some(automation) = &options.automation {
+        if options.link.is_some() {
+            return Err("--link can't be used with a headless run".into());
+        }
+        if !options.tabs.is_empty() {
+            return Err("--tab can't be used with a headless run".into());
+        }
+        emulator.set_stack_checks(options.stack_checks);
+        let diagnostics = options.diagnostics.or(options.stack_checks.then_some(Severity::Warning));
+        let passed = ci::run(emulator, automation, diagnostics, input_script.as_ref())?;
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    // Linked play: a second machine on the other end of the cable, shown to the right
+    let mut partner = match &options.link {
+        Some(path) => {
+            let mut second = load(path)?;
+            let (left, right) = LocalLink::pair();
+            emulator.connect_link(left);
+            second.connect_link(right);
+            eprintln!("Linked with {} as {}", path, second.model());
+            Some(second)
+        },
+        None => None,
+    };
+    let screens = if partner.is_some() { 2 } else { 1 };
+    if partner.is_some() && !options.tabs.is_empty() {
+        return Err("--tab and --link can't be used together".into());
+    }
+
+    // Input movie: a new recording from power-on, or playback that carries on recording
+    let movie = match (&options.record, &options.play) {
+        (Some(_), Some(_)) => return Err("--record and --play can't be used together".into()),
+        (Some(path), None) => Some(MovieFile { movie: Movie::new(&emulator, false), path: path.clone() }),
+        (None, Some(path)) => {
+            let data = std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let movie = Movie::from_bytes(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+            eprintln!("Playing {} ({} frames, {} re-records)", path.display(), movie.len(), movie.rerecords);
+            Some(MovieFile { movie, path: path.clone() })
+        },
+        (None, None) => None,
+    };
+    if let Some(file) = &movie {
+        file.movie.start(&mut emulator)?;
+    }
+    let movie_active = movie.is_some();
+    emulator.set_opcode_coverage(options.opcode_coverage.is_some());
+    emulator.set_stack_checks(options.stack_checks);
+
+    // Battery saves next to the ROMs; a movie runs from blank cartridge RAM and leaves them alone
+    // (other tabs don't play the movie and keep theirs)
+    let load_battery = |emulator: &mut Emulator, rom_path: &str| -> Option<BatterySave> {
+        if !emulator.has_battery() {
+            return None;
+        }
+        let battery = BatterySave::for_rom(rom_path, options.save_backups);
+        match battery.load(emulator) {
+            Ok(true) => eprintln!("Loaded save {}", battery.path().display()),
+            Ok(false) => {},
+            Err(e) => eprintln!("Failed to read {}: {}", battery.path().display(), e),
+        }
+        Some(battery)
+    };
+    let mut batteries = vec![if movie_active { None } else { load_battery(&mut emulator, rom_path) }];
+    if let (Some(second), Some(path), false) = (&mut partner, &options.link, movie_active) {
+        batteries.push(load_battery(second, path));
+    }
+    if let Some(seconds) = options.rtc_shift {
+        eprintln!("{}", rtc::describe(emulator.shift_rtc(seconds)));
+    }
+
+    let spectators = match &options.spectate {
+        Some(address) => {
+            let server = SpectatorServer::bind(address.as_str()).map_err(|e| format!("{}: {}", address, e))?;
+            eprintln!("Serving spectators on {}", address);
+            Some(server)
+        },
+        None => None,
+    };
+
+    // Serial peer outside the emulator; status messages go to stderr so stdout stays clean for it
+    let reply_timeout = options.serial_timeout.map_or(STREAM_REPLY_TIMEOUT, Duration::from_millis);
+    match options.serial.as_deref() {
+        Some(_) if partner.is_some() => return Err("--serial and --link can't be used together".into()),
+        Some("stdio") => emulator.connect_link(StreamLink::stdio().with_reply_timeout(reply_timeout)),
+        Some(path) => {
+            let pipe = std::fs::OpenOptions::new().read(true).write(true).open(path)
+                .map_err(|e| format!("{}: {}", path, e))?;
+            emulator.connect_link(StreamLink::new(pipe.try_clone()?, pipe).with_reply_timeout(reply_timeout));
+        },
+        None => {},
+    }
     
     // Initialize SDL2
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
     
+    let ui_scale = options.ui_scale.unwrap_or_else(|| display::detect_scale(&video_subsystem));
+    let (width, height) = display::window_size(&video_subsystem, SCREEN_WIDTH as u32 * SCALE * screens, SCREEN_HEIGHT as u32 * SCALE, ui_scale);
     let window = video_subsystem
-        .window("Game Boy Emulator", SCREEN_WIDTH as u32 * SCALE, SCREEN_HEIGHT as u32 * SCALE)
+        .window(WINDOW_TITLE, width, height)
         .position_centered()
+        .allow_highdpi()
         .build()?;
     
     let mut canvas = window.into_canvas().build()?;
     let texture_creator = canvas.texture_creator();
+    let mut scaler = display::SharpScaler::new(&texture_creator);
     
-    let mut texture = texture_creator
-        .create_texture_streaming(PixelFormatEnum::RGBA32, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)?;
+    // Screen textures hold the frame after the upscaling filter
+    let mut filter = options.filter;
+    let create_texture = |filter: Filter| {
+        let factor = filter.factor() as u32;
+        texture_creator.create_texture_streaming(PixelFormatEnum::RGBA32, SCREEN_WIDTH as u32 * factor, SCREEN_HEIGHT as u32 * factor)
+    };
+    let mut texture = create_texture(filter)?;
+    let mut partner_texture = create_texture(filter)?;
+    let mut filtered = Vec::new();
+    // Generation of the frame in `texture` when nothing was drawn over it, to skip uploading the
+    // same picture again (while paused, say)
+    let mut uploaded = None;
     
     let mut event_pump = sdl_context.event_pump()?;
 
-    // Initialize emulator components
-    let mut memory = MemoryBus::new(&rom_data);
-    let mut cpu = Cpu::new();
-    cpu.reset();
+    // Without a playback device the game runs silent
+    let (_audio_device, audio) = match audio::open(&sdl_context) {
+        Ok((device, queue)) => (Some(device), Some(queue)),
+        Err(e) => {
+            eprintln!("No sound: {}", e);
+            (None, None)
+        },
+    };
 
-    // Initialize VRAM viewer
-    let mut vram_viewer = VramViewer::new(&sdl_context)?;
-
-    // Timing variables
-    let mut last_frame_time = Instant::now();
+    // Start the emulator on its own thread
     let frame_duration = Duration::from_nanos(1_000_000_000 / 60); // Target 60 FPS
+    let error_policy = options.error_policy;
+    let pacing = if options.exact_frame_rate { Pacing::Hardware } else { Pacing::Fixed(frame_duration) };
+    let battery_flush = (options.save_interval > 0).then(|| Duration::from_secs(options.save_interval));
+    // The stack checks report through diagnostics, so they turn warnings on
+    let diagnostics = options.diagnostics.or(options.stack_checks.then_some(Severity::Warning));
+    // A play time file that can't be read turns tracking off rather than being overwritten
+    let play_times = match PlayTimes::load(Path::new(play_time::DEFAULT_PATH)) {
+        Ok(play_times) => Some(play_times),
+        Err(e) => {
+            eprintln!("Not tracking play time: {}", e);
+            None
+        },
+    };
+    let config = Config {
+        pacing,
+        script: options.script,
+        movie,
+        opcode_coverage: options.opcode_coverage,
+        batteries,
+        battery_flush,
+        achievements: options.achievements,
+        input_script,
+        diagnostics,
+        play_times: play_times.clone(),
+        audio: audio.clone(),
+    };
+
+    // Another ROM in a background tab, with the same settings but none of the first one's
+    // script, movie, cheats or achievements
+    let open_tab = |path: &str| -> Result<Tab, Box<dyn std::error::Error>> {
+        let mut emulator = load(path)?;
+        emulator.set_stack_checks(options.stack_checks);
+        let battery = load_battery(&mut emulator, path);
+        let config = Config {
+            pacing,
+            script: None,
+            movie: None,
+            opcode_coverage: None,
+            batteries: vec![battery],
+            battery_flush,
+            achievements: None,
+            input_script: None,
+            diagnostics,
+            play_times: play_times.clone(),
+            audio: audio.clone(),
+        };
+        let title = emulator.cartridge().title.clone();
+        Ok(Tab { emulator: EmulatorThread::spawn(emulator, config)?, rom_path: path.to_string(), title, paused: false, hud_frames: 0 })
+    };
+    let mut current_title = emulator.cartridge().title.clone();
+    #[cfg(feature = "discord")]
+    let mut presence = settings.discord.clone().map(|client_id| discord::Presence::start(client_id, discord::game_title(emulator.bus().rom())));
+    let (mut emulator, mut partner) = match partner.take() {
+        Some(second) => {
+            // Both machines share a thread so the cable stays in step
+            let (first, second) = EmulatorThread::spawn_linked(emulator, second, config)?;
+            (first, Some(second))
+        },
+        None => (EmulatorThread::spawn(emulator, config)?, None),
+    };
+    let mut tabs = Tabs::new();
+    for path in &options.tabs {
+        tabs.open(open_tab(path)?);
+        eprintln!("Opened {} in a tab", path);
+    }
+    let mut current_rom = rom_path.to_string();
+    canvas.window_mut().set_title(&window_title(&current_title, &current_rom, !tabs.is_empty()))?;
+    let mut state_picker = StatePicker::new();
+    let mut cheats_enabled = true;
+    let mut color_correction = ColorCorrection::new(options.gamma, options.brightness);
+    let mut osd_until = None;
+    let mut latency_test = options.latency_test.then(LatencyTest::new);
+    let mut paused = false;
+    let mut show_beam = false;
+    let mut sync_stats = SyncStats::new(frame_duration);
+    let mut show_sync = false;
+
+    // Initialize VRAM viewer
+    let mut vram_viewer = VramViewer::new(&sdl_context, settings.viewer)?;
+    let mut magnifier = Magnifier::new();
+    let mut ram_search = RamSearch::new(&sdl_context)?;
+    let mut cart_ram_editor = CartRamEditor::new(&sdl_context, rom_path)?;
+    let mut rumble = Rumble::new(&sdl_context);
+    let mut hud_updated = Instant::now();
+    let mut hud_frames = 0;
 
     // Main emulation loop
     'running: loop {
         // Handle SDL2 events
         for event in event_pump.poll_iter() {
+            if ram_search.handle_event(&event, &emulator) {
+                continue; // Typed into the RAM search window
+            }
+            if cart_ram_editor.handle_event(&event, &emulator) {
+                continue;
+            }
+            if magnifier.handle_event(&event, canvas.window(), screens) {
+                continue; // Mouse moved over the game screen
+            }
+            if state_picker.handle_event(&event, &emulator, &current_rom) {
+                continue; // Choosing a savestate slot
+            }
+            if rumble.handle_event(&event) {
+                continue; // A controller was connected or removed
+            }
             match event {
                 Event::Quit { .. } => {
                     break 'running;
@@ -87,93 +682,381 @@ fn run_emulator(rom_path: &str) -> Result<(), Box<dyn std::error::Error>> {
                 Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running;
                 },
+                Event::DropFile { filename, .. } if sdl_context.keyboard().mod_state().intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    // Dropped with Ctrl held: open it in a new background tab
+                    match open_tab(&filename) {
+                        Ok(tab) => {
+                            tabs.open(tab);
+                            canvas.window_mut().set_title(&window_title(&current_title, &current_rom, true))?;
+                            eprintln!("Opened {} in a tab (Ctrl+Tab to switch)", filename);
+                        },
+                        Err(e) => eprintln!("Failed to open {}: {}", filename, e),
+                    }
+                },
+                Event::DropFile { filename, .. } => {
+                    // Hot-swap the cartridge, keeping the window and viewer as they are
+                    match read_rom(&filename) {
+                        Ok(data) => {
+                            let rom_model = options.model.unwrap_or_else(|| Model::from_header(&data));
+                            current_title = Cartridge::parse(&data).title;
+                            #[cfg(feature = "discord")]
+                            if let Some(presence) = &mut presence {
+                                presence.set_game(discord::game_title(&data));
+                            }
+                            emulator.send(Command::LoadRom(data, rom_model, BatterySave::for_rom(&filename, options.save_backups)));
+                            cart_ram_editor.set_rom_path(&filename);
+                            eprintln!("Loaded ROM {}", filename);
+                            current_rom = filename;
+                            canvas.window_mut().set_title(&window_title(&current_title, &current_rom, !tabs.is_empty()))?;
+                        },
+                        Err(e) => eprintln!("Failed to read {}: {}", filename, e),
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::Tab), keymod, repeat: false, .. } if !tabs.is_empty() && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    // Next tab, the previous one with Shift. The debug windows follow the tab in front.
+                    let back = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    for command in [Command::CapturePpu(false), Command::CapturePixelSources(false), Command::CaptureRam(false), Command::CaptureCartRam(false)] {
+                        emulator.send(command);
+                    }
+                    let front = Tab { emulator, rom_path: current_rom, title: current_title, paused, hud_frames };
+                    let tab = tabs.switch(front, back);
+                    emulator = tab.emulator;
+                    // Generations are counted per emulator
+                    uploaded = None;
+                    current_rom = tab.rom_path;
+                    current_title = tab.title;
+                    paused = tab.paused;
+                    hud_frames = tab.hud_frames;
+                    emulator.send(Command::CapturePpu(vram_viewer.is_open()));
+                    emulator.send(Command::CapturePixelSources(magnifier.is_enabled()));
+                    emulator.send(Command::CaptureRam(ram_search.is_open()));
+                    emulator.send(Command::CaptureCartRam(cart_ram_editor.is_open()));
+                    cart_ram_editor.set_rom_path(&current_rom);
+                    canvas.window_mut().set_title(&window_title(&current_title, &current_rom, true))?;
+                    #[cfg(feature = "discord")]
+                    if let (Some(presence), Ok(data)) = (&mut presence, read_rom(&current_rom)) {
+                        presence.set_game(discord::game_title(&data));
+                        presence.set_paused(paused);
+                    }
+                    eprintln!("Switched to {}{}", current_rom, if paused { " (paused)" } else { "" });
+                },
                 Event::KeyDown { keycode: Some(Keycode::V), repeat: false, .. } => {
                     vram_viewer.toggle();
+                    emulator.send(Command::CapturePpu(vram_viewer.is_open()));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F3), repeat: false, .. } => {
+                    magnifier.toggle();
+                    emulator.send(Command::CapturePixelSources(magnifier.is_enabled()));
+                    eprintln!("Magnifier {}", if magnifier.is_enabled() { "on" } else { "off" });
+                },
+                Event::KeyDown { keycode: Some(Keycode::C), repeat: false, .. } if !options.cheats.is_empty() => {
+                    // Toggle the Game Genie codes from the command line
+                    cheats_enabled = !cheats_enabled;
+                    emulator.send(Command::SetCheatsEnabled(cheats_enabled));
+                    eprintln!("Cheats {}", if cheats_enabled { "enabled" } else { "disabled" });
+                },
+                Event::KeyDown { keycode: Some(Keycode::R), repeat: false, .. } => {
+                    ram_search.toggle();
+                    emulator.send(Command::CaptureRam(ram_search.is_open()));
+                },
+                Event::KeyDown { keycode: Some(Keycode::E), repeat: false, .. } => {
+                    cart_ram_editor.toggle();
+                    emulator.send(Command::CaptureCartRam(cart_ram_editor.is_open()));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F6), repeat: false, .. } => {
+                    paused = !paused;
+                    emulator.send(Command::SetPaused(paused));
+                    eprintln!("{}", if paused { "Paused" } else { "Resumed" });
+                    #[cfg(feature = "discord")]
+                    if let Some(presence) = &mut presence {
+                        presence.set_paused(paused);
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::F7), keymod, .. } if paused => {
+                    // Step a scanline, or a single instruction with Shift
+                    let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    emulator.send(Command::Step(if shift { Step::Instruction } else { Step::Scanline }));
+                },
+                Event::KeyDown { keycode: Some(Keycode::B), repeat: false, .. } => {
+                    show_beam = !show_beam;
+                    eprintln!("Beam indicator {}", if show_beam { "on" } else { "off" });
+                },
+                Event::KeyDown { keycode: Some(key @ (Keycode::LeftBracket | Keycode::RightBracket | Keycode::Minus | Keycode::Equals)), .. } => {
+                    // [ and ] adjust the gamma, - and = the brightness
+                    let (mut gamma, mut brightness) = (color_correction.gamma(), color_correction.brightness());
+                    match key {
+                        Keycode::LeftBracket => gamma -= gamma::STEP,
+                        Keycode::RightBracket => gamma += gamma::STEP,
+                        Keycode::Minus => brightness -= gamma::STEP,
+                        _ => brightness += gamma::STEP,
+                    }
+                    color_correction.set(gamma, brightness);
+                    osd_until = Some(Instant::now() + OSD_DURATION);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F2), repeat: false, .. } => {
+                    // Cycle through the upscaling filters (the partner screen catches up on its next frame)
+                    filter = filter.next();
+                    texture = create_texture(filter)?;
+                    uploaded = None;
+                    partner_texture = create_texture(filter)?;
+                    eprintln!("Filter: {}", filter);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F5), repeat: false, .. } => {
+                    // Quick save to the selected slot (written once the emulation thread replies)
+                    emulator.send(Command::SaveState);
+                },
+                Event::KeyDown { keycode: Some(key @ (Keycode::F10 | Keycode::F11)), keymod, .. } => {
+                    // Cartridge clock: an hour (F10) or a day (F11) forward, back with Shift
+                    let amount = if key == Keycode::F10 { 3_600 } else { 86_400 };
+                    let back = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    emulator.send(Command::ShiftRtc(if back { -amount } else { amount }));
+                },
+                Event::KeyDown { keycode: Some(Keycode::F12), repeat: false, window_id, .. } if window_id == canvas.window().id() => {
+                    // Machine state as JSON (written once the emulation thread replies); F12 in
+                    // the VRAM viewer takes a screenshot instead
+                    emulator.send(Command::DumpState);
+                },
+                Event::KeyDown { keycode: Some(Keycode::F9), repeat: false, .. } => {
+                    show_sync = !show_sync;
+                },
+                Event::KeyDown { keycode: Some(Keycode::F8), repeat: false, .. } => {
+                    // Quick load from the selected slot
+                    let state_path = state_picker.path(&current_rom);
+                    match std::fs::read(&state_path) {
+                        Ok(data) => emulator.send(Command::LoadState(data)),
+                        Err(e) => eprintln!("Failed to read {}: {}", state_path, e),
+                    }
+                },
+                Event::KeyDown { keycode: Some(key @ (Keycode::Comma | Keycode::Period | Keycode::PageUp | Keycode::PageDown | Keycode::Slash)), .. } if movie_active => {
+                    // Movie editing: step back / forward a frame, jump a second, resume
+                    emulator.send(match key {
+                        Keycode::Comma => Command::SeekMovie(-1),
+                        Keycode::Period => Command::AdvanceMovie,
+                        Keycode::PageUp => Command::SeekMovie(-60),
+                        Keycode::PageDown => Command::SeekMovie(60),
+                        _ => Command::ResumeMovie,
+                    });
                 },
                 _ => {
-                    if vram_viewer.is_open() {
-                        if vram_viewer.handle_event(&event) {
-                            continue; // Event was handled by viewer
-                        }
+                    if vram_viewer.is_open() && vram_viewer.handle_event(&event) {
+                        continue; // Event was handled by viewer
                     }
                     
-                    // Handle other events for the main emulator
-                    match &event {
-                        Event::KeyDown { keycode: Some(key), repeat: false, .. } => {
-                            memory.handle_key_event(*key, true);
+                    // Handle other events for the main emulator (and its partner when linked)
+                    let (key, pressed) = match &event {
+                        Event::KeyDown { keycode: Some(key), repeat: false, .. } => (*key, true),
+                        Event::KeyUp { keycode: Some(key), repeat: false, .. } => (*key, false),
+                        _ => continue,
+                    };
+                    match &partner {
+                        Some(partner) => match map_linked_key(key) {
+                            Some((0, button)) => emulator.send(Command::SetButton(button, pressed)),
+                            Some((_, button)) => partner.send(Command::SetButton(button, pressed)),
+                            None => {}
                         },
-                        Event::KeyUp { keycode: Some(key), repeat: false, .. } => {
-                            memory.handle_key_event(*key, false);
+                        None => {
+                            if let Some(button) = settings.joypad.button(key) {
+                                emulator.send(Command::SetButton(button, pressed));
+                            }
                         },
-                        _ => {}
                     }
                 }
             }
         }
         
-        // Run CPU cycles until a frame is ready (at 60 FPS)
-        let mut cycles_this_frame = 0;
-        while !memory.ppu.frame_ready && cycles_this_frame < 70224 { // ~70224 cycles per frame (@59.73 fps)
-            // Execute one CPU instruction
-            let cycles = cpu.step(&mut memory);
-            cycles_this_frame += cycles as u32;
-
-            // Update components cycle-by-cycle
-            for _ in 0..cycles {
-                // Update timer
-                if memory.update_timer_cycle() {
-                    memory.request_interrupt(InterruptType::Timer);
-                }
-                
-                // Update PPU
-                if let Some(interrupt) = memory.update_ppu_cycle() {
-                    memory.request_interrupt(interrupt);
+        // Replies to savestate commands
+        while let Some(reply) = emulator.try_reply() {
+            match reply {
+                Reply::StateSaved(data) => match std::fs::write(state_picker.path(&current_rom), data) {
+                    Ok(()) => {
+                        eprintln!("Saved state to {}", state_picker.path(&current_rom));
+                        state_picker.refresh(&current_rom);
+                    },
+                    Err(e) => eprintln!("Failed to save state: {}", e),
+                },
+                Reply::StateLoaded(Ok(())) => eprintln!("Loaded state from {}", state_picker.path(&current_rom)),
+                Reply::StateLoaded(Err(e)) => eprintln!("Failed to load state: {}", e),
+                Reply::StateDump(json) => {
+                    let path = format!("{}.dump.json", current_rom);
+                    match std::fs::write(&path, json) {
+                        Ok(()) => eprintln!("Dumped machine state to {}", path),
+                        Err(e) => eprintln!("Failed to write {}: {}", path, e),
+                    }
+                },
+                Reply::Error(e) if error_policy == ErrorPolicy::Stop => eprintln!("Emulation stopped: {}", e),
+                Reply::Error(e) => eprintln!("Emulation error: {}", e),
+                Reply::ScriptError(e) => eprintln!("Script error: {}", e),
+                Reply::Movie(message) => eprintln!("Movie: {}", message),
+                Reply::InputScriptFinished(frames) => eprintln!("Input script finished after {} frames", frames),
+                Reply::Diagnostic(event) => eprintln!("Diagnostic ({}): {}", event.severity(), event),
+                Reply::Rtc(reading) => eprintln!("{}", rtc::describe(reading)),
+                Reply::Achievement(message) => eprintln!("Achievements: {}", message),
+            }
+        }
+        for tab in tabs.iter() {
+            while let Some(reply) = tab.emulator.try_reply() {
+                match reply {
+                    Reply::Error(e) => eprintln!("{}: emulation error: {}", tabs::name(&tab.rom_path), e),
+                    Reply::Diagnostic(event) => eprintln!("{}: diagnostic ({}): {}", tabs::name(&tab.rom_path), event.severity(), event),
+                    _ => {},
                 }
-                
-                // Update serial
-                if memory.update_serial_cycle() {
-                    memory.request_interrupt(InterruptType::Serial);
+            }
+        }
+        if let Some(partner) = &partner {
+            while let Some(reply) = partner.try_reply() {
+                match reply {
+                    Reply::Error(e) => eprintln!("Player 2 emulation error: {}", e),
+                    Reply::Diagnostic(event) => eprintln!("Player 2 diagnostic ({}): {}", event.severity(), event),
+                    _ => {},
                 }
-                
-                // Update joypad
-                if memory.update_joypad_cycle() {
-                    memory.request_interrupt(InterruptType::Joypad);
+            }
+        }
+
+        if let Some(sent) = latency_test.as_mut().and_then(LatencyTest::probe_due) {
+            emulator.send(Command::LatencyProbe(sent));
+        }
+
+        // Wait for the next frame from the emulation thread (this also paces the UI)
+        let frame = match emulator.latest_frame(frame_duration) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => {
+                sync_stats.missed();
+                continue;
+            },
+            Err(_) => return Err("emulation thread stopped unexpectedly".into()),
+        };
+        sync_stats.presented(frame.stats.frames, SCREEN_WIDTH);
+        rumble.set(frame.rumble && !paused);
+
+        // Update the texture with the new frame buffer (spectators get it before any correction)
+        if let Some(spectators) = &spectators {
+            spectators.publish(0, &frame.pixels);
+        }
+        color_correction.apply(&mut frame.pixels);
+        if magnifier.is_enabled() {
+            magnifier.capture(&frame.pixels, &frame.pixel_sources);
+        }
+        let show_osd = osd_until.is_some_and(|until| Instant::now() < until);
+        if paused && show_beam {
+            beam::draw(&mut frame.pixels, SCREEN_WIDTH, frame.beam, frame.cpu_mode);
+        }
+        if show_osd {
+            color_correction.draw_osd(&mut frame.pixels, SCREEN_WIDTH);
+        }
+        if show_sync {
+            sync_stats.draw_osd(&mut frame.pixels, SCREEN_WIDTH, &frame.stats);
+        }
+        if state_picker.is_open() {
+            state_picker.draw(&mut frame.pixels, SCREEN_WIDTH);
+        }
+        let overlaid = (paused && show_beam) || show_osd || show_sync || state_picker.is_open();
+        if overlaid || frame.generation.is_none() || frame.generation != uploaded {
+            scale::upscale(filter, &frame.pixels, SCREEN_WIDTH, SCREEN_HEIGHT, &mut filtered);
+            texture.update(None, &filtered, SCREEN_WIDTH * filter.factor() * 4)?;
+        }
+        uploaded = frame.generation.filter(|_| !overlaid);
+        let probe = frame.probe.take().map(|(sent, published)| (sent, published, Instant::now()));
+        
+        // Clear the screen
+        canvas.clear();
+        
+        // Copy the texture to the canvas, the screens side by side across its drawable size
+        let (width, height) = canvas.output_size()?;
+        let screen_width = width / screens;
+        scaler.draw(&mut canvas, &texture, Rect::new(0, 0, screen_width, height))?;
+        if let Some(partner) = &mut partner {
+            // Published together with the main frame, so this never waits
+            if let Ok(Some(partner_frame)) = partner.latest_frame(Duration::ZERO) {
+                if let Some(spectators) = &spectators {
+                    spectators.publish(1, &partner_frame.pixels);
                 }
-                
-                // Process DMA transfers (one byte per cycle)
-                memory.process_dma_cycle();
+                color_correction.apply(&mut partner_frame.pixels);
+                scale::upscale(filter, &partner_frame.pixels, SCREEN_WIDTH, SCREEN_HEIGHT, &mut filtered);
+                partner_texture.update(None, &filtered, SCREEN_WIDTH * filter.factor() * 4)?;
             }
+            scaler.draw(&mut canvas, &partner_texture, Rect::new(screen_width as i32, 0, screen_width, height))?;
         }
+        magnifier.draw(&mut canvas, Rect::new(0, 0, screen_width, height))?;
         
-        // Check if a frame is ready
-        if memory.ppu.frame_ready {
-            memory.ppu.frame_ready = false;
-            
-            // Update the texture with the new frame buffer
-            texture.update(None, &memory.ppu.frame_buffer, SCREEN_WIDTH * 4)?;
-            
-            // Clear the screen
-            canvas.clear();
-            
-            // Copy the texture to the canvas
-            canvas.copy(&texture, None, Some(Rect::new(0, 0, SCREEN_WIDTH as u32 * SCALE, SCREEN_HEIGHT as u32 * SCALE)))?;
-            
-            // Present the canvas
-            canvas.present();
-
-            if vram_viewer.is_open() {
-                vram_viewer.update(&memory.ppu)?;
-            }
-            
-            // Frame timing for 60 FPS
-            let now = Instant::now();
-            let elapsed = now.duration_since(last_frame_time);
-            if elapsed < frame_duration {
-                sleep(frame_duration - elapsed);
-            }
-            last_frame_time = Instant::now();
+        // Present the canvas
+        canvas.present();
+        if let (Some(test), Some((sent, published, uploaded))) = (&mut latency_test, probe) {
+            let sample = latency::Sample { sent, published, uploaded, presented: Instant::now() };
+            if let Some(report) = test.record(sample) {
+                eprintln!("Latency: {}", report);
+            }
+        }
+
+        let since_hud = hud_updated.elapsed();
+        if since_hud >= HUD_INTERVAL {
+            let fps = frame.stats.frames.saturating_sub(hud_frames) as f64 / since_hud.as_secs_f64();
+            canvas.window_mut().set_title(&hud_title(&window_title(&current_title, &current_rom, !tabs.is_empty()), &frame.stats, fps, settings.show_play_time))?;
+            hud_updated = Instant::now();
+            hud_frames = frame.stats.frames;
+        }
+
+        if vram_viewer.is_open() {
+            vram_viewer.update(frame.ppu.take(), frame.wave_ram.map(|ram| (ram, frame.wave_position)))?;
+        }
+        if let (true, Some(ram)) = (ram_search.is_open(), frame.ram.take()) {
+            ram_search.update(ram, frame.stats.frames)?;
+        }
+        if let (true, Some(cart_ram)) = (cart_ram_editor.is_open(), frame.cart_ram.take()) {
+            cart_ram_editor.update(cart_ram, frame.cart_ram_bank)?;
         }
     }
 
+    if let Some(test) = &latency_test {
+        eprintln!("Latency: {}", test.report());
+    }
     Ok(())
-}
\ No newline at end of file
+}
+// Show one player's screen from a session hosted elsewhere with --spectate (read-only)
+fn watch(address: &str, player: u8) -> Result<(), Box<dyn std::error::Error>> {
+    let client = SpectatorClient::connect(address, player).map_err(|e| format!("{}: {}", address, e))?;
+    eprintln!("Watching player {} on {}", player + 1, address);
+
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let scale = display::detect_scale(&video_subsystem);
+    let (width, height) = display::window_size(&video_subsystem, SCREEN_WIDTH as u32 * SCALE, SCREEN_HEIGHT as u32 * SCALE, scale);
+    let window = video_subsystem
+        .window(&format!("{} - spectating player {}", WINDOW_TITLE, player + 1), width, height)
+        .position_centered()
+        .allow_highdpi()
+        .build()?;
+    let mut canvas = window.into_canvas().build()?;
+    let texture_creator = canvas.texture_creator();
+    let mut scaler = display::SharpScaler::new(&texture_creator);
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)?;
+    let mut event_pump = sdl_context.event_pump()?;
+    let frame_duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    'watching: loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'watching,
+                _ => {}
+            }
+        }
+
+        match client.latest_frame() {
+            Ok(Some(frame)) => {
+                texture.update(None, &frame, SCREEN_WIDTH * 4)?;
+                canvas.clear();
+                let (width, height) = canvas.output_size()?;
+                scaler.draw(&mut canvas, &texture, Rect::new(0, 0, width, height))?;
+                canvas.present();
+            },
+            Ok(None) => {},
+            Err(_) => {
+                eprintln!("The host ended the session");
+                break;
+            },
+        }
+        std::thread::sleep(frame_duration);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,92 @@
+// Play time per game
+// How long each game has been played, over all sessions, is kept in `emulator101-playtime.toml`
+// in the working directory, keyed by the ROM's hash so a renamed or re-zipped file keeps its
+// time:
+//
+//   [games.1a2b3c4d5e6f7a8b]
+//   title = "POKEMON RED"
+//   seconds = 45296
+//
+// Play time is emulated time (see Stats::play_time): fast-forward counts for more, pausing and
+// closed windows don't count. The emulation thread writes it when the game changes or stops and
+// once a minute in between. Headless runs don't touch the file.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use emulator101_core::Emulator;
+use emulator101_core::savestate;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_PATH: &str = "emulator101-playtime.toml";
+
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct File {
+    games: BTreeMap<String, Game>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Game {
+    title: String,
+    seconds: u64,
+}
+
+// The play time file, shared by every emulator; clones refer to the same one
+#[derive(Clone)]
+pub struct PlayTimes {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+impl PlayTimes {
+    // Read the file; a missing one starts empty, one that can't be read is an error rather than
+    // being overwritten
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = match std::fs::read_to_string(path) {
+            Ok(text) => toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => File::default(),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        Ok(PlayTimes { path: path.to_path_buf(), file: Arc::new(Mutex::new(file)) })
+    }
+
+    // Give the emulator the time already played with its ROM
+    pub fn resume(&self, emulator: &mut Emulator) {
+        let file = self.file.lock().unwrap();
+        let seconds = file.games.get(&key(emulator)).map_or(0, |game| game.seconds);
+        emulator.set_play_time(Duration::from_secs(seconds));
+    }
+
+    // Record the emulator's play time and write the file
+    pub fn store(&self, emulator: &Emulator) -> std::io::Result<()> {
+        let mut file = self.file.lock().unwrap();
+        let game = Game { title: emulator.cartridge().title.clone(), seconds: emulator.stats().play_time.as_secs() };
+        if file.games.get(&key(emulator)).is_some_and(|stored| stored.seconds == game.seconds) {
+            return Ok(());
+        }
+        file.games.insert(key(emulator), game);
+        let text = toml::to_string(&*file).map_err(std::io::Error::other)?;
+        // Renamed into place like battery saves, so a crash can't leave half a file
+        let temp = self.path.with_extension("toml.tmp");
+        std::fs::write(&temp, text)?;
+        std::fs::rename(&temp, &self.path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn key(emulator: &Emulator) -> String {
+    format!("{:016x}", savestate::rom_hash(emulator.bus().rom()))
+}
+
+// `12:34` (hours and minutes)
+pub fn format(play_time: Duration) -> String {
+    let minutes = play_time.as_secs() / 60;
+    format!("{}:{:02}", minutes / 60, minutes % 60)
+}
@@ -0,0 +1,434 @@
+// RAM search window
+// Classic cheat finder: start a search, play until the value you are after changes, then narrow
+// the candidates down with "increased", "decreased", "changed", ... filters. Every filter
+// compares the live RAM against the snapshot taken by the previous filter. Found addresses can
+// be moved to a watch list that shows their value every frame, or frozen.
+// The delta view lists every byte that differs between two frames with its old and new value,
+// which often finds a health or score variable in one step: mark a frame, take a hit, look.
+// Without a marked frame it compares each frame with the one before.
+//
+// Keys (while this window has focus):
+//   N            new search              I / D       increased / decreased
+//   C / U        changed / unchanged     0-9         type a value, Enter keeps equal ones
+//   Up / Down    select a candidate      W           watch the selected address
+//   F            freeze the selected address at the typed value (or its current one)
+//   Delete       remove the last watch (unfreezing it)
+//   Tab          switch between the search and the delta view
+// In the delta view:
+//   M            compare against this frame    Space       hold the frame compared with it
+//   Backspace    back to consecutive frames
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use crate::font;
+use crate::worker::{Command, EmulatorThread};
+
+// Memory the search covers: work RAM and high RAM
+const REGIONS: [(u16, usize); 2] = [(0xC000, 0x2000), (0xFF80, 0x7F)];
+// Total bytes captured from REGIONS
+pub const RAM_SIZE: usize = 0x2000 + 0x7F;
+
+const WINDOW_WIDTH: u32 = 300;
+const WINDOW_HEIGHT: u32 = 420;
+const LINE_HEIGHT: i32 = 12;
+// Candidates listed at once
+const VISIBLE_CANDIDATES: usize = 16;
+// Watched addresses listed at once (the newest ones)
+const WATCH_ROWS: usize = 8;
+
+const TEXT: Color = Color::RGB(0, 0, 0);
+const DIM: Color = Color::RGB(110, 110, 110);
+const SELECTED: Color = Color::RGB(200, 240, 200);
+
+// Address of byte `index` in a RAM capture
+pub fn ram_address(index: usize) -> u16 {
+    let mut index = index;
+    for (start, len) in REGIONS {
+        if index < len {
+            return start + index as u16;
+        }
+        index -= len;
+    }
+    panic!("RAM capture index out of range");
+}
+
+// Read the searched regions with `read` (the worker passes the bus)
+pub fn capture(mut read: impl FnMut(u16) -> u8) -> Vec<u8> {
+    (0..RAM_SIZE).map(|i| read(ram_address(i))).collect()
+}
+
+#[derive(Clone, Copy)]
+enum Filter {
+    Increased,
+    Decreased,
+    Changed,
+    Unchanged,
+    EqualTo(u8),
+}
+
+impl Filter {
+    fn keeps(self, previous: u8, current: u8) -> bool {
+        match self {
+            Filter::Increased => current > previous,
+            Filter::Decreased => current < previous,
+            Filter::Changed => current != previous,
+            Filter::Unchanged => current == previous,
+            Filter::EqualTo(value) => current == value,
+        }
+    }
+}
+
+// A RAM capture and the frame it was taken on
+struct Capture {
+    frame: u64,
+    ram: Vec<u8>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum View {
+    Search,
+    Delta,
+}
+
+// The two frames the delta view compares
+#[derive(Default)]
+struct Delta {
+    // The frame before the latest one
+    previous: Option<Capture>,
+    // Marked with M; compared against instead of `previous`
+    base: Option<Capture>,
+    // Held with Space; compared instead of the latest frame
+    held: Option<Capture>,
+    selected: usize,
+}
+
+// Candidate addresses (as capture indices) and the snapshot they are compared against
+struct Search {
+    candidates: Vec<usize>,
+    snapshot: Vec<u8>,
+}
+
+impl Search {
+    fn new(ram: &[u8]) -> Self {
+        Self { candidates: (0..ram.len()).collect(), snapshot: ram.to_vec() }
+    }
+
+    fn apply(&mut self, filter: Filter, ram: &[u8]) {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&i| filter.keeps(snapshot[i], ram[i]));
+        self.snapshot = ram.to_vec();
+    }
+}
+
+pub struct RamSearch {
+    canvas: Canvas<Window>,
+    is_open: bool,
+    search: Option<Search>,
+    // Latest RAM capture from the emulation thread and the frame it shows
+    ram: Vec<u8>,
+    frame: u64,
+    view: View,
+    delta: Delta,
+    selected: usize,
+    // Decimal digits typed for the "equal to" filter
+    value_input: String,
+    watches: Vec<u16>,
+    frozen: Vec<u16>,
+}
+
+impl RamSearch {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Result<Self, String> {
+        let video_subsystem = sdl_context.video()?;
+        let window = video_subsystem
+            .window("RAM search", WINDOW_WIDTH, WINDOW_HEIGHT)
+            .position_centered()
+            .hidden() // Start hidden
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+        Ok(RamSearch {
+            canvas,
+            is_open: false,
+            search: None,
+            ram: Vec::new(),
+            frame: 0,
+            view: View::Search,
+            delta: Delta::default(),
+            selected: 0,
+            value_input: String::new(),
+            watches: Vec::new(),
+            frozen: Vec::new(),
+        })
+    }
+
+    pub fn toggle(&mut self) {
+        self.is_open = !self.is_open;
+        if self.is_open {
+            self.canvas.window_mut().show();
+        } else {
+            self.canvas.window_mut().hide();
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    // Handle events aimed at this window; returns true if the event was used
+    pub fn handle_event(&mut self, event: &Event, emulator: &EmulatorThread) -> bool {
+        if !self.is_open || event.get_window_id() != Some(self.canvas.window().id()) {
+            return false;
+        }
+
+        match event {
+            Event::KeyDown { keycode: Some(key), .. } => {
+                self.handle_key(*key, emulator);
+                true
+            },
+            Event::Window { win_event: sdl2::event::WindowEvent::Close, .. } => {
+                self.toggle();
+                true
+            },
+            _ => false,
+        }
+    }
+
+    fn handle_key(&mut self, key: Keycode, emulator: &EmulatorThread) {
+        if key == Keycode::Tab {
+            self.view = if self.view == View::Search { View::Delta } else { View::Search };
+            return;
+        }
+        if self.view == View::Delta {
+            self.handle_delta_key(key);
+            return;
+        }
+        match key {
+            Keycode::N if !self.ram.is_empty() => {
+                self.search = Some(Search::new(&self.ram));
+                self.selected = 0;
+            },
+            Keycode::I => self.filter(Filter::Increased),
+            Keycode::D => self.filter(Filter::Decreased),
+            Keycode::C => self.filter(Filter::Changed),
+            Keycode::U => self.filter(Filter::Unchanged),
+            Keycode::Return => {
+                if let Ok(value) = self.value_input.parse() {
+                    self.filter(Filter::EqualTo(value));
+                }
+                self.value_input.clear();
+            },
+            Keycode::Backspace => {
+                self.value_input.pop();
+            },
+            Keycode::Up => self.selected = self.selected.saturating_sub(1),
+            Keycode::Down => self.selected += 1,
+            Keycode::W => {
+                if let Some(address) = self.selected_address() {
+                    self.watch(address);
+                }
+            },
+            Keycode::F => {
+                if let Some(address) = self.selected_address() {
+                    let typed = self.value_input.parse().ok();
+                    let value = typed.or_else(|| self.value_at(address)).unwrap_or(0);
+                    emulator.send(Command::Freeze(address, value));
+                    self.value_input.clear();
+                    self.watch(address);
+                    if !self.frozen.contains(&address) {
+                        self.frozen.push(address);
+                    }
+                }
+            },
+            Keycode::Delete => {
+                if let Some(address) = self.watches.pop()
+                    && self.frozen.contains(&address)
+                {
+                    emulator.send(Command::Unfreeze(address));
+                    self.frozen.retain(|&frozen| frozen != address);
+                }
+            },
+            _ => {
+                // Digits for the value filter (top row or keypad)
+                let name = key.name();
+                let digit = name.strip_prefix("Keypad ").unwrap_or(&name);
+                if digit.len() == 1 && digit.chars().all(|c| c.is_ascii_digit()) && self.value_input.len() < 3 {
+                    self.value_input.push_str(digit);
+                }
+            },
+        }
+        self.clamp_selection();
+    }
+
+    fn handle_delta_key(&mut self, key: Keycode) {
+        let latest = || Capture { frame: self.frame, ram: self.ram.clone() };
+        match key {
+            Keycode::M if !self.ram.is_empty() => self.delta.base = Some(latest()),
+            Keycode::Space if self.delta.held.is_some() => self.delta.held = None,
+            Keycode::Space if !self.ram.is_empty() => self.delta.held = Some(latest()),
+            Keycode::Backspace => {
+                self.delta.base = None;
+                self.delta.held = None;
+            },
+            Keycode::Up => self.delta.selected = self.delta.selected.saturating_sub(1),
+            Keycode::Down => self.delta.selected += 1,
+            Keycode::W => {
+                if let Some(&(i, _, _)) = self.changes().get(self.delta.selected) {
+                    self.watch(ram_address(i));
+                }
+            },
+            _ => {},
+        }
+    }
+
+    // Frame the delta view compares from: the marked one, or the one before the latest
+    fn delta_from(&self) -> Option<&Capture> {
+        self.delta.base.as_ref().or(self.delta.previous.as_ref())
+    }
+
+    // Frame it compares to, as (frame, RAM): the held one, or the latest
+    fn delta_to(&self) -> (u64, &[u8]) {
+        self.delta.held.as_ref().map_or((self.frame, self.ram.as_slice()), |held| (held.frame, held.ram.as_slice()))
+    }
+
+    // Bytes that differ between the compared frames: (capture index, old, new)
+    fn changes(&self) -> Vec<(usize, u8, u8)> {
+        let Some(from) = self.delta_from() else {
+            return Vec::new();
+        };
+        from.ram.iter().zip(self.delta_to().1).enumerate()
+            .filter(|&(_, (old, new))| old != new)
+            .map(|(i, (&old, &new))| (i, old, new))
+            .collect()
+    }
+
+    fn watch(&mut self, address: u16) {
+        if !self.watches.contains(&address) {
+            self.watches.push(address);
+        }
+    }
+
+    fn filter(&mut self, filter: Filter) {
+        if let Some(search) = &mut self.search
+            && self.ram.len() == search.snapshot.len()
+        {
+            search.apply(filter, &self.ram);
+        }
+    }
+
+    fn clamp_selection(&mut self) {
+        let count = self.search.as_ref().map_or(0, |search| search.candidates.len());
+        self.selected = self.selected.min(count.saturating_sub(1));
+    }
+
+    fn selected_address(&self) -> Option<u16> {
+        let search = self.search.as_ref()?;
+        search.candidates.get(self.selected).map(|&i| ram_address(i))
+    }
+
+    // Current value at `address`, if it is in the capture
+    fn value_at(&self, address: u16) -> Option<u8> {
+        let mut offset = 0;
+        for (start, len) in REGIONS {
+            if (start..start + len as u16).contains(&address) {
+                return self.ram.get(offset + (address - start) as usize).copied();
+            }
+            offset += len;
+        }
+        None
+    }
+
+    pub fn update(&mut self, ram: Vec<u8>, frame: u64) -> Result<(), String> {
+        if !self.is_open {
+            return Ok(());
+        }
+        // A paused game keeps sending the same frame
+        if frame != self.frame || self.ram.is_empty() {
+            let latest = Capture { frame: self.frame, ram: std::mem::replace(&mut self.ram, ram) };
+            self.delta.previous = (!latest.ram.is_empty()).then_some(latest);
+            self.frame = frame;
+        }
+
+        let lines = match self.view {
+            View::Search => self.search_lines(),
+            View::Delta => self.delta_lines(),
+        };
+
+        // Watch list at the bottom, newest entries last
+        let mut watches = vec![("Watch (W adds, F freezes, Delete removes)".to_string(), DIM, false)];
+        for &address in self.watches.iter().rev().take(WATCH_ROWS).rev() {
+            let frozen = if self.frozen.contains(&address) { "  frozen" } else { "" };
+            let text = format!("{:04X}  {:3}{}", address, self.value_at(address).unwrap_or(0), frozen);
+            watches.push((text, TEXT, false));
+        }
+
+        self.canvas.set_draw_color(Color::RGB(240, 240, 240));
+        self.canvas.clear();
+        self.draw_lines(&lines, 5)?;
+        self.draw_lines(&watches, WINDOW_HEIGHT as i32 - LINE_HEIGHT * (WATCH_ROWS as i32 + 1) - 5)?;
+        self.canvas.present();
+        Ok(())
+    }
+
+    fn search_lines(&self) -> Vec<(String, Color, bool)> {
+        let mut lines = vec![
+            ("N new  I inc  D dec  C changed  U same  Tab delta".to_string(), DIM, false),
+            (format!("Value: {:_<3}  (Enter = equal to)", self.value_input), TEXT, false),
+            (String::new(), TEXT, false),
+        ];
+        match &self.search {
+            None => lines.push(("Press N to start a search".to_string(), TEXT, false)),
+            Some(search) => {
+                lines.push((format!("Candidates: {}", search.candidates.len()), TEXT, false));
+                // Keep the selection visible
+                let first = self.selected.saturating_sub(VISIBLE_CANDIDATES - 1);
+                for (n, &i) in search.candidates.iter().enumerate().skip(first).take(VISIBLE_CANDIDATES) {
+                    let now = self.ram.get(i).copied().unwrap_or(0);
+                    let text = format!("{:04X}  prev {:3}  now {:3}", ram_address(i), search.snapshot[i], now);
+                    lines.push((text, TEXT, n == self.selected));
+                }
+            },
+        }
+        lines
+    }
+
+    fn delta_lines(&mut self) -> Vec<(String, Color, bool)> {
+        let mut lines = vec![
+            ("M mark  Space hold  Bksp clear  Tab search".to_string(), DIM, false),
+        ];
+        let Some(from) = self.delta_from() else {
+            lines.push(("Waiting for a second frame".to_string(), TEXT, false));
+            return lines;
+        };
+        let header = format!("Frame {} -> {}{}", from.frame, self.delta_to().0, if self.delta.held.is_some() { " (held)" } else { "" });
+        let changes = self.changes();
+        self.delta.selected = self.delta.selected.min(changes.len().saturating_sub(1));
+        lines.push((header, TEXT, false));
+        lines.push((String::new(), TEXT, false));
+        lines.push((format!("Changed: {}", changes.len()), TEXT, false));
+        let first = self.delta.selected.saturating_sub(VISIBLE_CANDIDATES - 1);
+        for (n, &(i, old, new)) in changes.iter().enumerate().skip(first).take(VISIBLE_CANDIDATES) {
+            let text = format!("{:04X}  {:3} -> {:3}  {:+}", ram_address(i), old, new, new as i16 - old as i16);
+            lines.push((text, TEXT, n == self.delta.selected));
+        }
+        lines
+    }
+
+    // Draw (text, color, highlighted) rows starting at `y`
+    fn draw_lines(&mut self, lines: &[(String, Color, bool)], mut y: i32) -> Result<(), String> {
+        for (text, color, highlighted) in lines {
+            if *highlighted {
+                self.canvas.set_draw_color(SELECTED);
+                self.canvas.fill_rect(Rect::new(0, y - 2, WINDOW_WIDTH, LINE_HEIGHT as u32))?;
+            }
+            font::draw_text(&mut self.canvas, text, 5, y, *color)?;
+            y += LINE_HEIGHT;
+        }
+        Ok(())
+    }
+}
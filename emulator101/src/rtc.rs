@@ -0,0 +1,72 @@
+// Cartridge clock adjustments
+// Amounts are written as a signed sum of days, hours, minutes and seconds: `+24h`, `-30m`,
+// `+1d12h`, `90s` (no sign means forward). They are used by `run --rtc-shift`, by the
+// `rtc` subcommand, which reads or adjusts the clock kept in a game's battery save without
+// playing it, and by F10 / F11 while playing (an hour / a day forward, back with Shift).
+
+use emulator101_core::{Emulator, RtcReading};
+
+use crate::battery::BatterySave;
+
+// Seconds in an amount such as `+1d12h`
+pub fn parse_shift(text: &str) -> Result<i64, String> {
+    let invalid = || format!("invalid time amount '{}' (expected e.g. +24h, -30m or +1d12h)", text);
+    let (sign, rest) = match text.as_bytes().first() {
+        Some(b'-') => (-1, &text[1..]),
+        Some(b'+') => (1, &text[1..]),
+        _ => (1, text),
+    };
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    let mut seconds = 0i64;
+    let mut number = String::new();
+    for c in rest.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            'd' => 86_400,
+            'h' => 3_600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(invalid()),
+        };
+        let value: i64 = number.parse().map_err(|_| invalid())?;
+        seconds = value.checked_mul(unit).and_then(|value| seconds.checked_add(value)).ok_or_else(invalid)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+    Ok(sign * seconds)
+}
+
+// `emulator101 rtc <rom> [<amount>]`: show the clock in the game's save, shifted first if asked
+pub fn run(mut emulator: Emulator, rom_path: &str, shift: Option<i64>) -> Result<(), Box<dyn std::error::Error>> {
+    if emulator.rtc().is_none() {
+        return Err(format!("{} has no cartridge clock", rom_path).into());
+    }
+    let mut battery = BatterySave::for_rom(rom_path, crate::battery::DEFAULT_BACKUPS);
+    if !battery.load(&mut emulator)? {
+        return Err(format!("{} doesn't exist yet; play the game first", battery.path().display()).into());
+    }
+    let reading = match shift {
+        Some(seconds) => {
+            let reading = emulator.shift_rtc(seconds);
+            battery.store(&emulator.battery_save())?;
+            reading
+        },
+        None => emulator.rtc(),
+    };
+    println!("{}", describe(reading));
+    Ok(())
+}
+
+pub fn describe(reading: Option<RtcReading>) -> String {
+    match reading {
+        Some(reading) => format!("Cartridge clock: {}", reading),
+        None => "The cartridge has no clock".to_string(),
+    }
+}
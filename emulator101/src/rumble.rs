@@ -0,0 +1,55 @@
+// Controller rumble
+// Rumble cartridges (MBC5+RUMBLE, e.g. Pokemon Pinball) switch a motor in the cartridge; it is
+// passed on to every connected game controller that can rumble. The motor is on for a frame if
+// the game ran it at any point during the frame, and it stops while the game is paused.
+// Controllers are only opened for this; they don't play the game yet.
+
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+
+// How long each frame's rumble lasts, so a stall in the frontend doesn't leave it running
+const PULSE_MS: u32 = 100;
+
+pub struct Rumble {
+    // None when SDL couldn't open the controller subsystem
+    subsystem: Option<GameControllerSubsystem>,
+    controllers: Vec<GameController>,
+    on: bool,
+}
+
+impl Rumble {
+    pub fn new(sdl_context: &sdl2::Sdl) -> Self {
+        Rumble { subsystem: sdl_context.game_controller().ok(), controllers: Vec::new(), on: false }
+    }
+
+    // Controllers coming and going; SDL also reports the ones already connected at start
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                if let Some(controller) = self.subsystem.as_ref().and_then(|subsystem| subsystem.open(which).ok()) {
+                    self.controllers.push(controller);
+                }
+                true
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers.retain(|controller| controller.instance_id() != which);
+                true
+            },
+            _ => false,
+        }
+    }
+
+    // Called once per frame shown
+    pub fn set(&mut self, on: bool) {
+        if !on && !self.on {
+            return;
+        }
+        self.on = on;
+        let strength = if on { u16::MAX } else { 0 };
+        for controller in &mut self.controllers {
+            // Controllers without a motor refuse, which is fine
+            let _ = controller.set_rumble(strength, strength, if on { PULSE_MS } else { 0 });
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// Upscaling filters
+// Optional CPU-side pass run on the RGBA32 frame before it is uploaded to the texture. Scale2x
+// and Scale3x (the EPX/AdvMAME family) round off diagonal edges by comparing each pixel with its
+// neighbors; SDL's nearest-neighbor scaling then covers the remaining factor up to the window.
+
+use std::fmt;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    Nearest,
+    Scale2x,
+    Scale3x,
+}
+
+impl Filter {
+    // Size multiplier of the filtered frame
+    pub fn factor(self) -> usize {
+        match self {
+            Filter::Nearest => 1,
+            Filter::Scale2x => 2,
+            Filter::Scale3x => 3,
+        }
+    }
+
+    // The filter selected after this one at runtime
+    pub fn next(self) -> Filter {
+        match self {
+            Filter::Nearest => Filter::Scale2x,
+            Filter::Scale2x => Filter::Scale3x,
+            Filter::Scale3x => Filter::Nearest,
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Filter::Nearest => "nearest",
+            Filter::Scale2x => "scale2x",
+            Filter::Scale3x => "scale3x",
+        })
+    }
+}
+
+// Upscale a width x height RGBA32 frame into `out`, which is resized to fit. Nearest copies the
+// frame as it is.
+pub fn upscale(filter: Filter, pixels: &[u8], width: usize, height: usize, out: &mut Vec<u8>) {
+    if filter == Filter::Nearest {
+        out.clear();
+        out.extend_from_slice(pixels);
+        return;
+    }
+    let factor = filter.factor();
+    let out_width = width * factor;
+    out.resize(out_width * height * factor * 4, 0);
+
+    // Neighbor lookup; the edges repeat the border pixels
+    let at = |x: isize, y: isize| -> [u8; 4] {
+        let x = x.clamp(0, width as isize - 1) as usize;
+        let y = y.clamp(0, height as isize - 1) as usize;
+        let offset = (y * width + x) * 4;
+        [pixels[offset], pixels[offset + 1], pixels[offset + 2], pixels[offset + 3]]
+    };
+    let mut put = |x: usize, y: usize, dx: usize, dy: usize, color: [u8; 4]| {
+        let offset = ((y * factor + dy) * out_width + x * factor + dx) * 4;
+        out[offset..offset + 4].copy_from_slice(&color);
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            let (xi, yi) = (x as isize, y as isize);
+            let (a, b, c) = (at(xi - 1, yi - 1), at(xi, yi - 1), at(xi + 1, yi - 1));
+            let (d, e, f) = (at(xi - 1, yi), at(xi, yi), at(xi + 1, yi));
+            let (g, h, i) = (at(xi - 1, yi + 1), at(xi, yi + 1), at(xi + 1, yi + 1));
+            match filter {
+                Filter::Nearest => {},
+                Filter::Scale2x => {
+                    let edge = b != h && d != f;
+                    put(x, y, 0, 0, if edge && d == b { d } else { e });
+                    put(x, y, 1, 0, if edge && b == f { f } else { e });
+                    put(x, y, 0, 1, if edge && d == h { d } else { e });
+                    put(x, y, 1, 1, if edge && h == f { f } else { e });
+                },
+                Filter::Scale3x => {
+                    let edge = b != h && d != f;
+                    let (db, bf, dh, hf) = (edge && d == b, edge && b == f, edge && d == h, edge && h == f);
+                    put(x, y, 0, 0, if db { d } else { e });
+                    put(x, y, 1, 0, if (db && e != c) || (bf && e != a) { b } else { e });
+                    put(x, y, 2, 0, if bf { f } else { e });
+                    put(x, y, 0, 1, if (db && e != g) || (dh && e != a) { d } else { e });
+                    put(x, y, 1, 1, e);
+                    put(x, y, 2, 1, if (bf && e != i) || (hf && e != c) { f } else { e });
+                    put(x, y, 0, 2, if dh { d } else { e });
+                    put(x, y, 1, 2, if (dh && e != i) || (hf && e != g) { h } else { e });
+                    put(x, y, 2, 2, if hf { f } else { e });
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// Lua scripting
+// A script registers callbacks that run on the emulation thread around every frame. While a
+// callback (or the script's top level) runs, these tables are available:
+//
+//   emu.before_frame(fn) / emu.after_frame(fn)   register frame callbacks
+//   emu.frame_count()                            frames emulated since the script was loaded
+//   memory.read(addr) / memory.write(addr, value)
+//   input.set(button, pressed)                   "a", "b", "select", "start", "up", "down", "left", "right"
+//   gui.text(x, y, text [, 0xRRGGBB])            on-screen text for the current frame
+//   savestate.save() -> string / savestate.load(string)
+
+use std::cell::{Cell, RefCell};
+use std::path::Path;
+
+use mlua::{Function, Lua};
+
+use emulator101_core::{Emulator, JoypadButton, SCREEN_WIDTH};
+
+use crate::font;
+
+const BEFORE_FRAME: &str = "before_frame";
+const AFTER_FRAME: &str = "after_frame";
+
+struct OsdText {
+    x: i32,
+    y: i32,
+    text: String,
+    color: [u8; 4],
+}
+
+pub struct Script {
+    lua: Lua,
+    osd: RefCell<Vec<OsdText>>,
+    frame_count: Cell<u64>,
+}
+
+impl Script {
+    // Load and run a script's top level (where it normally registers its callbacks)
+    pub fn load(path: &Path, emulator: &mut Emulator) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let script = Self {
+            lua: Lua::new(),
+            osd: RefCell::new(Vec::new()),
+            frame_count: Cell::new(0),
+        };
+        script.register_callbacks().map_err(|e| e.to_string())?;
+
+        let name = format!("@{}", path.display());
+        script
+            .with_api(emulator, |lua| lua.load(&source).set_name(name).exec())
+            .map_err(|e| e.to_string())?;
+        Ok(script)
+    }
+
+    // Called right before the emulator runs a frame
+    pub fn before_frame(&self, emulator: &mut Emulator) -> Result<(), String> {
+        self.osd.borrow_mut().clear();
+        self.call(BEFORE_FRAME, emulator)
+    }
+
+    // Called once the frame is complete, before it is shown
+    pub fn after_frame(&self, emulator: &mut Emulator) -> Result<(), String> {
+        self.frame_count.set(self.frame_count.get() + 1);
+        self.call(AFTER_FRAME, emulator)
+    }
+
+    // Draw this frame's gui.text calls on top of the RGBA32 frame
+    pub fn draw_osd(&self, pixels: &mut [u8]) {
+        for item in self.osd.borrow().iter() {
+            font::draw_text_rgba(pixels, SCREEN_WIDTH, item.x, item.y, &item.text, item.color);
+        }
+    }
+
+    // emu.before_frame / emu.after_frame store the function in the Lua registry
+    fn register_callbacks(&self) -> mlua::Result<()> {
+        let emu = self.lua.create_table()?;
+        for name in [BEFORE_FRAME, AFTER_FRAME] {
+            let register = self.lua.create_function(move |lua, callback: Function| {
+                lua.set_named_registry_value(name, callback)
+            })?;
+            emu.set(name, register)?;
+        }
+        self.lua.globals().set("emu", emu)
+    }
+
+    fn call(&self, name: &str, emulator: &mut Emulator) -> Result<(), String> {
+        let callback: Option<Function> = self.lua.named_registry_value(name).map_err(|e| e.to_string())?;
+        match callback {
+            Some(callback) => self.with_api(emulator, |_| callback.call::<_, ()>(())).map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    // Run `f` with the emulator-facing tables bound to `emulator`. The functions are scoped,
+    // so they stop working (with a Lua error) once `f` returns.
+    fn with_api<R>(&self, emulator: &mut Emulator, f: impl FnOnce(&Lua) -> mlua::Result<R>) -> mlua::Result<R> {
+        let emulator = RefCell::new(emulator);
+        let lua = &self.lua;
+
+        lua.scope(|scope| {
+            let globals = lua.globals();
+
+            let emu: mlua::Table = globals.get("emu")?;
+            emu.set("frame_count", scope.create_function(|_, ()| Ok(self.frame_count.get()))?)?;
+
+            let memory = lua.create_table()?;
+            memory.set("read", scope.create_function(|_, addr: u16| {
+                Ok(emulator.borrow().bus().read_byte(addr))
+            })?)?;
+            memory.set("write", scope.create_function(|_, (addr, value): (u16, u8)| {
+                emulator.borrow_mut().bus_mut().write_byte(addr, value);
+                Ok(())
+            })?)?;
+            globals.set("memory", memory)?;
+
+            let input = lua.create_table()?;
+            input.set("set", scope.create_function(|_, (name, pressed): (String, bool)| {
+                let button = name.parse::<JoypadButton>().map_err(mlua::Error::RuntimeError)?;
+                emulator.borrow_mut().set_button(button, pressed);
+                Ok(())
+            })?)?;
+            globals.set("input", input)?;
+
+            let gui = lua.create_table()?;
+            gui.set("text", scope.create_function(|_, (x, y, text, color): (i32, i32, String, Option<u32>)| {
+                let [_, r, g, b] = color.unwrap_or(0xFFFFFF).to_be_bytes();
+                self.osd.borrow_mut().push(OsdText { x, y, text, color: [r, g, b, 0xFF] });
+                Ok(())
+            })?)?;
+            globals.set("gui", gui)?;
+
+            let savestate = lua.create_table()?;
+            savestate.set("save", scope.create_function(|lua, ()| {
+                lua.create_string(emulator.borrow().save_state())
+            })?)?;
+            savestate.set("load", scope.create_function(|_, data: mlua::String| {
+                emulator.borrow_mut().load_state(data.as_bytes()).map_err(mlua::Error::external)
+            })?)?;
+            globals.set("savestate", savestate)?;
+
+            f(lua)
+        })
+    }
+}
@@ -0,0 +1,105 @@
+// Spectator streams
+// The process hosting a session can serve its screens over TCP to read-only viewers, e.g. to cast
+// a link battle. A viewer connects, sends one byte with the player it wants to follow (0 or 1)
+// and from then on receives every frame as raw RGBA32, SCREEN_WIDTH * SCREEN_HEIGHT * 4 bytes
+// with no framing. Each viewer has its own sending thread; a viewer that can't keep up misses
+// frames instead of slowing the host down.
+
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, SyncSender, TryRecvError, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use emulator101_core::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+pub const FRAME_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT * 4;
+// Frames queued per viewer before new ones are dropped
+const VIEWER_QUEUE: usize = 2;
+
+struct Viewer {
+    player: u8,
+    frames: SyncSender<Arc<Vec<u8>>>,
+}
+
+pub struct SpectatorServer {
+    viewers: Arc<Mutex<Vec<Viewer>>>,
+}
+
+impl SpectatorServer {
+    // Start accepting viewers on `addr` in the background
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<SpectatorServer> {
+        let listener = TcpListener::bind(addr)?;
+        let viewers = Arc::new(Mutex::new(Vec::new()));
+        let accepted = viewers.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let viewers = accepted.clone();
+                thread::spawn(move || serve_viewer(stream, &viewers));
+            }
+        });
+        Ok(SpectatorServer { viewers })
+    }
+
+    // Send a frame to everyone watching `player`
+    pub fn publish(&self, player: u8, pixels: &[u8]) {
+        let mut viewers = self.viewers.lock().unwrap();
+        if !viewers.iter().any(|viewer| viewer.player == player) {
+            return;
+        }
+        let frame = Arc::new(pixels.to_vec());
+        viewers.retain(|viewer| {
+            viewer.player != player || !matches!(viewer.frames.try_send(frame.clone()), Err(TrySendError::Disconnected(_)))
+        });
+    }
+}
+
+// Read the viewer's choice of player, register it and send it frames until it disconnects.
+// Runs on the viewer's own thread so one that never sends its choice can't hold up the others.
+fn serve_viewer(mut stream: TcpStream, viewers: &Mutex<Vec<Viewer>>) -> io::Result<()> {
+    let mut player = [0];
+    stream.read_exact(&mut player)?;
+    stream.set_nodelay(true)?;
+    let (frames, queue) = mpsc::sync_channel::<Arc<Vec<u8>>>(VIEWER_QUEUE);
+    viewers.lock().unwrap().push(Viewer { player: player[0], frames });
+    // Ends when the viewer disconnects; the next publish then drops it
+    for frame in queue {
+        stream.write_all(&frame)?;
+    }
+    Ok(())
+}
+
+// The watching side: receives one player's frames from a SpectatorServer
+pub struct SpectatorClient {
+    frames: Receiver<Vec<u8>>,
+}
+
+impl SpectatorClient {
+    pub fn connect(addr: impl ToSocketAddrs, player: u8) -> io::Result<SpectatorClient> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.write_all(&[player])?;
+        let (sender, frames) = mpsc::channel();
+        thread::spawn(move || {
+            loop {
+                let mut frame = vec![0; FRAME_SIZE];
+                if stream.read_exact(&mut frame).is_err() || sender.send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(SpectatorClient { frames })
+    }
+
+    // The newest frame received since the last call. Err once the host has gone away.
+    pub fn latest_frame(&self) -> Result<Option<Vec<u8>>, TryRecvError> {
+        let mut latest = None;
+        loop {
+            match self.frames.try_recv() {
+                Ok(frame) => latest = Some(frame),
+                Err(TryRecvError::Empty) => return Ok(latest),
+                Err(TryRecvError::Disconnected) if latest.is_some() => return Ok(latest),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// Start screen
+// Started without a ROM (from a file manager, say) or with one that fails to load, the frontend
+// still opens its window and says so in the bitmap font, with the reason the ROM was turned
+// down. A ROM dropped onto the window is then run as if it had been given on the command line;
+// closing the window or Esc quits.
+
+use emulator101_core::{SCREEN_WIDTH, SCREEN_HEIGHT};
+
+use crate::{display, font, tabs};
+use crate::{SCALE, WINDOW_TITLE};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+// Text is drawn at twice the size of the debug windows', on a screen this many points across
+const WIDTH: u32 = SCREEN_WIDTH as u32 * SCALE / 2;
+const HEIGHT: u32 = SCREEN_HEIGHT as u32 * SCALE / 2;
+const MARGIN: i32 = 8;
+const LINE_HEIGHT: i32 = font::GLYPH_HEIGHT as i32 + 3;
+// Error lines that fit between the heading and the instructions
+const MAX_ERROR_LINES: usize = 10;
+
+const BACKGROUND: Color = Color::RGB(0x0F, 0x38, 0x0F);
+const HEADING: Color = Color::RGB(0x9B, 0xBC, 0x0F);
+const TEXT: Color = Color::RGB(0x8B, 0xAC, 0x0F);
+const ERROR: Color = Color::RGB(0xE0, 0x60, 0x50);
+
+// What went wrong with the ROM given on the command line
+pub struct LoadFailure<'a> {
+    pub rom_path: &'a str,
+    pub error: String,
+}
+
+// Show the start screen (with `failure`, if any) until a ROM is dropped on it. Returns its path,
+// or None when the window is closed.
+pub fn wait_for_rom(failure: Option<LoadFailure>, ui_scale: Option<f32>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let ui_scale = ui_scale.unwrap_or_else(|| display::detect_scale(&video_subsystem));
+    let (width, height) = display::window_size(&video_subsystem, SCREEN_WIDTH as u32 * SCALE, SCREEN_HEIGHT as u32 * SCALE, ui_scale);
+    let window = video_subsystem
+        .window(WINDOW_TITLE, width, height)
+        .position_centered()
+        .allow_highdpi()
+        .resizable()
+        .build()?;
+    let mut canvas = window.into_canvas().build()?;
+    canvas.set_logical_size(WIDTH, HEIGHT)?;
+
+    let columns = (WIDTH as usize - 2 * MARGIN as usize) / font::ADVANCE;
+    let mut lines: Vec<(String, Color)> = Vec::new();
+    match &failure {
+        Some(failure) => {
+            let heading = format!("Could not load {}", tabs::name(failure.rom_path));
+            lines.extend(wrap(&heading, columns).into_iter().map(|line| (line, HEADING)));
+            lines.push((String::new(), TEXT));
+            lines.extend(wrap(&failure.error, columns).into_iter().take(MAX_ERROR_LINES).map(|line| (line, ERROR)));
+        },
+        None => lines.push(("No ROM loaded".to_string(), HEADING)),
+    }
+
+    let mut event_pump = sdl_context.event_pump()?;
+    draw(&mut canvas, &lines)?;
+    loop {
+        match event_pump.wait_event() {
+            Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => return Ok(None),
+            Event::DropFile { filename, .. } => return Ok(Some(filename)),
+            _ => draw(&mut canvas, &lines)?,
+        }
+    }
+}
+
+fn draw(canvas: &mut Canvas<Window>, lines: &[(String, Color)]) -> Result<(), String> {
+    canvas.set_draw_color(BACKGROUND);
+    canvas.clear();
+    font::draw_text(canvas, WINDOW_TITLE, MARGIN, MARGIN, HEADING)?;
+    let mut y = MARGIN + 2 * LINE_HEIGHT;
+    for (line, color) in lines {
+        font::draw_text(canvas, line, MARGIN, y, *color)?;
+        y += LINE_HEIGHT;
+    }
+    let instructions = ["Drop a ROM (.gb, .zip) onto", "this window to play it.", "", "Esc quits."];
+    let mut y = HEIGHT as i32 - MARGIN - instructions.len() as i32 * LINE_HEIGHT;
+    for line in instructions {
+        font::draw_text(canvas, line, MARGIN, y, TEXT)?;
+        y += LINE_HEIGHT;
+    }
+    canvas.present();
+    Ok(())
+}
+
+// Break `text` at spaces into lines of at most `columns` characters; longer words (paths, mostly)
+// are cut wherever the line runs out
+fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line: Vec<char> = Vec::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.chars().count() > columns {
+            lines.push(line.drain(..).collect());
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.extend(word.chars());
+        while line.len() > columns {
+            lines.push(line.drain(..columns).collect());
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line.into_iter().collect());
+    }
+    lines
+}
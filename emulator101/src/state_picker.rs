@@ -0,0 +1,186 @@
+// Savestate slots
+// F5 saves and F8 loads the selected slot: slot 0 is `game.gb.state`, slots 1-9 are
+// `game.gb.state1` to `game.gb.state9`. F4 opens a picker over the game showing the screenshot
+// kept in the slot's state, how long ago it was saved and the play time in it, so slots can be
+// told apart. Left/Right or 0-9 select a slot, Return loads it and closes the picker, F5 saves
+// over it, F4 or Esc closes it. The game keeps running underneath without input.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use emulator101_core::savestate::{self, SaveStateError, StateInfo, THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT};
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+
+use crate::font;
+use crate::worker::{Command, EmulatorThread};
+
+const SLOTS: usize = 10;
+
+const THUMBNAIL_Y: usize = 12;
+const TEXT: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+const SHADOW: [u8; 4] = [0x00, 0x00, 0x00, 0xFF];
+const EMPTY: [u8; 4] = [0x70, 0x70, 0x70, 0xFF];
+const SELECTED: [u8; 4] = [0x30, 0x60, 0xC0, 0xFF];
+
+pub struct StatePicker {
+    is_open: bool,
+    slot: usize,
+    // Header of each slot's state: None without a file, an error when it can't be read
+    slots: Vec<Option<Result<StateInfo, SaveStateError>>>,
+}
+
+impl StatePicker {
+    pub fn new() -> Self {
+        StatePicker { is_open: false, slot: 0, slots: Vec::new() }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    // File of the selected slot for the game at `rom_path`
+    pub fn path(&self, rom_path: &str) -> String {
+        slot_path(rom_path, self.slot)
+    }
+
+    // Read the slots again, e.g. after a save
+    pub fn refresh(&mut self, rom_path: &str) {
+        if !self.is_open {
+            return;
+        }
+        self.slots = (0..SLOTS)
+            .map(|slot| match std::fs::read(slot_path(rom_path, slot)) {
+                Ok(data) => Some(savestate::info(&data)),
+                Err(_) => None,
+            })
+            .collect();
+    }
+
+    // Keys for the picker: F4 anywhere, everything but F5 and F8 while it is open
+    pub fn handle_event(&mut self, event: &Event, emulator: &EmulatorThread, rom_path: &str) -> bool {
+        let Event::KeyDown { keycode: Some(key), repeat, .. } = *event else {
+            return false;
+        };
+        if !self.is_open {
+            if key == Keycode::F4 && !repeat {
+                self.is_open = true;
+                self.refresh(rom_path);
+                return true;
+            }
+            return false;
+        }
+        match key {
+            Keycode::F5 | Keycode::F8 => return false,
+            Keycode::F4 | Keycode::Escape => self.is_open = false,
+            Keycode::Left | Keycode::Up => self.slot = (self.slot + SLOTS - 1) % SLOTS,
+            Keycode::Right | Keycode::Down => self.slot = (self.slot + 1) % SLOTS,
+            Keycode::Return => {
+                let path = self.path(rom_path);
+                match std::fs::read(&path) {
+                    Ok(data) => emulator.send(Command::LoadState(data)),
+                    Err(e) => eprintln!("Failed to read {}: {}", path, e),
+                }
+                self.is_open = false;
+            },
+            _ => {
+                let digit = key.name().parse::<usize>().ok().filter(|&digit| digit < SLOTS);
+                if let Some(digit) = digit {
+                    self.slot = digit;
+                }
+            },
+        }
+        true
+    }
+
+    // Draw the picker over a frame of `width` pixels across
+    pub fn draw(&self, pixels: &mut [u8], width: usize) {
+        // Darken the game behind it
+        for pixel in pixels.chunks_exact_mut(4) {
+            for channel in &mut pixel[..3] {
+                *channel /= 3;
+            }
+        }
+        let thumbnail_x = (width - THUMBNAIL_WIDTH) / 2;
+        text(pixels, width, 2, 2, &format!("State {}", self.slot), TEXT);
+
+        let lines = match self.slots.get(self.slot) {
+            Some(Some(Ok(info))) => {
+                for y in 0..THUMBNAIL_HEIGHT {
+                    let from = y * THUMBNAIL_WIDTH * 4;
+                    let to = ((THUMBNAIL_Y + y) * width + thumbnail_x) * 4;
+                    pixels[to..to + THUMBNAIL_WIDTH * 4].copy_from_slice(&info.thumbnail[from..from + THUMBNAIL_WIDTH * 4]);
+                }
+                [format!("Saved {}", age(info.saved_at)), format!("Played {}", clock(info.play_time))]
+            },
+            Some(Some(Err(SaveStateError::UnsupportedVersion(_)))) => ["Made by another version".to_string(), String::new()],
+            Some(Some(Err(_))) => ["Not a readable state".to_string(), String::new()],
+            _ => ["Empty".to_string(), String::new()],
+        };
+        frame(pixels, width, thumbnail_x - 1, THUMBNAIL_Y - 1, THUMBNAIL_WIDTH + 2, THUMBNAIL_HEIGHT + 2);
+        let mut y = (THUMBNAIL_Y + THUMBNAIL_HEIGHT + 4) as i32;
+        for line in &lines {
+            text(pixels, width, 2, y, line, TEXT);
+            y += font::GLYPH_HEIGHT as i32 + 2;
+        }
+
+        // The slots in a row, filled ones in white
+        let row_y = y as usize + 4;
+        for slot in 0..SLOTS {
+            let x = 2 + slot * 2 * font::ADVANCE;
+            if slot == self.slot {
+                fill(pixels, width, x - 1, row_y - 1, font::ADVANCE + 1, font::GLYPH_HEIGHT + 2, SELECTED);
+            }
+            let color = if matches!(self.slots.get(slot), Some(Some(_))) { TEXT } else { EMPTY };
+            font::draw_text_rgba(pixels, width, x as i32, row_y as i32, &slot.to_string(), color);
+        }
+        let bottom = (pixels.len() / (width * 4) - font::GLYPH_HEIGHT - 2) as i32;
+        text(pixels, width, 2, bottom, "F5 save  Return load", TEXT);
+    }
+}
+
+fn slot_path(rom_path: &str, slot: usize) -> String {
+    match slot {
+        0 => format!("{}.state", rom_path),
+        _ => format!("{}.state{}", rom_path, slot),
+    }
+}
+
+// `5 min ago`, `3 days ago`, ...
+fn age(saved_at: Duration) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = now.saturating_sub(saved_at).as_secs();
+    match seconds {
+        0..60 => "just now".to_string(),
+        60..3_600 => format!("{} min ago", seconds / 60),
+        3_600..86_400 => format!("{} h ago", seconds / 3_600),
+        _ => format!("{} days ago", seconds / 86_400),
+    }
+}
+
+// `1:02:03`
+fn clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3_600, seconds / 60 % 60, seconds % 60)
+}
+
+fn text(pixels: &mut [u8], width: usize, x: i32, y: i32, text: &str, color: [u8; 4]) {
+    font::draw_text_rgba(pixels, width, x + 1, y + 1, text, SHADOW);
+    font::draw_text_rgba(pixels, width, x, y, text, color);
+}
+
+fn fill(pixels: &mut [u8], width: usize, x: usize, y: usize, w: usize, h: usize, color: [u8; 4]) {
+    for row in y..y + h {
+        for column in x..x + w {
+            let offset = (row * width + column) * 4;
+            pixels[offset..offset + 4].copy_from_slice(&color);
+        }
+    }
+}
+
+// One-pixel outline
+fn frame(pixels: &mut [u8], width: usize, x: usize, y: usize, w: usize, h: usize) {
+    fill(pixels, width, x, y, w, 1, TEXT);
+    fill(pixels, width, x, y + h - 1, w, 1, TEXT);
+    fill(pixels, width, x, y, 1, h, TEXT);
+    fill(pixels, width, x + w - 1, y, 1, h, TEXT);
+}
@@ -0,0 +1,103 @@
+// Sync statistics
+// How the frames the emulation thread publishes line up with what the window shows: frames
+// dropped because a newer one replaced them before they were presented, and waits that ran out
+// with no new frame, so the window showed the previous one again. Together with the drift between
+// emulated and host time and the queued audio from Stats, `F9` shows them on screen above a graph
+// of the time between the last presented frames.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use emulator101_core::Stats;
+
+use crate::font;
+
+// Height of the graph in pixels; a bar this tall is twice the target interval
+const GRAPH_HEIGHT: usize = 24;
+
+const ON_TIME: [u8; 4] = [0x40, 0xD0, 0x40, 0xFF];
+const LATE: [u8; 4] = [0xF0, 0xD0, 0x20, 0xFF];
+const DROPPED: [u8; 4] = [0xF0, 0x30, 0x30, 0xFF];
+const TARGET_LINE: [u8; 4] = [0xFF, 0xFF, 0xFF, 0xFF];
+
+pub struct SyncStats {
+    pub dropped: u64,
+    pub duplicated: u64,
+    target: Duration,
+    // Frame counter of the last presented frame, and when it was presented
+    last_frame: Option<u64>,
+    last_present: Option<Instant>,
+    // Time since the previous presented frame and whether frames were dropped in between, newest
+    // last; one entry per column of the graph
+    intervals: VecDeque<(Duration, bool)>,
+}
+
+impl SyncStats {
+    pub fn new(target: Duration) -> Self {
+        SyncStats { dropped: 0, duplicated: 0, target, last_frame: None, last_present: None, intervals: VecDeque::new() }
+    }
+
+    // The wait for a frame ran out; the window keeps showing the last one
+    pub fn missed(&mut self) {
+        self.duplicated += 1;
+    }
+
+    // A frame with Stats::frames `frames` is about to be presented
+    pub fn presented(&mut self, frames: u64, width: usize) {
+        let skipped = match self.last_frame {
+            Some(last) => frames.saturating_sub(last + 1),
+            None => 0,
+        };
+        self.dropped += skipped;
+        self.last_frame = Some(frames);
+
+        let now = Instant::now();
+        if let Some(last) = self.last_present.replace(now) {
+            if self.intervals.len() == width {
+                self.intervals.pop_front();
+            }
+            self.intervals.push_back((now - last, skipped > 0));
+        }
+    }
+
+    // Counters and the interval graph at the bottom of an RGBA32 frame
+    pub fn draw_osd(&self, pixels: &mut [u8], width: usize, stats: &Stats) {
+        let height = pixels.len() / (width * 4);
+        let bottom = height - 1;
+        let target = self.target.as_secs_f64();
+        for (column, &(interval, dropped)) in self.intervals.iter().enumerate() {
+            let ratio = interval.as_secs_f64() / (2.0 * target);
+            let bar = ((ratio * GRAPH_HEIGHT as f64) as usize).clamp(1, GRAPH_HEIGHT);
+            let color = if dropped {
+                DROPPED
+            } else if interval.as_secs_f64() > target * 1.2 {
+                LATE
+            } else {
+                ON_TIME
+            };
+            for row in bottom + 1 - bar..=bottom {
+                set(pixels, width, column, row, color);
+            }
+        }
+        for column in (0..width).step_by(2) {
+            set(pixels, width, column, bottom - GRAPH_HEIGHT / 2, TARGET_LINE);
+        }
+
+        let lines = [
+            format!("Drift {:+.3}s", stats.drift),
+            format!("Dropped {} dup {}", self.dropped, self.duplicated),
+            format!("Audio queued {}", stats.audio_buffered),
+        ];
+        let mut y = 2;
+        for line in &lines {
+            font::draw_text_rgba(pixels, width, 3, y + 1, line, [0x00, 0x00, 0x00, 0xFF]);
+            font::draw_text_rgba(pixels, width, 2, y, line, [0xFF, 0xFF, 0xFF, 0xFF]);
+            y += font::GLYPH_HEIGHT as i32 + 2;
+        }
+    }
+}
+
+fn set(pixels: &mut [u8], width: usize, x: usize, y: usize, color: [u8; 4]) {
+    let offset = (y * width + x) * 4;
+    pixels[offset..offset + 4].copy_from_slice(&color);
+}
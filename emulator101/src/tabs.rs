@@ -0,0 +1,70 @@
+// Tabbed sessions
+// Extra ROMs (--tab, or a file dropped with Ctrl held) run on emulation threads of their own.
+// Only the tab in front is shown and takes input; the others are held with a debug pause until
+// Ctrl+Tab brings them back, so two games or two revisions of one can be compared side by side
+// in a single process.
+
+use std::collections::VecDeque;
+use std::path::Path;
+
+use crate::worker::{Command, EmulatorThread};
+
+// A tab in the background and what the frontend keeps for it
+pub struct Tab {
+    pub emulator: EmulatorThread,
+    pub rom_path: String,
+    // Title from the cartridge header
+    pub title: String,
+    // Paused with F6 before it went to the background; restored when it comes back
+    pub paused: bool,
+    // Frame count of the last title bar update, for the frames per second
+    pub hud_frames: u64,
+}
+
+// The tabs behind the one being shown, in the order Ctrl+Tab goes through them
+pub struct Tabs {
+    background: VecDeque<Tab>,
+}
+
+impl Tabs {
+    pub fn new() -> Self {
+        Tabs { background: VecDeque::new() }
+    }
+
+    // Only the tab in front is open
+    pub fn is_empty(&self) -> bool {
+        self.background.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Tab> {
+        self.background.iter()
+    }
+
+    // Add a tab behind the others, paused
+    pub fn open(&mut self, tab: Tab) {
+        tab.emulator.send(Command::SetPaused(true));
+        self.background.push_back(tab);
+    }
+
+    // Put `front` in the background and bring the next tab forward, or the previous one with
+    // `back`. The tab leaving is paused and the one arriving gets its own pause state back.
+    pub fn switch(&mut self, front: Tab, back: bool) -> Tab {
+        let next = if back { self.background.pop_back() } else { self.background.pop_front() };
+        let Some(next) = next else {
+            return front;
+        };
+        front.emulator.send(Command::SetPaused(true));
+        next.emulator.send(Command::SetPaused(next.paused));
+        if back {
+            self.background.push_front(front);
+        } else {
+            self.background.push_back(front);
+        }
+        next
+    }
+}
+
+// Name a tab goes by: the ROM's file name
+pub fn name(rom_path: &str) -> &str {
+    Path::new(rom_path).file_name().and_then(|name| name.to_str()).unwrap_or(rom_path)
+}
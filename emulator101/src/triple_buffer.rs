@@ -0,0 +1,93 @@
+// Triple buffer
+// Hands the newest value from one producer thread to one consumer thread without either side
+// ever waiting on the other. The producer always owns a slot to fill and the consumer a slot to
+// read; the third slot holds the latest published value and is swapped with an atomic exchange.
+// A value the consumer never picked up is simply overwritten by the next one.
+
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread::{self, Thread};
+use std::time::Duration;
+
+// Low bits of `back`: index of the middle slot; FRESH: it holds a value not read yet
+const INDEX: u8 = 0x03;
+const FRESH: u8 = 0x04;
+
+struct Shared<T> {
+    slots: [UnsafeCell<T>; 3],
+    back: AtomicU8,
+    // Woken on every publish so a waiting consumer sees the value right away
+    consumer: Thread,
+}
+
+// SAFETY: each slot is only touched by the side whose index currently names it, and ownership
+// only changes hands through the AcqRel swap on `back`
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+pub struct Producer<T> {
+    shared: Arc<Shared<T>>,
+    index: u8,
+}
+
+pub struct Consumer<T> {
+    shared: Arc<Shared<T>>,
+    index: u8,
+}
+
+// The producer gone: no value will ever be published again
+#[derive(Debug)]
+pub struct Disconnected;
+
+// Create both ends; the calling thread is the one that consumes
+pub fn triple_buffer<T: Default + Send>() -> (Producer<T>, Consumer<T>) {
+    let shared = Arc::new(Shared {
+        slots: Default::default(),
+        back: AtomicU8::new(1),
+        consumer: thread::current(),
+    });
+    (Producer { shared: shared.clone(), index: 0 }, Consumer { shared, index: 2 })
+}
+
+impl<T> Producer<T> {
+    // The slot to fill; it still holds whatever value it carried last
+    pub fn slot(&mut self) -> &mut T {
+        // SAFETY: the producer owns slot `index` until it publishes it
+        unsafe { &mut *self.shared.slots[self.index as usize].get() }
+    }
+
+    // Make the filled slot the latest value and take the previous middle slot to fill next
+    pub fn publish(&mut self) {
+        let previous = self.shared.back.swap(self.index | FRESH, Ordering::AcqRel);
+        self.index = previous & INDEX;
+        self.shared.consumer.unpark();
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        // Let a waiting consumer notice
+        self.shared.consumer.unpark();
+    }
+}
+
+impl<T> Consumer<T> {
+    // Wait up to `timeout` for a value published since the last call. Must be called from the
+    // thread that created the buffer.
+    pub fn latest(&mut self, timeout: Duration) -> Result<Option<&mut T>, Disconnected> {
+        if self.shared.back.load(Ordering::Acquire) & FRESH == 0 {
+            if Arc::strong_count(&self.shared) == 1 {
+                return Err(Disconnected);
+            }
+            thread::park_timeout(timeout);
+            if self.shared.back.load(Ordering::Acquire) & FRESH == 0 {
+                return Ok(None);
+            }
+        }
+
+        let previous = self.shared.back.swap(self.index, Ordering::AcqRel);
+        self.index = previous & INDEX;
+        // SAFETY: the consumer owns slot `index` until its next swap
+        Ok(Some(unsafe { &mut *self.shared.slots[self.index as usize].get() }))
+    }
+}
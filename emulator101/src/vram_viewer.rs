@@ -1,9 +1,16 @@
-use crate::ppu::{Ppu, SCREEN_WIDTH, SCREEN_HEIGHT};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use emulator101_core::ppu::{Ppu, SCREEN_WIDTH, SCREEN_HEIGHT};
+
+use crate::config::ViewerKeys;
+use crate::font;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::render::{Canvas, TextureCreator};
 use sdl2::video::{Window, WindowContext};
 
 // Constants for viewer layout
@@ -15,6 +22,8 @@ const BG_MAP_WIDTH: u32 = 32; // Width of BG map in tiles
 const BG_MAP_HEIGHT: u32 = 32; // Height of BG map in tiles
 const PADDING: u32 = 1; // Padding between tiles
 const SIDEBAR_WIDTH: u32 = 180; // Width of sidebar with info
+const SCREENSHOT_HEADER_LINES: usize = 3; // Lines of register values above a screenshot
+const PINNED_PANEL_HEIGHT: i32 = 100; // Pinned item details at the bottom of the sidebar
 
 // Tabs in the viewer
 #[derive(PartialEq, Clone, Copy)]
@@ -23,6 +32,30 @@ enum ViewerTab {
     Tiles,
     Oam,
     Palettes,
+    Wave,
+}
+
+// In tab bar order, which is also the order of the 1-5 shortcuts
+const TABS: [ViewerTab; 5] = [ViewerTab::BgMap, ViewerTab::Tiles, ViewerTab::Oam, ViewerTab::Palettes, ViewerTab::Wave];
+
+impl ViewerTab {
+    // Columns and rows of the tab's item grid (none for the palettes and wave RAM)
+    fn grid_size(self) -> (u32, u32) {
+        match self {
+            ViewerTab::BgMap => (BG_MAP_WIDTH, BG_MAP_HEIGHT),
+            ViewerTab::Tiles => (GRID_WIDTH, 384 / GRID_WIDTH),
+            ViewerTab::Oam => (10, 4),
+            ViewerTab::Palettes | ViewerTab::Wave => (0, 0),
+        }
+    }
+}
+
+// Item kept in the sidebar after a click, shown live until unpinned
+#[derive(PartialEq, Clone, Copy)]
+enum Pinned {
+    Tile(usize),   // Index in 0x8000-0x97FF
+    MapEntry(u16), // BG map address
+    Sprite(usize), // OAM index
 }
 
 struct HoveredTile {
@@ -44,17 +77,29 @@ struct ViewerOptions {
     bg_map_offset: u16,   // 0x9800 or 0x9C00
     current_tab: ViewerTab,
     hovered_tile: Option<HoveredTile>,
+    pinned: Option<Pinned>,
+    // Grid cell picked with the arrow keys, shown until the mouse moves
+    cursor: Option<(u32, u32)>,
+    screenshot_requested: bool, // Save the tab as a PNG on the next update
 }
 
 pub struct VramViewer {
     canvas: Canvas<Window>,
     texture_creator: TextureCreator<WindowContext>,
     options: ViewerOptions,
+    keys: ViewerKeys,
     is_open: bool,
+    // Latest PPU snapshot; the emulation thread sends one about 15 times a second
+    ppu: Option<Box<Ppu>>,
+    // Wave RAM sent with the snapshot, and the sample channel 3 was playing (None while off)
+    wave_ram: [u8; 16],
+    wave_position: Option<u8>,
+    // Input changed what is shown, so redraw before the next snapshot arrives
+    dirty: bool,
 }
 
 impl VramViewer {
-    pub fn new(sdl_context: &sdl2::Sdl) -> Result<Self, String> {
+    pub fn new(sdl_context: &sdl2::Sdl, keys: ViewerKeys) -> Result<Self, String> {
         let video_subsystem = sdl_context.video()?;
         
         // Calculate window dimensions based on largest view (BG map)
@@ -80,13 +125,21 @@ impl VramViewer {
             bg_map_offset: 0x9800,
             current_tab: ViewerTab::BgMap,
             hovered_tile: None,
+            pinned: None,
+            cursor: None,
+            screenshot_requested: false,
         };
         
         Ok(VramViewer {
             canvas,
             texture_creator,
             options,
+            keys,
             is_open: false,
+            ppu: None,
+            wave_ram: [0; 16],
+            wave_position: None,
+            dirty: false,
         })
     }
 
@@ -96,6 +149,7 @@ impl VramViewer {
             self.canvas.window_mut().show(); // Show the window
         } else {
             self.canvas.window_mut().hide(); // Hide the window
+            self.ppu = None;
         }
     }
     
@@ -107,6 +161,7 @@ impl VramViewer {
         if !self.is_open {
             return false;
         }
+        self.dirty |= event.get_window_id() == Some(self.canvas.window().id());
         
         match event {
             // Switching tabs by clicking
@@ -118,119 +173,33 @@ impl VramViewer {
 
                     // Determine which tab was clicked
                     let tab_index = *x / (tab_width + tab_padding);
-                    if tab_index < 4 {
-                        self.options.current_tab = match tab_index {
-                            0 => ViewerTab::BgMap,
-                            1 => ViewerTab::Tiles,
-                            2 => ViewerTab::Oam,
-                            3 => ViewerTab::Palettes,
-                            _ => self.options.current_tab,
-                        };
+                    if let Some(&tab) = TABS.get(tab_index as usize) {
+                        self.select_tab(tab);
                         return true;
                     }
                 }
-                false
-            },
-            Event::MouseMotion { x, y, .. } => {
-                // Clear hover state when moving the mouse outside of content area
-                if *y < 30 { // Below tabs but above content
-                    self.options.hovered_tile = None;
-                    return false;
-                }
-
-                // Calculate which tile is being hovered based on current tab
-                match self.options.current_tab {
-                    ViewerTab::BgMap => {
-                        // For background map view
-                        let content_x = *x;
-                        let content_y = *y - 30; // Adjust for tab height
-
-                        // Calculate tile position
-                        let tile_x = content_x as u32 / (TILE_WIDTH * TILE_DISPLAY_SCALE);
-                        let tile_y = content_y as u32 / (TILE_HEIGHT * TILE_DISPLAY_SCALE);
-
-                        if tile_x < BG_MAP_WIDTH && tile_y < BG_MAP_HEIGHT {
-                            let map_idx = tile_y * BG_MAP_WIDTH + tile_x;
-                            let map_addr = self.options.bg_map_offset + map_idx as u16;
-                            self.options.hovered_tile = Some(HoveredTile {
-                                index: map_idx as usize,
-                                address: map_addr,
-                                screen_x: *x,
-                                screen_y: *y,
-                                tab: ViewerTab::BgMap,
-                            });
-                            return true;
-                        }
-                    },
-                    ViewerTab::Tiles => {
-                        // For tiles view
-                        let content_x = *x;
-                        let content_y = *y - 30; // Adjust for tab height
-                        
-                        // Calculate tile position
-                        let tile_x = content_x as u32 / (TILE_WIDTH * TILE_DISPLAY_SCALE);
-                        let tile_y = content_y as u32 / (TILE_HEIGHT * TILE_DISPLAY_SCALE);
-                        
-                        if tile_x < GRID_WIDTH {
-                            let tile_idx = (tile_y * GRID_WIDTH + tile_x) as usize;
-                            if tile_idx < 384 { // Valid tile index
-                                let tile_addr = 0x8000 + (tile_idx as u16) * 16;
-                                self.options.hovered_tile = Some(HoveredTile {
-                                    index: tile_idx,
-                                    address: tile_addr,
-                                    screen_x: *x,
-                                    screen_y: *y,
-                                    tab: ViewerTab::Tiles,
-                                });
-                                return true;
-                            }
-                        }
-                    },
-                    ViewerTab::Oam => {
-                        let content_x = *x;
-                        let content_y = *y - 30; // Adjust for tab height
-                        
-                        // Calculate sprite position
-                        let sprite_x = content_x as u32 / (TILE_WIDTH * TILE_DISPLAY_SCALE);
-                        let sprite_y = content_y as u32 / (TILE_HEIGHT * TILE_DISPLAY_SCALE);
-                        
-                        if sprite_x < 10 && sprite_y < 4 { // 10x4 grid of sprites
-                            let sprite_idx = sprite_y * 10 + sprite_x;
-                            if sprite_idx < 40 { // Valid sprite index
-                                self.options.hovered_tile = Some(HoveredTile {
-                                    index: sprite_idx as usize,
-                                    address: 0xFE00 + (sprite_idx as u16 * 4), // OAM starts at 0xFE00
-                                    screen_x: *x,
-                                    screen_y: *y,
-                                    tab: ViewerTab::Oam,
-                                });
-                                return true;
-                            }
-                        }
-                    },
-                    _ => {
-                        // Clear hover state for other tabs
-                        self.options.hovered_tile = None;
-                    }
+                // Clicking an item pins it to the sidebar; clicking it again unpins it
+                if event.get_window_id() == Some(self.canvas.window().id())
+                    && let Some(item) = self.item_at(*x, *y)
+                {
+                    self.toggle_pin(&item);
+                    return true;
                 }
-                // Clear hover state if not hovering over a valid tile
-                self.options.hovered_tile = None;
                 false
             },
-            Event::KeyDown { keycode: Some(Keycode::G), .. } => {
-                // Toggle grid
-                self.options.show_grid = !self.options.show_grid;
-                true
+            Event::MouseMotion { x, y, .. } => {
+                self.options.cursor = None;
+                self.options.hovered_tile = self.item_at(*x, *y);
+                self.options.hovered_tile.is_some()
             },
-            Event::KeyDown { keycode: Some(Keycode::P), .. } => {
-                // Toggle palettes
-                self.options.show_palettes = !self.options.show_palettes;
+            Event::MouseButtonDown { mouse_btn: sdl2::mouse::MouseButton::Right, window_id, .. }
+                if *window_id == self.canvas.window().id() =>
+            {
+                self.options.pinned = None;
                 true
             },
-            Event::KeyDown { keycode: Some(Keycode::M), .. } => {
-                // Toggle background map (0x9800 or 0x9C00)
-                self.options.bg_map_offset = if self.options.bg_map_offset == 0x9800 { 0x9C00 } else { 0x9800 };
-                true
+            Event::KeyDown { keycode: Some(key), keymod, repeat, window_id, .. } if *window_id == self.canvas.window().id() => {
+                self.handle_key(*key, *keymod, *repeat)
             },
             Event::Window { win_event: sdl2::event::WindowEvent::Close, .. } => {
                 self.toggle();
@@ -240,13 +209,136 @@ impl VramViewer {
         }
     }
     
-    // Update method
-    pub fn update(&mut self, ppu: &Ppu) -> Result<(), String> {
-        // Check if viewer is open
+    // Keys while the viewer has focus; returns true if the key was used
+    fn handle_key(&mut self, key: Keycode, keymod: Mod, repeat: bool) -> bool {
+        if key == self.keys.grid {
+            self.options.show_grid = !self.options.show_grid;
+        } else if key == self.keys.palettes {
+            self.options.show_palettes = !self.options.show_palettes;
+        } else if key == self.keys.map {
+            // Toggle background map (0x9800 or 0x9C00)
+            self.options.bg_map_offset = if self.options.bg_map_offset == 0x9800 { 0x9C00 } else { 0x9800 };
+        } else {
+            match key {
+                // Screenshot of the current tab
+                Keycode::F12 if !repeat => self.options.screenshot_requested = true,
+                // The palettes in use, for art tools
+                Keycode::X if !repeat => match self.ppu.as_deref().map(export_palettes) {
+                    Some(Ok(path)) => eprintln!("Saved palettes to {}.pal and .json", path),
+                    Some(Err(e)) => eprintln!("Failed to export palettes: {}", e),
+                    None => {},
+                },
+                Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5 => {
+                    self.select_tab(TABS[(key as i32 - Keycode::Num1 as i32) as usize]);
+                },
+                Keycode::Tab => {
+                    let current = TABS.iter().position(|&tab| tab == self.options.current_tab).unwrap_or(0);
+                    let step = if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) { TABS.len() - 1 } else { 1 };
+                    self.select_tab(TABS[(current + step) % TABS.len()]);
+                },
+                Keycode::Left => self.move_cursor(-1, 0),
+                Keycode::Right => self.move_cursor(1, 0),
+                Keycode::Up => self.move_cursor(0, -1),
+                Keycode::Down => self.move_cursor(0, 1),
+                Keycode::Return | Keycode::Space => {
+                    if let Some(item) = self.options.cursor.and_then(|cell| self.item_in_cell(cell)) {
+                        self.toggle_pin(&item);
+                    }
+                },
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    fn select_tab(&mut self, tab: ViewerTab) {
+        if tab != self.options.current_tab {
+            self.options.current_tab = tab;
+            self.options.cursor = None;
+            self.options.hovered_tile = None;
+        }
+    }
+
+    // Move the keyboard cursor over the current tab's grid, showing the item under it
+    fn move_cursor(&mut self, dx: i32, dy: i32) {
+        let (columns, rows) = self.options.current_tab.grid_size();
+        if columns == 0 {
+            return;
+        }
+        let (column, row) = match self.options.cursor {
+            Some((column, row)) => ((column as i32 + dx).clamp(0, columns as i32 - 1) as u32, (row as i32 + dy).clamp(0, rows as i32 - 1) as u32),
+            None => (0, 0),
+        };
+        self.options.cursor = Some((column, row));
+        self.options.hovered_tile = self.item_in_cell((column, row));
+    }
+
+    fn item_in_cell(&self, (column, row): (u32, u32)) -> Option<HoveredTile> {
+        let size = TILE_WIDTH * TILE_DISPLAY_SCALE;
+        self.item_at((column * size + size / 2) as i32, 30 + (row * size + size / 2) as i32)
+    }
+
+    // Pin an item to the sidebar, or unpin it if it already is
+    fn toggle_pin(&mut self, item: &HoveredTile) {
+        let pinned = match item.tab {
+            ViewerTab::Tiles => Pinned::Tile(item.index),
+            ViewerTab::Oam => Pinned::Sprite(item.index),
+            _ => Pinned::MapEntry(item.address),
+        };
+        self.options.pinned = (self.options.pinned != Some(pinned)).then_some(pinned);
+    }
+
+    // The tile, map entry or sprite under the mouse on the current tab
+    fn item_at(&self, x: i32, y: i32) -> Option<HoveredTile> {
+        if y < 30 { // Tabs, above the content
+            return None;
+        }
+        let content_y = y - 30; // Adjust for tab height
+
+        // Calculate tile position
+        let tile_x = x as u32 / (TILE_WIDTH * TILE_DISPLAY_SCALE);
+        let tile_y = content_y as u32 / (TILE_HEIGHT * TILE_DISPLAY_SCALE);
+        let (index, address) = match self.options.current_tab {
+            ViewerTab::BgMap if tile_x < BG_MAP_WIDTH && tile_y < BG_MAP_HEIGHT => {
+                let map_idx = tile_y * BG_MAP_WIDTH + tile_x;
+                (map_idx as usize, self.options.bg_map_offset + map_idx as u16)
+            },
+            ViewerTab::Tiles if tile_x < GRID_WIDTH && tile_y * GRID_WIDTH + tile_x < 384 => {
+                let tile_idx = (tile_y * GRID_WIDTH + tile_x) as usize;
+                (tile_idx, 0x8000 + (tile_idx as u16) * 16)
+            },
+            // 10x4 grid of sprites; OAM starts at 0xFE00
+            ViewerTab::Oam if tile_x < 10 && tile_y < 4 => {
+                let sprite_idx = (tile_y * 10 + tile_x) as usize;
+                (sprite_idx, 0xFE00 + (sprite_idx as u16 * 4))
+            },
+            _ => return None,
+        };
+        Some(HoveredTile { index, address, screen_x: x, screen_y: y, tab: self.options.current_tab })
+    }
+
+    // Redraw with a new snapshot, or with the last one after input; otherwise there is nothing to do
+    pub fn update(&mut self, ppu: Option<Box<Ppu>>, wave: Option<([u8; 16], Option<u8>)>) -> Result<(), String> {
         if !self.is_open {
             return Ok(());
         }
-        
+        if let Some((wave_ram, wave_position)) = wave {
+            self.wave_ram = wave_ram;
+            self.wave_position = wave_position;
+        }
+        if ppu.is_some() {
+            self.ppu = ppu;
+        } else if !self.dirty {
+            return Ok(());
+        }
+        self.dirty = false;
+        let Some(ppu) = self.ppu.take() else { return Ok(()) };
+        let result = self.render(&ppu);
+        self.ppu = Some(ppu);
+        result
+    }
+
+    fn render(&mut self, ppu: &Ppu) -> Result<(), String> {
         // Clear the canvas
         self.canvas.set_draw_color(Color::RGB(240, 240, 240));
         self.canvas.clear();
@@ -257,6 +349,7 @@ impl VramViewer {
             ViewerTab::Tiles => self.render_tiles(ppu)?,
             ViewerTab::Oam => self.render_oam(ppu)?,
             ViewerTab::Palettes => self.render_palettes(ppu)?,
+            ViewerTab::Wave => self.render_wave()?,
         }
         
         // Render tab buttons
@@ -264,8 +357,25 @@ impl VramViewer {
         
         // Render sidebar info
         self.render_sidebar(ppu)?;
+        if let Some(pinned) = self.options.pinned {
+            self.render_pinned(pinned, ppu)?;
+        }
 
-        // Draw tooltip if a tile is being hovered
+        // Capture before the tooltip goes on top
+        if self.options.screenshot_requested {
+            self.options.screenshot_requested = false;
+            match self.save_screenshot(ppu) {
+                Ok(path) => eprintln!("Saved VRAM screenshot to {}", path),
+                Err(e) => eprintln!("Failed to save VRAM screenshot: {}", e),
+            }
+        }
+
+        // Keyboard cursor, then the tooltip for the item under it or the mouse
+        if let Some((column, row)) = self.options.cursor {
+            let size = TILE_WIDTH * TILE_DISPLAY_SCALE;
+            self.canvas.set_draw_color(Color::RGB(0, 90, 220));
+            self.canvas.draw_rect(Rect::new((column * size) as i32, 30 + (row * size) as i32, size + 1, size + 1))?;
+        }
         if self.options.hovered_tile.is_some() {
             self.draw_tile_tooltip()?;
         }
@@ -311,6 +421,7 @@ impl VramViewer {
                 ViewerTab::BgMap => "BG Map",
                 ViewerTab::Oam => "Sprite",
                 ViewerTab::Palettes => "Palette",
+                ViewerTab::Wave => "Wave",
             };
             
             // Format the tooltip text based on the tab
@@ -326,7 +437,7 @@ impl VramViewer {
     }
     
     fn render_tabs(&mut self) -> Result<(), String> {
-        let tabs = ["BG map", "Tiles", "OAM", "Palettes"];
+        let tabs = ["BG map", "Tiles", "OAM", "Palettes", "Wave"];
         let tab_width = 80;
         let tab_height = 25;
         let tab_padding = 5;
@@ -337,6 +448,7 @@ impl VramViewer {
                 1 => self.options.current_tab == ViewerTab::Tiles,
                 2 => self.options.current_tab == ViewerTab::Oam,
                 3 => self.options.current_tab == ViewerTab::Palettes,
+                4 => self.options.current_tab == ViewerTab::Wave,
                 _ => false,
             };
             
@@ -472,6 +584,29 @@ impl VramViewer {
                 checkbox_y += 20;
                 self.draw_text(&format!("WX: 0x{:02X}", ppu.wx), 
                               sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
+
+                // Window internals, to tell why a HUD bar is missing
+                checkbox_y += 20;
+                self.draw_text(&format!("Win line: {}", ppu.window_line()),
+                              sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
+
+                checkbox_y += 20;
+                self.draw_text(&format!("Win triggered: {}", if ppu.window_triggered() { "Yes" } else { "No" }),
+                              sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
+
+                checkbox_y += 20;
+                self.draw_text(&format!("Win active: {}", if ppu.window_active() { "Yes" } else { "No" }),
+                              sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
+
+                let stats = ppu.window_stats();
+                checkbox_y += 20;
+                self.draw_text(&format!("Win lines: {} drawn", stats.lines_drawn),
+                              sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
+                if stats.lines_skipped > 0 {
+                    checkbox_y += 20;
+                    self.draw_text(&format!("Win skipped: {} lines", stats.lines_skipped),
+                                  sidebar_x + 10, checkbox_y, Color::RGB(160, 0, 0))?;
+                }
             },
             ViewerTab::Tiles => {
                 // Show tile info
@@ -495,6 +630,23 @@ impl VramViewer {
                 self.draw_text(&format!("Sprites enabled: {}", 
                                       if ppu.lcdc & 0x02 != 0 { "Yes" } else { "No" }),
                               sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
+                
+                // Mid-transfer OAM reads as 0xFF, which explains an empty-looking sprite list
+                checkbox_y += 20;
+                match ppu.oam_dma() {
+                    Some(dma) => {
+                        let state = if dma.starting { "starting" } else { "running" };
+                        self.draw_text(&format!("DMA {} from 0x{:04X}", state, dma.source),
+                                      sidebar_x + 10, checkbox_y, Color::RGB(160, 0, 0))?;
+                        checkbox_y += 20;
+                        self.draw_text(&format!("Bytes: {}/160", dma.bytes_transferred),
+                                      sidebar_x + 10, checkbox_y, Color::RGB(160, 0, 0))?;
+                        checkbox_y += 20;
+                        self.draw_text(&format!("Cycles left: {}", dma.remaining_cycles),
+                                      sidebar_x + 10, checkbox_y, Color::RGB(160, 0, 0))?;
+                    },
+                    None => self.draw_text("DMA: idle", sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?,
+                }
             },
             ViewerTab::Palettes => {
                 // Show palette info
@@ -512,11 +664,129 @@ impl VramViewer {
                 self.draw_text(&format!("OBP1: 0x{:02X}", ppu.obp1), 
                               sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
             },
+            ViewerTab::Wave => {
+                self.draw_text("Wave RAM FF30-FF3F", sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
+                let wave_ram = self.wave_ram;
+                for half in wave_ram.chunks(8) {
+                    checkbox_y += 20;
+                    let bytes: Vec<String> = half.iter().map(|byte| format!("{:02X}", byte)).collect();
+                    self.draw_text(&bytes.join(" "), sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
+                }
+                checkbox_y += 30;
+                let playing = match self.wave_position {
+                    Some(position) => format!("Playing sample {}", position),
+                    None => "Channel 3 off".to_string(),
+                };
+                self.draw_text(&playing, sidebar_x + 10, checkbox_y, Color::RGB(0, 0, 0))?;
+            },
         }
         
         Ok(())
     }
     
+    // Details and a live preview of the pinned item at the bottom of the sidebar, and an outline
+    // around it when its tab is shown
+    fn render_pinned(&mut self, pinned: Pinned, ppu: &Ppu) -> Result<(), String> {
+        let (window_width, window_height) = self.canvas.window().size();
+        let x = window_width as i32 - SIDEBAR_WIDTH as i32 + 10;
+        let top = window_height as i32 - PINNED_PANEL_HEIGHT;
+        let tall_sprites = ppu.lcdc & 0x04 != 0;
+
+        // (tab, cell in the tab's grid), tile data addresses to preview, text lines
+        let (cell, tiles, lines) = match pinned {
+            Pinned::Tile(index) => {
+                // Index a BG map entry needs to show this tile under the current LCDC
+                let bg_index = match index {
+                    0..=127 if ppu.lcdc & 0x10 != 0 => Some(index as u8),
+                    128..=255 => Some(index as u8),
+                    256.. if ppu.lcdc & 0x10 == 0 => Some((index - 256) as u8),
+                    _ => None,
+                };
+                let sprites = ppu.oam_entries.iter()
+                    .filter(|sprite| index < 256 && (sprite.tile_idx as usize == index || (tall_sprites && (sprite.tile_idx as usize | 1) == (index | 1))))
+                    .count();
+                let lines = vec![
+                    format!("Tile #{}", index),
+                    format!("Addr: ${:04X}", 0x8000 + index * 16),
+                    match bg_index {
+                        Some(bg_index) => format!("BG index: ${:02X}", bg_index),
+                        None => "BG index: none".to_string(),
+                    },
+                    format!("Sprites: {}", sprites),
+                ];
+                let cell = (ViewerTab::Tiles, index as u32 % GRID_WIDTH, index as u32 / GRID_WIDTH);
+                (Some(cell), vec![0x8000 + index as u16 * 16], lines)
+            },
+            Pinned::MapEntry(address) => {
+                let tile_index = ppu.vram_snapshot()[(address & 0x1FFF) as usize];
+                let tile = if ppu.lcdc & 0x10 != 0 { tile_index as u16 } else { (256 + tile_index as i8 as i16) as u16 };
+                let offset = (address & 0x3FF) as u32;
+                let lines = vec![
+                    format!("Map entry ${:04X}", address),
+                    format!("Col {} Row {}", offset % BG_MAP_WIDTH, offset / BG_MAP_WIDTH),
+                    format!("Tile index: ${:02X}", tile_index),
+                    format!("Tile addr: ${:04X}", 0x8000 + tile * 16),
+                ];
+                let shown = address & 0xFC00 == self.options.bg_map_offset;
+                let cell = shown.then_some((ViewerTab::BgMap, offset % BG_MAP_WIDTH, offset / BG_MAP_WIDTH));
+                (cell, vec![0x8000 + tile * 16], lines)
+            },
+            Pinned::Sprite(index) => {
+                let sprite = ppu.oam_entries[index];
+                let mut tiles = vec![0x8000 + sprite.tile_idx as u16 * 16];
+                if tall_sprites {
+                    tiles = vec![0x8000 + (sprite.tile_idx & 0xFE) as u16 * 16, 0x8000 + (sprite.tile_idx | 0x01) as u16 * 16];
+                }
+                let flags: Vec<&str> = [(0x20, "FlipX"), (0x40, "FlipY"), (0x80, "Behind"), (0x10, "OBP1")]
+                    .iter()
+                    .filter(|(bit, _)| sprite.attributes & bit != 0)
+                    .map(|&(_, name)| name)
+                    .collect();
+                let lines = vec![
+                    format!("Sprite #{} ${:04X}", index, 0xFE00 + index * 4),
+                    format!("X: {} Y: {}", sprite.x_pos, sprite.y_pos),
+                    format!("Tile: ${:02X}", sprite.tile_idx),
+                    format!("Attr: ${:02X}", sprite.attributes),
+                    flags.join(" "),
+                ];
+                (Some((ViewerTab::Oam, index as u32 % 10, index as u32 / 10)), tiles, lines)
+            },
+        };
+
+        self.draw_text("Pinned (right click clears)", x, top, Color::RGB(0, 0, 160))?;
+        for (i, line) in lines.iter().enumerate() {
+            self.draw_text(line, x, top + 15 + i as i32 * 12, Color::RGB(0, 0, 0))?;
+        }
+
+        // Live preview, right of the text
+        let mut texture = self.texture_creator.create_texture_streaming(
+            PixelFormatEnum::RGB24,
+            TILE_WIDTH,
+            TILE_HEIGHT * tiles.len() as u32
+        ).map_err(|e| e.to_string())?;
+        texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
+            for (i, &tile_addr) in tiles.iter().enumerate() {
+                self.draw_tile(buffer, pitch, tile_addr, 0, i as u32 * TILE_HEIGHT, ppu);
+            }
+        })?;
+        let preview_height = 48;
+        let preview_width = preview_height / tiles.len() as u32;
+        let preview = Rect::new(x + SIDEBAR_WIDTH as i32 - 20 - preview_width as i32, top + 15, preview_width, preview_height);
+        self.canvas.copy(&texture, None, preview)?;
+        self.canvas.set_draw_color(Color::RGB(100, 100, 100));
+        self.canvas.draw_rect(preview)?;
+
+        // Outline the item in its grid
+        if let Some((tab, column, row)) = cell
+            && tab == self.options.current_tab
+        {
+            let size = TILE_WIDTH * TILE_DISPLAY_SCALE;
+            self.canvas.set_draw_color(Color::RGB(220, 0, 0));
+            self.canvas.draw_rect(Rect::new((column * size) as i32, 30 + (row * size) as i32, size + 1, size + 1))?;
+        }
+        Ok(())
+    }
+
     fn render_bg_map(&mut self, ppu: &Ppu) -> Result<(), String> {
         // Create a texture to hold the entire map
         let mut texture = self.texture_creator.create_texture_streaming(
@@ -526,12 +796,13 @@ impl VramViewer {
         ).unwrap();
         
         // Update the texture with the BG map data
+        let vram = ppu.vram_snapshot();
         texture.with_lock(None, |buffer: &mut [u8], pitch: usize| {
             for y in 0..BG_MAP_HEIGHT {
                 for x in 0..BG_MAP_WIDTH {
                     // Calculate map address and fetch tile index
                     let map_addr = self.options.bg_map_offset + y as u16 * 32 + x as u16;
-                    let tile_index = ppu.read_vram(map_addr as u16);
+                    let tile_index = vram[(map_addr & 0x1FFF) as usize];
                     
                     // Get tile data address - handle both addressing modes correctly
                     // This is crucial for proper rendering
@@ -553,8 +824,8 @@ impl VramViewer {
                         buffer,
                         pitch,
                         tile_data_addr,
-                        x as u32 * TILE_WIDTH,
-                        y as u32 * TILE_HEIGHT,
+                        x * TILE_WIDTH,
+                        y * TILE_HEIGHT,
                         ppu
                     );
                 }
@@ -608,8 +879,8 @@ impl VramViewer {
     
     fn render_tiles(&mut self, ppu: &Ppu) -> Result<(), String> {
         // Calculate number of tiles to display and create texture
-        let num_tiles = 384; // 384 tiles total (half in each bank)
-        let rows = (num_tiles + GRID_WIDTH as usize - 1) / GRID_WIDTH as usize;
+        let num_tiles: usize = 384; // 384 tiles total (half in each bank)
+        let rows = num_tiles.div_ceil(GRID_WIDTH as usize);
         
         let mut texture = self.texture_creator.create_texture_streaming(
             PixelFormatEnum::RGB24,
@@ -776,10 +1047,65 @@ impl VramViewer {
         Ok(())
     }
     
+    // The 32 4-bit samples of wave RAM as one period of the waveform, high nibble of each byte
+    // first, with the sample being played shaded
+    fn render_wave(&mut self) -> Result<(), String> {
+        const LEFT: i32 = 20;
+        const TOP: i32 = 50;
+        const SAMPLE_WIDTH: i32 = 15;
+        const LEVEL_HEIGHT: i32 = 12;
+        let width = 32 * SAMPLE_WIDTH;
+        let height = 16 * LEVEL_HEIGHT;
+        let samples: Vec<u8> = self.wave_ram.iter().flat_map(|byte| [byte >> 4, byte & 0x0F]).collect();
+
+        self.canvas.set_draw_color(Color::RGB(255, 255, 255));
+        self.canvas.fill_rect(Rect::new(LEFT, TOP, width as u32, height as u32))?;
+        if let Some(position) = self.wave_position {
+            self.canvas.set_draw_color(Color::RGB(255, 236, 160));
+            self.canvas.fill_rect(Rect::new(LEFT + position as i32 * SAMPLE_WIDTH, TOP, SAMPLE_WIDTH as u32, height as u32))?;
+        }
+        if self.options.show_grid {
+            self.canvas.set_draw_color(Color::RGB(220, 220, 220));
+            for level in (4..16).step_by(4) {
+                let y = TOP + height - level * LEVEL_HEIGHT;
+                self.canvas.draw_line((LEFT, y), (LEFT + width - 1, y))?;
+            }
+            for sample in (8..32).step_by(8) {
+                let x = LEFT + sample * SAMPLE_WIDTH;
+                self.canvas.draw_line((x, TOP), (x, TOP + height - 1))?;
+            }
+        }
+        self.canvas.set_draw_color(Color::RGB(100, 100, 100));
+        self.canvas.draw_rect(Rect::new(LEFT, TOP, width as u32, height as u32))?;
+
+        // A step for each sample, joined to the next, two pixels thick
+        self.canvas.set_draw_color(Color::RGB(52, 104, 86));
+        let level_y = |sample: u8| TOP + height - LEVEL_HEIGHT / 2 - sample as i32 * LEVEL_HEIGHT;
+        for (index, &sample) in samples.iter().enumerate() {
+            let x = LEFT + index as i32 * SAMPLE_WIDTH;
+            let y = level_y(sample);
+            self.canvas.fill_rect(Rect::new(x, y - 1, SAMPLE_WIDTH as u32, 2))?;
+            if let Some(&next) = samples.get(index + 1) {
+                let next_y = level_y(next);
+                self.canvas.fill_rect(Rect::new(x + SAMPLE_WIDTH - 1, y.min(next_y) - 1, 2, (y - next_y).unsigned_abs() + 2))?;
+            }
+        }
+
+        // Sample values under the graph, and the level scale to its left
+        for (index, &sample) in samples.iter().enumerate() {
+            let x = LEFT + index as i32 * SAMPLE_WIDTH + SAMPLE_WIDTH / 2 - font::ADVANCE as i32 / 2;
+            self.draw_text(&format!("{:X}", sample), x, TOP + height + 6, Color::RGB(0, 0, 0))?;
+        }
+        for level in [0, 15] {
+            self.draw_text(&format!("{:X}", level), LEFT - 12, level_y(level) - font::GLYPH_HEIGHT as i32 / 2, Color::RGB(0, 0, 0))?;
+        }
+        Ok(())
+    }
+
     fn draw_dmg_palette(&mut self, palette: u8, name: &str, x: i32, y: i32, width: u32, height: u32) -> Result<(), String> {
         // Calculate the four colors in the palette
         let colors = [
-            self.get_dmg_color((palette >> 0) & 0x3),
+            self.get_dmg_color(palette & 0x3),
             self.get_dmg_color((palette >> 2) & 0x3),
             self.get_dmg_color((palette >> 4) & 0x3),
             self.get_dmg_color((palette >> 6) & 0x3),
@@ -788,7 +1114,7 @@ impl VramViewer {
         // Draw each color square
         let square_width = width / 4;
         for i in 0..4 {
-            let square_x = x + (i as i32 * square_width as i32);
+            let square_x = x + (i * square_width as i32);
             let square_rect = Rect::new(square_x, y, square_width, height);
             
             self.canvas.set_draw_color(colors[i as usize]);
@@ -818,7 +1144,7 @@ impl VramViewer {
     
     fn draw_tile(&self, buffer: &mut [u8], pitch: usize, tile_addr: u16, x: u32, y: u32, ppu: &Ppu) {
         // Ensure we're within the bounds of VRAM
-        if tile_addr < 0x8000 || tile_addr >= 0x9800 {
+        if !(0x8000..0x9800).contains(&tile_addr) {
             // Invalid tile address, fill with a red pattern to indicate an error
             for row in 0..8 {
                 for col in 0..8 {
@@ -843,19 +1169,11 @@ impl VramViewer {
         }
         
         // Draw the 8x8 tile
+        let tile = ppu.tile(((tile_addr - 0x8000) / 16) as usize);
         for row in 0..8 {
-            // Get the two bytes that define this row of the tile
-            let low_byte = ppu.read_vram(tile_addr + (row * 2) as u16);
-            let high_byte = ppu.read_vram(tile_addr + (row * 2 + 1) as u16);
-            
             // Render all 8 pixels in this row
             for col in 0..8 {
-                // For each pixel, combine bits from both data bytes
-                // The bits are in MSB order (leftmost pixel is highest bit)
-                let bit_position = 7 - col;
-                let low_bit = (low_byte >> bit_position) & 0x01;
-                let high_bit = (high_byte >> bit_position) & 0x01;
-                let color_idx = (high_bit << 1) | low_bit;
+                let color_idx = tile[row as usize][col as usize];
                 
                 // Apply palette - convert color index (0-3) to actual gray shade
                 let gb_color = (ppu.bgp >> (color_idx * 2)) & 0x03;
@@ -896,123 +1214,80 @@ impl VramViewer {
         }
     }
 
+    // Write the tab's content area (without tabs and sidebar) to a PNG in the working directory,
+    // under a header with the LCD registers
+    fn save_screenshot(&mut self, ppu: &Ppu) -> Result<String, String> {
+        let (window_width, window_height) = self.canvas.output_size()?;
+        let content = Rect::new(0, 30, window_width - SIDEBAR_WIDTH, window_height - 30);
+        let pixels = self.canvas.read_pixels(content, PixelFormatEnum::RGBA32)?;
+
+        let (tab_name, slug) = match self.options.current_tab {
+            ViewerTab::BgMap => (format!("BG map {:04X}", self.options.bg_map_offset), "bgmap"),
+            ViewerTab::Tiles => ("Tiles 8000-97FF".to_string(), "tiles"),
+            ViewerTab::Oam => ("OAM".to_string(), "oam"),
+            ViewerTab::Palettes => ("Palettes".to_string(), "palettes"),
+            ViewerTab::Wave => ("Wave RAM FF30-FF3F".to_string(), "wave"),
+        };
+        let header = [
+            tab_name,
+            format!("LCDC:{:02X} STAT:{:02X} LY:{:02X} LYC:{:02X} SCX:{:02X} SCY:{:02X} WX:{:02X} WY:{:02X}",
+                ppu.lcdc, ppu.stat, ppu.ly, ppu.lyc, ppu.scx, ppu.scy, ppu.wx, ppu.wy),
+            format!("BGP:{:02X} OBP0:{:02X} OBP1:{:02X}", ppu.bgp, ppu.obp0, ppu.obp1),
+        ];
+
+        // Header lines on white, then the content
+        let width = content.width() as usize;
+        let header_height = SCREENSHOT_HEADER_LINES * 10 + 4;
+        let mut image = vec![0xFF; width * header_height * 4];
+        for (line, text) in header.iter().enumerate() {
+            font::draw_text_rgba(&mut image, width, 4, 4 + line as i32 * 10, text, [0x00, 0x00, 0x00, 0xFF]);
+        }
+        image.extend_from_slice(&pixels);
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        let path = format!("vram-{}-{}.png", slug, timestamp);
+        let file = File::create(&path).map_err(|e| e.to_string())?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, (image.len() / (width * 4)) as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(&image).map_err(|e| e.to_string())?;
+        Ok(path)
+    }
+
     fn draw_text(&mut self, text: &str, x: i32, y: i32, color: Color) -> Result<(), String> {
-        // Simple 5x7 bitmap font implementation for VRAM viewer
-        // Each character is represented as a series of bits in a 5x7 grid
-        
-        // Define a simple font for the basic characters we need
-        let font_data: std::collections::HashMap<char, [u8; 7]> = [
-            // Each value represents a row of 5 pixels (1=on, 0=off)
-            ('A', [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b00000]),
-            ('B', [0b11110, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110, 0b00000]),
-            ('C', [0b01110, 0b10001, 0b10000, 0b10000, 0b10001, 0b01110, 0b00000]),
-            ('D', [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110, 0b00000]),
-            ('E', [0b11111, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111, 0b00000]),
-            ('F', [0b11111, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000, 0b00000]),
-            ('G', [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b01111, 0b00000]),
-            ('H', [0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001, 0b00000]),
-            ('I', [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000]),
-            ('J', [0b00111, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100, 0b00000]),
-            ('K', [0b10001, 0b10010, 0b11100, 0b10010, 0b10001, 0b10001, 0b00000]),
-            ('L', [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111, 0b00000]),
-            ('M', [0b10001, 0b11011, 0b10101, 0b10001, 0b10001, 0b10001, 0b00000]),
-            ('N', [0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001, 0b00000]),
-            ('O', [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110, 0b00000]),
-            ('P', [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b00000]),
-            ('Q', [0b01110, 0b10001, 0b10001, 0b10001, 0b10011, 0b01111, 0b00000]),
-            ('R', [0b11110, 0b10001, 0b10001, 0b11110, 0b10010, 0b10001, 0b00000]),
-            ('S', [0b01111, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110, 0b00000]),
-            ('T', [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000]),
-            ('U', [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110, 0b00000]),
-            ('V', [0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00000]),
-            ('W', [0b10001, 0b10001, 0b10001, 0b10101, 0b11011, 0b10001, 0b00000]),
-            ('X', [0b10001, 0b01010, 0b00100, 0b00100, 0b01010, 0b10001, 0b00000]),
-            ('Y', [0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000]),
-            ('Z', [0b11111, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111, 0b00000]),
-            ('0', [0b01110, 0b10011, 0b10101, 0b10101, 0b11001, 0b01110, 0b00000]),
-            ('1', [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000]),
-            ('2', [0b01110, 0b10001, 0b00010, 0b00100, 0b01000, 0b11111, 0b00000]),
-            ('3', [0b01110, 0b10001, 0b00010, 0b00110, 0b10001, 0b01110, 0b00000]),
-            ('4', [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00000]),
-            ('5', [0b11111, 0b10000, 0b11110, 0b00001, 0b10001, 0b01110, 0b00000]),
-            ('6', [0b01110, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110, 0b00000]),
-            ('7', [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b00000]),
-            ('8', [0b01110, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110, 0b00000]),
-            ('9', [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110, 0b00000]),
-            (':', [0b00000, 0b00100, 0b00000, 0b00000, 0b00100, 0b00000, 0b00000]),
-            (' ', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000]),
-            ('.', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00100, 0b00000]),
-            (',', [0b00000, 0b00000, 0b00000, 0b00000, 0b00100, 0b00100, 0b01000]),
-            ('(', [0b00010, 0b00100, 0b01000, 0b01000, 0b00100, 0b00010, 0b00000]),
-            (')', [0b01000, 0b00100, 0b00010, 0b00010, 0b00100, 0b01000, 0b00000]),
-            ('[', [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110, 0b00000]),
-            (']', [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110, 0b00000]),
-            ('+', [0b00000, 0b00100, 0b01110, 0b00100, 0b00000, 0b00000, 0b00000]),
-            ('-', [0b00000, 0b00000, 0b01110, 0b00000, 0b00000, 0b00000, 0b00000]),
-            ('/', [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000]),
-            ('\\', [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000]),
-            ('=', [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000]),
-            ('_', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111, 0b00000]),
-            ('x', [0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b00000]),
-            ('a', [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b01111, 0b00000]),
-            ('b', [0b10000, 0b10000, 0b11110, 0b10001, 0b10001, 0b11110, 0b00000]),
-            ('c', [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b01110, 0b00000]),
-            ('d', [0b00001, 0b00001, 0b01111, 0b10001, 0b10001, 0b01111, 0b00000]),
-            ('e', [0b00000, 0b00000, 0b01110, 0b10001, 0b11110, 0b01111, 0b00000]),
-            ('f', [0b00110, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000, 0b00000]),
-            ('g', [0b00000, 0b00000, 0b01111, 0b10001, 0b01111, 0b00001, 0b01110]),
-            ('h', [0b10000, 0b10000, 0b11110, 0b10001, 0b10001, 0b10001, 0b00000]),
-            ('i', [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b01110, 0b00000]),
-            ('j', [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100]),
-            ('k', [0b10000, 0b10000, 0b10010, 0b11100, 0b10010, 0b10001, 0b00000]),
-            ('l', [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110, 0b00000]),
-            ('m', [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b00000]),
-            ('n', [0b00000, 0b00000, 0b11110, 0b10001, 0b10001, 0b10001, 0b00000]),
-            ('o', [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b01110, 0b00000]),
-            ('p', [0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000]),
-            ('q', [0b00000, 0b00000, 0b01111, 0b10001, 0b01111, 0b00001, 0b00001]),
-            ('r', [0b00000, 0b00000, 0b10110, 0b11000, 0b10000, 0b10000, 0b00000]),
-            ('s', [0b00000, 0b00000, 0b01111, 0b10000, 0b01110, 0b11110, 0b00000]),
-            ('t', [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b00110, 0b00000]),
-            ('u', [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01111, 0b00000]),
-            ('v', [0b00000, 0b00000, 0b10001, 0b10001, 0b01010, 0b00100, 0b00000]),
-            ('w', [0b00000, 0b00000, 0b10001, 0b10101, 0b10101, 0b01010, 0b00000]),
-            ('y', [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01000, 0b10000]),
-            ('z', [0b00000, 0b00000, 0b11111, 0b00010, 0b01100, 0b11111, 0b00000]),
-        ].iter().cloned().collect();
-
-        // Set drawing color
-        self.canvas.set_draw_color(color);
-
-        // Character dimensions
-        let _char_width = 6; // 5 pixels + 1 spacing
-        let _char_height = 8; // 7 pixels + 1 spacing
-        
-        // Draw each character
-        let mut cursor_x = x;
-        for c in text.chars() {
-            // Convert to uppercase for consistency
-            let c_upper = c.to_ascii_uppercase();
-            
-            // Get the bitmap data for this character (or use space if not found)
-            let char_bitmap = font_data.get(&c_upper).unwrap_or(&font_data[&' ']);
-            
-            // Draw the character pixel by pixel
-            for (row, &bitmap_row) in char_bitmap.iter().enumerate() {
-                for col in 0..5 {
-                    let bit = (bitmap_row >> (4 - col)) & 0x01;
-                    if bit == 1 {
-                        let pixel_x = cursor_x + col as i32;
-                        let pixel_y = y + row as i32;
-                        self.canvas.draw_point((pixel_x, pixel_y))?;
-                    }
-                }
-            }
-            
-            // Move cursor to next character position
-            cursor_x += _char_width;
+        font::draw_text(&mut self.canvas, text, x, y, color)
+    }
+}
+
+// Write BGP, OBP0 and OBP1 as the RGB colors they currently produce, to a JASC-PAL file (12
+// entries, four per register, color 0 first) and a JSON file that also has the register values.
+// Returns the path without the extension.
+fn export_palettes(ppu: &Ppu) -> Result<String, String> {
+    let colors = ppu.palette().0;
+    let registers = [("BGP", ppu.bgp), ("OBP0", ppu.obp0), ("OBP1", ppu.obp1)];
+    let shades = |value: u8| [0, 1, 2, 3].map(|index| colors[((value >> (index * 2)) & 0x03) as usize]);
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let path = format!("palettes-{}", timestamp);
+
+    let mut pal = String::from("JASC-PAL\r\n0100\r\n12\r\n");
+    for &(_, value) in &registers {
+        for [r, g, b] in shades(value) {
+            pal += &format!("{} {} {}\r\n", r, g, b);
         }
-        
-        Ok(())
     }
+    std::fs::write(format!("{}.pal", path), pal).map_err(|e| e.to_string())?;
+
+    let entries: Vec<String> = registers
+        .iter()
+        .map(|&(name, value)| {
+            let hex: Vec<String> = shades(value).iter().map(|[r, g, b]| format!("\"#{:02X}{:02X}{:02X}\"", r, g, b)).collect();
+            format!("    {{ \"name\": \"{}\", \"register\": {}, \"colors\": [{}] }}", name, value, hex.join(", "))
+        })
+        .collect();
+    let mut json = BufWriter::new(File::create(format!("{}.json", path)).map_err(|e| e.to_string())?);
+    write!(json, "{{\n  \"palettes\": [\n{}\n  ]\n}}\n", entries.join(",\n")).map_err(|e| e.to_string())?;
+    json.flush().map_err(|e| e.to_string())?;
+    Ok(path)
 }
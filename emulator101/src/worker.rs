@@ -0,0 +1,735 @@
+// Emulation thread
+// The core runs on its own thread and talks to the SDL frontend through channels: input and
+// commands go in, replies come out and frames are published through a triple buffer. Slow
+// presentation or the VRAM viewer on the UI side can only cost dropped frames, never emulation
+// timing.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use emulator101_core::ppu::{PixelSource, Ppu};
+use emulator101_core::savestate::SaveStateError;
+use emulator101_core::clock::Clock;
+use emulator101_core::emulator::CYCLES_PER_FRAME;
+use emulator101_core::{CpuMode, Diagnostic, DiagnosticEvent, Emulator, EmulatorError, EmulatorEvent, Greenzone, JoypadButton, Model, Movie, RtcReading, Severity, Stats};
+
+use crate::audio::AudioQueue;
+use crate::battery::BatterySave;
+use crate::dump;
+use crate::input_script::InputScript;
+use crate::play_time::PlayTimes;
+use crate::ram_search;
+#[cfg(feature = "achievements")]
+use crate::achievements::Achievements;
+#[cfg(feature = "lua")]
+use crate::scripting::Script;
+use crate::triple_buffer::{self, Consumer, Disconnected, Producer};
+
+pub enum Command {
+    SetButton(JoypadButton, bool),
+    // Swap the cartridge and reset as the given model; the previous game's battery save is
+    // written out first and the new one loaded if the game has a battery
+    LoadRom(Vec<u8>, Model, BatterySave),
+    SaveState,
+    LoadState(Vec<u8>),
+    // Machine state as JSON (see dump.rs)
+    DumpState,
+    // Attach a copy of the PPU to every frame (for the VRAM viewer)
+    CapturePpu(bool),
+    // Attach each pixel's color number and palette to every frame (for the magnifier)
+    CapturePixelSources(bool),
+    // Attach a copy of work RAM and high RAM to every frame (for RAM search)
+    CaptureRam(bool),
+    // Attach a copy of cartridge RAM to every frame (for the cartridge RAM editor)
+    CaptureCartRam(bool),
+    // Overwrite cartridge RAM at an offset
+    WriteCartRam(usize, Vec<u8>),
+    SetCheatsEnabled(bool),
+    Freeze(u16, u8),
+    Unfreeze(u16),
+    // Movie editing: jump by this many frames and pause there
+    SeekMovie(isize),
+    // Run a single frame of a paused movie
+    AdvanceMovie,
+    ResumeMovie,
+    // Latency test input sent at this time; answered with a white frame
+    LatencyProbe(Instant),
+    // Debug pause: hold emulation wherever it is, mid-frame included
+    SetPaused(bool),
+    // Run a little while paused; the frame published afterwards is drawn up to where the PPU is
+    Step(Step),
+    // Move the cartridge clock by this many seconds (back when negative)
+    ShiftRtc(i64),
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Instruction,
+    Scanline,
+}
+
+pub enum Reply {
+    StateSaved(Vec<u8>),
+    StateLoaded(Result<(), SaveStateError>),
+    StateDump(String),
+    Error(EmulatorError),
+    // The script failed to load or raised an error; it is disabled afterwards
+    ScriptError(String),
+    // Something the user should know about the movie (playback ended, recording stopped, ...)
+    Movie(String),
+    // The input script is over
+    InputScriptFinished(u64),
+    Diagnostic(DiagnosticEvent),
+    // The cartridge clock after a ShiftRtc, None without one
+    Rtc(Option<RtcReading>),
+    // Achievements loaded or unlocked, or why they couldn't be loaded
+    Achievement(String),
+}
+
+// How the emulation thread paces frames
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pacing {
+    // A fixed duration per frame (1/60 s, matching most displays)
+    Fixed(Duration),
+    // Real time of the cycles each frame actually ran, from the emulated clock: about 59.7275
+    // frames per second, so audio and link timing don't drift
+    Hardware,
+}
+
+// Settings the emulation thread starts with
+pub struct Config {
+    pub pacing: Pacing,
+    pub script: Option<PathBuf>,
+    // Movie for the first emulator, already started (see Movie::start)
+    pub movie: Option<MovieFile>,
+    // Where the first emulator's opcode coverage report is written when the thread stops
+    pub opcode_coverage: Option<PathBuf>,
+    // Battery save of each emulator, in spawn order (None for games without a battery); the
+    // saves are expected to be loaded already
+    pub batteries: Vec<Option<BatterySave>>,
+    // How often changed battery saves are written while running; None only writes them when the
+    // thread stops or the ROM changes
+    pub battery_flush: Option<Duration>,
+    // Achievement definitions for the first emulator (see achievements.rs)
+    pub achievements: Option<PathBuf>,
+    // Buttons for the first emulator from launch (see input_script.rs)
+    pub input_script: Option<InputScript>,
+    // Least severe diagnostic passed on, None for none; each is sent once per instruction
+    pub diagnostics: Option<Severity>,
+    // Where every emulator's play time is kept, None to not track it
+    pub play_times: Option<PlayTimes>,
+    // Where the first emulator's sound goes, None to run silent
+    pub audio: Option<AudioQueue>,
+}
+
+// A movie and where it is written back to when the emulation thread stops
+pub struct MovieFile {
+    pub movie: Movie,
+    pub path: PathBuf,
+}
+
+// Greenzone: a state every quarter second, covering the last two minutes or so, in at most 32 MiB
+const GREENZONE_INTERVAL: usize = 15;
+const GREENZONE_CAPACITY: usize = 512;
+const GREENZONE_BUDGET: usize = 32 << 20;
+
+// How often play time is written while running
+const PLAY_TIME_INTERVAL: Duration = Duration::from_secs(60);
+
+// The VRAM viewer redraws at about 15 Hz; copying the PPU for every frame would be wasted
+const PPU_CAPTURE_INTERVAL: Duration = Duration::from_millis(66);
+
+// A movie being played back or recorded. Frames it has inputs for are played back; after the
+// last one the held buttons are recorded. Loading a quicksave made during the movie rewinds it
+// to that frame and records from there.
+struct MovieState {
+    file: MovieFile,
+    // Next frame to run
+    frame: usize,
+    // Frame of the last quicksave
+    saved_frame: Option<usize>,
+    // Past the recorded inputs
+    recording: bool,
+    greenzone: Greenzone,
+    // Paused after a seek; `advance` runs one more frame
+    paused: bool,
+    advance: bool,
+}
+
+impl MovieState {
+    // Apply or record the input for the next frame
+    fn next_frame(&mut self, emulator: &mut Emulator, replies: &Sender<Reply>) {
+        self.greenzone.capture(self.frame, emulator);
+        let movie = &mut self.file.movie;
+        match movie.input(self.frame) {
+            Some(buttons) => emulator.set_buttons(buttons),
+            None => {
+                if !self.recording {
+                    self.recording = true;
+                    if self.frame > 0 {
+                        let _ = replies.send(Reply::Movie(format!("playback finished at frame {}, recording from here", self.frame)));
+                    }
+                }
+                movie.push(emulator.buttons());
+            },
+        }
+        self.frame += 1;
+    }
+
+    // Jump `delta` frames from the current one (clamped to the recording) and pause there
+    fn seek(&mut self, emulator: &mut Emulator, delta: isize) -> Result<usize, String> {
+        let target = self.frame.saturating_add_signed(delta);
+        let frame = self.file.movie.seek(emulator, &mut self.greenzone, target).map_err(|e| e.to_string())?;
+        self.frame = frame;
+        self.recording = frame >= self.file.movie.len();
+        self.paused = true;
+        Ok(frame)
+    }
+
+    fn save(&self) -> std::io::Result<()> {
+        std::fs::write(&self.file.path, self.file.movie.to_bytes())
+    }
+}
+
+#[derive(Default)]
+pub struct Frame {
+    pub pixels: Vec<u8>,
+    // Generation of the emulator's picture in `pixels` (see FrameBuffer); None when this thread
+    // drew over it
+    pub generation: Option<u64>,
+    pub ppu: Option<Box<Ppu>>,
+    // Wave RAM (0xFF30-0xFF3F) and the sample channel 3 is playing, taken along with `ppu`
+    pub wave_ram: Option<[u8; 16]>,
+    pub wave_position: Option<u8>,
+    // Empty unless captured, like `ppu`
+    pub pixel_sources: Vec<PixelSource>,
+    pub ram: Option<Vec<u8>>,
+    pub cart_ram: Option<Vec<u8>>,
+    // RAM bank mapped at 0xA000 when the frame was taken
+    pub cart_ram_bank: Option<usize>,
+    pub stats: Stats,
+    // PPU position when the frame was taken: LY and the column being drawn (see Ppu::beam_position)
+    pub beam: (u8, usize),
+    // Whether the CPU was running, halted, stopped or switching speed
+    pub cpu_mode: CpuMode,
+    // Latency probe this frame answers: (time the probe was sent, time the frame was published)
+    pub probe: Option<(Instant, Instant)>,
+    // The cartridge's rumble motor ran during the frame
+    pub rumble: bool,
+}
+
+pub struct EmulatorThread {
+    commands: Sender<Command>,
+    frames: Consumer<Frame>,
+    replies: Receiver<Reply>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl EmulatorThread {
+    // Must be called from the thread that takes the frames
+    pub fn spawn(emulator: Emulator, config: Config) -> std::io::Result<Self> {
+        Ok(Self::spawn_all(vec![emulator], config)?.remove(0))
+    }
+
+    // Run two emulators on a link cable on one thread, in lockstep. Each gets its own handle;
+    // dropping either stops both. The script, if any, runs on the first.
+    pub fn spawn_linked(first: Emulator, second: Emulator, config: Config) -> std::io::Result<(Self, Self)> {
+        let mut threads = Self::spawn_all(vec![first, second], config)?;
+        let second = threads.remove(1);
+        Ok((threads.remove(0), second))
+    }
+
+    fn spawn_all(emulators: Vec<Emulator>, config: Config) -> std::io::Result<Vec<Self>> {
+        let mut sessions = Vec::new();
+        let mut threads = Vec::new();
+        for emulator in emulators {
+            let (command_tx, command_rx) = mpsc::channel();
+            let (frame_tx, frame_rx) = triple_buffer::triple_buffer();
+            let (reply_tx, reply_rx) = mpsc::channel();
+            sessions.push(Session {
+                emulator,
+                commands: command_rx,
+                frames: frame_tx,
+                replies: reply_tx,
+                capture_ppu: false,
+                ppu_captured: Instant::now(),
+                capture_pixel_sources: false,
+                capture_ram: false,
+                capture_cart_ram: false,
+                movie: None,
+                probe: None,
+                battery: None,
+                play_times: None,
+                paused: false,
+                step: None,
+                rumbled: Arc::new(AtomicBool::new(false)),
+            });
+            threads.push(Self {
+                commands: command_tx,
+                frames: frame_rx,
+                replies: reply_rx,
+                handle: None,
+            });
+        }
+
+        let handle = thread::Builder::new()
+            .name("emulation".to_string())
+            .spawn(move || run(sessions, config))?;
+        threads[0].handle = Some(handle);
+        Ok(threads)
+    }
+
+    pub fn send(&self, command: Command) {
+        // A send only fails once the thread has exited, which the frame receiver reports
+        let _ = self.commands.send(command);
+    }
+
+    // Wait up to `timeout` for a frame newer than the last one taken; frames the UI was too slow
+    // for are skipped. Returns Err when the emulation thread has stopped.
+    pub fn latest_frame(&mut self, timeout: Duration) -> Result<Option<&mut Frame>, Disconnected> {
+        self.frames.latest(timeout)
+    }
+
+    pub fn try_reply(&self) -> Option<Reply> {
+        self.replies.try_recv().ok()
+    }
+}
+
+impl Drop for EmulatorThread {
+    fn drop(&mut self) {
+        self.send(Command::Quit);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+// One emulator on the emulation thread and its channels to the UI
+struct Session {
+    emulator: Emulator,
+    commands: Receiver<Command>,
+    frames: Producer<Frame>,
+    replies: Sender<Reply>,
+    capture_ppu: bool,
+    // When the PPU was last copied for the VRAM viewer
+    ppu_captured: Instant,
+    capture_pixel_sources: bool,
+    capture_ram: bool,
+    capture_cart_ram: bool,
+    movie: Option<MovieState>,
+    // Latency probe waiting for the next frame
+    probe: Option<Instant>,
+    battery: Option<BatterySave>,
+    play_times: Option<PlayTimes>,
+    paused: bool,
+    step: Option<Step>,
+    // Set by the rumble hook when the motor starts, cleared as each frame is taken
+    rumbled: Arc<AtomicBool>,
+}
+
+impl Session {
+    fn store_battery(&mut self) {
+        if let Some(battery) = &mut self.battery {
+            match battery.store(&self.emulator.battery_save()) {
+                Ok(()) => self.emulator.mark_battery_saved(),
+                Err(e) => eprintln!("Failed to write {}: {}", battery.path().display(), e),
+            }
+        }
+    }
+
+    // Write the battery save if the game changed it since it was last written
+    fn flush_battery(&mut self) {
+        if self.emulator.battery_dirty() {
+            self.store_battery();
+        }
+    }
+
+    fn store_play_time(&self) {
+        if let Some(play_times) = &self.play_times
+            && let Err(e) = play_times.store(&self.emulator)
+        {
+            eprintln!("Failed to write {}: {}", play_times.path().display(), e);
+        }
+    }
+
+    // Apply everything the UI sent since the last frame; returns false once told to quit
+    fn apply_commands(&mut self) -> bool {
+        loop {
+            match self.commands.try_recv() {
+                Ok(Command::SetButton(button, pressed)) => self.emulator.set_button(button, pressed),
+                Ok(Command::LoadRom(rom, model, battery)) => {
+                    self.store_battery();
+                    self.store_play_time();
+                    self.emulator.set_model(model);
+                    self.emulator.load_rom(rom);
+                    if let Some(play_times) = &self.play_times {
+                        play_times.resume(&mut self.emulator);
+                    }
+                    self.battery = self.emulator.has_battery().then_some(battery);
+                    if let Some(battery) = &self.battery
+                        && let Err(e) = battery.load(&mut self.emulator)
+                    {
+                        eprintln!("Failed to read {}: {}", battery.path().display(), e);
+                    }
+                    self.stop_movie("a different ROM was loaded");
+                },
+                Ok(Command::SaveState) => {
+                    self.flush_battery();
+                    if let Some(movie) = &mut self.movie {
+                        movie.saved_frame = Some(movie.frame);
+                    }
+                    let _ = self.replies.send(Reply::StateSaved(self.emulator.save_state()));
+                },
+                Ok(Command::LoadState(data)) => {
+                    let result = self.emulator.load_state(&data);
+                    if result.is_ok() && let Some(movie) = &mut self.movie {
+                        if let Some(frame) = movie.saved_frame {
+                            movie.file.movie.rerecord(frame);
+                            movie.greenzone.truncate(frame);
+                            movie.frame = frame;
+                            movie.recording = true;
+                            let _ = self.replies.send(Reply::Movie(format!("recording from frame {} (re-record {})", frame, movie.file.movie.rerecords)));
+                        } else {
+                            self.stop_movie("the state was not saved during the movie");
+                        }
+                    }
+                    let _ = self.replies.send(Reply::StateLoaded(result));
+                },
+                Ok(Command::DumpState) => {
+                    let _ = self.replies.send(Reply::StateDump(dump::to_json(&self.emulator)));
+                },
+                Ok(Command::CapturePpu(enabled)) => self.capture_ppu = enabled,
+                Ok(Command::CapturePixelSources(enabled)) => self.capture_pixel_sources = enabled,
+                Ok(Command::CaptureRam(enabled)) => self.capture_ram = enabled,
+                Ok(Command::CaptureCartRam(enabled)) => self.capture_cart_ram = enabled,
+                Ok(Command::WriteCartRam(offset, data)) => self.emulator.write_cart_ram(offset, &data),
+                Ok(Command::SetCheatsEnabled(enabled)) => self.emulator.cheats_mut().set_all_enabled(enabled),
+                Ok(Command::Freeze(addr, value)) => self.emulator.freeze(addr, value),
+                Ok(Command::Unfreeze(addr)) => {
+                    self.emulator.unfreeze(addr);
+                },
+                Ok(Command::SeekMovie(delta)) => {
+                    if let Some(movie) = &mut self.movie {
+                        let message = match movie.seek(&mut self.emulator, delta) {
+                            Ok(frame) => format!("paused at frame {} of {}", frame, movie.file.movie.len()),
+                            Err(e) => format!("seek failed: {}", e),
+                        };
+                        let _ = self.replies.send(Reply::Movie(message));
+                    }
+                },
+                Ok(Command::AdvanceMovie) => {
+                    if let Some(movie) = &mut self.movie {
+                        movie.paused = true;
+                        movie.advance = true;
+                    }
+                },
+                Ok(Command::LatencyProbe(sent)) => self.probe = Some(sent),
+                Ok(Command::ResumeMovie) => {
+                    if let Some(movie) = &mut self.movie {
+                        movie.paused = false;
+                    }
+                },
+                Ok(Command::SetPaused(paused)) => self.paused = paused,
+                Ok(Command::Step(step)) => self.step = Some(step),
+                Ok(Command::ShiftRtc(seconds)) => {
+                    let _ = self.replies.send(Reply::Rtc(self.emulator.shift_rtc(seconds)));
+                },
+                Ok(Command::Quit) | Err(mpsc::TryRecvError::Disconnected) => return false,
+                Err(mpsc::TryRecvError::Empty) => return true,
+            }
+        }
+    }
+
+    // Write the movie out and stop recording
+    fn stop_movie(&mut self, reason: &str) {
+        if let Some(movie) = self.movie.take() {
+            let message = match movie.save() {
+                Ok(()) => format!("recording stopped: {}; saved {}", reason, movie.file.path.display()),
+                Err(e) => format!("recording stopped: {}; failed to write {}: {}", reason, movie.file.path.display(), e),
+            };
+            let _ = self.replies.send(Reply::Movie(message));
+        }
+    }
+
+    // Fill the free frame slot in place, reusing its pixel buffer; it goes to the UI with
+    // `frames.publish()`
+    fn fill_frame(&mut self) {
+        let emulator = &self.emulator;
+        let frame = self.frames.slot();
+        let buffer = emulator.frame();
+        frame.pixels.clear();
+        frame.pixels.extend_from_slice(buffer.pixels);
+        frame.generation = Some(buffer.generation);
+        // While paused every frame is a step, and each should show up in the viewer
+        let ppu_due = self.paused || self.ppu_captured.elapsed() >= PPU_CAPTURE_INTERVAL;
+        frame.ppu = (self.capture_ppu && ppu_due).then(|| Box::new(emulator.ppu().clone()));
+        frame.wave_ram = frame.ppu.is_some().then(|| emulator.bus().apu.wave_ram());
+        frame.wave_position = emulator.bus().apu.wave_position();
+        if frame.ppu.is_some() {
+            self.ppu_captured = Instant::now();
+        }
+        frame.pixel_sources.clear();
+        if self.capture_pixel_sources {
+            frame.pixel_sources.extend_from_slice(emulator.ppu().pixel_sources());
+        }
+        frame.ram = self.capture_ram.then(|| ram_search::capture(|addr| emulator.bus().read_byte(addr)));
+        frame.cart_ram = self.capture_cart_ram.then(|| emulator.cart_ram().to_vec());
+        frame.cart_ram_bank = emulator.bus().cart_ram_bank();
+        frame.stats = emulator.stats();
+        frame.beam = emulator.ppu().beam_position();
+        frame.cpu_mode = emulator.cpu_mode();
+        // Pulses shorter than a frame count too
+        frame.rumble = self.rumbled.swap(emulator.rumble(), Ordering::Relaxed);
+        frame.probe = self.probe.take().map(|sent| (sent, Instant::now()));
+        if frame.probe.is_some() {
+            frame.pixels.fill(0xFF);
+            frame.generation = None;
+        }
+    }
+}
+
+// M-cycles each linked emulator runs before the other gets its turn: one scanline, so a byte
+// clocked over the cable is picked up well within the time the sender's transfer takes
+const LOCKSTEP_SLICE: u64 = 114;
+
+// Run linked emulators for one frame, taking turns a slice at a time
+fn run_lockstep(sessions: &mut [Session]) {
+    let frame = CYCLES_PER_FRAME as u64 / 4;
+    let starts: Vec<u64> = sessions.iter().map(|session| session.emulator.clock().m_cycles()).collect();
+    for slice in 1..=frame / LOCKSTEP_SLICE {
+        for (session, start) in sessions.iter_mut().zip(&starts) {
+            session.emulator.run_until(start + slice * LOCKSTEP_SLICE);
+        }
+    }
+}
+
+fn run(mut sessions: Vec<Session>, mut config: Config) {
+    for (session, battery) in sessions.iter_mut().zip(config.batteries.drain(..)) {
+        session.battery = battery;
+        if let Some(play_times) = &config.play_times {
+            play_times.resume(&mut session.emulator);
+            session.play_times = Some(play_times.clone());
+        }
+    }
+    if let Some(audio) = config.audio.take() {
+        sessions[0].emulator.on_audio_samples(move |samples| audio.push(samples));
+    }
+    sessions[0].movie = config.movie.map(|file| MovieState {
+        file,
+        frame: 0,
+        saved_frame: None,
+        recording: false,
+        greenzone: Greenzone::new(GREENZONE_INTERVAL, GREENZONE_CAPACITY).with_budget(GREENZONE_BUDGET),
+        paused: false,
+        advance: false,
+    });
+    for session in &mut sessions {
+        let error_replies = session.replies.clone();
+        session.emulator.on_event(move |event| {
+            if let EmulatorEvent::Error(error) = event {
+                let _ = error_replies.send(Reply::Error(error));
+            }
+        });
+        let rumbled = session.rumbled.clone();
+        session.emulator.on_rumble(move |on| {
+            if on {
+                rumbled.store(true, Ordering::Relaxed);
+            }
+        });
+        if let Some(min_severity) = config.diagnostics {
+            let replies = session.replies.clone();
+            let mut seen = HashSet::new();
+            session.emulator.on_diagnostic(move |event| {
+                // Errors already arrive through the error policy, and a game with a tearing bug
+                // repeats the same write every frame
+                if event.severity() >= min_severity
+                    && !matches!(event.diagnostic, Diagnostic::Error(_))
+                    && seen.insert((std::mem::discriminant(&event.diagnostic), event.pc))
+                {
+                    let _ = replies.send(Reply::Diagnostic(event));
+                }
+            });
+        }
+    }
+
+    #[cfg(feature = "lua")]
+    let script_replies = sessions[0].replies.clone();
+    #[cfg(feature = "lua")]
+    let mut script = config.script.as_deref().and_then(|path| match Script::load(path, &mut sessions[0].emulator) {
+        Ok(script) => Some(script),
+        Err(e) => {
+            let _ = script_replies.send(Reply::ScriptError(e));
+            None
+        },
+    });
+    #[cfg(not(feature = "lua"))]
+    if config.script.is_some() {
+        let _ = sessions[0].replies.send(Reply::ScriptError("built without Lua support".to_string()));
+    }
+
+    #[cfg(feature = "achievements")]
+    let mut achievements = config.achievements.as_deref().and_then(|path| match Achievements::load(path) {
+        Ok(achievements) => {
+            let _ = sessions[0].replies.send(Reply::Achievement(format!("loaded {} from {}", achievements.count(), path.display())));
+            Some(achievements)
+        },
+        Err(e) => {
+            let _ = sessions[0].replies.send(Reply::Achievement(e));
+            None
+        },
+    });
+    #[cfg(not(feature = "achievements"))]
+    if config.achievements.is_some() {
+        let _ = sessions[0].replies.send(Reply::Achievement("built without achievements support".to_string()));
+    }
+
+    // The script and the frame it is on
+    let mut input_script = config.input_script.take().map(|script| (script, 0));
+    let mut next_frame = Instant::now();
+    let mut battery_flushed = Instant::now();
+    let mut play_time_stored = Instant::now();
+
+    'running: loop {
+        // Apply everything the UI sent since the last frame
+        for session in &mut sessions {
+            if !session.apply_commands() {
+                break 'running;
+            }
+        }
+
+        // A debug pause holds every machine wherever it is; steps run them all by the same amount
+        // and leave the movie alone
+        if let Some(step) = sessions[0].step.take()
+            && sessions[0].paused
+        {
+            for session in &mut sessions {
+                match step {
+                    Step::Instruction => {
+                        session.emulator.step();
+                    },
+                    Step::Scanline => {
+                        session.emulator.run_for_m_cycles(CYCLES_PER_FRAME as u64 / 4 / 154);
+                    },
+                }
+            }
+        }
+
+        // A paused movie holds everything on the current frame
+        let mut emulated = 0;
+        let first = &mut sessions[0];
+        let run_frame = !first.paused && match &mut first.movie {
+            Some(movie) if movie.paused => std::mem::take(&mut movie.advance),
+            _ => true,
+        };
+        if run_frame {
+            // Before the movie, so a recording takes the scripted buttons
+            if let Some((script, frame)) = &mut input_script {
+                first.emulator.set_buttons(script.buttons(*frame));
+                *frame += 1;
+                if *frame > script.length() {
+                    let _ = first.replies.send(Reply::InputScriptFinished(script.length()));
+                    input_script = None;
+                }
+            }
+            if let Some(movie) = &mut first.movie {
+                movie.next_frame(&mut first.emulator, &first.replies);
+            }
+
+            #[cfg(feature = "lua")]
+            run_script(&mut script, &script_replies, |script| script.before_frame(&mut sessions[0].emulator));
+
+            let start = sessions[0].emulator.clock().m_cycles();
+            match &mut sessions[..] {
+                [session] => session.emulator.run_until_frame(),
+                linked => run_lockstep(linked),
+            }
+            emulated = sessions[0].emulator.clock().m_cycles().saturating_sub(start);
+
+            #[cfg(feature = "lua")]
+            run_script(&mut script, &script_replies, |script| script.after_frame(&mut sessions[0].emulator));
+
+            #[cfg(feature = "achievements")]
+            if let Some(achievements) = &mut achievements {
+                for title in achievements.do_frame(&sessions[0].emulator) {
+                    let _ = sessions[0].replies.send(Reply::Achievement(format!("unlocked \"{}\"", title)));
+                }
+            }
+        }
+
+        for session in &mut sessions {
+            session.fill_frame();
+        }
+        #[cfg(feature = "lua")]
+        if let Some(script) = &script {
+            let frame = sessions[0].frames.slot();
+            script.draw_osd(&mut frame.pixels);
+            // The script's text can change while the picture doesn't
+            frame.generation = None;
+        }
+        for session in &mut sessions {
+            session.frames.publish();
+        }
+
+        if let Some(interval) = config.battery_flush
+            && battery_flushed.elapsed() >= interval
+        {
+            for session in &mut sessions {
+                session.flush_battery();
+            }
+            battery_flushed = Instant::now();
+        }
+        if play_time_stored.elapsed() >= PLAY_TIME_INTERVAL {
+            for session in &sessions {
+                session.store_play_time();
+            }
+            play_time_stored = Instant::now();
+        }
+
+        // Pace emulation against its own schedule rather than the UI's
+        next_frame += match config.pacing {
+            Pacing::Fixed(duration) => duration,
+            Pacing::Hardware if emulated > 0 => Clock::duration_of(emulated),
+            // Nothing ran (paused): wait as long as a full frame would take
+            Pacing::Hardware => Clock::duration_of(CYCLES_PER_FRAME as u64 / 4),
+        };
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            // Fell behind (e.g. the machine was suspended); don't try to catch up in a burst
+            tracing::debug!(behind = ?(now - next_frame), "emulation fell behind");
+            next_frame = now;
+        }
+    }
+
+    // Nobody reads replies any more at this point
+    for session in &mut sessions {
+        session.store_battery();
+        session.store_play_time();
+    }
+    if let Some(movie) = &sessions[0].movie
+        && let Err(e) = movie.save()
+    {
+        eprintln!("Failed to write movie {}: {}", movie.file.path.display(), e);
+    }
+    if let (Some(path), Some(coverage)) = (&config.opcode_coverage, sessions[0].emulator.opcode_coverage())
+        && let Err(e) = std::fs::write(path, coverage.report())
+    {
+        eprintln!("Failed to write opcode coverage {}: {}", path.display(), e);
+    }
+}
+
+// Run a script callback, reporting and disabling the script if it fails
+#[cfg(feature = "lua")]
+fn run_script(script: &mut Option<Script>, replies: &Sender<Reply>, f: impl FnOnce(&Script) -> Result<(), String>) {
+    if let Some(Err(e)) = script.as_ref().map(f) {
+        let _ = replies.send(Reply::ScriptError(e));
+        *script = None;
+    }
+}
@@ -0,0 +1,127 @@
+// ROMs in ZIP archives
+// Most ROM collections are zipped one game per archive. An archive holding exactly one `.gb` or
+// `.gbc` file is loaded by unpacking that file in memory; other files in it (readmes, .nfo) are
+// ignored. Only stored and deflated entries are supported, which is what ZIP tools write by
+// default, and no encryption or ZIP64 (a Game Boy ROM is at most 8 MiB).
+
+use miniz_oxide::inflate::decompress_to_vec_with_limit;
+
+const LOCAL_HEADER: u32 = 0x0403_4B50;
+const CENTRAL_HEADER: u32 = 0x0201_4B50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4B50;
+// Fixed part of the end of central directory record; a comment of up to 64 KiB may follow it
+const END_RECORD_SIZE: usize = 22;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+// Largest Game Boy ROM; bigger entries are refused before anything is allocated for them
+const MAX_ROM_SIZE: usize = 8 * 1024 * 1024;
+
+// File in the archive, from its central directory entry
+struct Entry {
+    name: String,
+    flags: u16,
+    method: u16,
+    compressed_size: usize,
+    size: usize,
+    header_offset: usize,
+}
+
+// Whether `data` starts like a ZIP archive
+pub fn is_zip(data: &[u8]) -> bool {
+    data.len() >= 4 && u32_at(data, 0) == Some(LOCAL_HEADER)
+}
+
+// The one Game Boy ROM in the archive, unpacked
+pub fn extract_rom(data: &[u8]) -> Result<Vec<u8>, String> {
+    let entries = entries(data)?;
+    let roms: Vec<&Entry> = entries.iter().filter(|entry| is_rom_name(&entry.name)).collect();
+    let entry = match roms.as_slice() {
+        [entry] => *entry,
+        [] => return Err("no .gb or .gbc file in the ZIP archive".to_string()),
+        _ => {
+            let names: Vec<&str> = roms.iter().map(|entry| entry.name.as_str()).collect();
+            return Err(format!("the ZIP archive has {} ROMs ({}), expected one", roms.len(), names.join(", ")));
+        },
+    };
+    if entry.flags & 0x0001 != 0 {
+        return Err(format!("{} is encrypted", entry.name));
+    }
+    if entry.size > MAX_ROM_SIZE {
+        return Err(format!("{} is {} bytes, larger than any Game Boy ROM", entry.name, entry.size));
+    }
+
+    // The local header repeats the name and has its own extra field before the data
+    let truncated = || format!("{} is cut off", entry.name);
+    let header = entry.header_offset;
+    if u32_at(data, header) != Some(LOCAL_HEADER) {
+        return Err(format!("bad local header for {}", entry.name));
+    }
+    let name_length = u16_at(data, header + 26).ok_or_else(truncated)? as usize;
+    let extra_length = u16_at(data, header + 28).ok_or_else(truncated)? as usize;
+    let start = header + 30 + name_length + extra_length;
+    let compressed = data.get(start..start + entry.compressed_size).ok_or_else(truncated)?;
+
+    let rom = match entry.method {
+        STORED => compressed.to_vec(),
+        DEFLATED => decompress_to_vec_with_limit(compressed, entry.size).map_err(|e| format!("{} is corrupt: {}", entry.name, e))?,
+        method => return Err(format!("{} uses compression method {}, only stored and deflated files are supported", entry.name, method)),
+    };
+    if rom.len() != entry.size {
+        return Err(format!("{} unpacked to {} bytes, expected {}", entry.name, rom.len(), entry.size));
+    }
+    Ok(rom)
+}
+
+// Files listed in the central directory
+fn entries(data: &[u8]) -> Result<Vec<Entry>, String> {
+    let bad = || "not a valid ZIP archive".to_string();
+    // The end record is the last thing in the file, unless the archive has a comment
+    let end = (0..=data.len().saturating_sub(END_RECORD_SIZE))
+        .rev()
+        .take(0x10000)
+        .find(|&offset| u32_at(data, offset) == Some(END_OF_CENTRAL_DIRECTORY))
+        .ok_or_else(bad)?;
+    let count = u16_at(data, end + 10).ok_or_else(bad)? as usize;
+    let mut offset = u32_at(data, end + 16).ok_or_else(bad)? as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    for _ in 0..count {
+        if u32_at(data, offset) != Some(CENTRAL_HEADER) {
+            return Err(bad());
+        }
+        let field = |at: usize| u16_at(data, offset + at).ok_or_else(bad);
+        let size_field = |at: usize| u32_at(data, offset + at).ok_or_else(bad);
+        let name_length = field(28)? as usize;
+        let name = data.get(offset + 46..offset + 46 + name_length).ok_or_else(bad)?;
+        let (compressed_size, size) = (size_field(20)?, size_field(24)?);
+        if compressed_size == u32::MAX || size == u32::MAX {
+            return Err("ZIP64 archives are not supported".to_string());
+        }
+        entries.push(Entry {
+            name: String::from_utf8_lossy(name).into_owned(),
+            flags: field(8)?,
+            method: field(10)?,
+            compressed_size: compressed_size as usize,
+            size: size as usize,
+            header_offset: size_field(42)? as usize,
+        });
+        offset += 46 + name_length + field(30)? as usize + field(32)? as usize;
+    }
+    Ok(entries)
+}
+
+fn is_rom_name(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    // Skip macOS resource forks (`__MACOSX/._game.gb`) that some archivers add
+    !name.starts_with("__macosx/") && (name.ends_with(".gb") || name.ends_with(".gbc"))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+}